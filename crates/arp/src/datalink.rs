@@ -0,0 +1,46 @@
+//! The datalink layer used to send and receive frames, the [ArpManager](crate::ArpManager) uses
+//! the [SystemDataLink] by default, another implementation can be given with
+//! [ArpManager::with_datalink](crate::ArpManager::with_datalink), eg: to test scanning without a
+//! real network interface
+
+use pnet::datalink::{Channel, Config, DataLinkReceiver, DataLinkSender, NetworkInterface};
+use std::io;
+use std::time::Duration;
+
+/// How long the receive loop blocks waiting for a frame before checking for cancellation, a
+/// receiver should return an error of kind [io::ErrorKind::TimedOut] if no frame arrives in time
+pub const RECEIVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The sending and receiving ends of an ethernet channel
+pub type EthernetChannel = (Box<dyn DataLinkSender>, Box<dyn DataLinkReceiver>);
+
+/// A source of network interfaces and ethernet channels on those interfaces
+pub trait DataLink: Send + Sync {
+    /// Returns the network interfaces which may be scanned
+    fn interfaces(&self) -> Vec<NetworkInterface>;
+
+    /// Open an ethernet channel on the given interface
+    fn channel(&self, interface: &NetworkInterface) -> io::Result<EthernetChannel>;
+}
+
+/// The datalink layer of the operating system, this opens raw sockets so requires root or the
+/// `CAP_NET_RAW` capability
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemDataLink;
+
+impl DataLink for SystemDataLink {
+    fn interfaces(&self) -> Vec<NetworkInterface> {
+        pnet::datalink::interfaces()
+    }
+
+    fn channel(&self, interface: &NetworkInterface) -> io::Result<EthernetChannel> {
+        let cfg = Config {
+            read_timeout: Some(RECEIVE_TIMEOUT),
+            ..Default::default()
+        };
+        match pnet::datalink::channel(interface, cfg)? {
+            Channel::Ethernet(tx, rx) => Ok((tx, rx)),
+            _ => Err(io::Error::other("unknown channel type")),
+        }
+    }
+}
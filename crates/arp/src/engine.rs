@@ -0,0 +1,330 @@
+//! The [Engine] shared by the scanners of each interface, and the cache of addresses observed on
+//! the network which is shared between the engines
+
+use pnet::datalink::{DataLinkReceiver, DataLinkSender, NetworkInterface};
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::util::MacAddr;
+use crate::datalink::DataLink;
+use crate::{icmp, ndp};
+use control::util::lock;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tracing::{error, trace};
+
+/// How long an address observed on the network is used before a full sweep is needed again
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// The last address observed for each MAC address, shared between the engines of all
+/// interfaces so that addresses seen by one scanner benefit the others
+pub(crate) type Cache = Arc<Mutex<HashMap<MacAddr, (IpAddr, Instant)>>>;
+
+/// An ARP reply received from a device
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reply {
+    /// The MAC address of the device which replied
+    pub mac: MacAddr,
+    /// The IP address the device replied with
+    pub ip: IpAddr,
+    /// The time the reply was received
+    pub received: Instant,
+}
+
+/// The addresses of the local machine on an interface
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Local {
+    pub mac: MacAddr,
+    pub ipv4: Option<Ipv4Addr>,
+    /// The link-local IPv6 address
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl Local {
+    /// Returns the addresses of the given interface, or None if it has no MAC address
+    pub fn of(interface: &NetworkInterface) -> Option<Self> {
+        let ipv4 = interface.ips.iter().find_map(|ip| match ip.ip() {
+            IpAddr::V4(addr) => Some(addr),
+            IpAddr::V6(_) => None,
+        });
+        let ipv6 = interface.ips.iter().find_map(|ip| match ip.ip() {
+            IpAddr::V6(addr) if addr.is_unicast_link_local() => Some(addr),
+            _ => None,
+        });
+        Some(Self {
+            mac: interface.mac?,
+            ipv4,
+            ipv6,
+        })
+    }
+}
+
+/// The ARP engine owns the datalink channel of a single interface, it sends requests on behalf
+/// of each scanner and a single receive loop passes the replies on to the scanners subscribed to
+/// the MAC address of the replying device
+pub(crate) struct Engine {
+    pub local: Local,
+    sender: Mutex<(Box<dyn DataLinkSender>, ArpTemplate)>,
+    subscribers: Mutex<HashMap<MacAddr, Vec<UnboundedSender<Reply>>>>,
+    cache: Cache,
+}
+
+impl Engine {
+    /// Open a channel on the given interface, the returned receiver should be passed to
+    /// [receive](Self::receive) on a blocking thread
+    pub fn open(
+        datalink: &dyn DataLink,
+        interface: &NetworkInterface,
+        local: Local,
+        cache: Cache,
+    ) -> Result<(Self, Box<dyn DataLinkReceiver>), io::Error> {
+        let (sender, receiver) = datalink.channel(interface)?;
+        let source_ip = local.ipv4.unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let engine = Self {
+            local,
+            sender: Mutex::new((sender, ArpTemplate::new(local.mac, source_ip))),
+            subscribers: Mutex::new(HashMap::new()),
+            cache,
+        };
+        Ok((engine, receiver))
+    }
+
+    /// Subscribe to all replies from any of the given MAC addresses
+    pub fn subscribe(&self, macs: impl IntoIterator<Item = MacAddr>) -> UnboundedReceiver<Reply> {
+        let (sender, receiver) = unbounded_channel();
+        let mut subscribers = lock(&self.subscribers);
+        for mac in macs {
+            subscribers.entry(mac).or_default().push(sender.clone());
+        }
+        receiver
+    }
+
+    /// Returns the last address observed for the given MAC address if it is recent enough
+    pub fn cached(&self, mac: MacAddr) -> Option<IpAddr> {
+        let cache = lock(&self.cache);
+        let (ip, seen) = cache.get(&mac)?;
+        (seen.elapsed() < CACHE_TTL).then_some(*ip)
+    }
+
+    fn remember(&self, mac: MacAddr, ip: IpAddr) {
+        if ip.is_unspecified() {
+            return;
+        }
+        lock(&self.cache).insert(mac, (ip, Instant::now()));
+    }
+
+    /// Send an ARP request for the given IP address to the given MAC address, which may be the
+    /// broadcast address, returns false if the request could not be sent
+    pub fn send(&self, ip: Ipv4Addr, mac: MacAddr) -> bool {
+        let mut guard = lock(&self.sender);
+        let (sender, template) = &mut *guard;
+        let pkt = template.execute(ip, mac);
+        send_frame(sender.as_mut(), pkt)
+    }
+
+    /// Send an ICMPv6 echo request to all nodes on the link, each IPv6 device on the link will
+    /// reply, returns false if the request could not be sent
+    pub fn send_multicast_ping(&self) -> bool {
+        let Some(source_ip) = self.local.ipv6 else {
+            return false;
+        };
+        let destination = (ndp::multicast_mac(ndp::ALL_NODES), ndp::ALL_NODES);
+        let pkt = ndp::echo_request((self.local.mac, source_ip), destination);
+        send_frame(lock(&self.sender).0.as_mut(), &pkt)
+    }
+
+    /// Send a neighbor solicitation for the given IPv6 address to the given MAC address, returns
+    /// false if the request could not be sent
+    pub fn send_neighbor_solicitation(&self, ip: Ipv6Addr, mac: MacAddr) -> bool {
+        let Some(source_ip) = self.local.ipv6 else {
+            return false;
+        };
+        let pkt = ndp::neighbor_solicitation((self.local.mac, source_ip), (mac, ip));
+        send_frame(lock(&self.sender).0.as_mut(), &pkt)
+    }
+
+    /// Send an ICMP echo request to the given address at the given MAC address, returns false
+    /// if the request could not be sent
+    pub fn send_ping(&self, ip: IpAddr, mac: MacAddr) -> bool {
+        let pkt = match ip {
+            IpAddr::V4(ip) => {
+                let Some(source_ip) = self.local.ipv4 else {
+                    return false;
+                };
+                icmp::echo_request((self.local.mac, source_ip), (mac, ip))
+            }
+            IpAddr::V6(ip) => {
+                let Some(source_ip) = self.local.ipv6 else {
+                    return false;
+                };
+                ndp::echo_request((self.local.mac, source_ip), (mac, ip))
+            }
+        };
+        send_frame(lock(&self.sender).0.as_mut(), &pkt)
+    }
+
+    /// Receives frames from the interface until cancelled, passing ARP replies, ping replies and
+    /// IPv6 neighbor discovery replies to subscribers, this blocks the thread
+    pub fn receive(&self, mut receiver: Box<dyn DataLinkReceiver>, token: CancellationToken) {
+        while !token.is_cancelled() {
+            let buf = match receiver.next() {
+                Ok(buf) => buf,
+                Err(error) if error.kind() == io::ErrorKind::TimedOut => continue,
+                Err(error) => {
+                    error!("Error receiving ARP frame: {error}");
+                    continue;
+                }
+            };
+            let Some(pkt_eth) = EthernetPacket::new(buf) else {
+                continue;
+            };
+            let ethertype = pkt_eth.get_ethertype();
+            if ethertype == EtherTypes::Arp {
+                // every ARP packet reveals the address of its sender, including the requests
+                // broadcast by other hosts
+                self.observe_arp(buf);
+            }
+            if pkt_eth.get_destination() != self.local.mac {
+                continue;
+            }
+            let reply = if ethertype == EtherTypes::Arp {
+                self.parse_arp(buf)
+            } else if ethertype == EtherTypes::Ipv4 {
+                icmp::parse_reply(&pkt_eth).map(|ip| Reply {
+                    mac: pkt_eth.get_source(),
+                    ip: ip.into(),
+                    received: Instant::now(),
+                })
+            } else if ethertype == EtherTypes::Ipv6 {
+                ndp::parse_reply(&pkt_eth).map(|ip| Reply {
+                    mac: pkt_eth.get_source(),
+                    ip: ip.into(),
+                    received: Instant::now(),
+                })
+            } else {
+                None
+            };
+            if let Some(reply) = reply {
+                trace!("reply from {}: {}", reply.mac, reply.ip);
+                self.remember(reply.mac, reply.ip);
+                self.dispatch(reply);
+            }
+        }
+    }
+
+    fn observe_arp(&self, buf: &[u8]) {
+        let Some(pkt_arp) = buf
+            .get(EthernetPacket::minimum_packet_size()..)
+            .and_then(ArpPacket::new)
+        else {
+            return;
+        };
+        self.remember(pkt_arp.get_sender_hw_addr(), pkt_arp.get_sender_proto_addr().into());
+    }
+
+    fn parse_arp(&self, buf: &[u8]) -> Option<Reply> {
+        if buf.len() < COMBINED_PACKET_SIZE {
+            return None;
+        }
+        let Some(pkt_arp) = ArpPacket::new(&buf[EthernetPacket::minimum_packet_size()..]) else {
+            error!("Buffer not large enough for ARP frame");
+            return None;
+        };
+        if pkt_arp.get_operation() != ArpOperations::Reply
+            || pkt_arp.get_target_hw_addr() != self.local.mac
+        {
+            return None;
+        }
+        Some(Reply {
+            mac: pkt_arp.get_sender_hw_addr(),
+            ip: pkt_arp.get_sender_proto_addr().into(),
+            received: Instant::now(),
+        })
+    }
+
+    fn dispatch(&self, reply: Reply) {
+        let mut subscribers = lock(&self.subscribers);
+        if let Some(senders) = subscribers.get_mut(&reply.mac) {
+            // drop the subscribers of scanners which have stopped
+            senders.retain(|sender| sender.send(reply).is_ok());
+        }
+    }
+}
+
+fn send_frame(sender: &mut dyn DataLinkSender, pkt: &[u8]) -> bool {
+    match sender.send_to(pkt, None) {
+        Some(Ok(())) => true,
+        Some(Err(error)) => {
+            error!("Error sending frame: {error}");
+            false
+        }
+        None => {
+            error!("Error sending frame");
+            false
+        }
+    }
+}
+
+const COMBINED_PACKET_SIZE: usize =
+    EthernetPacket::minimum_packet_size() + ArpPacket::minimum_packet_size();
+
+struct ArpTemplate([u8; COMBINED_PACKET_SIZE]);
+
+impl ArpTemplate {
+    fn new(source_mac: MacAddr, source_ip: Ipv4Addr) -> Self {
+        let mut pkt_buf = [0u8; COMBINED_PACKET_SIZE];
+
+        // Use scope blocks so we can reborrow our buffer
+        {
+            // Build our base ethernet frame
+            #[allow(clippy::expect_used)]
+            let mut pkt_eth = MutableEthernetPacket::new(&mut pkt_buf)
+                .expect("buffer is large enough for EthernetPacket");
+
+            pkt_eth.set_destination(MacAddr::broadcast());
+            pkt_eth.set_source(source_mac);
+            pkt_eth.set_ethertype(EtherTypes::Arp);
+        }
+
+        {
+            // Build the ARP frame on top of the ethernet frame
+            #[allow(clippy::expect_used)]
+            let mut pkt_arp =
+                MutableArpPacket::new(&mut pkt_buf[EthernetPacket::minimum_packet_size()..])
+                    .expect("buffer is large enough for ArpPacket");
+
+            pkt_arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+            pkt_arp.set_protocol_type(EtherTypes::Ipv4);
+            pkt_arp.set_hw_addr_len(6);
+            pkt_arp.set_proto_addr_len(4);
+            pkt_arp.set_operation(ArpOperations::Request);
+            pkt_arp.set_sender_hw_addr(source_mac);
+            pkt_arp.set_sender_proto_addr(source_ip);
+        }
+        Self(pkt_buf)
+    }
+
+    fn execute(&mut self, ip: Ipv4Addr, mac: MacAddr) -> &[u8] {
+        {
+            // Build our base ethernet frame
+            #[allow(clippy::expect_used)]
+            let mut pkt_eth = MutableEthernetPacket::new(&mut self.0)
+                .expect("buffer is large enough for EthernetPacket");
+
+            pkt_eth.set_destination(mac);
+        }
+        {
+            #[allow(clippy::expect_used)]
+            let mut pkt_arp =
+                MutableArpPacket::new(&mut self.0[EthernetPacket::minimum_packet_size()..])
+                    .expect("buffer is large enough for ArpPacket");
+            pkt_arp.set_target_proto_addr(ip);
+            pkt_arp.set_target_hw_addr(mac)
+        }
+        &self.0
+    }
+}
@@ -0,0 +1,65 @@
+//! Packet building and parsing for IPv4 ICMP echo, used to confirm devices which ignore ARP
+
+use pnet::packet::Packet;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmp::{self, IcmpPacket, IcmpTypes, MutableIcmpPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{self, Ipv4Packet, MutableIpv4Packet};
+use pnet::util::MacAddr;
+use std::net::Ipv4Addr;
+
+/// Builds an ICMP echo request frame
+pub(crate) fn echo_request(
+    (source_mac, source_ip): (MacAddr, Ipv4Addr),
+    (destination_mac, destination_ip): (MacAddr, Ipv4Addr),
+) -> Vec<u8> {
+    const ETHERNET: usize = EthernetPacket::minimum_packet_size();
+    const IPV4: usize = Ipv4Packet::minimum_packet_size();
+    // type, code, checksum, identifier and sequence number
+    const ICMP: usize = 8;
+    let mut buf = vec![0u8; ETHERNET + IPV4 + ICMP];
+
+    // Use scope blocks so we can reborrow our buffer
+    {
+        #[allow(clippy::expect_used)]
+        let mut pkt_eth = MutableEthernetPacket::new(&mut buf)
+            .expect("buffer is large enough for EthernetPacket");
+        pkt_eth.set_destination(destination_mac);
+        pkt_eth.set_source(source_mac);
+        pkt_eth.set_ethertype(EtherTypes::Ipv4);
+    }
+    {
+        #[allow(clippy::expect_used)]
+        let mut pkt_ipv4 = MutableIpv4Packet::new(&mut buf[ETHERNET..])
+            .expect("buffer is large enough for Ipv4Packet");
+        pkt_ipv4.set_version(4);
+        // in 32-bit words, without options
+        pkt_ipv4.set_header_length(5);
+        pkt_ipv4.set_total_length((IPV4 + ICMP) as u16);
+        pkt_ipv4.set_ttl(64);
+        pkt_ipv4.set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+        pkt_ipv4.set_source(source_ip);
+        pkt_ipv4.set_destination(destination_ip);
+        let checksum = ipv4::checksum(&pkt_ipv4.to_immutable());
+        pkt_ipv4.set_checksum(checksum);
+    }
+    {
+        #[allow(clippy::expect_used)]
+        let mut pkt_icmp = MutableIcmpPacket::new(&mut buf[ETHERNET + IPV4..])
+            .expect("buffer is large enough for IcmpPacket");
+        pkt_icmp.set_icmp_type(IcmpTypes::EchoRequest);
+        let checksum = icmp::checksum(&pkt_icmp.to_immutable());
+        pkt_icmp.set_checksum(checksum);
+    }
+    buf
+}
+
+/// Returns the source address of the frame if it is an ICMP echo reply
+pub(crate) fn parse_reply(frame: &EthernetPacket) -> Option<Ipv4Addr> {
+    let ipv4 = Ipv4Packet::new(frame.payload())?;
+    if ipv4.get_next_level_protocol() != IpNextHeaderProtocols::Icmp {
+        return None;
+    }
+    let icmp = IcmpPacket::new(ipv4.payload())?;
+    (icmp.get_icmp_type() == IcmpTypes::EchoReply).then(|| ipv4.get_source())
+}
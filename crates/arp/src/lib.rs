@@ -1,30 +1,41 @@
 #![doc= include_str!("../README.md")]
 
+mod datalink;
+mod engine;
+mod icmp;
+mod ndp;
+mod scanner;
+mod wol;
+
 use bon::bon;
-use derive_more::Deref;
-use futures::future::BoxFuture;
+use futures::future::{BoxFuture, join_all, try_join_all};
 use futures::stream::BoxStream;
 use futures::{Stream, StreamExt};
-use pnet::datalink::{Channel, DataLinkReceiver, DataLinkSender, NetworkInterface};
-use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
-use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
 use std::collections::HashMap;
 use std::future::ready;
-use std::io;
 use std::net::{IpAddr, Ipv4Addr};
 use std::ops::Range;
-use std::thread::sleep;
-use std::time::{Duration, Instant};
-use tokio::sync::watch::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch::Receiver;
 use tokio_stream::wrappers::WatchStream;
-use tracing::{debug, debug_span, error, trace};
+use tracing::{error, info_span};
 
+use anyhow::{Context, anyhow};
 use control::device::Device;
-use control::device_manager::DeviceManager;
+use control::{ReadValue, Sensor};
+use control::device_manager::{DeviceManager, Supervisor};
+use control::dry_run::DryRun;
 use control::reflect;
 use control::reflect::value::{Value, ValueType};
 use control::reflect::{DeviceInfo, Field, Operation, Operations, SetError};
+pub use datalink::{DataLink, EthernetChannel, RECEIVE_TIMEOUT, SystemDataLink};
+use engine::{Cache, Engine};
+pub use pnet::datalink::{DataLinkReceiver, DataLinkSender, NetworkInterface};
 pub use pnet::util::MacAddr;
+pub use scanner::ArpScanner;
+use scanner::{Addresses, Receivers};
+pub use wol::{WolConfig, WolDevice};
 use thiserror::Error;
 use tokio::spawn;
 use tokio::task::spawn_blocking;
@@ -43,22 +54,82 @@ pub struct NetworkScannerConfig {
     pub confirm_interval: Duration,
     /// the interval between each scan for the device while it is offline
     pub scan_interval: Duration,
-    /// The range of IP addresses to check
-    pub ip_range: Range<Ipv4Addr>,
-    /// The device to scan for
-    pub device: MacAddr,
+    /// The range of IP addresses to check, if None this is the subnet of the interface
+    pub ip_range: Option<Range<Ipv4Addr>>,
+    /// The devices to scan for, these are all checked in the same sweep of the IP range
+    pub devices: Vec<MacAddr>,
+    /// The protocols used to find the devices
+    pub mode: ScanMode,
+    /// If true, a device which does not answer a confirmation is pinged before it is deemed
+    /// offline, phones in deep sleep often ignore ARP but still answer ICMP echo requests
+    pub ping_fallback: bool,
+    /// The number of confirmations in a row a device must miss before it is deemed offline, a
+    /// value of 1 marks it offline on the first miss
+    pub misses_before_offline: u32,
+}
+
+/// The protocols used to find devices on the network
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum ScanMode {
+    /// Find devices by their IPv4 address using ARP
+    #[default]
+    Arp,
+    /// Find devices by their IPv6 address using [NDP](https://en.wikipedia.org/wiki/Neighbor_Discovery_Protocol),
+    /// for IPv6-only networks
+    Ndp,
+    /// Use both ARP and NDP, a device is found by whichever protocol it replies to first
+    Both,
+}
+
+impl ScanMode {
+    fn arp(self) -> bool {
+        matches!(self, ScanMode::Arp | ScanMode::Both)
+    }
+
+    fn ndp(self) -> bool {
+        matches!(self, ScanMode::Ndp | ScanMode::Both)
+    }
+
+    fn accepts(self, ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(_) => self.arp(),
+            IpAddr::V6(_) => self.ndp(),
+        }
+    }
 }
 
 /// A manager of ARP scanners. Collects created scanners until ready to begin scanning
-#[derive(Default)]
 pub struct ArpManager {
     scanners: Vec<ArpScanner>,
+    datalink: Arc<dyn DataLink>,
+    dry_run: DryRun,
+}
+
+impl Default for ArpManager {
+    fn default() -> Self {
+        Self::with_datalink(SystemDataLink)
+    }
 }
 
 impl DeviceManager for ArpManager {
     fn start(self: Box<Self>, token: CancellationToken) {
         spawn(self.run(token));
     }
+
+    fn supervise(self: Box<Self>, supervisor: &Supervisor) {
+        let token = supervisor.token();
+        let cache = Cache::default();
+        for (interface, scanners) in by_interface(self.scanners) {
+            let (datalink, cache, token) = (self.datalink.clone(), cache.clone(), token.clone());
+            supervisor.spawn(format!("arp {interface}"), move || {
+                run_interface(datalink.clone(), scanners.clone(), cache.clone(), token.clone())
+            });
+        }
+    }
+
+    fn set_dry_run(&mut self, dry_run: DryRun) {
+        self.dry_run = dry_run;
+    }
 }
 
 impl ArpManager {
@@ -67,36 +138,109 @@ impl ArpManager {
         Self::default()
     }
 
-    /// Run all scanners
-    pub async fn run(self, token: CancellationToken) {
-        let handles = self
-            .scanners
-            .into_iter()
-            .map(|scanner| spawn_blocking(|| scanner.run()))
-            .collect::<Vec<_>>();
-        token.cancelled().await;
-        for handle in handles {
-            handle.abort();
+    /// Create a new manager which sends and receives frames using the given datalink layer
+    /// rather than the raw sockets of the operating system
+    pub fn with_datalink(datalink: impl DataLink + 'static) -> Self {
+        Self {
+            scanners: Vec::new(),
+            datalink: Arc::new(datalink),
+            dry_run: DryRun::default(),
         }
     }
+
+    /// Run all scanners until cancelled, a single engine is started for each interface in use
+    /// which is shared by all scanners on that interface
+    pub async fn run(self, token: CancellationToken) {
+        let cache = Cache::default();
+        let interfaces = by_interface(self.scanners).into_iter().map(|(interface, scanners)| {
+            let run = run_interface(self.datalink.clone(), scanners, cache.clone(), token.clone());
+            async move {
+                if let Err(error) = run.await {
+                    error!("ARP scanning on interface {interface} failed: {error:#}");
+                }
+            }
+        });
+        join_all(interfaces).await;
+    }
 }
 
-/// The ARP scanner, separate from the ARP device, this is the part that performs the actual
-/// scanning
-#[derive(Debug, Deref)]
-pub struct ArpScanner {
-    #[deref]
-    config: NetworkScannerConfig,
-    interface: NetworkInterface,
-    sender: Sender<Option<Ipv4Addr>>,
-    local: (MacAddr, Ipv4Addr),
+/// Group the scanners by the interface they scan on
+fn by_interface(scanners: Vec<ArpScanner>) -> HashMap<String, Vec<Arc<ArpScanner>>> {
+    let mut by_interface: HashMap<String, Vec<Arc<ArpScanner>>> = HashMap::new();
+    for scanner in scanners {
+        by_interface
+            .entry(scanner.interface.name.clone())
+            .or_default()
+            .push(Arc::new(scanner));
+    }
+    by_interface
+}
+
+/// Open an engine on the interface and run it's scanners until cancelled, an error is returned if
+/// the interface cannot be opened or any task panics, the remaining tasks are then stopped so it
+/// can be started again
+async fn run_interface(
+    datalink: Arc<dyn DataLink>,
+    scanners: Vec<Arc<ArpScanner>>,
+    cache: Cache,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let Some(first) = scanners.first() else {
+        return Ok(());
+    };
+    let interface = first.interface.name.clone();
+    let (engine, receiver) = Engine::open(datalink.as_ref(), &first.interface, first.local, cache)
+        .with_context(|| format!("failed to open channel on interface {interface}"))?;
+    let engine = Arc::new(engine);
+    // stops the tasks of this run when it returns, including when one of them panicked
+    let token = token.child_token();
+    let _stop = token.clone().drop_guard();
+    let mut handles = Vec::new();
+    let receive_engine = engine.clone();
+    let receive_token = token.clone();
+    let span = info_span!(target: "arp", "arp_engine", interface = %interface);
+    handles.push(spawn_blocking(move || {
+        span.in_scope(|| receive_engine.receive(receiver, receive_token))
+    }));
+    for scanner in scanners {
+        handles.push(spawn(scanner.run(engine.clone(), token.clone())));
+    }
+    // each task stops by itself once cancelled, so wait for the raw sockets to be closed
+    try_join_all(handles)
+        .await
+        .with_context(|| format!("ARP task on interface {interface} failed"))?;
+    Ok(())
 }
 
 /// An ARP device, this represents a watched device and exposes some methods for getting current
 /// status and listening for changes
 pub struct ArpDevice {
     info: DeviceInfo,
-    receiver: Receiver<Option<Ipv4Addr>>,
+    devices: Vec<MacAddr>,
+    receiver: Receiver<Addresses>,
+    hostname: Receiver<Option<String>>,
+    latency: Latency,
+}
+
+/// The round trip time of the latest confirmation of an [ArpDevice], this gives an indication of
+/// the link quality
+pub struct Latency(Receiver<Option<Duration>>);
+
+impl Sensor for Latency {
+    type Item = Duration;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(WatchStream::from_changes(self.0.clone()).filter_map(ready))
+    }
+}
+
+impl ReadValue for Latency {
+    type Item = Duration;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        let latency = *self.0.borrow();
+        Box::pin(ready(latency.ok_or_else(|| anyhow!("device has not been confirmed yet"))))
+    }
 }
 
 #[bon]
@@ -118,10 +262,23 @@ impl ArpDevice {
         confirm_interval: Duration,
         /// the length of time to wait before scanning for an offline device
         scan_interval: Duration,
-        /// The range of IP addresses to check
-        ip_range: Range<Ipv4Addr>,
+        /// The range of IP addresses to check, defaults to the subnet of the interface
+        ip_range: Option<Range<Ipv4Addr>>,
         /// The device to scan for
         device: MacAddr,
+        /// Other devices to scan for in the same sweep, the ARP device is online while any of
+        /// its devices are connected
+        #[builder(default)]
+        other_devices: Vec<MacAddr>,
+        /// The protocols used to find the devices, defaults to ARP only
+        #[builder(default)]
+        mode: ScanMode,
+        /// If true, ping a device which does not answer a confirmation before deeming it offline
+        #[builder(default)]
+        ping_fallback: bool,
+        /// The number of confirmations in a row a device must miss before it is deemed offline
+        #[builder(default = 1)]
+        misses_before_offline: u32,
     ) -> anyhow::Result<Self> {
         let name = info.name.clone();
         Self::new_with_args(
@@ -134,33 +291,77 @@ impl ArpDevice {
                 confirm_interval,
                 scan_interval,
                 ip_range,
-                device,
+                devices: [device].into_iter().chain(other_devices).collect(),
+                mode,
+                ping_fallback,
+                misses_before_offline,
             },
         )
         .await
     }
 
-    /// Returns the IP address of the device if it is connected to the network, and None otherwise
-    pub fn ip_addr(&self) -> Option<Ipv4Addr> {
-        *self.receiver.borrow()
+    /// Returns the IP address of the first connected device in the order they were given, and
+    /// None if no devices are connected, this is an IPv6 address if the device was found using NDP
+    pub fn ip_addr(&self) -> Option<IpAddr> {
+        first_ip(&self.receiver.borrow())
     }
 
-    /// Returns true if the device is currently connected to the network
+    /// Returns the IP address of each device which is currently connected
+    pub fn ip_addrs(&self) -> HashMap<MacAddr, IpAddr> {
+        connected(&self.devices, &self.receiver.borrow())
+    }
+
+    /// Returns true if any of the devices are currently connected to the network
     pub fn online(&self) -> bool {
-        self.receiver.borrow().is_some()
+        self.ip_addr().is_some()
     }
 
     /// Returns a stream of updates from the scanner, if the value is `None`, that implies that
-    /// the device is not connected to the network,. otherwise when the value is `Some(ip_addr)`
-    /// it means that the device is connected and has the given IP address
-    pub fn ip_addr_changes(&self) -> impl Stream<Item = Option<Ipv4Addr>> {
+    /// no devices are connected to the network, otherwise when the value is `Some(ip_addr)`
+    /// it means that a device is connected and has the given IP address
+    pub fn ip_addr_changes(&self) -> impl Stream<Item = Option<IpAddr>> {
+        WatchStream::from_changes(self.receiver.clone()).map(|addresses| first_ip(&addresses))
+    }
+
+    /// Returns a stream of the IP addresses of each connected device
+    pub fn ip_addrs_changes(&self) -> impl Stream<Item = HashMap<MacAddr, IpAddr>> {
+        let devices = self.devices.clone();
         WatchStream::from_changes(self.receiver.clone())
+            .map(move |addresses| connected(&devices, &addresses))
     }
 
     /// Returns a stream of changes to the online status of the device
     pub fn online_changes(&self) -> impl Stream<Item = bool> {
         self.ip_addr_changes().map(|ip| ip.is_some())
     }
+
+    /// Returns the hostname of the device found by a reverse DNS lookup of its IP address, this
+    /// is kept while the device is offline and is None until a hostname is found
+    pub fn hostname(&self) -> Option<String> {
+        self.hostname.borrow().clone()
+    }
+
+    /// Returns a stream of changes to the hostname of the device
+    pub fn hostname_changes(&self) -> impl Stream<Item = Option<String>> {
+        WatchStream::from_changes(self.hostname.clone())
+    }
+
+    /// The round trip time of the latest confirmation that the device is connected
+    pub fn latency(&self) -> &Latency {
+        &self.latency
+    }
+}
+
+fn first_ip(addresses: &Addresses) -> Option<IpAddr> {
+    addresses.iter().find_map(|ip| *ip)
+}
+
+fn connected(devices: &[MacAddr], addresses: &Addresses) -> HashMap<MacAddr, IpAddr> {
+    devices
+        .iter()
+        .zip(addresses)
+        .filter_map(|(mac, ip)| Some((*mac, (*ip)?)))
+        .collect()
 }
 
 impl Device for ArpDevice {
@@ -176,9 +377,16 @@ impl Device for ArpDevice {
         info: DeviceInfo,
         config: NetworkScannerConfig,
     ) -> anyhow::Result<Self> {
-        let (scanner, receiver) = ArpScanner::new(config)?;
+        let devices = config.devices.clone();
+        let (scanner, receivers) = ArpScanner::new(config, manager.datalink.interfaces())?;
         manager.scanners.push(scanner);
-        Ok(ArpDevice { info, receiver })
+        Ok(ArpDevice {
+            info,
+            devices,
+            receiver: receivers.addresses,
+            hostname: receivers.hostname,
+            latency: Latency(receivers.latency),
+        })
     }
 }
 
@@ -255,337 +463,6 @@ impl reflect::Device for ArpDevice {
     }
 }
 
-struct State {
-    sender: Box<dyn DataLinkSender>,
-    receiver: Box<dyn DataLinkReceiver>,
-    template: ArpTemplate,
-}
-
-impl ArpScanner {
-    fn new(config: NetworkScannerConfig) -> Result<(Self, Receiver<Option<Ipv4Addr>>), Error> {
-        let interface = pnet::datalink::interfaces()
-            .into_iter()
-            .find(|i| {
-                config
-                    .interface_name
-                    .as_ref()
-                    .is_none_or(|name| name == &i.name)
-                    && !i.is_loopback()
-            })
-            .ok_or_else(|| Error::InterfaceNotFound(config.interface_name.clone()))?;
-        let local_ipv4 = interface
-            .ips
-            .iter()
-            .find_map(|ip| match ip.ip() {
-                IpAddr::V4(addr) => Some(addr),
-                IpAddr::V6(_) => None,
-            })
-            .ok_or(Error::IPv4NotSupported)?;
-
-        let (sender, receiver) = channel(None);
-
-        let local_mac = interface.mac.ok_or(Error::NoMacAddr)?;
-        Ok((
-            Self {
-                config,
-                interface,
-                sender,
-                local: (local_mac, local_ipv4),
-            },
-            receiver,
-        ))
-    }
-
-    /// Runs the ARP scanner on this thread, will never return.
-    ///
-    /// keeps scanning forever sleeping the thread between scans, updates are communicated to the
-    /// `ArpDevice` using a channel
-    fn run(self) {
-        debug_span!(target: "arp", "ARP scanner running for device: {}", self.name);
-        let (sender, receiver) = match build_eth_channel(&self.interface) {
-            Ok(channel) => channel,
-            Err(error) => {
-                error!("Error sending ARP frame: {error}");
-                return;
-            }
-        };
-        let (source_mac, source_ip) = self.local;
-        let template = ArpTemplate::new(source_mac, source_ip);
-        let mut state = State {
-            sender,
-            receiver,
-            template,
-        };
-        debug!("Beginning device loop");
-        let mut current_ip = None;
-        let mac = self.device;
-        loop {
-            trace!("Checking {}", mac);
-            if let Some(ip) = current_ip {
-                debug!("confirming IP: {ip}");
-                if !self.confirm_ip(&mut state, mac, ip) {
-                    debug!("IP outdated");
-                    current_ip = None;
-                } else {
-                    debug!("IP confirmed")
-                }
-            }
-            if current_ip.is_none() {
-                debug!("Scanning for new IP");
-                current_ip = self.get_ip_for(&mut state, mac);
-                if current_ip.is_none() {
-                    trace!("Device is offline")
-                }
-            }
-            let result = self.sender.send(current_ip);
-            if let Err(error) = result {
-                error!("Error sending ARP IP: {}", error);
-            }
-
-            if current_ip.is_none() {
-                sleep(self.scan_interval);
-            } else {
-                sleep(self.confirm_interval);
-            }
-        }
-    }
-
-    #[allow(dead_code)] // not currently used, but may still be a useful example
-    fn all_current_ips(&self, state: &mut State, ip_by_mac: &mut HashMap<MacAddr, Ipv4Addr>) {
-        ip_by_mac.clear();
-        let (source_mac, source_ip) = self.local;
-        let start = Instant::now();
-        for ip in self.ip_range.clone() {
-            if ip == source_ip {
-                // do not check this machine's IP, that would be silly
-                continue;
-            }
-            let pkt = state.template.execute(ip, MacAddr::broadcast());
-            match state.sender.send_to(pkt, None) {
-                Some(Ok(())) => {}
-                None => {
-                    error!("Error sending ARP to IP: {ip}");
-                }
-                Some(Err(error)) => {
-                    error!("Error sending ARP to IP: {ip}; {error}");
-                }
-            }
-        }
-
-        loop {
-            let buf = match state.receiver.next() {
-                Ok(buf) => buf,
-                Err(error) => {
-                    error!("failed to read frame from interface: {error}");
-                    continue;
-                }
-            };
-
-            if buf.len() < EthernetPacket::minimum_packet_size() + ArpPacket::minimum_packet_size()
-            {
-                if Instant::now().duration_since(start) > self.timeout {
-                    break;
-                }
-                continue;
-            }
-
-            let Some(pkt_arp) = ArpPacket::new(&buf[EthernetPacket::minimum_packet_size()..])
-            else {
-                error!("Buffer not large enough for ARP frame");
-                continue;
-            };
-
-            if pkt_arp.get_target_hw_addr() == source_mac {
-                let ip = pkt_arp.get_sender_proto_addr();
-                let mac = pkt_arp.get_sender_hw_addr();
-                ip_by_mac.insert(mac, ip);
-            }
-
-            if Instant::now().duration_since(start) > self.timeout {
-                break;
-            }
-        }
-    }
-
-    fn get_ip_for(&self, state: &mut State, mac: MacAddr) -> Option<Ipv4Addr> {
-        for ip in self.ip_range.clone() {
-            if ip == self.local.1 {
-                // do not check this machine's IP, that would be silly
-                continue;
-            }
-            let pkt = state.template.execute(ip, mac);
-            match state.sender.send_to(pkt, None) {
-                Some(Ok(())) => {}
-                Some(Err(error)) => {
-                    error!("Error sending ARP frame: {error}");
-                    continue;
-                }
-                None => {
-                    error!("Error sending ARP frame");
-                    continue;
-                }
-            };
-        }
-        let start = Instant::now();
-
-        loop {
-            let buf = match state.receiver.next() {
-                Ok(buf) => buf,
-                Err(error) => {
-                    error!("Error receiving ARP frame: {error}");
-                    continue;
-                }
-            };
-
-            if buf.len() < EthernetPacket::minimum_packet_size() + ArpPacket::minimum_packet_size()
-            {
-                if Instant::now().duration_since(start) > self.timeout {
-                    break None;
-                }
-                continue;
-            }
-
-            let Some(pkt_arp) = ArpPacket::new(&buf[EthernetPacket::minimum_packet_size()..])
-            else {
-                error!("Buffer not large enough for ARP frame");
-                continue;
-            };
-
-            if pkt_arp.get_target_hw_addr() == self.local.0 && pkt_arp.get_sender_hw_addr() == mac {
-                break Some(pkt_arp.get_sender_proto_addr());
-            }
-
-            if Instant::now().duration_since(start) > self.timeout {
-                break None;
-            }
-        }
-    }
-
-    fn confirm_ip(&self, state: &mut State, mac: MacAddr, ip: Ipv4Addr) -> bool {
-        let pkt = state.template.execute(ip, mac);
-        match state.sender.send_to(pkt, None) {
-            Some(Ok(())) => {}
-            Some(Err(error)) => {
-                error!("Error sending ARP frame: {error}");
-                return false;
-            }
-            None => {
-                error!("Error sending ARP frame");
-                return false;
-            }
-        };
-        let start = Instant::now();
-
-        loop {
-            let buf = match state.receiver.next() {
-                Ok(buf) => buf,
-                Err(error) => {
-                    error!("Error receiving ARP frame: {error}");
-                    continue;
-                }
-            };
-
-            if buf.len() < EthernetPacket::minimum_packet_size() + ArpPacket::minimum_packet_size()
-            {
-                if Instant::now().duration_since(start) > self.timeout {
-                    break false;
-                }
-                continue;
-            }
-
-            let Some(pkt_arp) = ArpPacket::new(&buf[EthernetPacket::minimum_packet_size()..])
-            else {
-                error!("Buffer not large enough for ARP frame");
-                continue;
-            };
-
-            if pkt_arp.get_target_hw_addr() == self.local.0
-                && pkt_arp.get_sender_hw_addr() == mac
-                && pkt_arp.get_sender_proto_addr() == ip
-            {
-                break true;
-            }
-
-            if Instant::now().duration_since(start) > self.timeout {
-                break false;
-            }
-        }
-    }
-}
-
-const COMBINED_PACKET_SIZE: usize =
-    EthernetPacket::minimum_packet_size() + ArpPacket::minimum_packet_size();
-
-struct ArpTemplate([u8; COMBINED_PACKET_SIZE]);
-
-impl ArpTemplate {
-    fn new(source_mac: MacAddr, source_ip: Ipv4Addr) -> Self {
-        let mut pkt_buf = [0u8; COMBINED_PACKET_SIZE];
-
-        // Use scope blocks so we can reborrow our buffer
-        {
-            // Build our base ethernet frame
-            #[allow(clippy::expect_used)]
-            let mut pkt_eth = MutableEthernetPacket::new(&mut pkt_buf)
-                .expect("buffer is large enough for EthernetPacket");
-
-            pkt_eth.set_destination(MacAddr::broadcast());
-            pkt_eth.set_source(source_mac);
-            pkt_eth.set_ethertype(EtherTypes::Arp);
-        }
-
-        {
-            // Build the ARP frame on top of the ethernet frame
-            #[allow(clippy::expect_used)]
-            let mut pkt_arp =
-                MutableArpPacket::new(&mut pkt_buf[EthernetPacket::minimum_packet_size()..])
-                    .expect("buffer is large enough for ArpPacket");
-
-            pkt_arp.set_hardware_type(ArpHardwareTypes::Ethernet);
-            pkt_arp.set_protocol_type(EtherTypes::Ipv4);
-            pkt_arp.set_hw_addr_len(6);
-            pkt_arp.set_proto_addr_len(4);
-            pkt_arp.set_operation(ArpOperations::Request);
-            pkt_arp.set_sender_hw_addr(source_mac);
-            pkt_arp.set_sender_proto_addr(source_ip);
-        }
-        Self(pkt_buf)
-    }
-
-    fn execute(&mut self, ip: Ipv4Addr, mac: MacAddr) -> &[u8] {
-        {
-            // Build our base ethernet frame
-            #[allow(clippy::expect_used)]
-            let mut pkt_eth = MutableEthernetPacket::new(&mut self.0)
-                .expect("buffer is large enough for EthernetPacket");
-
-            pkt_eth.set_destination(mac);
-        }
-        {
-            #[allow(clippy::expect_used)]
-            let mut pkt_arp =
-                MutableArpPacket::new(&mut self.0[EthernetPacket::minimum_packet_size()..])
-                    .expect("buffer is large enough for ArpPacket");
-            pkt_arp.set_target_proto_addr(ip);
-            pkt_arp.set_target_hw_addr(mac)
-        }
-        &self.0
-    }
-}
-
-type NetworkChannel = (Box<dyn DataLinkSender>, Box<dyn DataLinkReceiver>);
-
-/// Construct a sender/receiver channel from an interface
-fn build_eth_channel(
-    interface: &NetworkInterface,
-) -> Result<NetworkChannel, io::Error> {
-    let cfg = pnet::datalink::Config::default();
-    Ok(match pnet::datalink::channel(interface, cfg)? {
-        Channel::Ethernet(tx, rx) => (tx, rx),
-        _ => unreachable!("Unknown Channel enum variant"),
-    })
-}
-
 /// Simple error types for this demo
 #[derive(Debug, Error)]
 pub enum Error {
@@ -595,7 +472,13 @@ pub enum Error {
     #[error("interface does not support IPv4")]
     /// No IPv4 address found on interface
     IPv4NotSupported,
+    #[error("interface does not have a link-local IPv6 address")]
+    /// No link-local IPv6 address found on interface
+    IPv6NotSupported,
     /// No MAC address found for network interface
     #[error("No mac address found for interface")]
     NoMacAddr,
+    /// The subnet of the interface is too large to sweep, an `ip_range` must be given
+    #[error("subnet /{0} is too large to scan, set ip_range instead")]
+    SubnetTooLarge(u8),
 }
@@ -0,0 +1,99 @@
+//! Packet building and parsing for IPv6 [neighbor discovery](https://en.wikipedia.org/wiki/Neighbor_Discovery_Protocol)
+
+use pnet::packet::Packet;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmpv6::{self, Icmpv6Packet, Icmpv6Type, Icmpv6Types, MutableIcmpv6Packet};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+use pnet::util::MacAddr;
+use std::net::Ipv6Addr;
+
+/// The link-local all nodes multicast address
+pub(crate) const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// NDP option type for the source link-layer address
+const SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+
+/// Returns the ethernet multicast address for the given IPv6 multicast address
+pub(crate) fn multicast_mac(ip: Ipv6Addr) -> MacAddr {
+    let octets = ip.octets();
+    MacAddr(0x33, 0x33, octets[12], octets[13], octets[14], octets[15])
+}
+
+/// Builds an ICMPv6 echo request frame, when sent to [ALL_NODES] every IPv6 device on the link
+/// will reply from its link-local address, otherwise only the destination replies
+pub(crate) fn echo_request(source: (MacAddr, Ipv6Addr), destination: (MacAddr, Ipv6Addr)) -> Vec<u8> {
+    // identifier and sequence number, these are not checked on the replies
+    let body = [0u8; 4];
+    icmpv6_frame(source, destination, Icmpv6Types::EchoRequest, &body)
+}
+
+/// Builds a unicast neighbor solicitation frame for the given address, a device which still
+/// holds the address will reply with a neighbor advertisement
+pub(crate) fn neighbor_solicitation(source: (MacAddr, Ipv6Addr), target: (MacAddr, Ipv6Addr)) -> Vec<u8> {
+    let (source_mac, _) = source;
+    let (_, target_ip) = target;
+    let mut body = Vec::with_capacity(28);
+    // reserved
+    body.extend_from_slice(&[0; 4]);
+    body.extend_from_slice(&target_ip.octets());
+    body.extend_from_slice(&[SOURCE_LINK_LAYER_ADDRESS, 1]);
+    body.extend_from_slice(&source_mac.octets());
+    icmpv6_frame(source, target, Icmpv6Types::NeighborSolicit, &body)
+}
+
+/// Returns the source address of the frame if it is an echo reply or neighbor advertisement
+pub(crate) fn parse_reply(frame: &EthernetPacket) -> Option<Ipv6Addr> {
+    let ipv6 = Ipv6Packet::new(frame.payload())?;
+    if ipv6.get_next_header() != IpNextHeaderProtocols::Icmpv6 {
+        return None;
+    }
+    let icmp = Icmpv6Packet::new(ipv6.payload())?;
+    let icmp_type = icmp.get_icmpv6_type();
+    (icmp_type == Icmpv6Types::EchoReply || icmp_type == Icmpv6Types::NeighborAdvert)
+        .then(|| ipv6.get_source())
+}
+
+fn icmpv6_frame(
+    (source_mac, source_ip): (MacAddr, Ipv6Addr),
+    (destination_mac, destination_ip): (MacAddr, Ipv6Addr),
+    icmp_type: Icmpv6Type,
+    body: &[u8],
+) -> Vec<u8> {
+    const ETHERNET: usize = EthernetPacket::minimum_packet_size();
+    const IPV6: usize = Ipv6Packet::minimum_packet_size();
+    let icmp_length = Icmpv6Packet::minimum_packet_size() + body.len();
+    let mut buf = vec![0u8; ETHERNET + IPV6 + icmp_length];
+
+    // Use scope blocks so we can reborrow our buffer
+    {
+        #[allow(clippy::expect_used)]
+        let mut pkt_eth = MutableEthernetPacket::new(&mut buf)
+            .expect("buffer is large enough for EthernetPacket");
+        pkt_eth.set_destination(destination_mac);
+        pkt_eth.set_source(source_mac);
+        pkt_eth.set_ethertype(EtherTypes::Ipv6);
+    }
+    {
+        #[allow(clippy::expect_used)]
+        let mut pkt_ipv6 = MutableIpv6Packet::new(&mut buf[ETHERNET..])
+            .expect("buffer is large enough for Ipv6Packet");
+        pkt_ipv6.set_version(6);
+        pkt_ipv6.set_payload_length(icmp_length as u16);
+        pkt_ipv6.set_next_header(IpNextHeaderProtocols::Icmpv6);
+        // neighbor discovery messages are only accepted with the maximum hop limit
+        pkt_ipv6.set_hop_limit(255);
+        pkt_ipv6.set_source(source_ip);
+        pkt_ipv6.set_destination(destination_ip);
+    }
+    {
+        #[allow(clippy::expect_used)]
+        let mut pkt_icmp = MutableIcmpv6Packet::new(&mut buf[ETHERNET + IPV6..])
+            .expect("buffer is large enough for Icmpv6Packet");
+        pkt_icmp.set_icmpv6_type(icmp_type);
+        pkt_icmp.set_payload(body);
+        let checksum = icmpv6::checksum(&pkt_icmp.to_immutable(), &source_ip, &destination_ip);
+        pkt_icmp.set_checksum(checksum);
+    }
+    buf
+}
@@ -0,0 +1,400 @@
+use crate::engine::{Engine, Local, Reply};
+use crate::{Error, NetworkScannerConfig};
+use derive_more::Deref;
+use pnet::datalink::NetworkInterface;
+use pnet::ipnetwork::IpNetwork;
+use pnet::util::MacAddr;
+use dns_lookup::lookup_addr;
+use std::net::{IpAddr, Ipv4Addr};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::watch::{Receiver, Sender, channel};
+use tokio::task::spawn_blocking;
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, debug, error, info_span, trace};
+
+/// The current IP address of each device being scanned for, in the same order as
+/// [NetworkScannerConfig::devices], `None` when that device is offline
+pub(crate) type Addresses = Vec<Option<IpAddr>>;
+
+/// The smallest prefix length for which the range to sweep is derived from the interface
+const MIN_PREFIX: u8 = 16;
+
+/// The ARP scanner, separate from the ARP device, this is the part that performs the actual
+/// scanning
+#[derive(Debug, Deref)]
+pub struct ArpScanner {
+    #[deref]
+    config: NetworkScannerConfig,
+    pub(crate) interface: NetworkInterface,
+    /// The range of IP addresses to check, either as configured or derived from the interface
+    sweep_range: Range<Ipv4Addr>,
+    sender: Sender<Addresses>,
+    hostname: Sender<Option<String>>,
+    latency: Sender<Option<Duration>>,
+    pub(crate) local: Local,
+}
+
+/// The receiving ends of the channels used to communicate with the `ArpDevice`
+pub(crate) struct Receivers {
+    pub addresses: Receiver<Addresses>,
+    pub hostname: Receiver<Option<String>>,
+    pub latency: Receiver<Option<Duration>>,
+}
+
+/// A confirmation request awaiting a reply
+struct Probe {
+    mac: MacAddr,
+    ip: IpAddr,
+    sent: Instant,
+}
+
+impl ArpScanner {
+    /// Create a scanner on one of the given interfaces, the interface is chosen by name or is the
+    /// first which is not a loopback interface
+    pub(crate) fn new(
+        config: NetworkScannerConfig,
+        interfaces: Vec<NetworkInterface>,
+    ) -> Result<(Self, Receivers), Error> {
+        let interface = interfaces
+            .into_iter()
+            .find(|i| {
+                config
+                    .interface_name
+                    .as_ref()
+                    .is_none_or(|name| name == &i.name)
+                    && !i.is_loopback()
+            })
+            .ok_or_else(|| Error::InterfaceNotFound(config.interface_name.clone()))?;
+        let local = Local::of(&interface).ok_or(Error::NoMacAddr)?;
+        if config.mode.arp() && local.ipv4.is_none() {
+            return Err(Error::IPv4NotSupported);
+        }
+        if config.mode.ndp() && local.ipv6.is_none() {
+            return Err(Error::IPv6NotSupported);
+        }
+
+        let sweep_range = match &config.ip_range {
+            Some(range) => range.clone(),
+            None if config.mode.arp() => subnet_of(&interface)?,
+            None => Ipv4Addr::UNSPECIFIED..Ipv4Addr::UNSPECIFIED,
+        };
+
+        let (sender, receiver) = channel(vec![None; config.devices.len()]);
+        let (hostname, hostname_receiver) = channel(None);
+        let (latency, latency_receiver) = channel(None);
+
+        Ok((
+            Self {
+                config,
+                sweep_range,
+                interface,
+                sender,
+                hostname,
+                latency,
+                local,
+            },
+            Receivers {
+                addresses: receiver,
+                hostname: hostname_receiver,
+                latency: latency_receiver,
+            },
+        ))
+    }
+
+    /// Runs the ARP scanner using the given engine until cancelled.
+    ///
+    /// keeps scanning sleeping between scans, updates are communicated to the `ArpDevice` using
+    /// a channel
+    pub(crate) async fn run(self: Arc<Self>, engine: Arc<Engine>, token: CancellationToken) {
+        // info level so that warnings and errors carry the device name, even when debug logs are off
+        let span = info_span!(target: "arp", "arp_scanner", device = %self.name, interface = %self.interface.name);
+        let scan = async move {
+            let mut replies = engine.subscribe(self.devices.iter().copied());
+            debug!("Beginning device loop");
+            let mut current: Addresses = vec![None; self.devices.len()];
+            let mut misses = vec![0; self.devices.len()];
+            let mut resolved = None;
+            loop {
+                trace!("Checking {:?}", self.devices);
+                self.confirm_ips(&engine, &mut replies, &mut current, &mut misses).await;
+                if current.iter().any(Option::is_none) {
+                    debug!("Scanning for new IPs");
+                    self.scan(&engine, &mut replies, &mut current).await;
+                }
+                let result = self.sender.send(current.clone());
+                if let Err(error) = result {
+                    error!("Error sending ARP IP: {}", error);
+                }
+                // the hostname is kept while offline, so it is only resolved for new addresses
+                let first = current.iter().find_map(|ip| *ip);
+                if first.is_some() && first != resolved {
+                    resolved = first;
+                    if let Some(hostname) = resolve(first).await {
+                        self.hostname.send_if_modified(|current| {
+                            current.replace(hostname.clone()).as_ref() != Some(&hostname)
+                        });
+                    }
+                }
+
+                if current.iter().any(Option::is_none) {
+                    sleep(self.scan_interval).await;
+                } else {
+                    sleep(self.confirm_interval).await;
+                }
+            }
+        };
+        async {
+            if token.run_until_cancelled(scan).await.is_none() {
+                debug!("ARP scanner stopped");
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Sweeps the IP range for every device which is currently offline, a single sweep is
+    /// broadcast when more than one device is offline
+    async fn scan(
+        &self,
+        engine: &Engine,
+        replies: &mut UnboundedReceiver<Reply>,
+        current: &mut Addresses,
+    ) {
+        drain(replies);
+        // addresses already observed by any scanner are tried first, a full sweep is only
+        // needed for devices which have not been seen recently
+        let mut cached = Vec::new();
+        for (mac, ip) in self.devices.iter().zip(current.iter()) {
+            let Some(addr) = ip.is_none().then(|| engine.cached(*mac)).flatten() else {
+                continue;
+            };
+            if !self.mode.accepts(addr) {
+                continue;
+            }
+            let sent = match addr {
+                IpAddr::V4(addr) => engine.send(addr, *mac),
+                IpAddr::V6(addr) => engine.send_neighbor_solicitation(addr, *mac),
+            };
+            if sent {
+                cached.push(*mac);
+            }
+        }
+        if !cached.is_empty() {
+            trace!("checking {} cached addresses", cached.len());
+            self.await_replies(replies, |reply| {
+                self.record(current, reply);
+                cached.retain(|mac| *mac != reply.mac);
+                cached.is_empty()
+            })
+            .await;
+            if current.iter().all(Option::is_some) {
+                return;
+            }
+        }
+        let missing: Vec<MacAddr> = self
+            .devices
+            .iter()
+            .zip(current.iter())
+            .filter(|(_, ip)| ip.is_none())
+            .map(|(mac, _)| *mac)
+            .collect();
+        let target = match missing.as_slice() {
+            [mac] => *mac,
+            _ => MacAddr::broadcast(),
+        };
+        if self.mode.arp() {
+            for ip in self.sweep_range.clone() {
+                if Some(ip) == self.local.ipv4 {
+                    // do not check this machine's IP, that would be silly
+                    continue;
+                }
+                engine.send(ip, target);
+            }
+        }
+        if self.mode.ndp() {
+            engine.send_multicast_ping();
+        }
+        self.await_replies(replies, |reply| {
+            self.record(current, reply);
+            current.iter().all(Option::is_some)
+        })
+        .await;
+    }
+
+    /// Records the address of a device which has replied, if it was offline
+    fn record(&self, current: &mut Addresses, reply: &Reply) {
+        if !self.mode.accepts(reply.ip) {
+            return;
+        }
+        if let Some(index) = self.devices.iter().position(|mac| *mac == reply.mac) {
+            current[index].get_or_insert(reply.ip);
+        }
+    }
+
+    /// Confirms that each online device is still at its last known IP address, a device is only
+    /// deemed offline after missing `misses_before_offline` confirmations in a row. The round
+    /// trip time of the first confirmed device is published as the latency
+    async fn confirm_ips(
+        &self,
+        engine: &Engine,
+        replies: &mut UnboundedReceiver<Reply>,
+        current: &mut Addresses,
+        misses: &mut [u32],
+    ) {
+        drain(replies);
+        let mut pending = Vec::new();
+        let mut failed = Vec::new();
+        for (mac, ip) in self.devices.iter().zip(current.iter()) {
+            let Some(addr) = *ip else {
+                continue;
+            };
+            debug!(mac = %mac, "confirming IP: {addr}");
+            let sent = Instant::now();
+            let success = match addr {
+                IpAddr::V4(addr) => engine.send(addr, *mac),
+                IpAddr::V6(addr) => engine.send_neighbor_solicitation(addr, *mac),
+            };
+            let probe = Probe {
+                mac: *mac,
+                ip: addr,
+                sent,
+            };
+            if success {
+                pending.push(probe);
+            } else {
+                failed.push(probe);
+            }
+        }
+        let mut confirmed = Vec::new();
+        self.await_confirmations(replies, &mut pending, &mut confirmed).await;
+        if self.ping_fallback && !pending.is_empty() {
+            debug!("pinging {} unconfirmed devices", pending.len());
+            let (pinged, unsent): (Vec<Probe>, Vec<Probe>) = pending
+                .drain(..)
+                .map(|probe| Probe {
+                    sent: Instant::now(),
+                    ..probe
+                })
+                .partition(|probe| engine.send_ping(probe.ip, probe.mac));
+            pending = pinged;
+            self.await_confirmations(replies, &mut pending, &mut confirmed).await;
+            // a device which could not be pinged is still unconfirmed, so it counts as a miss
+            pending.extend(unsent);
+        }
+        // a device which could not be sent a confirmation request counts as a miss too
+        pending.append(&mut failed);
+        for ((mac, ip), misses) in self.devices.iter().zip(current.iter_mut()).zip(misses) {
+            let Some(addr) = *ip else {
+                *misses = 0;
+                continue;
+            };
+            if !pending.iter().any(|probe| probe.mac == *mac && probe.ip == addr) {
+                *misses = 0;
+                continue;
+            }
+            *misses += 1;
+            if *misses >= self.misses_before_offline.max(1) {
+                debug!(mac = %mac, "IP outdated");
+                *ip = None;
+                *misses = 0;
+            } else {
+                debug!(mac = %mac, "missed {misses} confirmations");
+            }
+        }
+        let latency = self.devices.iter().find_map(|mac| {
+            confirmed
+                .iter()
+                .find_map(|(confirmed, latency)| (confirmed == mac).then_some(*latency))
+        });
+        if latency.is_some() {
+            self.latency.send_replace(latency);
+        }
+    }
+
+    /// Moves each confirmed device from `pending` to `confirmed` along with its round trip time,
+    /// until all are confirmed or the timeout elapses
+    async fn await_confirmations(
+        &self,
+        replies: &mut UnboundedReceiver<Reply>,
+        pending: &mut Vec<Probe>,
+        confirmed: &mut Vec<(MacAddr, Duration)>,
+    ) {
+        if pending.is_empty() {
+            return;
+        }
+        self.await_replies(replies, |reply| {
+            pending.retain(|probe| {
+                if probe.mac != reply.mac || probe.ip != reply.ip {
+                    return true;
+                }
+                confirmed.push((probe.mac, reply.received.saturating_duration_since(probe.sent)));
+                false
+            });
+            pending.is_empty()
+        })
+        .await;
+    }
+
+    /// Passes replies to `done` until it returns true or the configured timeout elapses
+    async fn await_replies(
+        &self,
+        replies: &mut UnboundedReceiver<Reply>,
+        mut done: impl FnMut(&Reply) -> bool,
+    ) {
+        let wait = async {
+            while let Some(reply) = replies.recv().await {
+                if done(&reply) {
+                    return;
+                }
+            }
+        };
+        // a timeout is expected whenever a device does not reply
+        let _ = timeout(self.timeout, wait).await;
+    }
+}
+
+/// Returns the range of host addresses in the IPv4 subnet of the interface
+fn subnet_of(interface: &NetworkInterface) -> Result<Range<Ipv4Addr>, Error> {
+    let network = interface
+        .ips
+        .iter()
+        .find_map(|ip| match ip {
+            IpNetwork::V4(network) => Some(*network),
+            IpNetwork::V6(_) => None,
+        })
+        .ok_or(Error::IPv4NotSupported)?;
+    if network.prefix() < MIN_PREFIX {
+        return Err(Error::SubnetTooLarge(network.prefix()));
+    }
+    // skip the network address, the range excludes the broadcast address
+    let start = Ipv4Addr::from(u32::from(network.network()).saturating_add(1));
+    Ok(start..network.broadcast())
+}
+
+/// Looks up the hostname of the given address, returns None if it has no hostname
+async fn resolve(ip: Option<IpAddr>) -> Option<String> {
+    let ip = ip?;
+    let result = spawn_blocking(move || lookup_addr(&ip)).await;
+    match result {
+        // the resolver returns the address itself when there is no hostname
+        Ok(Ok(hostname)) if hostname != ip.to_string() => Some(hostname),
+        Ok(Ok(_)) => None,
+        Ok(Err(error)) => {
+            debug!("failed to resolve hostname of {ip}: {error}");
+            None
+        }
+        Err(error) => {
+            error!("hostname lookup panicked: {error}");
+            None
+        }
+    }
+}
+
+/// Discard any replies received since the last request
+fn drain(replies: &mut UnboundedReceiver<Reply>) {
+    while replies.try_recv().is_ok() {}
+}
@@ -0,0 +1,165 @@
+use bon::bon;
+use crate::ArpManager;
+use control::WriteValue;
+use control::dry_run::DryRun;
+use control::device::Device;
+use control::reflect;
+use control::reflect::value::{Value, ValueType};
+use control::reflect::{DeviceInfo, Field, Operation, Operations, SetError};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use pnet::util::MacAddr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+/// The port conventionally used for wake-on-LAN packets
+const WOL_PORT: u16 = 9;
+
+/// The configuration data for a wake-on-LAN device
+#[derive(Debug, Clone)]
+pub struct WolConfig {
+    /// The MAC address of the device to wake
+    pub mac: MacAddr,
+    /// The address to send the magic packet to, this is usually the broadcast address of the
+    /// device's network
+    pub target: SocketAddr,
+}
+
+/// A device which can be woken by a [wake-on-LAN](https://en.wikipedia.org/wiki/Wake-on-LAN)
+/// magic packet, eg: a media PC which should be woken when the TV turns on
+pub struct WolDevice {
+    info: DeviceInfo,
+    config: WolConfig,
+    dry_run: DryRun,
+}
+
+#[bon]
+impl WolDevice {
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    #[builder]
+    pub async fn create(
+        manager: &mut ArpManager,
+        info: DeviceInfo,
+        /// The MAC address of the device to wake
+        mac: MacAddr,
+        /// The address to send the magic packet to, defaults to the broadcast address on port 9
+        #[builder(default = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::BROADCAST, WOL_PORT)))]
+        target: SocketAddr,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_args(manager, info, WolConfig { mac, target }).await
+    }
+
+    /// Send a magic packet to wake the device, the device may take some time to start. In dry-run
+    /// mode the packet is logged instead
+    pub async fn wake(&self) -> anyhow::Result<()> {
+        if self.dry_run.intercept(&self.info.id, "wake", ()) {
+            return Ok(());
+        }
+        debug!("Waking {} ({})", self.info.name, self.config.mac);
+        let bind = match self.config.target {
+            SocketAddr::V4(_) => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.set_broadcast(true)?;
+        socket.send_to(&magic_packet(self.config.mac), self.config.target).await?;
+        Ok(())
+    }
+}
+
+/// Six bytes of `0xFF` followed by sixteen repetitions of the target MAC address
+fn magic_packet(mac: MacAddr) -> Vec<u8> {
+    let mut packet = vec![0xFF; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac.octets());
+    }
+    packet
+}
+
+impl WriteValue for WolDevice {
+    type Item = ();
+
+    fn set(&self, (): ()) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(self.wake())
+    }
+}
+
+impl Device for WolDevice {
+    type Args = WolConfig;
+    type Manager = ArpManager;
+
+    fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    async fn new_with_args(
+        manager: &mut Self::Manager,
+        info: DeviceInfo,
+        config: WolConfig,
+    ) -> anyhow::Result<Self> {
+        Ok(WolDevice { info, config, dry_run: manager.dry_run })
+    }
+}
+
+impl reflect::Device for WolDevice {
+    fn info(&self) -> DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field {
+                name: "wake".to_string(),
+                description: "Writing any value to this field sends a wake-on-LAN packet to the device".to_string(),
+                operations: Operations {
+                    subscribe: false,
+                    get: false,
+                    set: true,
+                    toggle: false,
+                },
+                value_type: ValueType::Bool,
+            }
+        ]
+    }
+
+    fn subscribe(&self, field: &str) -> Result<BoxFuture<'_, BoxStream<'_, Value>>, reflect::Error> {
+        Err(self.unsupported(field, Operation::Subscribe))
+    }
+
+    fn get(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<Value>>, reflect::Error> {
+        Err(self.unsupported(field, Operation::Get))
+    }
+
+    fn set(&self, field: &str, _: Value) -> Result<BoxFuture<'_, anyhow::Result<()>>, SetError> {
+        if field == "wake" {
+            Ok(WriteValue::set(self, ()))
+        } else {
+            Err(self.unsupported(field, Operation::Set).into())
+        }
+    }
+
+    fn toggle(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<()>>, reflect::Error> {
+        Err(self.unsupported(field, Operation::Toggle))
+    }
+}
+
+impl WolDevice {
+    fn unsupported(&self, field: &str, operation: Operation) -> reflect::Error {
+        if field == "wake" {
+            reflect::Error::OperationNotSupported {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+                operation,
+            }
+        } else {
+            reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            }
+        }
+    }
+}
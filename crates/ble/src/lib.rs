@@ -0,0 +1,394 @@
+#![doc= include_str!("../README.md")]
+
+use anyhow::{Context, anyhow};
+use bon::bon;
+use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral as _, PeripheralProperties, ScanFilter};
+use btleplug::platform::{Adapter, Manager, PeripheralId};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use std::future::ready;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::sync::watch::{Receiver, Sender, channel};
+use tokio::time::interval;
+use tokio_stream::wrappers::WatchStream;
+use tracing::{debug, error, trace};
+
+pub use btleplug::api::BDAddr;
+pub use uuid::Uuid;
+
+use control::device::Device;
+use control::device_manager::{DeviceManager, Supervisor};
+use control::reflect;
+use control::reflect::value::{Value, ValueType};
+use control::reflect::{DeviceInfo, Field, Operation, Operations, SetError};
+use thiserror::Error;
+use tokio::spawn;
+use tokio_util::sync::CancellationToken;
+
+/// How often devices are checked for expiry
+const TICK: Duration = Duration::from_secs(1);
+/// The company identifier assigned to Apple, used in iBeacon advertisements
+const APPLE: u16 = 0x004C;
+/// The type and length which prefix the manufacturer data of an iBeacon advertisement
+const IBEACON_PREFIX: [u8; 2] = [0x02, 0x15];
+
+/// The configuration data for a BLE listener, at least one of `address`, `local_name` or
+/// `beacon` must be given, an advertisement matches the device if it matches all of those given
+#[derive(Debug)]
+pub struct BleConfig {
+    /// The name of the target device (to be included in logs)
+    pub name: String,
+    /// The Bluetooth address of the device, this is only stable for devices which do not
+    /// randomize their address, eg: most beacons and smartwatches
+    pub address: Option<BDAddr>,
+    /// The local name advertised by the device, this is not case sensitive
+    pub local_name: Option<String>,
+    /// The proximity UUID broadcast by an iBeacon
+    pub beacon: Option<Uuid>,
+    /// Advertisements received with a signal strength (in dBm) below this are ignored
+    pub min_rssi: Option<i16>,
+    /// the length of time since the device was last heard from before deeming it absent
+    pub timeout: Duration,
+}
+
+impl BleConfig {
+    fn matches(&self, properties: &PeripheralProperties) -> bool {
+        if self
+            .address
+            .is_some_and(|address| address != properties.address)
+        {
+            return false;
+        }
+        if let Some(name) = &self.local_name
+            && !properties
+                .local_name
+                .as_ref()
+                .is_some_and(|local_name| local_name.eq_ignore_ascii_case(name))
+        {
+            return false;
+        }
+        if let Some(beacon) = self.beacon
+            && beacon_uuid(properties) != Some(beacon)
+        {
+            return false;
+        }
+        match (self.min_rssi, properties.rssi) {
+            (Some(min), Some(rssi)) => rssi >= min,
+            // a device which did not report the signal strength can't be judged to be too far away
+            _ => true,
+        }
+    }
+}
+
+/// Returns the proximity UUID if the device is advertising as an iBeacon
+fn beacon_uuid(properties: &PeripheralProperties) -> Option<Uuid> {
+    let data = properties.manufacturer_data.get(&APPLE)?;
+    let uuid = data.strip_prefix(&IBEACON_PREFIX)?.get(..16)?;
+    Uuid::from_slice(uuid).ok()
+}
+
+/// A manager of BLE listeners. Collects created listeners until ready to begin scanning, all
+/// devices share a single scan on the first Bluetooth adapter
+#[derive(Default)]
+pub struct BleManager {
+    listeners: Vec<Listener>,
+}
+
+struct Listener {
+    config: BleConfig,
+    sender: Sender<bool>,
+    last_seen: Option<Instant>,
+}
+
+impl DeviceManager for BleManager {
+    fn start(self: Box<Self>, token: CancellationToken) {
+        spawn(self.run(token));
+    }
+
+    fn supervise(self: Box<Self>, supervisor: &Supervisor) {
+        let token = supervisor.token();
+        // the listeners are kept across restarts, so a device is not deemed absent by a restart
+        let listeners = Arc::new(AsyncMutex::new(self.listeners));
+        supervisor.spawn("ble", move || scan(listeners.clone(), token.clone()));
+    }
+}
+
+impl BleManager {
+    /// Create a new manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan for all devices until cancelled
+    pub async fn run(self, token: CancellationToken) {
+        if let Err(error) = scan(Arc::new(AsyncMutex::new(self.listeners)), token).await {
+            error!("{error:#}");
+        }
+    }
+}
+
+/// Scan for the devices until cancelled, an error is returned if the scan cannot be started or the
+/// adapter stops sending events
+async fn scan(listeners: Arc<AsyncMutex<Vec<Listener>>>, token: CancellationToken) -> anyhow::Result<()> {
+    let adapter = first_adapter().await.context("Error opening Bluetooth adapter")?;
+    let mut events = adapter.events().await.context("Error subscribing to Bluetooth events")?;
+    adapter
+        .start_scan(ScanFilter::default())
+        .await
+        .context("Error starting Bluetooth scan")?;
+    let mut listeners = listeners.lock().await;
+    let mut ticks = interval(TICK);
+    let result = loop {
+        tokio::select! {
+            _ = token.cancelled() => break Ok(()),
+            _ = ticks.tick() => {
+                let now = Instant::now();
+                for listener in listeners.iter_mut() {
+                    listener.expire(now);
+                }
+            }
+            event = events.next() => {
+                let Some(event) = event else {
+                    break Err(anyhow!("Bluetooth event stream ended"));
+                };
+                let Some(id) = advertiser(event) else {
+                    continue;
+                };
+                let properties = match adapter.peripheral(&id).await {
+                    Ok(peripheral) => peripheral.properties().await,
+                    Err(error) => Err(error),
+                };
+                let properties = match properties {
+                    Ok(Some(properties)) => properties,
+                    Ok(None) => continue,
+                    Err(error) => {
+                        trace!("Error reading advertisement from {id:?}: {error}");
+                        continue;
+                    }
+                };
+                let now = Instant::now();
+                for listener in listeners.iter_mut() {
+                    if listener.config.matches(&properties) {
+                        listener.seen(now);
+                    }
+                }
+            }
+        }
+    };
+    if let Err(error) = adapter.stop_scan().await {
+        error!("Error stopping Bluetooth scan: {error}");
+    }
+    result
+}
+
+/// Returns the first Bluetooth adapter on this machine
+async fn first_adapter() -> Result<Adapter, Error> {
+    let manager = Manager::new().await?;
+    manager
+        .adapters()
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(Error::NoAdapter)
+}
+
+/// Returns the ID of the peripheral if the event was caused by an advertisement
+fn advertiser(event: CentralEvent) -> Option<PeripheralId> {
+    match event {
+        CentralEvent::DeviceDiscovered(id)
+        | CentralEvent::DeviceUpdated(id)
+        | CentralEvent::ManufacturerDataAdvertisement { id, .. }
+        | CentralEvent::ServiceDataAdvertisement { id, .. }
+        | CentralEvent::ServicesAdvertisement { id, .. } => Some(id),
+        _ => None,
+    }
+}
+
+impl Listener {
+    fn seen(&mut self, now: Instant) {
+        trace!("{} advertised", self.config.name);
+        self.last_seen = Some(now);
+        self.sender.send_if_modified(|present| !std::mem::replace(present, true));
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let expired = self
+            .last_seen
+            .is_none_or(|seen| now.duration_since(seen) > self.config.timeout);
+        if expired && self.sender.send_if_modified(|present| std::mem::replace(present, false)) {
+            debug!("{} has not advertised for {:?}", self.config.name, self.config.timeout);
+        }
+    }
+}
+
+/// A BLE device, this represents a watched device and exposes some methods for getting current
+/// status and listening for changes
+pub struct BleDevice {
+    info: DeviceInfo,
+    receiver: Receiver<bool>,
+}
+
+#[bon]
+impl BleDevice {
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    #[builder]
+    pub async fn create(
+        manager: &mut BleManager,
+        info: DeviceInfo,
+        /// The Bluetooth address of the device
+        address: Option<BDAddr>,
+        /// The local name advertised by the device
+        local_name: Option<String>,
+        /// The proximity UUID broadcast by an iBeacon
+        beacon: Option<Uuid>,
+        /// Advertisements received with a signal strength (in dBm) below this are ignored
+        min_rssi: Option<i16>,
+        /// the length of time since the device was last heard from before deeming it absent
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let name = info.name.clone();
+        Self::new_with_args(
+            manager,
+            info,
+            BleConfig {
+                name,
+                address,
+                local_name,
+                beacon,
+                min_rssi,
+                timeout,
+            },
+        )
+        .await
+    }
+
+    /// Returns true if the device has been heard from within the timeout
+    pub fn online(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Returns a stream of changes to the online status of the device
+    pub fn online_changes(&self) -> impl Stream<Item = bool> {
+        WatchStream::from_changes(self.receiver.clone())
+    }
+}
+
+impl Device for BleDevice {
+    type Args = BleConfig;
+    type Manager = BleManager;
+
+    fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    async fn new_with_args(
+        manager: &mut Self::Manager,
+        info: DeviceInfo,
+        config: BleConfig,
+    ) -> anyhow::Result<Self> {
+        if config.address.is_none() && config.local_name.is_none() && config.beacon.is_none() {
+            return Err(Error::NoIdentifier.into());
+        }
+        let (sender, receiver) = channel(false);
+        manager.listeners.push(Listener {
+            config,
+            sender,
+            last_seen: None,
+        });
+        Ok(BleDevice { info, receiver })
+    }
+}
+
+impl reflect::Device for BleDevice {
+    fn info(&self) -> DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field {
+                name: "detected".to_string(),
+                description: "This value is true whenever the given device is advertising nearby".to_string(),
+                operations: Operations {
+                    subscribe: true,
+                    get: true,
+                    set: false,
+                    toggle: false,
+                },
+                value_type: ValueType::Bool,
+            }
+        ]
+    }
+
+    fn subscribe(&self, field: &str) -> Result<BoxFuture<'_, BoxStream<'_, Value>>, reflect::Error> {
+        if field == "detected" {
+            Ok(Box::pin(ready(Box::pin(self.online_changes().map(Value::from)) as BoxStream<_>)))
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+
+    fn get(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<Value>>, reflect::Error> {
+        if field == "detected" {
+            Ok(Box::pin(ready(Ok(self.online().into()))))
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+
+    fn set(&self, field: &str, _: Value) -> Result<BoxFuture<'_, anyhow::Result<()>>, SetError> {
+        if field == "detected" {
+            Err(reflect::Error::OperationNotSupported {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+                operation: Operation::Set,
+            }.into())
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            }.into())
+        }
+    }
+
+    fn toggle(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<()>>, reflect::Error> {
+        if field == "detected" {
+            Err(reflect::Error::OperationNotSupported {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+                operation: Operation::Toggle,
+            })
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+}
+
+/// Errors creating a BLE device or opening the Bluetooth adapter
+#[derive(Debug, Error)]
+pub enum Error {
+    /// None of the address, local name or beacon UUID were given
+    #[error("one of address, local_name or beacon must be given")]
+    NoIdentifier,
+    /// No Bluetooth adapter was found on this machine
+    #[error("no Bluetooth adapter found")]
+    NoAdapter,
+    /// An error from the Bluetooth stack
+    #[error("Bluetooth error: {0}")]
+    Bluetooth(#[from] btleplug::Error),
+}
@@ -0,0 +1,218 @@
+#![doc = include_str!("../README.md")]
+
+use anyhow::{Context, bail};
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
+use reqwest::{Client, RequestBuilder};
+use serde_json::Value;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+#[derive(Parser)]
+#[command(name = "home-control", about = "Control a running home control system through it's REST API")]
+struct Cli {
+    /// The address of the REST API
+    #[arg(long, env = "HOME_CONTROL_URL", default_value = "http://localhost:8080")]
+    url: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the devices and the latest value of each of their fields
+    List,
+    /// Read the current value of a field
+    Get {
+        /// The id of the device
+        device: String,
+        /// The field to read
+        field: String,
+    },
+    /// Set a field, the value is JSON, ON/OFF are accepted for boolean fields and anything else is
+    /// sent as a string
+    Set {
+        /// The id of the device
+        device: String,
+        /// The field to set
+        field: String,
+        /// The value to set the field to
+        value: String,
+    },
+    /// Toggle a field
+    Toggle {
+        /// The id of the device
+        device: String,
+        /// The field to toggle
+        field: String,
+    },
+    /// List the automations and the counters of their runs
+    Automations,
+    /// Reload the automations from wherever they are defined, eg: the config file
+    Reload,
+    /// Print device updates and automation runs as they happen
+    Tail,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let api = Api {
+        client: Client::new(),
+        url: cli.url.trim_end_matches('/').to_string(),
+    };
+    match cli.command {
+        Command::List => list(&api).await,
+        Command::Get { device, field } => {
+            let value = api.send(api.client.get(api.path(&format!("devices/{device}/{field}")))).await?;
+            println!("{}", format_value(&value));
+            Ok(())
+        }
+        Command::Set { device, field, value } => {
+            let described = api.send(api.client.get(api.path(&format!("devices/{device}")))).await?;
+            let value_type = described["fields"]
+                .as_array()
+                .and_then(|fields| fields.iter().find(|f| f["name"] == field.as_str()))
+                .and_then(|f| f["value_type"]["type"].as_str());
+            let value = parse_value(&value, value_type);
+            api.send(api.client.put(api.path(&format!("devices/{device}/{field}"))).json(&value))
+                .await?;
+            Ok(())
+        }
+        Command::Toggle { device, field } => {
+            api.send(api.client.post(api.path(&format!("devices/{device}/{field}/toggle"))))
+                .await?;
+            Ok(())
+        }
+        Command::Automations => automations(&api).await,
+        Command::Reload => {
+            let reloaded = api.send(api.client.post(api.path("reload"))).await?;
+            println!("reloaded {} automations", reloaded.as_array().map_or(0, Vec::len));
+            Ok(())
+        }
+        Command::Tail => tail(&api).await,
+    }
+}
+
+/// The REST API of the running system
+struct Api {
+    client: Client,
+    url: String,
+}
+
+impl Api {
+    fn path(&self, path: &str) -> String {
+        format!("{}/api/{path}", self.url)
+    }
+
+    /// Send the request, returning the JSON body or `null` if there is none
+    async fn send(&self, request: RequestBuilder) -> anyhow::Result<Value> {
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("failed to reach the API at {}", self.url))?;
+        let status = response.status();
+        let body = response.text().await.context("failed to read response")?;
+        if !status.is_success() {
+            // errors are returned as {"error": "..."}
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|error| error["error"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            bail!("{status}: {message}");
+        }
+        if body.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&body).context("invalid response")
+    }
+}
+
+async fn list(api: &Api) -> anyhow::Result<()> {
+    let devices = api.send(api.client.get(api.path("devices"))).await?;
+    for device in devices.as_array().into_iter().flatten() {
+        let id = device["id"].as_str().unwrap_or_default();
+        let name = device["name"].as_str().unwrap_or_default();
+        if id == name {
+            println!("{id}");
+        } else {
+            println!("{id} ({name})");
+        }
+        for field in device["fields"].as_array().into_iter().flatten() {
+            let field = field["name"].as_str().unwrap_or_default();
+            let value = device["values"].get(field).map_or("-".to_string(), format_value);
+            println!("  {field}: {value}");
+        }
+    }
+    Ok(())
+}
+
+async fn automations(api: &Api) -> anyhow::Result<()> {
+    let automations = api.send(api.client.get(api.path("automations"))).await?;
+    for automation in automations.as_array().into_iter().flatten() {
+        println!(
+            "{}: triggered {}, succeeded {}, failed {}",
+            automation["name"].as_str().unwrap_or_default(),
+            automation["triggered"],
+            automation["succeeded"],
+            automation["failed"],
+        );
+    }
+    Ok(())
+}
+
+async fn tail(api: &Api) -> anyhow::Result<()> {
+    // http becomes ws and https becomes wss
+    let url = format!("{}/api/events", api.url.replacen("http", "ws", 1));
+    let (mut socket, _) = connect_async(&url)
+        .await
+        .with_context(|| format!("failed to connect to {url}"))?;
+    while let Some(message) = socket.next().await {
+        let Message::Text(text) = message.context("event stream failed")? else {
+            continue;
+        };
+        let event: Value = serde_json::from_str(&text).context("invalid event")?;
+        println!("{}", format_event(&event));
+    }
+    Ok(())
+}
+
+fn format_event(event: &Value) -> String {
+    match event["type"].as_str() {
+        Some("device_update") => format!(
+            "{}.{} = {}",
+            event["device"].as_str().unwrap_or_default(),
+            event["field"].as_str().unwrap_or_default(),
+            format_value(&event["value"]),
+        ),
+        Some("automation_run") => {
+            let automation = event["automation"].as_str().unwrap_or_default();
+            let run = event["event"].as_str().unwrap_or_default();
+            match event["error"].as_str() {
+                Some(error) => format!("automation {automation} {run}: {error}"),
+                None => format!("automation {automation} {run}"),
+            }
+        }
+        _ => event.to_string(),
+    }
+}
+
+/// Strings are printed without quotes, everything else as JSON
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Parse a value given on the command line for a field of the given type
+fn parse_value(raw: &str, value_type: Option<&str>) -> Value {
+    if value_type == Some("Bool") {
+        match raw.to_ascii_lowercase().as_str() {
+            "on" => return Value::Bool(true),
+            "off" => return Value::Bool(false),
+            _ => {}
+        }
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
@@ -0,0 +1,121 @@
+use crate::{FakeToggle, Guarded, ReadValue, Sensor, ToggleValue, WriteValue};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use std::ops::Not;
+
+/// Adapts a value to a different item type, allowing devices with differing capabilities to be
+/// used where a simpler capability is expected, eg: a dimmable light's brightness can be treated
+/// as an on/off switch:
+/// ```
+/// use control::{Mapped, ReadValue, ValueExt, WriteValue};
+/// use light_ranged_integers::RangedU8;
+///
+/// fn as_switch<V>(brightness: V) -> Mapped<V, bool, RangedU8<0, 254>>
+/// where
+///     V: ReadValue<Item = RangedU8<0, 254>> + WriteValue<Item = RangedU8<0, 254>>
+/// {
+///     brightness.map_value(
+///         |on| RangedU8::new(if on { 254 } else { 0 }),
+///         |brightness| brightness.inner() > 0,
+///     )
+/// }
+/// ```
+pub struct Mapped<V, T, U> {
+    value: V,
+    to_value: fn(T) -> U,
+    from_value: fn(U) -> T,
+}
+
+impl<V: Sensor<Item = U>, T, U> Sensor for Mapped<V, T, U> {
+    type Item = T;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(self.value.subscribe().map(self.from_value))
+    }
+}
+
+impl<V: ReadValue<Item = U>, T, U> ReadValue for Mapped<V, T, U> {
+    type Item = T;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        let from_value = self.from_value;
+        Box::pin(self.value.get().map(move |result| result.map(from_value)))
+    }
+}
+
+impl<V: WriteValue<Item = U>, T, U> WriteValue for Mapped<V, T, U> {
+    type Item = T;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.value.set((self.to_value)(value))
+    }
+}
+
+impl<V: ToggleValue<Item = U>, T, U> ToggleValue for Mapped<V, T, U> {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.value.toggle()
+    }
+}
+
+/// Helpers for adapting values to a different set of capabilities
+pub trait ValueExt: Sized {
+    /// Convert the item type of this value, `to_value` is used when writing and `from_value` is
+    /// used when reading or subscribing, any toggle support is preserved
+    fn map_value<T, U>(self, to_value: fn(T) -> U, from_value: fn(U) -> T) -> Mapped<Self, T, U> {
+        Mapped {
+            value: self,
+            to_value,
+            from_value,
+        }
+    }
+
+    /// Emulate a toggle for a value which supports reading and writing but not toggling
+    fn fake_toggle(self) -> FakeToggle<Self>
+    where
+        Self: ReadValue + WriteValue + Sync,
+        <Self as ReadValue>::Item: Not<Output = <Self as WriteValue>::Item>,
+    {
+        FakeToggle::new(self)
+    }
+
+    /// Protect this value with a rate limit or interlocks, see [Guarded]
+    fn guarded<'a>(self, name: impl Into<String>) -> Guarded<'a, Self>
+    where
+        Self: WriteValue,
+    {
+        Guarded::new(self, name)
+    }
+}
+
+impl<V> ValueExt for V {}
+
+impl<V: Sensor + ?Sized> Sensor for &V {
+    type Item = V::Item;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        (**self).subscribe()
+    }
+}
+
+impl<V: ReadValue + ?Sized> ReadValue for &V {
+    type Item = V::Item;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        (**self).get()
+    }
+}
+
+impl<V: WriteValue + ?Sized> WriteValue for &V {
+    type Item = V::Item;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        (**self).set(value)
+    }
+}
+
+impl<V: ToggleValue + ?Sized> ToggleValue for &V {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        (**self).toggle()
+    }
+}
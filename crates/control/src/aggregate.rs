@@ -0,0 +1,182 @@
+use crate::Sensor;
+use futures::Stream;
+use futures::stream::BoxStream;
+use pin_project::pin_project;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep, sleep_until};
+
+/// The aggregation to apply to the values within a window
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Aggregation {
+    /// The smallest value within the window
+    Min,
+    /// The largest value within the window
+    Max,
+    /// The mean of all values received within the window
+    Mean,
+}
+
+/// A sensor derived from another numeric sensor, which reports an aggregate of the values
+/// received over a sliding window of time, eg: "max power over the last 15 minutes"
+///
+/// The aggregate is computed incrementally as each value is received, so a new value is
+/// reported every time the underlying sensor reports a value, and again whenever an old value
+/// leaves the window, once every value has left the window nothing is reported until the next
+/// value is received
+pub struct AggregateSensor<S> {
+    sensor: S,
+    window: Duration,
+    aggregation: Aggregation,
+}
+
+impl<S> AggregateSensor<S> {
+    /// Create a new aggregate sensor over the given window
+    pub fn new(sensor: S, window: Duration, aggregation: Aggregation) -> Self {
+        Self {
+            sensor,
+            window,
+            aggregation,
+        }
+    }
+
+    /// The smallest value reported over the given window
+    pub fn min(sensor: S, window: Duration) -> Self {
+        Self::new(sensor, window, Aggregation::Min)
+    }
+
+    /// The largest value reported over the given window
+    pub fn max(sensor: S, window: Duration) -> Self {
+        Self::new(sensor, window, Aggregation::Max)
+    }
+
+    /// The mean of the values reported over the given window
+    pub fn mean(sensor: S, window: Duration) -> Self {
+        Self::new(sensor, window, Aggregation::Mean)
+    }
+}
+
+impl<S> Sensor for AggregateSensor<S>
+where
+    S: Sensor,
+    S::Item: Into<f64>,
+{
+    type Item = f64;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(Windowed::new(self.sensor.subscribe(), self.window, self.aggregation))
+    }
+}
+
+#[pin_project(project = WindowedProj)]
+pub(crate) struct Windowed<S> {
+    #[pin]
+    stream: S,
+    window: Duration,
+    aggregation: Aggregation,
+    /// For min/max this is a monotonic queue where the front is always the current aggregate,
+    /// for mean this contains every sample within the window
+    samples: VecDeque<(Instant, f64)>,
+    sum: f64,
+    /// Fires once the front sample leaves the window, so the aggregate is updated while the
+    /// underlying stream is quiet
+    expiry: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Windowed<S> {
+    pub(crate) fn new(stream: S, window: Duration, aggregation: Aggregation) -> Self {
+        Self {
+            stream,
+            window,
+            aggregation,
+            samples: VecDeque::new(),
+            sum: 0.0,
+            expiry: None,
+        }
+    }
+}
+
+impl<S> Stream for Windowed<S>
+where
+    S: Stream,
+    S::Item: Into<f64>,
+{
+    type Item = f64;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        match this.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                let now = Instant::now();
+                this.expire(now);
+                this.push(now, item.into());
+                this.schedule();
+                return Poll::Ready(this.aggregate());
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+        let Some(expiry) = this.expiry.as_mut() else {
+            return Poll::Pending;
+        };
+        ready!(expiry.as_mut().poll(cx));
+        this.expire(Instant::now());
+        this.schedule();
+        // once every sample has expired there is nothing to report until the next value
+        match this.aggregate() {
+            Some(aggregate) => Poll::Ready(Some(aggregate)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<S> WindowedProj<'_, S> {
+    /// Drop the samples that have fallen out of the window
+    fn expire(&mut self, now: Instant) {
+        while let Some((time, old)) = self.samples.front()
+            && now.duration_since(*time) > *self.window
+        {
+            if *self.aggregation == Aggregation::Mean {
+                *self.sum -= old;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    fn push(&mut self, now: Instant, value: f64) {
+        match self.aggregation {
+            Aggregation::Min => {
+                while self.samples.back().is_some_and(|(_, last)| *last >= value) {
+                    self.samples.pop_back();
+                }
+            }
+            Aggregation::Max => {
+                while self.samples.back().is_some_and(|(_, last)| *last <= value) {
+                    self.samples.pop_back();
+                }
+            }
+            Aggregation::Mean => *self.sum += value,
+        }
+        self.samples.push_back((now, value));
+    }
+
+    fn aggregate(&self) -> Option<f64> {
+        match self.aggregation {
+            Aggregation::Min | Aggregation::Max => self.samples.front().map(|(_, value)| *value),
+            Aggregation::Mean => {
+                (!self.samples.is_empty()).then(|| *self.sum / self.samples.len() as f64)
+            }
+        }
+    }
+
+    /// Set the timer for when the front sample leaves the window, a sample exactly on the edge
+    /// of the window is still within it, so it expires just after
+    fn schedule(&mut self) {
+        *self.expiry = self.samples.front().map(|(time, _)| {
+            Box::pin(sleep_until(*time + *self.window + Duration::from_nanos(1)))
+        });
+    }
+}
@@ -1,27 +1,124 @@
 //! Automations run when a trigger fires and executes some action
 
+use crate::util::send_broadcast;
 use futures::Stream;
+use futures::channel::mpsc::UnboundedSender;
 use futures::future::BoxFuture;
 use futures::stream::BoxStream;
 use pin_project::pin_project;
 use std::marker::PhantomData;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, debug, warn};
 
+tokio::task_local! {
+    /// The name of the automation whose run is being polled
+    static CURRENT: String;
+}
+
+/// The name of the automation whose run is being polled, if any, eg: to attribute a write to the
+/// automation which made it
+pub fn current() -> Option<String> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
 #[must_use = "An automation does nothing unless it is passed into Manager::start"]
 /// An Automation definition, with a trigger stream and an action
 pub struct Automation<'a> {
     pub(crate) name: String,
     pub(crate) stream: BoxStream<'a, (String, BoxFuture<'a, ()>)>,
+    pub(crate) token: CancellationToken,
+    stats: Arc<AutomationStats>,
+}
+
+/// A set of automations which can be started together by passing it to `Manager::start`
+///
+/// This can be derived for any struct whose fields are automations, or anything else yielding automations, eg: a `Vec`,
+/// an `Option` or another set, this allows automations to be grouped by field rather than collected into one list
+pub trait AutomationSet<'a>: IntoIterator<Item = Automation<'a>> {}
+
+/// The number of run events buffered for each subscriber, a subscriber which falls further behind
+/// misses the oldest events
+const RUN_EVENT_CAPACITY: usize = 64;
+
+/// Counters of the runs of an automation, eg: for exporting as metrics
+#[derive(Debug)]
+pub struct AutomationStats {
+    triggered: AtomicU64,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    events: broadcast::Sender<RunEvent>,
+}
+
+/// An event in a run of an automation, see [AutomationStats::subscribe]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunEvent {
+    /// The automation was triggered and a run started
+    Triggered,
+    /// A run completed successfully
+    Succeeded,
+    /// A run returned an error
+    Failed(String),
+}
+
+impl Default for AutomationStats {
+    fn default() -> Self {
+        Self {
+            triggered: AtomicU64::default(),
+            succeeded: AtomicU64::default(),
+            failed: AtomicU64::default(),
+            events: broadcast::channel(RUN_EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl AutomationStats {
+    /// Subscribe to the events of the automation's runs from now on, eg: to show runs live
+    pub fn subscribe(&self) -> broadcast::Receiver<RunEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: RunEvent) {
+        send_broadcast(&self.events, event);
+    }
+
+    /// The number of times the automation has been triggered
+    pub fn triggered(&self) -> u64 {
+        self.triggered.load(Ordering::Relaxed)
+    }
+
+    /// The number of runs which completed successfully
+    pub fn succeeded(&self) -> u64 {
+        self.succeeded.load(Ordering::Relaxed)
+    }
+
+    /// The number of runs which returned an error
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
 }
 
 /// An Automation action, to be run each time the automation triggers, is already implemented for:
 ///
 /// `Fn(Trigger) -> impl Future<Output=Result<(), String>> + Send`
-pub trait Action<Trigger>: Send + Sync + Copy {
-    /// Run this action
-    fn run(self, trigger: Trigger) -> impl Future<Output = Result<(), String>> + Send;
+///
+/// Actions which need to know when to stop should be wrapped in [Cancellable].
+///
+/// The action is cloned for each run, so closures may capture shared state by move, eg: an
+/// `Arc<Mutex<_>>`, as long as it is cheap to clone
+pub trait Action<Trigger>: Send + Sync + Clone {
+    /// Run this action, `token` is unique to this run and is cancelled when the run should stop,
+    /// eg: on shutdown, long-running actions should check it and return early when cancelled
+    fn run(
+        self,
+        trigger: Trigger,
+        token: CancellationToken,
+    ) -> impl Future<Output = Result<(), String>> + Send;
 }
 
 // pub trait MutableAction<Trigger>: Send + Sync {
@@ -31,13 +128,29 @@ pub trait Action<Trigger>: Send + Sync + Copy {
 impl<F: Fn(T) -> Fut, Fut, T> Action<T> for F
 where
     Fut: Future<Output = Result<(), String>> + Send,
-    F: Send + Sync + Copy,
+    F: Send + Sync + Clone,
 {
-    fn run(self, trigger: T) -> impl Future<Output = Result<(), String>> {
+    fn run(self, trigger: T, _: CancellationToken) -> impl Future<Output = Result<(), String>> {
         self(trigger)
     }
 }
 
+/// An [Action] which is given the [CancellationToken] of each run, implemented for:
+///
+/// `Fn(Trigger, CancellationToken) -> impl Future<Output=Result<(), String>> + Send`
+#[derive(Debug, Clone, Copy)]
+pub struct Cancellable<F>(pub F);
+
+impl<F: Fn(T, CancellationToken) -> Fut, Fut, T> Action<T> for Cancellable<F>
+where
+    Fut: Future<Output = Result<(), String>> + Send,
+    F: Send + Sync + Clone,
+{
+    fn run(self, trigger: T, token: CancellationToken) -> impl Future<Output = Result<(), String>> {
+        (self.0)(trigger, token)
+    }
+}
+
 impl<'a> Automation<'a> {
     /// Create a new automation
     ///
@@ -50,12 +163,175 @@ impl<'a> Automation<'a> {
         A: Action<S::Item> + 'a,
     {
         let name = name.into();
-        let futures = JobStream::new(name.clone(), input, action);
+        let token = CancellationToken::new();
+        let stats = Arc::new(AutomationStats::default());
+        let futures = JobStream::new(name.clone(), input, action, token.clone(), stats.clone());
         Automation {
             name,
             stream: Box::pin(futures),
+            token,
+            stats,
         }
     }
+
+    /// Create a new automation whose action is given a [CancellationToken] for each run, this is
+    /// shorthand for `Automation::new(name, input, Cancellable(action))`
+    pub fn new_cancellable<S, F, Fut>(name: impl Into<String>, input: S, action: F) -> Self
+    where
+        S: Stream + Send + 'a,
+        F: Fn(S::Item, CancellationToken) -> Fut + Send + Sync + Clone + 'a,
+        Fut: Future<Output = Result<(), String>> + Send,
+    {
+        Self::new(name, input, Cancellable(action))
+    }
+
+    /// The parent token of every run of this automation, cancelling it cancels all current and
+    /// future runs, this is cancelled by the manager on shutdown
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// The name of this automation
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The counters of this automation's runs, these are updated for as long as it runs
+    pub fn stats(&self) -> Arc<AutomationStats> {
+        self.stats.clone()
+    }
+}
+
+/// A handle to the automations of a manager, automations can be started, stopped and replaced
+/// through it while the manager is running without restarting the device managers, get it from
+/// [Manager::automations](crate::Manager::automations)
+#[derive(Clone)]
+pub struct AutomationHandle<'a> {
+    pub(crate) commands: UnboundedSender<Command<'a>>,
+}
+
+pub(crate) enum Command<'a> {
+    /// Start an automation alongside any running automations
+    Start(Automation<'a>),
+    /// Start an automation, stopping any running automations with the same name
+    Replace(Automation<'a>),
+    /// Stop the automation with the given name
+    Stop(String),
+    /// Stop every automation
+    StopAll,
+}
+
+/// The manager has stopped, so it's automations can no longer be changed
+#[derive(Debug, Error)]
+#[error("the manager has stopped")]
+pub struct ManagerStopped;
+
+impl<'a> AutomationHandle<'a> {
+    /// Start an automation alongside the running automations, including any with the same name,
+    /// see [restart](Self::restart) to replace a running automation
+    ///
+    /// # Errors
+    /// Returns an error if the manager has stopped
+    pub fn start(&self, automation: Automation<'a>) -> Result<(), ManagerStopped> {
+        self.send(Command::Start(automation))
+    }
+
+    /// Start an automation, the running automations with the same name are stopped and replaced,
+    /// their current runs are cancelled
+    ///
+    /// # Errors
+    /// Returns an error if the manager has stopped
+    pub fn restart(&self, automation: Automation<'a>) -> Result<(), ManagerStopped> {
+        self.send(Command::Replace(automation))
+    }
+
+    /// Stop the automations with the given name, their current runs are cancelled
+    ///
+    /// # Errors
+    /// Returns an error if the manager has stopped
+    pub fn stop(&self, name: impl Into<String>) -> Result<(), ManagerStopped> {
+        self.send(Command::Stop(name.into()))
+    }
+
+    /// Stop every running automation and start the given ones instead, eg: after the file
+    /// defining them has changed
+    ///
+    /// # Errors
+    /// Returns an error if the manager has stopped
+    pub fn replace(&self, automations: impl IntoIterator<Item = Automation<'a>>) -> Result<(), ManagerStopped> {
+        self.send(Command::StopAll)?;
+        automations
+            .into_iter()
+            .try_for_each(|automation| self.start(automation))
+    }
+
+    fn send(&self, command: Command<'a>) -> Result<(), ManagerStopped> {
+        self.commands
+            .unbounded_send(command)
+            .map_err(|_| ManagerStopped)
+    }
+}
+
+/// The automations started by a reload, by name with the counters of their runs
+pub type Reloaded = Vec<(String, Arc<AutomationStats>)>;
+
+/// Requests that the automations are reloaded from wherever they are defined, eg: a config file,
+/// this is how the REST API triggers a reload, see [reload_channel]
+#[derive(Debug, Clone)]
+pub struct ReloadTrigger {
+    requests: mpsc::Sender<ReloadRequest>,
+}
+
+/// The reload requests of a [ReloadTrigger], to be answered by whatever defines the automations
+#[derive(Debug)]
+pub struct ReloadRequests {
+    requests: mpsc::Receiver<ReloadRequest>,
+}
+
+/// A single request to reload the automations, it must be answered with the result of the reload
+#[derive(Debug)]
+pub struct ReloadRequest {
+    response: oneshot::Sender<Result<Reloaded, String>>,
+}
+
+/// Create a trigger for reloading automations and the requests it sends
+pub fn reload_channel() -> (ReloadTrigger, ReloadRequests) {
+    // reloads are rare, a second request while one is in progress may as well wait
+    let (sender, receiver) = mpsc::channel(1);
+    (ReloadTrigger { requests: sender }, ReloadRequests { requests: receiver })
+}
+
+impl ReloadTrigger {
+    /// Reload the automations, returning the automations which were started
+    ///
+    /// # Errors
+    /// Returns an error if the automations could not be reloaded, eg: the file defining them is
+    /// invalid, in which case the previous automations are left running
+    pub async fn reload(&self) -> Result<Reloaded, String> {
+        let (response, result) = oneshot::channel();
+        self.requests
+            .send(ReloadRequest { response })
+            .await
+            .map_err(|_| "nothing is listening for reload requests".to_string())?;
+        result
+            .await
+            .map_err(|_| "the reload was abandoned".to_string())?
+    }
+}
+
+impl ReloadRequests {
+    /// Wait for the next request, None is returned once every trigger has been dropped
+    pub async fn next(&mut self) -> Option<ReloadRequest> {
+        self.requests.recv().await
+    }
+}
+
+impl ReloadRequest {
+    /// Answer the request with the result of the reload
+    pub fn respond(self, result: Result<Reloaded, String>) {
+        // the requester may have given up waiting, which is fine
+        let _ = self.response.send(result);
+    }
 }
 
 #[pin_project]
@@ -64,6 +340,8 @@ struct JobStream<'a, S, A> {
     #[pin]
     input: S,
     action: A,
+    token: CancellationToken,
+    stats: Arc<AutomationStats>,
     _a: PhantomData<&'a ()>,
 }
 
@@ -72,11 +350,19 @@ where
     S: Stream + 'a,
     A: Action<S::Item> + 'a,
 {
-    pub fn new(name: String, input: S, action: A) -> Self {
+    pub fn new(
+        name: String,
+        input: S,
+        action: A,
+        token: CancellationToken,
+        stats: Arc<AutomationStats>,
+    ) -> Self {
         JobStream {
             name,
             input,
             action,
+            token,
+            stats,
             _a: PhantomData,
         }
     }
@@ -92,21 +378,30 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.project();
         let action = this.action;
+        let token = this.token;
+        let stats = this.stats;
         this.input.poll_next(cx).map(move |option| {
             option.map(move |trigger| {
-                let run = action.run(trigger);
+                let run = action.clone().run(trigger, token.child_token());
                 let name = this.name.clone();
+                let stats = stats.clone();
                 let future = async move {
                     debug!("Automation {name} triggered");
+                    stats.triggered.fetch_add(1, Ordering::Relaxed);
+                    stats.emit(RunEvent::Triggered);
                     if let Err(error) = run.await {
+                        stats.failed.fetch_add(1, Ordering::Relaxed);
                         warn!("automation {name} failed: {error}");
+                        stats.emit(RunEvent::Failed(error));
                     } else {
+                        stats.succeeded.fetch_add(1, Ordering::Relaxed);
                         debug!("Automation {name} completed");
+                        stats.emit(RunEvent::Succeeded);
                     }
                 };
                 (
                     this.name.clone(),
-                    Box::pin(future.instrument(tracing::info_span!(
+                    Box::pin(CURRENT.scope(this.name.clone(), future).instrument(tracing::info_span!(
                         "automation_run",
                         name = this.name.clone()
                     ))) as BoxFuture<'a, ()>,
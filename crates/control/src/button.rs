@@ -1,12 +1,11 @@
 use crate::ButtonEvent;
-use async_timer::new_timer;
-use async_timer::timer::Platform as Timer;
 use futures::{Stream, StreamExt};
 use light_ranged_integers::RangedU8;
 use std::ops::DerefMut;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
+use tokio::time::{Sleep, sleep};
 
 const PRESS_INTERVAL: Duration = Duration::from_millis(500);
 
@@ -14,7 +13,7 @@ pub struct ButtonPressStream<S: Stream<Item = ButtonEvent> + Unpin, const MAX: u
     stream: S,
     count: RangedU8<1, MAX>,
     released: bool,
-    timer: Option<Pin<Box<Timer>>>
+    timer: Option<Pin<Box<Sleep>>>
 }
 
 impl<S: Stream<Item=ButtonEvent> + Unpin, const MAX: u8> ButtonPressStream<S, MAX> {
@@ -39,7 +38,7 @@ impl<S: Stream<Item = ButtonEvent> + Unpin, const MAX: u8> Stream for ButtonPres
                 Poll::Ready(Some(ButtonEvent::Press)) => {
                     *count = RangedU8::new(1);
                     *released = false;
-                    *timer = Some(Box::pin(new_timer(PRESS_INTERVAL)));
+                    *timer = Some(Box::pin(sleep(PRESS_INTERVAL)));
                     Poll::Pending
                 }
                 Poll::Ready(Some(_)) | Poll::Pending => Poll::Pending,
@@ -58,7 +57,7 @@ impl<S: Stream<Item = ButtonEvent> + Unpin, const MAX: u8> Stream for ButtonPres
                         Poll::Ready(Some(ButtonPressEvent::Press(*count)))
                     } else {
                         *released = false;
-                        *timer = Some(Box::pin(new_timer(PRESS_INTERVAL)));
+                        *timer = Some(Box::pin(sleep(PRESS_INTERVAL)));
                         Poll::Pending
                     }
                 }
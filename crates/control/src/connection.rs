@@ -0,0 +1,110 @@
+//! The state of a connection to an external system, eg: the MQTT broker of a device manager, for
+//! exporting as metrics or for automations which react to the connection going down
+
+use crate::Sensor;
+use crate::util::{broadcast_stream, lock, send_broadcast};
+use futures::stream::BoxStream;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::broadcast;
+
+/// The number of connection events buffered for each subscriber
+const EVENT_CAPACITY: usize = 16;
+
+/// The lifecycle of a connection, it is updated by whatever owns the connection and is also a
+/// sensor of each [ConnectionEvent], eg: to flash a light while the broker is unreachable
+#[derive(Debug)]
+pub struct ConnectionStats {
+    connected: AtomicBool,
+    connects: AtomicU64,
+    disconnects: AtomicU64,
+    reconnect_attempts: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    events: broadcast::Sender<ConnectionEvent>,
+}
+
+/// A change in the state of a connection, see [ConnectionStats]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The connection was established
+    Connected,
+    /// An established connection was lost, with the error which caused it
+    Disconnected(String),
+    /// An attempt to reconnect is being made
+    Reconnecting,
+}
+
+impl Default for ConnectionStats {
+    fn default() -> Self {
+        Self {
+            connected: AtomicBool::default(),
+            connects: AtomicU64::default(),
+            disconnects: AtomicU64::default(),
+            reconnect_attempts: AtomicU64::default(),
+            last_error: Mutex::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+        }
+    }
+}
+
+impl ConnectionStats {
+    /// True while the connection is established
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// The number of times the connection has been established
+    pub fn connects(&self) -> u64 {
+        self.connects.load(Ordering::Relaxed)
+    }
+
+    /// The number of times an established connection has been lost
+    pub fn disconnects(&self) -> u64 {
+        self.disconnects.load(Ordering::Relaxed)
+    }
+
+    /// The number of attempts made to reconnect after an error
+    pub fn reconnect_attempts(&self) -> u64 {
+        self.reconnect_attempts.load(Ordering::Relaxed)
+    }
+
+    /// The most recent connection error, if there has been one
+    pub fn last_error(&self) -> Option<String> {
+        lock(&self.last_error).clone()
+    }
+
+    /// Report that the connection was established
+    pub fn report_connected(&self) {
+        self.connected.store(true, Ordering::Relaxed);
+        self.connects.fetch_add(1, Ordering::Relaxed);
+        self.emit(ConnectionEvent::Connected);
+    }
+
+    /// Report a connection error, this is a disconnect if the connection was established
+    pub fn report_error(&self, error: impl Into<String>) {
+        let error = error.into();
+        *lock(&self.last_error) = Some(error.clone());
+        if self.connected.swap(false, Ordering::Relaxed) {
+            self.disconnects.fetch_add(1, Ordering::Relaxed);
+            self.emit(ConnectionEvent::Disconnected(error));
+        }
+    }
+
+    /// Report an attempt to reconnect
+    pub fn report_reconnect_attempt(&self) {
+        self.reconnect_attempts.fetch_add(1, Ordering::Relaxed);
+        self.emit(ConnectionEvent::Reconnecting);
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        send_broadcast(&self.events, event);
+    }
+}
+
+impl Sensor for ConnectionStats {
+    type Item = ConnectionEvent;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        broadcast_stream(self.events.subscribe())
+    }
+}
@@ -1,7 +1,8 @@
 //! Devices and related types
 
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use thiserror::Error;
+use tracing::warn;
 use reflect::DeviceInfo;
 use crate::device_manager::{DeviceManager, DeviceManagerNotFound};
 use crate::{reflect, Manager};
@@ -13,6 +14,30 @@ use crate::{reflect, Manager};
 ///
 /// This function is used by duck typing (The macro calls the function, resulting in a compile error if the function is not present) rather than using triats
 /// This allows additional parameters to be defined in the device as needed rather than being tied to a trait definition
+///
+/// The name of a device defaults to it's id, the `name` param can be any expression evaluated when the set is created,
+/// and `#[device(name_from_env = "OFFICE_LIGHT_NAME")]` reads the name from an environment variable, falling back to the
+/// id if it is not set, eg: so the same binary can run against a test broker
+///
+/// A field marked with `#[device(set)]` is itself a device set, eg: the devices of a single room, it is created by
+/// calling it's own `DeviceSet::new` and it's devices are included when iterating over the parent set
+///
+/// A `Vec` or array field is created with one device for each id in it's `#[device(names = [...])]` param, any other
+/// params are shared by every device in the field
+///
+/// An `Option` field is `None` if the device failed to be created, eg: an unreachable bulb, a warning is logged instead of
+/// failing the whole set
+///
+/// An `Option` field with `#[device(profiles = ["home"])]` is only created when the manager's profile is one of those
+/// listed, otherwise it is `None`, eg: so the same automations can run against a reduced set of devices on a development
+/// broker, every device is created if the manager has no profile
+///
+/// A field marked with `#[device(skip)]` is not a device, eg: helper state or a config value, it is created with
+/// `Default::default()` and is not included when iterating over the set
+///
+/// Adding `#[tagged]` above the derive allows fields to be tagged with `#[tag(downstairs, lights)]`, an accessor is
+/// generated for each tag returning the devices with that tag, along with a `with_tag` method, eg: to operate on a
+/// whole area
 pub trait DeviceSet: Sized + IntoIterator<Item=Box<dyn reflect::Device>> {
     /// Create a new device set from the manager
     async fn new(manager: &mut Manager) -> Result<Self, CreateDeviceError>;
@@ -47,3 +72,16 @@ pub enum CreateDeviceError {
     #[error(transparent)]
     Device(#[from] anyhow::Error),
 }
+
+/// Used by the `DeviceSet` derive for `Option` fields, a device which failed to be created is
+/// logged and left out so the rest of the set can still be used
+#[doc(hidden)]
+pub fn optional_device<D, E: Display>(id: &str, result: Result<D, E>) -> Option<D> {
+    match result {
+        Ok(device) => Some(device),
+        Err(error) => {
+            warn!("failed to create device {id}, continuing without it: {error:#}");
+            None
+        }
+    }
+}
@@ -1,8 +1,24 @@
 //! Defines the Manager and associated types
 
+use crate::Sensor;
+use crate::dry_run::DryRun;
+use crate::health::Health;
+use crate::util::{broadcast_stream, send_broadcast};
+use bon::Builder;
+use futures::FutureExt;
+use futures::stream::BoxStream;
 use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::sync::broadcast;
+use tokio::time::{Instant, sleep};
+use tokio::{select, spawn};
 use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, info, info_span, warn};
+
+/// The number of supervisor events buffered for each subscriber
+const EVENT_CAPACITY: usize = 16;
 
 /// A [Device] manager, this can be used to handle all devices of a certain type,
 /// for example, the manager might communicate with an external server which manages the devices
@@ -13,6 +29,21 @@ use tokio_util::sync::CancellationToken;
 pub trait DeviceManager: Any + DeviceManagerExt + Send {
     /// Starts this manager, spawn any tasks in the tokio runtime
     fn start(self: Box<Self>, token: CancellationToken);
+
+    /// Starts this manager under a supervisor, a manager should spawn any task which can be
+    /// restarted through the [Supervisor], by default this is the same as
+    /// [start](DeviceManager::start)
+    fn supervise(self: Box<Self>, supervisor: &Supervisor) {
+        self.start(supervisor.token());
+    }
+
+    /// Called before [start](DeviceManager::start), a manager with something worth reporting,
+    /// eg: the connection to a broker, should register a reporter and keep it up to date
+    fn attach_health(&mut self, _health: &Health) {}
+
+    /// Called when the [Manager](crate::Manager) is built, before any device is created, a manager
+    /// which creates devices that write should pass it on to them, see [dry_run](crate::dry_run)
+    fn set_dry_run(&mut self, _dry_run: DryRun) {}
 }
 
 pub(crate) trait DeviceManagerExt: Any {
@@ -30,6 +61,118 @@ impl DeviceManager for () {
     fn start(self: Box<Self>, _: CancellationToken) {}
 }
 
+/// Runs the background tasks of the device managers, a task which fails or panics before shutdown
+/// is restarted after a delay, the delay doubles with each consecutive restart up to a maximum
+///
+/// The supervisor is also a sensor of each [SupervisorEvent], eg: to alert when the connection
+/// to a broker keeps failing
+#[derive(Debug, Clone, Builder)]
+pub struct Supervisor {
+    /// The token cancelled on shutdown, a task is not restarted after shutdown
+    token: CancellationToken,
+    /// The delay before the first restart of a task, defaults to 1 second
+    #[builder(default = Duration::from_secs(1))]
+    restart_delay: Duration,
+    /// The longest delay between restarts, a task which ran for longer than this is considered to
+    /// have recovered, so the delay starts again from `restart_delay`, defaults to 1 minute
+    #[builder(default = Duration::from_secs(60))]
+    max_restart_delay: Duration,
+    #[builder(skip = broadcast::channel(EVENT_CAPACITY).0)]
+    events: broadcast::Sender<SupervisorEvent>,
+}
+
+/// A change in the state of a supervised task, see [Supervisor]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorEvent {
+    /// A task stopped unexpectedly, with the reason it stopped
+    Stopped {
+        /// The name of the task
+        task: String,
+        /// Why the task stopped, eg: the error it returned
+        reason: String,
+    },
+    /// A task was started again, after the given number of consecutive restarts
+    Restarted {
+        /// The name of the task
+        task: String,
+        /// The number of restarts since the task last recovered
+        attempt: u32,
+    },
+}
+
+impl Supervisor {
+    /// The token cancelled on shutdown, for any task not spawned through the supervisor
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Spawn a task which is restarted whenever it fails or panics, `task` is called to create the
+    /// task each time it is started, a task which returns `Ok` has finished and is not restarted
+    pub fn spawn<F, Fut>(&self, name: impl Into<String>, mut task: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let name = name.into();
+        let span = info_span!("supervisor", task = %name);
+        let supervisor = self.clone();
+        spawn(async move {
+            let mut delay = supervisor.restart_delay;
+            let mut attempt = 0;
+            loop {
+                let started = Instant::now();
+                let result = select! {
+                    _ = supervisor.token.cancelled() => return,
+                    result = AssertUnwindSafe(task()).catch_unwind() => result,
+                };
+                let reason = match result {
+                    Ok(Ok(())) => {
+                        info!("Task finished");
+                        return;
+                    }
+                    Ok(Err(error)) => format!("failed: {error:#}"),
+                    Err(panic) => format!("panicked: {panic:?}"),
+                };
+                if supervisor.token.is_cancelled() {
+                    return;
+                }
+                if started.elapsed() >= supervisor.max_restart_delay {
+                    delay = supervisor.restart_delay;
+                    attempt = 0;
+                }
+                warn!("Task {reason}, restarting in {delay:?}");
+                supervisor.emit(SupervisorEvent::Stopped {
+                    task: name.clone(),
+                    reason,
+                });
+                select! {
+                    _ = supervisor.token.cancelled() => return,
+                    _ = sleep(delay) => {}
+                }
+                delay = (delay * 2).min(supervisor.max_restart_delay);
+                attempt += 1;
+                info!("Restarting task, attempt {attempt}");
+                supervisor.emit(SupervisorEvent::Restarted {
+                    task: name.clone(),
+                    attempt,
+                });
+            }
+        }.instrument(span));
+    }
+
+    fn emit(&self, event: SupervisorEvent) {
+        send_broadcast(&self.events, event);
+    }
+}
+
+impl Sensor for Supervisor {
+    type Item = SupervisorEvent;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        broadcast_stream(self.events.subscribe())
+    }
+}
+
 /// This error occurs when a device manager is not found in the manager
 #[derive(Debug, Error)]
 #[error("Manager not registered")]
@@ -0,0 +1,46 @@
+//! Dry-run mode, where writes to devices are logged rather than made, eg: to validate new
+//! automations against live sensor data without anything in the house reacting to them
+//!
+//! The mode is switched on by the `dry_run` option of the `Manager`, which hands it to each device
+//! manager through [DeviceManager::set_dry_run](crate::device_manager::DeviceManager::set_dry_run)
+//! before any device is created, each integration passes it on to it's devices, which check it
+//! with [DryRun::intercept] before publishing a write
+
+use crate::automation;
+use std::fmt::Debug;
+use tracing::info;
+
+/// Whether writes to devices are only logged, this is off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DryRun {
+    enabled: bool,
+}
+
+impl DryRun {
+    /// Dry-run mode switched on or off
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Returns true while writes are only logged
+    pub fn is_enabled(self) -> bool {
+        self.enabled
+    }
+
+    /// Returns true if the write should be skipped because dry-run mode is on, the write is logged
+    /// with the automation which made it, so an integration returns success without publishing
+    pub fn intercept(self, device: &str, attribute: &str, value: impl Debug) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let automation = automation::current();
+        info!(
+            target: "dry_run",
+            device,
+            attribute,
+            automation = automation.as_deref().unwrap_or("none"),
+            "skipped write of {value:?}"
+        );
+        true
+    }
+}
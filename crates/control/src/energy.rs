@@ -0,0 +1,212 @@
+//! Accumulating the energy used from instantaneous power readings, eg: to track the cost of
+//! running an appliance
+
+use crate::ReadValue;
+use crate::Sensor;
+use crate::automation::Automation;
+use crate::util::lock;
+use crate::recipes::local_time_at;
+use futures::future::{BoxFuture, ready};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// Readings further apart than this are not integrated, the sensor was likely offline and the
+/// power in between is unknown
+const MAX_GAP: Duration = Duration::from_secs(15 * 60);
+
+/// Integrates the readings of one or more power sensors, in watts, into kWh counters, with daily
+/// and monthly rollups in local time
+///
+/// The counters are only updated while the automations returned by
+/// [`automation`](Self::automation) are running, the readings of every sensor added are summed, eg:
+/// to meter a whole room
+///
+/// Readings are timed on tokio's clock, the wall clock is only read when the meter is created and
+/// advanced with tokio's clock from then on, so days roll over on a paused clock too
+pub struct EnergyMeter {
+    utc_offset_minutes: i32,
+    /// The wall clock time the meter started at, along with when that was on tokio's clock
+    started: (SystemTime, Instant),
+    totals: Mutex<Totals>,
+}
+
+/// The period of an [Energy] counter
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Period {
+    /// All the energy used since the meter was created
+    Total,
+    /// The energy used since local midnight
+    Today,
+    /// The energy used during the previous day
+    Yesterday,
+    /// The energy used since the start of the month
+    ThisMonth,
+    /// The energy used during the previous month
+    LastMonth,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    total: f64,
+    today: f64,
+    yesterday: f64,
+    this_month: f64,
+    last_month: f64,
+    /// The day the current daily counter started, in days since the epoch
+    day: i64,
+    /// The year and month the current monthly counter started
+    month: (i64, i64),
+}
+
+impl EnergyMeter {
+    /// Create a new meter, `utc_offset_minutes` is the offset of local time from UTC and decides
+    /// when days start
+    pub fn new(utc_offset_minutes: i32) -> Self {
+        let now = SystemTime::now();
+        let (day, _) = local_time_at(now, utc_offset_minutes);
+        Self {
+            utc_offset_minutes,
+            started: (now, Instant::now()),
+            totals: Mutex::new(Totals {
+                day,
+                month: month(day),
+                ..Totals::default()
+            }),
+        }
+    }
+
+    /// Start the meter at a wall clock time other than now, eg: just before midnight to test the
+    /// daily rollover, the counters start from this day and month
+    pub fn with_start_time(mut self, time: SystemTime) -> Self {
+        self.started = (time, Instant::now());
+        let (day, _) = local_time_at(time, self.utc_offset_minutes);
+        let mut totals = lock(&self.totals);
+        totals.day = day;
+        totals.month = month(day);
+        drop(totals);
+        self
+    }
+
+    /// Start the meter from a total, eg: one restored from history after a restart
+    pub fn with_total(self, kwh: f64) -> Self {
+        lock(&self.totals).total = kwh;
+        self
+    }
+
+    /// Returns an automation which adds the energy used by the sensor to this meter, `sensor`
+    /// should report power in watts
+    pub fn automation<'a, S>(&'a self, name: impl Into<String>, sensor: &'a S) -> Automation<'a>
+    where
+        S: Sensor + ?Sized,
+        S::Item: Into<f64> + Send,
+    {
+        let last = Arc::new(Mutex::new(None::<(Instant, f64)>));
+        Automation::new(name, sensor.subscribe(), move |watts: S::Item| {
+            let watts = watts.into();
+            let now = Instant::now();
+            let previous = lock(&last).replace((now, watts));
+            if let Some((then, previous)) = previous {
+                let elapsed = now.duration_since(then);
+                // the mean of the two readings is assumed for the time in between
+                if elapsed <= MAX_GAP {
+                    self.add((previous + watts) / 2.0 * elapsed.as_secs_f64() / 3600.0 / 1000.0);
+                }
+            }
+            ready(Ok(()))
+        })
+    }
+
+    /// Returns a counter of the energy used in a period
+    pub fn energy(&self, period: Period) -> Energy<'_> {
+        Energy { meter: self, period }
+    }
+
+    /// Returns a counter of all the energy used since the meter was created
+    pub fn total(&self) -> Energy<'_> {
+        self.energy(Period::Total)
+    }
+
+    /// Returns a counter of the energy used since local midnight
+    pub fn today(&self) -> Energy<'_> {
+        self.energy(Period::Today)
+    }
+
+    /// Returns a counter of the energy used since the start of the month
+    pub fn this_month(&self) -> Energy<'_> {
+        self.energy(Period::ThisMonth)
+    }
+
+    fn add(&self, kwh: f64) {
+        let mut totals = self.rolled_over();
+        totals.total += kwh;
+        totals.today += kwh;
+        totals.this_month += kwh;
+    }
+
+    /// Returns the totals, with the daily and monthly counters reset if a new period has started
+    fn rolled_over(&self) -> MutexGuard<'_, Totals> {
+        let mut totals = lock(&self.totals);
+        let (started, at) = self.started;
+        let (day, _) = local_time_at(started + at.elapsed(), self.utc_offset_minutes);
+        if day != totals.day {
+            totals.yesterday = if day == totals.day + 1 { totals.today } else { 0.0 };
+            totals.today = 0.0;
+            totals.day = day;
+        }
+        let month = month(day);
+        if month != totals.month {
+            let next = if totals.month.1 == 12 {
+                (totals.month.0 + 1, 1)
+            } else {
+                (totals.month.0, totals.month.1 + 1)
+            };
+            totals.last_month = if month == next { totals.this_month } else { 0.0 };
+            totals.this_month = 0.0;
+            totals.month = month;
+        }
+        totals
+    }
+}
+
+/// A counter of the energy used in a [Period], in kWh
+#[derive(Clone, Copy)]
+pub struct Energy<'a> {
+    meter: &'a EnergyMeter,
+    period: Period,
+}
+
+impl ReadValue for Energy<'_> {
+    type Item = f64;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<f64>> {
+        let totals = self.meter.rolled_over();
+        let kwh = match self.period {
+            Period::Total => totals.total,
+            Period::Today => totals.today,
+            Period::Yesterday => totals.yesterday,
+            Period::ThisMonth => totals.this_month,
+            Period::LastMonth => totals.last_month,
+        };
+        Box::pin(async move { Ok(kwh) })
+    }
+}
+
+/// Returns the year and month of a day since the epoch, see
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn month(days: i64) -> (i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
@@ -0,0 +1,206 @@
+use crate::automation::Automation;
+use crate::{ReadValue, Sensor, ToggleValue, WriteValue};
+use crate::util::lock;
+use futures::future::{BoxFuture, ready};
+use futures::stream::BoxStream;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// Wraps a writable value to protect the device behind it from runaway automations, a write is
+/// rejected with a [GuardError] when it would exceed the rate limit or while an [Interlock] which
+/// blocks it is engaged, eg: never turn on the towel heater while the leak sensor is wet.
+///
+/// Reading, subscribing and writing otherwise pass straight through to the wrapped value, so a
+/// guarded value can be used anywhere the value itself could
+pub struct Guarded<'a, V: WriteValue> {
+    value: V,
+    name: String,
+    rate_limit: Option<(usize, Duration)>,
+    writes: Mutex<VecDeque<Instant>>,
+    interlocks: Vec<(&'a Interlock, fn(&V::Item) -> bool)>,
+}
+
+impl<'a, V: WriteValue> Guarded<'a, V> {
+    /// Wrap the given value, `name` identifies it in the errors and logs of rejected writes
+    pub fn new(value: V, name: impl Into<String>) -> Self {
+        Self {
+            value,
+            name: name.into(),
+            rate_limit: None,
+            writes: Mutex::default(),
+            interlocks: Vec::new(),
+        }
+    }
+
+    /// Allow at most `max_writes` writes within any `period`, further writes are rejected until
+    /// the earliest falls out of the period, a toggle counts as a write
+    pub fn with_rate_limit(mut self, max_writes: usize, period: Duration) -> Self {
+        self.rate_limit = Some((max_writes, period));
+        self
+    }
+
+    /// Reject any write for which `blocks` returns true while the interlock is engaged, eg:
+    /// `|on| *on` to only reject turning the value on, a toggle is always rejected since the
+    /// value it would write is unknown
+    pub fn with_interlock(mut self, interlock: &'a Interlock, blocks: fn(&V::Item) -> bool) -> Self {
+        self.interlocks.push((interlock, blocks));
+        self
+    }
+
+    /// Returns the wrapped value
+    pub fn inner(&self) -> &V {
+        &self.value
+    }
+
+    /// Check a write against the interlocks and rate limit, `value` is `None` for a toggle
+    fn check(&self, value: Option<&V::Item>) -> Result<(), GuardError> {
+        let interlocked = self.interlocks.iter().find(|(interlock, blocks)| {
+            interlock.is_engaged() && value.is_none_or(|value| blocks(value))
+        });
+        if let Some((interlock, _)) = interlocked {
+            return Err(GuardError::Interlocked {
+                value: self.name.clone(),
+                interlock: interlock.name.clone(),
+            });
+        }
+        if let Some((max_writes, period)) = self.rate_limit {
+            let now = Instant::now();
+            let mut writes = lock(&self.writes);
+            while writes.front().is_some_and(|write| now.duration_since(*write) >= period) {
+                writes.pop_front();
+            }
+            if writes.len() >= max_writes {
+                return Err(GuardError::RateLimited {
+                    value: self.name.clone(),
+                    max_writes,
+                    period,
+                });
+            }
+            writes.push_back(now);
+        }
+        Ok(())
+    }
+
+    fn rejected(&self, error: GuardError) -> BoxFuture<'_, anyhow::Result<()>> {
+        warn!(value = self.name, "{error}");
+        Box::pin(ready(Err(error.into())))
+    }
+}
+
+/// A write rejected by a [Guarded] value
+#[derive(Debug, Error)]
+pub enum GuardError {
+    /// The value has already been written too often
+    #[error("write to {value} rejected, it was already written {max_writes} times in the last {period:?}")]
+    RateLimited {
+        /// The name of the guarded value
+        value: String,
+        /// The maximum number of writes allowed within the period
+        max_writes: usize,
+        /// The period writes are counted over
+        period: Duration,
+    },
+    /// An interlock blocking the write is engaged
+    #[error("write to {value} rejected, the {interlock} interlock is engaged")]
+    Interlocked {
+        /// The name of the guarded value
+        value: String,
+        /// The name of the interlock
+        interlock: String,
+    },
+}
+
+/// A condition which blocks writes to [Guarded] values while it is engaged, eg: a leak sensor
+/// being wet, one interlock can guard many values.
+///
+/// The interlock starts engaged, so guarded writes are blocked until the state of the condition is
+/// known, it can be set directly or kept up to date with a sensor by the automation returned by
+/// [monitor](Self::monitor)
+#[derive(Debug)]
+pub struct Interlock {
+    name: String,
+    engaged: AtomicBool,
+}
+
+impl Interlock {
+    /// Create a new engaged interlock, `name` identifies it in the errors and logs of rejected
+    /// writes
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            engaged: AtomicBool::new(true),
+        }
+    }
+
+    /// The name of this interlock
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns true while writes blocked by this interlock are rejected
+    pub fn is_engaged(&self) -> bool {
+        self.engaged.load(Ordering::Relaxed)
+    }
+
+    /// Engage or release this interlock
+    pub fn set_engaged(&self, engaged: bool) {
+        if self.engaged.swap(engaged, Ordering::Relaxed) != engaged {
+            debug!(interlock = self.name, engaged, "interlock changed");
+        }
+    }
+
+    /// Returns an automation which engages this interlock while `engaged` returns true for the
+    /// latest reading of the sensor, the interlock stays engaged until the first reading arrives,
+    /// this must be started along with any other automations
+    pub fn monitor<'a, S>(&'a self, sensor: &'a S, engaged: fn(&S::Item) -> bool) -> Automation<'a>
+    where
+        S: Sensor + Sync,
+        S::Item: Send,
+    {
+        Automation::new(format!("{} interlock", self.name), sensor.subscribe(), async move |reading| {
+            self.set_engaged(engaged(&reading));
+            Ok(())
+        })
+    }
+}
+
+impl<V: WriteValue + Sensor> Sensor for Guarded<'_, V> {
+    type Item = <V as Sensor>::Item;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        self.value.subscribe()
+    }
+}
+
+impl<V: WriteValue + ReadValue> ReadValue for Guarded<'_, V> {
+    type Item = <V as ReadValue>::Item;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        self.value.get()
+    }
+}
+
+impl<V: WriteValue + Sync> WriteValue for Guarded<'_, V> {
+    type Item = V::Item;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        match self.check(Some(&value)) {
+            Ok(()) => self.value.set(value),
+            Err(error) => self.rejected(error),
+        }
+    }
+}
+
+impl<V: ToggleValue + Sync> ToggleValue for Guarded<'_, V> {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        match self.check(None) {
+            Ok(()) => self.value.toggle(),
+            Err(error) => self.rejected(error),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! The health of the running system, reported by the device managers and the automation loop, eg:
+//! for the health endpoints of the REST API
+
+use crate::util::lock;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// A shared view of the health of each component of the system and whether it is ready, get it
+/// from [Manager::health](crate::Manager::health)
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    state: Arc<Mutex<HealthState>>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    ready: bool,
+    components: BTreeMap<String, ComponentHealth>,
+}
+
+/// The last reported health of a component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentHealth {
+    /// True if the component is working
+    pub healthy: bool,
+    /// A description of the component's state, eg: `connected to localhost:1883`
+    pub detail: String,
+}
+
+impl Health {
+    /// Register a component, it is unhealthy until it first reports otherwise
+    pub fn reporter(&self, component: impl Into<String>) -> HealthReporter {
+        let component = component.into();
+        lock(&self.state).components.insert(component.clone(), ComponentHealth {
+            healthy: false,
+            detail: "starting".to_string(),
+        });
+        HealthReporter {
+            health: self.clone(),
+            component,
+        }
+    }
+
+    /// True once every device has been created and the manager has started
+    pub fn is_ready(&self) -> bool {
+        lock(&self.state).ready
+    }
+
+    /// Mark the system as ready or not, this is done by the manager when it starts
+    pub fn set_ready(&self, ready: bool) {
+        lock(&self.state).ready = ready;
+    }
+
+    /// True if every registered component is healthy
+    pub fn is_healthy(&self) -> bool {
+        lock(&self.state).components.values().all(|component| component.healthy)
+    }
+
+    /// The last reported health of each component, by name
+    pub fn components(&self) -> BTreeMap<String, ComponentHealth> {
+        lock(&self.state).components.clone()
+    }
+}
+
+/// Reports the health of a single component, see [Health::reporter]
+#[derive(Debug, Clone)]
+pub struct HealthReporter {
+    health: Health,
+    component: String,
+}
+
+impl HealthReporter {
+    /// Report the component is working
+    pub fn healthy(&self, detail: impl Into<String>) {
+        self.report(true, detail.into());
+    }
+
+    /// Report the component is not working, eg: a lost connection
+    pub fn unhealthy(&self, detail: impl Into<String>) {
+        self.report(false, detail.into());
+    }
+
+    fn report(&self, healthy: bool, detail: String) {
+        lock(&self.health.state)
+            .components
+            .insert(self.component.clone(), ComponentHealth { healthy, detail });
+    }
+}
@@ -1,34 +1,53 @@
 #![doc = include_str!("../README.md")]
 
+mod adapters;
+mod aggregate;
 pub mod automation;
 mod button;
+pub mod connection;
 pub mod device;
 pub mod device_manager;
+pub mod dry_run;
+pub mod energy;
+mod guard;
+pub mod health;
+mod manual;
+pub mod recipes;
 pub use reflect;
 mod set;
 mod streams;
+#[doc(hidden)]
+pub mod util;
 mod values;
 
-use crate::automation::Automation;
+use crate::automation::{Automation, AutomationHandle, Command};
 use crate::device::{CreateDeviceError, Device, DeviceSet};
-use crate::device_manager::{DeviceManager, DeviceManagerNotFound};
-use async_scoped::TokioScope;
+use crate::device_manager::{DeviceManager, DeviceManagerNotFound, Supervisor};
+use crate::dry_run::DryRun;
+use crate::health::Health;
 use bon::bon;
+pub use adapters::{Mapped, ValueExt};
+pub use aggregate::{AggregateSensor, Aggregation};
 pub use button::ButtonPressEvent;
-use futures::executor::block_on_stream;
+pub use guard::{GuardError, Guarded, Interlock};
+pub use manual::ManualOverride;
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded};
 use futures::future::{BoxFuture, ready};
-use futures::stream::select_all;
+use futures::stream::{self, BoxStream, FuturesUnordered, SelectAll};
 use futures::{FutureExt, StreamExt};
 pub use set::*;
 use std::any::Any;
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 use std::panic::AssertUnwindSafe;
+use std::pin::pin;
+use std::time::Duration;
 pub use streams::*;
 use tokio::signal::unix::{SignalKind, signal};
-use tokio::spawn;
+use tokio::select;
+use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
-use tracing::{Instrument, debug, error, info, info_span};
+use tracing::{Instrument, debug, error, info, info_span, warn};
 use reflect::{DeviceInfo, DeviceType};
 pub use values::*;
 
@@ -37,6 +56,13 @@ pub use values::*;
 pub struct Manager<'a> {
     device_managers: Vec<Box<dyn DeviceManager>>,
     services: Vec<(String, BoxFuture<'a, anyhow::Result<()>>)>,
+    profile: Option<String>,
+    health: Health,
+    commands: UnboundedSender<Command<'a>>,
+    command_receiver: UnboundedReceiver<Command<'a>>,
+    shutdown: CancellationToken,
+    supervisor: Supervisor,
+    dry_run: DryRun,
 }
 
 /// A service to run in the background
@@ -55,11 +81,39 @@ impl<'a> Manager<'a> {
     pub fn new(
         #[builder(field)] mut device_managers: Vec<Box<dyn DeviceManager>>,
         #[builder(field)] services: Vec<(String, BoxFuture<'a, anyhow::Result<()>>)>,
+        /// The profile to create devices for, eg: `test_rig` for a reduced set of devices on a
+        /// development broker, every device is created if this is not set
+        #[builder(into)] profile: Option<String>,
+        /// The delay before a failed device manager task is first restarted, see [Supervisor]
+        restart_delay: Option<Duration>,
+        /// The longest delay between restarts of a failed device manager task, see [Supervisor]
+        max_restart_delay: Option<Duration>,
+        /// Log writes to devices rather than making them, see [dry_run]
+        #[builder(default)]
+        dry_run: bool,
     ) -> Self {
         device_managers.insert(0, Box::new(()));
+        let dry_run = DryRun::new(dry_run);
+        for manager in &mut device_managers {
+            manager.set_dry_run(dry_run);
+        }
+        let (commands, command_receiver) = unbounded();
+        let shutdown = CancellationToken::new();
+        let supervisor = Supervisor::builder()
+            .token(shutdown.clone())
+            .maybe_restart_delay(restart_delay)
+            .maybe_max_restart_delay(max_restart_delay)
+            .build();
         Self {
             device_managers,
-            services
+            services,
+            profile,
+            health: Health::default(),
+            commands,
+            command_receiver,
+            shutdown,
+            supervisor,
+            dry_run,
         }
     }
 }
@@ -79,6 +133,43 @@ impl<'a, S: manager_builder::State> ManagerBuilder<'a, S> {
 }
 
 impl<'a> Manager<'a> {
+    /// The profile devices are created for, see `DeviceSet` for how devices are assigned to profiles
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// The health of the system, the device managers report their health once started and the
+    /// system is ready once started, eg: for the health endpoints of the REST API
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// A handle for starting, stopping and replacing automations once the manager is running, the
+    /// device managers keep running throughout so changing automations does not reconnect to any
+    /// devices
+    pub fn automations(&self) -> AutomationHandle<'a> {
+        AutomationHandle {
+            commands: self.commands.clone(),
+        }
+    }
+
+    /// A token which shuts the manager down once cancelled, the same as an interrupt signal, eg: to
+    /// stop the manager together with the rest of the host application
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Whether writes to devices are only logged, see [dry_run]
+    pub fn dry_run(&self) -> DryRun {
+        self.dry_run
+    }
+
+    /// The supervisor restarting the tasks of the device managers, subscribe to it for each task
+    /// which stops and is restarted
+    pub fn supervisor(&self) -> Supervisor {
+        self.supervisor.clone()
+    }
+
     /// Fetch the given device manager
     ///
     /// # Errors
@@ -129,81 +220,249 @@ impl<'a> Manager<'a> {
     /// Start the manager, this starts all device managers and automations.
     ///
     /// This is the main entry point for the program and should be called after all devices and
-    /// automations have been set up
+    /// automations have been set up, automations can be changed afterwards through the handle from
+    /// [automations](Self::automations), the manager runs until shutdown or until every
+    /// automation has stopped, every handle has been dropped and every service has finished
+    ///
+    /// Shutdown is on an interrupt or terminate signal or once the token from
+    /// [shutdown_token](Self::shutdown_token) is cancelled, the jobs which are already running are
+    /// given [SHUTDOWN_GRACE] to finish, the services are dropped
     pub async fn start(self, automations: impl IntoIterator<Item = Automation<'a>>) {
         async {
-            let token = CancellationToken::new();
+            let token = self.shutdown;
             debug!("Starting automations");
-            for manager in self.device_managers {
-                manager.start(token.clone());
+            for mut manager in self.device_managers {
+                manager.attach_health(&self.health);
+                manager.supervise(&self.supervisor);
             }
+            // every device is created before the manager is started
+            self.health.set_ready(true);
+            let automation_health = self.health.reporter("automations");
 
-            debug!("Starting signal listener");
-            #[allow(
-                clippy::unwrap_used,
-                reason = "signal creation is not expected to fail"
-            )]
-            spawn(async move {
-                let mut interrupt = signal(SignalKind::interrupt()).unwrap();
-                let mut terminate = signal(SignalKind::terminate()).unwrap();
-                let termination = futures::future::join(interrupt.recv(), terminate.recv());
-                termination.await;
-                token.cancel();
-            });
-
-            TokioScope::scope_and_block(move |scope| {
-                info!("Starting services");
-                for (name, future) in self.services {
-                    let service_span = info_span!("service", service = name.as_str());
-
-                    scope.spawn(async move {
-                        match AssertUnwindSafe(future)
-                            .catch_unwind()
-                            .await {
-                            Err(panic) => {
-                                error!(service = name, "Service panicked: {:?}", panic);
+            // the names and tokens of the running automations, these are cancelled on shutdown
+            let mut running = Running::default();
+            let mut streams: SelectAll<BoxStream<'a, Next<'a>>> = SelectAll::new();
+            for automation in automations {
+                streams.push(running.run(automation));
+            }
+            // the manager's own sender is dropped so the commands end once every handle is dropped
+            drop(self.commands);
+            streams.push(Box::pin(self.command_receiver.map(Next::Command)));
+
+            info!("Starting services");
+            let mut services: FuturesUnordered<_> = self.services
+                .into_iter()
+                .map(|(name, future)| supervise(name, future))
+                .collect();
+            let mut jobs = FuturesUnordered::new();
+            let mut shutdown = pin!(shutdown_signal(&token));
+
+            info!("Starting main automation loop");
+            let report = |count: usize| {
+                automation_health.healthy(format!("{count} automations running"));
+            };
+            report(running.len());
+            // set once every automation has stopped and every handle has been dropped
+            let mut stopped = false;
+            loop {
+                select! {
+                    () = &mut shutdown => {
+                        info!("Shutting down");
+                        token.cancel();
+                        running.stop_all();
+                        break;
+                    }
+                    next = streams.next(), if !stopped => match next {
+                        Some(Next::Job(name, job)) => {
+                            info!("Job started");
+                            jobs.push(run_job(name, job));
+                        }
+                        Some(Next::Command(Command::Start(automation))) => {
+                            info!(automation = automation.name, "Starting automation");
+                            streams.push(running.run(automation));
+                            report(running.len());
+                        }
+                        Some(Next::Command(Command::Replace(automation))) => {
+                            if running.stop(&automation.name) {
+                                info!(automation = automation.name, "Replacing running automation");
+                            } else {
+                                info!(automation = automation.name, "Starting automation");
                             }
-                            Ok(Err(error)) => {
-                                error!(service = name, "Service error: {:?}", error);
+                            streams.push(running.run(automation));
+                            report(running.len());
+                        }
+                        Some(Next::Command(Command::Stop(name))) => {
+                            if running.stop(&name) {
+                                info!(automation = name, "Stopping automation");
+                            } else {
+                                warn!(automation = name, "Cannot stop automation, it is not running");
                             }
-                            Ok(Ok(())) => {
-                                info!(service = name, "Service finished");
+                            report(running.len());
+                        }
+                        Some(Next::Command(Command::StopAll)) => {
+                            info!("Stopping {} automations", running.len());
+                            running.stop_all();
+                            report(running.len());
+                        }
+                        Some(Next::Ended(id)) => {
+                            // an automation which was stopped has already been removed
+                            if let Some(name) = running.ended(id) {
+                                info!(automation = name, "Automation trigger ended");
+                                report(running.len());
                             }
                         }
-                    }.instrument(service_span))
+                        None => stopped = true,
+                    },
+                    Some(()) = jobs.next() => {}
+                    Some(()) = services.next() => {}
                 }
+                if stopped && jobs.is_empty() && services.is_empty() {
+                    break;
+                }
+            }
+            automation_health.unhealthy("every automation has stopped");
 
-                info!("Starting main automation loop");
-                let all_jobs = select_all(automations.into_iter().map(|automation| {
-                    let name = automation.name;
-                    AssertUnwindSafe(automation.stream)
-                        .catch_unwind()
-                        .filter_map(move |result| {
-                            let name = name.clone();
-                            let option = match result {
-                                Ok(job) => Some(job),
-                                Err(panic) => {
-                                    error!(
-                                        automation = name,
-                                        "Automation trigger panicked: {panic:?}"
-                                    );
-                                    None
-                                }
-                            };
-                            ready(option)
-                        })
-                }));
-                for (name, job) in block_on_stream(all_jobs) {
-                    info!("Job started");
-                    scope.spawn(async move {
-                        if let Err(panic) = AssertUnwindSafe(job).catch_unwind().await {
-                            error!(automation = name, "Automation panicked: {:?}", panic);
-                        }
-                    })
+            if !jobs.is_empty() {
+                info!("Waiting for {} running jobs", jobs.len());
+                let finished = timeout(SHUTDOWN_GRACE, async {
+                    while jobs.next().await.is_some() {}
+                });
+                if finished.await.is_err() {
+                    warn!("Abandoning {} jobs which did not finish within {SHUTDOWN_GRACE:?}", jobs.len());
                 }
-            });
+            }
+            if !services.is_empty() {
+                info!("Stopping {} services", services.len());
+            }
         }
         .instrument(info_span!("automation_runner"))
         .await
     }
 }
+
+/// How long the running jobs are given to finish once the manager is shutting down
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// The next thing for the automation loop to do
+enum Next<'a> {
+    /// Run a job of an automation
+    Job(String, BoxFuture<'a, ()>),
+    /// Change the running automations
+    Command(Command<'a>),
+    /// The trigger of the automation registered under the id has ended
+    Ended(u64),
+}
+
+/// The running automations, each is registered under a unique id as several automations may share
+/// a name, eg: a recipe used for more than one room
+#[derive(Default)]
+struct Running {
+    next_id: u64,
+    automations: HashMap<u64, (String, CancellationToken)>,
+}
+
+impl Running {
+    fn len(&self) -> usize {
+        self.automations.len()
+    }
+
+    /// Register an automation as running and return it's jobs, the jobs end once it is stopped or
+    /// it's trigger ends, followed by [Next::Ended]
+    fn run<'a>(&mut self, automation: Automation<'a>) -> BoxStream<'a, Next<'a>> {
+        let id = self.next_id;
+        self.automations.insert(id, (automation.name.clone(), automation.token.clone()));
+        self.next_id += 1;
+        Box::pin(jobs(automation).chain(stream::once(ready(Next::Ended(id)))))
+    }
+
+    /// Remove an automation whose trigger has ended, returning it's name if it was still running
+    fn ended(&mut self, id: u64) -> Option<String> {
+        self.automations.remove(&id).map(|(name, _)| name)
+    }
+
+    /// Stop every running automation with the given name, returning whether any were running
+    fn stop(&mut self, name: &str) -> bool {
+        let before = self.automations.len();
+        self.automations.retain(|_, (running, token)| {
+            if running == name {
+                token.cancel();
+            }
+            running != name
+        });
+        self.automations.len() < before
+    }
+
+    fn stop_all(&mut self) {
+        for (_, (_, token)) in self.automations.drain() {
+            token.cancel();
+        }
+    }
+}
+
+/// The jobs of an automation, these end once it is stopped
+fn jobs<'a>(automation: Automation<'a>) -> BoxStream<'a, Next<'a>> {
+    let name = automation.name;
+    let stopped = automation.token.cancelled_owned();
+    Box::pin(
+        AssertUnwindSafe(automation.stream)
+            .catch_unwind()
+            .take_until(stopped)
+            .filter_map(move |result| {
+                let option = match result {
+                    Ok((job_name, job)) => Some(Next::Job(job_name, job)),
+                    Err(panic) => {
+                        error!(
+                            automation = name,
+                            "Automation trigger panicked: {panic:?}"
+                        );
+                        None
+                    }
+                };
+                ready(option)
+            }),
+    )
+}
+
+/// Run a job of an automation, a panic is logged rather than taking down the automation loop
+async fn run_job(name: String, job: BoxFuture<'_, ()>) {
+    if let Err(panic) = AssertUnwindSafe(job).catch_unwind().await {
+        error!(automation = name, "Automation panicked: {:?}", panic);
+    }
+}
+
+/// Run a service, logging how it finished
+fn supervise(name: String, future: BoxFuture<'_, anyhow::Result<()>>) -> impl Future<Output = ()> + Send + '_ {
+    let service_span = info_span!("service", service = name.as_str());
+    async move {
+        match AssertUnwindSafe(future)
+            .catch_unwind()
+            .await {
+            Err(panic) => {
+                error!(service = name, "Service panicked: {:?}", panic);
+            }
+            Ok(Err(error)) => {
+                error!(service = name, "Service error: {:?}", error);
+            }
+            Ok(Ok(())) => {
+                info!(service = name, "Service finished");
+            }
+        }
+    }.instrument(service_span)
+}
+
+/// Resolves on an interrupt or terminate signal or once the token is cancelled
+async fn shutdown_signal(token: &CancellationToken) {
+    #[allow(
+        clippy::unwrap_used,
+        reason = "signal creation is not expected to fail"
+    )]
+    let (mut interrupt, mut terminate) = (
+        signal(SignalKind::interrupt()).unwrap(),
+        signal(SignalKind::terminate()).unwrap(),
+    );
+    select! {
+        _ = interrupt.recv() => info!("Received interrupt signal"),
+        _ = terminate.recv() => info!("Received terminate signal"),
+        () = token.cancelled() => {}
+    }
+}
@@ -0,0 +1,147 @@
+use crate::automation::Automation;
+use crate::{Sensor, StreamCustomExt, ToggleValue, WriteValue};
+use crate::util::lock;
+use futures::future::{BoxFuture, ready};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// The default length of time after a write by the controller during which a change reported by
+/// the device is considered to be caused by that write
+const DEFAULT_ECHO_WINDOW: Duration = Duration::from_secs(2);
+
+/// Wraps a device value to detect when it has been controlled manually, ie: its state changed
+/// without the controller having written to it, for example a person flipping a light switch.
+///
+/// After a manual change is detected, automations can be suppressed for a configurable period
+/// using [`suppress`](Self::suppress), so that a motion automation does not immediately undo
+/// the change a person just made by hand.
+///
+/// All writes to the value must go through this wrapper for it to tell the difference, and the
+/// automation returned by [`monitor`](Self::monitor) must be passed to `Manager::start`
+pub struct ManualOverride<V> {
+    value: V,
+    period: Duration,
+    echo_window: Duration,
+    state: Mutex<OverrideState>,
+}
+
+#[derive(Default)]
+struct OverrideState {
+    last_write: Option<Instant>,
+    overridden_until: Option<Instant>,
+}
+
+impl<V> ManualOverride<V> {
+    /// Wrap the given value, automations will be suppressed for `period` after a manual change
+    pub fn new(value: V, period: Duration) -> Self {
+        Self {
+            value,
+            period,
+            echo_window: DEFAULT_ECHO_WINDOW,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Set the length of time after a write by the controller during which a reported change is
+    /// still considered to be caused by the controller, defaults to 2 seconds
+    pub fn with_echo_window(mut self, echo_window: Duration) -> Self {
+        self.echo_window = echo_window;
+        self
+    }
+
+    /// Returns the wrapped value
+    pub fn inner(&self) -> &V {
+        &self.value
+    }
+
+    /// Returns true if the value was changed manually within the suppression period
+    pub fn is_overridden(&self) -> bool {
+        self.with_state(|state| state.overridden_until.is_some_and(|until| Instant::now() < until))
+    }
+
+    /// Clear any active override, allowing suppressed automations to run again immediately
+    pub fn clear(&self) {
+        self.with_state(|state| state.overridden_until = None)
+    }
+
+    /// Filters out any items from the given stream while the value is overridden, this is
+    /// intended to wrap the input of any automation that should not fight manual control
+    pub fn suppress<'a, S>(&'a self, stream: S) -> impl Stream<Item = S::Item> + 'a
+    where
+        S: Stream + 'a,
+    {
+        stream.filter(move |_| {
+            let overridden = self.is_overridden();
+            if overridden {
+                debug!("automation trigger suppressed by manual override");
+            }
+            ready(!overridden)
+        })
+    }
+
+    fn record_write(&self) {
+        self.with_state(|state| state.last_write = Some(Instant::now()))
+    }
+
+    pub(crate) fn record_change(&self) {
+        let now = Instant::now();
+        self.with_state(|state| {
+            let echo = state
+                .last_write
+                .is_some_and(|write| now.duration_since(write) <= self.echo_window);
+            if !echo {
+                debug!("manual change detected, suppressing automations for {:?}", self.period);
+                state.overridden_until = Some(now + self.period);
+            }
+        })
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut OverrideState) -> R) -> R {
+        f(&mut lock(&self.state))
+    }
+}
+
+impl<V> ManualOverride<V>
+where
+    V: Sensor + Sync,
+    V::Item: PartialEq + Clone + Send,
+{
+    /// Returns an automation which watches the value for changes not caused by the controller,
+    /// this must be started along with any other automations for overrides to be detected
+    pub fn monitor(&self, name: impl Into<String>) -> Automation<'_> {
+        // the first value only establishes the current state, it is not a change
+        let changes = self.value.subscribe().filter_changes().skip(1);
+        Automation::new(name, changes, async |_| {
+            self.record_change();
+            Ok(())
+        })
+    }
+}
+
+impl<V: Sensor> Sensor for ManualOverride<V> {
+    type Item = V::Item;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        self.value.subscribe()
+    }
+}
+
+impl<V: WriteValue + Sync> WriteValue for ManualOverride<V> {
+    type Item = V::Item;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.record_write();
+        self.value.set(value)
+    }
+}
+
+impl<V: ToggleValue + Sync> ToggleValue for ManualOverride<V> {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.record_write();
+        self.value.toggle()
+    }
+}
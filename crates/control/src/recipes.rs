@@ -0,0 +1,44 @@
+//! Ready-made automations for common scenarios, each recipe wires up the devices given to it
+//! into one or more [Automation](crate::automation::Automation)s which can be passed to
+//! `Manager::start` like any other
+
+mod circadian;
+mod motion_light;
+mod presence;
+mod thermostat;
+
+pub use circadian::*;
+pub use motion_light::*;
+pub use presence::*;
+pub use thermostat::*;
+
+use futures::future::ready;
+use futures::{Stream, StreamExt, stream};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// A stream which yields immediately and then once every `period`
+fn ticks(period: Duration) -> impl Stream<Item = ()> + Send {
+    stream::once(ready(())).chain(stream::unfold((), move |()| async move {
+        sleep(period).await;
+        Some(((), ()))
+    }))
+}
+
+/// Returns the number of days since the epoch along with the time since midnight, in local time
+pub(crate) fn local_time(utc_offset_minutes: i32) -> (i64, Duration) {
+    local_time_at(SystemTime::now(), utc_offset_minutes)
+}
+
+/// Returns the number of days since the epoch along with the time since midnight, in local time,
+/// at the given time
+pub(crate) fn local_time_at(time: SystemTime, utc_offset_minutes: i32) -> (i64, Duration) {
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let local = since_epoch + i64::from(utc_offset_minutes) * 60;
+    (local.div_euclid(DAY), Duration::from_secs(local.rem_euclid(DAY) as u64))
+}
@@ -0,0 +1,93 @@
+use super::{local_time, ticks};
+use crate::automation::Automation;
+use crate::{ColorLight, Percentage};
+use bon::Builder;
+use futures::future::join_all;
+use std::f64::consts::PI;
+use std::time::Duration;
+
+/// The curve followed by circadian lighting over the course of a day.
+///
+/// Between sunrise and sunset the colour temperature and brightness rise towards their maximum
+/// at midday and fall back again, following a sine curve, outside of those hours the minimum
+/// values are used. All times are measured from local midnight
+#[derive(Debug, Clone, Copy, Builder)]
+pub struct Circadian {
+    /// The time of sunrise
+    #[builder(default = Duration::from_secs(7 * 60 * 60))]
+    sunrise: Duration,
+    /// The time of sunset
+    #[builder(default = Duration::from_secs(19 * 60 * 60))]
+    sunset: Duration,
+    /// The offset of local time from UTC in minutes
+    #[builder(default = 0)]
+    utc_offset_minutes: i32,
+    /// The colour temperature used at night, in kelvin
+    #[builder(default = 2200)]
+    min_kelvin: u16,
+    /// The colour temperature used at midday, in kelvin
+    #[builder(default = 5500)]
+    max_kelvin: u16,
+    /// The brightness used at night, as a percentage
+    #[builder(default = 30)]
+    min_brightness: u8,
+    /// The brightness used at midday, as a percentage
+    #[builder(default = 100)]
+    max_brightness: u8,
+    /// How often the lights are updated
+    #[builder(default = Duration::from_secs(5 * 60))]
+    update_interval: Duration,
+}
+
+impl Circadian {
+    /// Returns the colour temperature and brightness for the given time since local midnight
+    pub fn at(&self, time_of_day: Duration) -> (u16, Percentage) {
+        let factor = if self.sunrise <= time_of_day && time_of_day < self.sunset {
+            let progress = (time_of_day - self.sunrise).as_secs_f64()
+                / (self.sunset - self.sunrise).as_secs_f64();
+            (progress * PI).sin()
+        } else {
+            0.0
+        };
+        let interpolate = |min: f64, max: f64| min + (max - min) * factor;
+        let kelvin = interpolate(self.min_kelvin.into(), self.max_kelvin.into()).round() as u16;
+        let brightness = interpolate(self.min_brightness.into(), self.max_brightness.into())
+            .round()
+            .clamp(0.0, 100.0) as u8;
+        (kelvin, Percentage::new(brightness))
+    }
+
+    /// Returns the colour temperature and brightness for the current time
+    pub fn now(&self) -> (u16, Percentage) {
+        self.at(local_time(self.utc_offset_minutes).1)
+    }
+}
+
+/// Builds an automation which periodically adjusts the colour temperature and brightness of the
+/// given lights to follow the [Circadian] curve, lights which are off are left untouched
+pub fn circadian<'a>(
+    name: impl Into<String>,
+    curve: Circadian,
+    lights: &'a [&'a (dyn ColorLight + Sync)],
+) -> Automation<'a> {
+    Automation::new(name, ticks(curve.update_interval), async move |()| {
+        let (kelvin, brightness) = curve.now();
+        let results = join_all(lights.iter().map(|light| async move {
+            if light.is_on().await? {
+                light.set_white(kelvin, brightness).await?;
+            }
+            anyhow::Ok(())
+        }))
+        .await;
+        let errors: Vec<String> = results
+            .into_iter()
+            .filter_map(|result| result.err())
+            .map(|error| error.to_string())
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("failed to update lights: {}", errors.join(", ")))
+        }
+    })
+}
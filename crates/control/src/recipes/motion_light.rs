@@ -0,0 +1,136 @@
+use crate::automation::Automation;
+use crate::{ManualOverride, Sensor, StreamCustomExt, WriteValue};
+use futures::future::{Either, select};
+use futures::stream::{self, BoxStream, select as merge};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::{Sleep, sleep};
+
+/// Only allows a motion light to turn on while the illuminance is at or below a threshold,
+/// so that lights are not turned on in rooms which are already bright
+pub struct LuxGate<'a> {
+    /// The illuminance sensor, in lux
+    pub sensor: &'a (dyn Sensor<Item = u32> + Sync),
+    /// The maximum illuminance at which the light may be turned on
+    pub max_lux: u32,
+}
+
+enum Input {
+    Motion(bool),
+    Lux(u32),
+}
+
+enum Trigger {
+    TurnOn,
+    TurnOff,
+    LightChanged,
+}
+
+/// Builds an automation which turns on the light when motion is detected and turns it off again
+/// once no motion has been reported for `timeout`.
+///
+/// * `sensor` reports `true` when motion/occupancy is detected
+/// * `light` is wrapped in a [ManualOverride], if the light is controlled by hand then motion is
+///   ignored for the override period so the automation does not fight a person
+/// * `lux_gate` optionally prevents the light from turning on while the room is bright enough
+///
+/// The returned automation also monitors the light for manual changes, so there is no need to
+/// start [ManualOverride::monitor] separately
+pub fn motion_light<'a, M, L>(
+    name: impl Into<String>,
+    sensor: &'a M,
+    light: &'a ManualOverride<L>,
+    timeout: Duration,
+    lux_gate: Option<LuxGate<'a>>,
+) -> Automation<'a>
+where
+    M: Sensor<Item = bool> + Sync,
+    L: Sensor<Item = bool> + WriteValue<Item = bool> + Sync,
+{
+    let motion = sensor.subscribe().map(Input::Motion);
+    let (inputs, max_lux): (BoxStream<'a, Input>, _) = match lux_gate {
+        Some(LuxGate { sensor, max_lux }) => (
+            Box::pin(merge(motion, sensor.subscribe().map(Input::Lux))),
+            Some(max_lux),
+        ),
+        None => (Box::pin(motion), None),
+    };
+    let commands = light_commands(inputs, timeout, max_lux);
+    // the first value only establishes the current state, it is not a change
+    let changes = light
+        .inner()
+        .subscribe()
+        .filter_changes()
+        .skip(1)
+        .map(|_| Trigger::LightChanged);
+
+    Automation::new(name, merge(commands, changes), async |trigger| {
+        match trigger {
+            Trigger::LightChanged => {
+                light.record_change();
+                Ok(())
+            }
+            Trigger::TurnOn | Trigger::TurnOff if light.is_overridden() => Ok(()),
+            Trigger::TurnOn => light
+                .set(true)
+                .await
+                .map_err(|err| format!("failed to turn on light: {err}")),
+            Trigger::TurnOff => light
+                .set(false)
+                .await
+                .map_err(|err| format!("failed to turn off light: {err}")),
+        }
+    })
+}
+
+struct State<'a> {
+    inputs: BoxStream<'a, Input>,
+    /// The off timer, only present while the light is on
+    timer: Option<Pin<Box<Sleep>>>,
+    lux: Option<u32>,
+}
+
+/// Turns the raw inputs into on/off commands, restarting the off timer on each motion report
+fn light_commands(
+    inputs: BoxStream<'_, Input>,
+    timeout: Duration,
+    max_lux: Option<u32>,
+) -> impl Stream<Item = Trigger> + Send + '_ {
+    let state = State {
+        inputs,
+        timer: None,
+        lux: None,
+    };
+    stream::unfold(state, move |mut state| async move {
+        loop {
+            // None indicates that the off timer has finished
+            let next = match state.timer.as_mut() {
+                Some(timer) => match select(state.inputs.next(), timer).await {
+                    Either::Left((input, _)) => Some(input),
+                    Either::Right(_) => None,
+                },
+                None => Some(state.inputs.next().await),
+            };
+            let Some(input) = next else {
+                state.timer = None;
+                return Some((Trigger::TurnOff, state));
+            };
+            match input? {
+                Input::Lux(lux) => state.lux = Some(lux),
+                Input::Motion(false) => {}
+                Input::Motion(true) if state.timer.is_some() => {
+                    // motion while the light is on restarts the off timer
+                    state.timer = Some(Box::pin(sleep(timeout)));
+                }
+                Input::Motion(true) => {
+                    let dark = max_lux.is_none_or(|max| state.lux.is_none_or(|lux| lux <= max));
+                    if dark {
+                        state.timer = Some(Box::pin(sleep(timeout)));
+                        return Some((Trigger::TurnOn, state));
+                    }
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,195 @@
+use super::{DAY, local_time_at, ticks};
+use crate::automation::Automation;
+use crate::{Sensor, StreamCustomExt, WriteValue};
+use crate::util::lock;
+use futures::future::join_all;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+use tracing::debug;
+
+/// How often the schedule is checked while away
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// A recorded change replaces any previous change to the same state within this window
+const RECORD_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// A scheduled change of a light's state, at a time since local midnight
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ScheduleEntry {
+    /// The time since local midnight
+    pub time_of_day: Duration,
+    /// Whether the light should be turned on or off
+    pub on: bool,
+}
+
+struct SimulatedLight<'a> {
+    light: &'a (dyn WriteValue<Item = bool> + Sync),
+    schedule: Mutex<Vec<ScheduleEntry>>,
+}
+
+/// Simulates occupancy while the house is empty by turning lights on and off at the times they
+/// would typically be used, with some random jitter so that the pattern is not identical each day.
+///
+/// Each light's schedule can be given up front, or recorded from normal use with
+/// [`recorder`](Self::recorder). Away mode can be toggled at runtime with
+/// [`set_away`](Self::set_away), recording is paused while away so the simulation does not
+/// record itself
+///
+/// The schedules are followed on tokio's clock, the wall clock is only read when the simulation is
+/// created and advanced with tokio's clock from then on, so it can be tested on a paused clock
+pub struct PresenceSimulation<'a> {
+    lights: Vec<SimulatedLight<'a>>,
+    away: AtomicBool,
+    jitter: Duration,
+    utc_offset_minutes: i32,
+    /// The wall clock time the simulation started at, along with when that was on tokio's clock
+    started: (SystemTime, Instant),
+    last_check: Mutex<Option<(i64, Duration)>>,
+}
+
+impl<'a> PresenceSimulation<'a> {
+    /// Create a new simulation, each scheduled change will happen up to `jitter` before or after
+    /// the scheduled time, `utc_offset_minutes` is the offset of local time from UTC
+    pub fn new(jitter: Duration, utc_offset_minutes: i32) -> Self {
+        Self {
+            lights: vec![],
+            away: AtomicBool::new(false),
+            jitter,
+            utc_offset_minutes,
+            started: (SystemTime::now(), Instant::now()),
+            last_check: Mutex::new(None),
+        }
+    }
+
+    /// Start the simulation at a wall clock time other than now, eg: just before midnight to test
+    /// the changes either side of it
+    pub fn with_start_time(mut self, time: SystemTime) -> Self {
+        self.started = (time, Instant::now());
+        self
+    }
+
+    /// Add a light to the simulation with an initial schedule which may be empty if it is to be
+    /// recorded, returns the index of the light to be used with [`recorder`](Self::recorder)
+    pub fn add_light(
+        &mut self,
+        light: &'a (dyn WriteValue<Item = bool> + Sync),
+        schedule: Vec<ScheduleEntry>,
+    ) -> usize {
+        self.lights.push(SimulatedLight {
+            light,
+            schedule: Mutex::new(schedule),
+        });
+        self.lights.len() - 1
+    }
+
+    /// Enable or disable away mode
+    pub fn set_away(&self, away: bool) {
+        debug!("presence simulation away mode: {away}");
+        self.away.store(away, Ordering::Relaxed);
+    }
+
+    /// Returns true if away mode is enabled
+    pub fn is_away(&self) -> bool {
+        self.away.load(Ordering::Relaxed)
+    }
+
+    /// Returns the current schedule of the light with the given index
+    pub fn schedule(&self, index: usize) -> Vec<ScheduleEntry> {
+        self.lights
+            .get(index)
+            .map(|light| lock(&light.schedule).clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns an automation which records the changes in state of the light with the given
+    /// index into its schedule, `sensor` should report the state of the same light
+    pub fn recorder<'s>(
+        &'s self,
+        name: impl Into<String>,
+        index: usize,
+        sensor: &'s (impl Sensor<Item = bool> + Sync),
+    ) -> Automation<'s> {
+        let changes = sensor.subscribe().filter_changes();
+        Automation::new(name, changes, async move |on| {
+            if self.is_away() {
+                return Ok(());
+            }
+            let Some(light) = self.lights.get(index) else {
+                return Err(format!("no light with index {index}"));
+            };
+            let (_, time_of_day) = self.local_time();
+            let mut schedule = lock(&light.schedule);
+            schedule.retain(|entry| {
+                entry.on != on || distance(entry.time_of_day, time_of_day) > RECORD_WINDOW
+            });
+            schedule.push(ScheduleEntry { time_of_day, on });
+            schedule.sort_by_key(|entry| entry.time_of_day);
+            Ok(())
+        })
+    }
+
+    /// Returns an automation which replays the schedules while away mode is enabled
+    pub fn automation(&self, name: impl Into<String>) -> Automation<'_> {
+        Automation::new(name, ticks(CHECK_INTERVAL), async |()| {
+            let now = self.local_time();
+            let Some(last) = lock(&self.last_check).replace(now) else {
+                return Ok(());
+            };
+            if !self.is_away() {
+                return Ok(());
+            }
+            // jitter can move an entry into the day before or after the one it's scheduled on, so
+            // the entries of the days either side are checked too, eg: at the first check after
+            // midnight the entries of the day before which were due before midnight
+            let days = now.0 - 1..=now.0 + 1;
+            let changes = self.lights.iter().enumerate().flat_map(|(index, light)| {
+                let schedule = lock(&light.schedule);
+                days.clone()
+                    .flat_map(|day| schedule.iter().enumerate().map(move |entry| (day, entry)))
+                    .filter(|(day, (entry_index, entry))| {
+                        let time = self.jittered(*day, index, *entry_index, entry.time_of_day);
+                        last < time && time <= now
+                    })
+                    .map(|(_, (_, entry))| (light.light, entry.on))
+                    .collect::<Vec<_>>()
+            });
+            let results = join_all(changes.map(|(light, on)| light.set(on))).await;
+            let failures = results.into_iter().filter(Result::is_err).count();
+            if failures == 0 {
+                Ok(())
+            } else {
+                Err(format!("failed to update {failures} lights"))
+            }
+        })
+    }
+
+    /// Returns the number of days since the epoch along with the time since midnight, in local
+    /// time, on tokio's clock
+    fn local_time(&self) -> (i64, Duration) {
+        let (started, at) = self.started;
+        local_time_at(started + at.elapsed(), self.utc_offset_minutes)
+    }
+
+    /// Offsets the scheduled time by a pseudo-random amount, this is stable for a given entry on
+    /// a given day so that repeated checks agree on when the change should happen, the day and
+    /// time returned are normalised so the time is within a day
+    fn jittered(&self, day: i64, light: usize, entry: usize, time_of_day: Duration) -> (i64, Duration) {
+        let mut seconds = time_of_day.as_secs() as i64;
+        if !self.jitter.is_zero() {
+            let mut hasher = DefaultHasher::new();
+            (day, light, entry).hash(&mut hasher);
+            let range = self.jitter.as_secs() * 2 + 1;
+            seconds += (hasher.finish() % range) as i64 - self.jitter.as_secs() as i64;
+        }
+        (
+            day + seconds.div_euclid(DAY),
+            Duration::from_secs(seconds.rem_euclid(DAY) as u64),
+        )
+    }
+}
+
+fn distance(a: Duration, b: Duration) -> Duration {
+    if a > b { a - b } else { b - a }
+}
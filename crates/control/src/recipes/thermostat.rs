@@ -0,0 +1,201 @@
+use crate::automation::Automation;
+use crate::{Sensor, StreamCustomExt, WriteValue};
+use futures::future::{Either, ready, select};
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::{Sleep, sleep};
+
+/// Builds an automation which switches a heater to keep a temperature close to the setpoint.
+///
+/// The heater is turned on when the temperature drops below `setpoint - hysteresis` and turned
+/// off when it rises above `setpoint + hysteresis`, between those values the heater is left as
+/// is, which prevents it from rapidly switching on and off around the setpoint.
+///
+/// For a tighter control loop see [pid_thermostat]
+pub fn thermostat<'a, T, H>(
+    name: impl Into<String>,
+    temperature_sensor: &'a T,
+    heater_switch: &'a H,
+    setpoint: f64,
+    hysteresis: f64,
+) -> Automation<'a>
+where
+    T: Sensor + Sync,
+    T::Item: Into<f64>,
+    H: WriteValue<Item = bool> + Sync,
+{
+    let commands = temperature_sensor
+        .subscribe()
+        .filter_map(move |temperature| {
+            let temperature: f64 = temperature.into();
+            ready(if temperature < setpoint - hysteresis {
+                Some(true)
+            } else if temperature > setpoint + hysteresis {
+                Some(false)
+            } else {
+                None
+            })
+        })
+        .filter_changes();
+    switch_heater(name, commands, heater_switch)
+}
+
+/// Gains for a [PID controller](https://en.wikipedia.org/wiki/Proportional%E2%80%93integral%E2%80%93derivative_controller)
+#[derive(Debug, Clone, Copy)]
+pub struct PidGains {
+    /// The proportional gain
+    pub kp: f64,
+    /// The integral gain, per second
+    pub ki: f64,
+    /// The derivative gain, in seconds
+    pub kd: f64,
+}
+
+/// A PID controller producing an output between 0 and 1
+#[derive(Debug, Clone, Copy)]
+pub struct PidController {
+    gains: PidGains,
+    integral: f64,
+    last_error: Option<f64>,
+}
+
+impl PidController {
+    /// Create a new controller with the given gains
+    pub fn new(gains: PidGains) -> Self {
+        Self {
+            gains,
+            integral: 0.0,
+            last_error: None,
+        }
+    }
+
+    /// Update the controller with the current error, which is `setpoint - measurement`, and the
+    /// time since the last update, returns the output between 0 and 1
+    pub fn update(&mut self, error: f64, elapsed: Duration) -> f64 {
+        let PidGains { kp, ki, kd } = self.gains;
+        let dt = elapsed.as_secs_f64();
+        let derivative = match self.last_error {
+            Some(last) if dt > 0.0 => (error - last) / dt,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+        let integral = self.integral + error * dt;
+        let output = kp * error + ki * integral + kd * derivative;
+        // only accumulate the integral while the output is not saturated to prevent wind-up
+        if (0.0..=1.0).contains(&output) || ki == 0.0 {
+            self.integral = integral;
+        }
+        output.clamp(0.0, 1.0)
+    }
+}
+
+/// Builds an automation which controls a heater using a PID controller, since most heaters can
+/// only be switched on and off, the heater is switched on for a portion of each `cycle`
+/// proportional to the controller's output, eg: an output of 0.25 with a 10-minute cycle will
+/// run the heater for 2.5 minutes of every 10.
+///
+/// The output is recalculated at the start of each cycle using the latest temperature
+pub fn pid_thermostat<'a, T, H>(
+    name: impl Into<String>,
+    temperature_sensor: &'a T,
+    heater_switch: &'a H,
+    setpoint: f64,
+    gains: PidGains,
+    cycle: Duration,
+) -> Automation<'a>
+where
+    T: Sensor + Sync,
+    T::Item: Into<f64>,
+    H: WriteValue<Item = bool> + Sync,
+{
+    let temperatures = Box::pin(temperature_sensor.subscribe().map(Into::into));
+    let commands = duty_cycle(temperatures, PidController::new(gains), setpoint, cycle);
+    switch_heater(name, commands, heater_switch)
+}
+
+fn switch_heater<'a, H>(
+    name: impl Into<String>,
+    commands: impl Stream<Item = bool> + Send + 'a,
+    heater: &'a H,
+) -> Automation<'a>
+where
+    H: WriteValue<Item = bool> + Sync,
+{
+    Automation::new(name, commands, async |on| {
+        heater
+            .set(on)
+            .await
+            .map_err(|err| format!("failed to switch heater: {err}"))
+    })
+}
+
+enum Phase {
+    /// Waiting for the start of the next cycle
+    Idle,
+    /// The heater is on, it will turn off after the timer, leaving the rest of the cycle idle
+    Heating { remaining: Duration },
+}
+
+struct DutyCycle<'a> {
+    temperatures: BoxStream<'a, f64>,
+    latest: Option<f64>,
+    pid: PidController,
+    timer: Pin<Box<Sleep>>,
+    phase: Phase,
+}
+
+fn duty_cycle(
+    temperatures: BoxStream<'_, f64>,
+    pid: PidController,
+    setpoint: f64,
+    cycle: Duration,
+) -> impl Stream<Item = bool> + Send + '_ {
+    let state = DutyCycle {
+        temperatures,
+        latest: None,
+        pid,
+        timer: Box::pin(sleep(Duration::ZERO)),
+        phase: Phase::Idle,
+    };
+    stream::unfold(state, move |mut state| async move {
+        loop {
+            if state.latest.is_none() {
+                // nothing can be calculated until the first temperature has been received
+                state.latest = Some(state.temperatures.next().await?);
+            }
+            let next = match select(state.temperatures.next(), &mut state.timer).await {
+                Either::Left((temperature, _)) => Some(temperature),
+                Either::Right(_) => None,
+            };
+            if let Some(temperature) = next {
+                state.latest = Some(temperature?);
+                continue;
+            }
+            match state.phase {
+                Phase::Idle => {
+                    let temperature = state.latest.unwrap_or(setpoint);
+                    let duty = state.pid.update(setpoint - temperature, cycle);
+                    let on_time = cycle.mul_f64(duty);
+                    if on_time.is_zero() {
+                        state.timer = Box::pin(sleep(cycle));
+                        return Some((false, state));
+                    }
+                    state.timer = Box::pin(sleep(on_time));
+                    state.phase = Phase::Heating {
+                        remaining: cycle.saturating_sub(on_time),
+                    };
+                    return Some((true, state));
+                }
+                Phase::Heating { remaining } => {
+                    state.timer = Box::pin(sleep(remaining));
+                    state.phase = Phase::Idle;
+                    if !remaining.is_zero() {
+                        return Some((false, state));
+                    }
+                }
+            }
+        }
+    })
+}
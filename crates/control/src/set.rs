@@ -1,20 +1,47 @@
-use crate::{ToggleValue, WriteValue};
+use crate::values::FakeToggle;
+use crate::{ReadValue, ToggleValue, WriteValue};
 use futures::future::{join_all, BoxFuture};
 use futures::FutureExt;
 use anyhow::Result;
+use std::ops::Not;
+
+type BoxToggle<'a, T> = Box<dyn ToggleValue<Item = T> + Send + Sync + 'a>;
+type BoxWrite<'a, T> = Box<dyn WriteValue<Item = T> + Send + Sync + 'a>;
 
 /// A set of many toggle values which can be operated as one
+///
+/// Members may be a mix of borrowed and owned values, values without a native toggle can be
+/// included with [with_fake_toggle](Self::with_fake_toggle)
 pub struct ToggleSet<'a, T: Clone> {
-    switches: Vec<&'a (dyn ToggleValue<Item = T> + Send + Sync)>,
+    switches: Vec<BoxToggle<'a, T>>,
 }
 
-impl<'a, T: Clone> ToggleSet<'a, T> {
+impl<'a, T: Clone + 'a> ToggleSet<'a, T> {
     /// create a new set
     pub fn new(switches: impl IntoIterator<Item = &'a (dyn ToggleValue<Item = T> + Send + Sync)>) -> Self {
         Self {
-            switches: switches.into_iter().collect(),
+            switches: switches
+                .into_iter()
+                .map(|switch| Box::new(switch) as BoxToggle<'a, T>)
+                .collect(),
         }
     }
+
+    /// Add a member to this set, this may be owned or borrowed
+    pub fn with(mut self, switch: impl ToggleValue<Item = T> + Send + Sync + 'a) -> Self {
+        self.switches.push(Box::new(switch));
+        self
+    }
+
+    /// Add a value which can be read and written but not toggled natively, toggling the set
+    /// will read the value and write its inverse
+    pub fn with_fake_toggle<V>(self, value: V) -> Self
+    where
+        V: ReadValue + WriteValue<Item = T> + Send + Sync + 'a,
+        <V as ReadValue>::Item: Not<Output = T>,
+    {
+        self.with(FakeToggle::new(value))
+    }
 }
 
 impl<T: Clone> WriteValue for ToggleSet<'_, T> {
@@ -39,16 +66,25 @@ impl<T: Clone> ToggleValue for ToggleSet<'_, T> {
 
 /// A set of many write values which can be operated as one
 pub struct WriteSet<'a, T: Clone> {
-    switches: Vec<&'a dyn ToggleValue<Item = T>>,
+    switches: Vec<BoxWrite<'a, T>>,
 }
 
-impl<'a, T: Clone> WriteSet<'a, T> {
+impl<'a, T: Clone + 'a> WriteSet<'a, T> {
     /// create a new set
-    pub fn new(switches: impl IntoIterator<Item = &'a dyn ToggleValue<Item = T>>) -> Self {
+    pub fn new(switches: impl IntoIterator<Item = &'a (dyn WriteValue<Item = T> + Send + Sync)>) -> Self {
         Self {
-            switches: switches.into_iter().collect(),
+            switches: switches
+                .into_iter()
+                .map(|switch| Box::new(switch) as BoxWrite<'a, T>)
+                .collect(),
         }
     }
+
+    /// Add a member to this set, this may be owned or borrowed
+    pub fn with(mut self, switch: impl WriteValue<Item = T> + Send + Sync + 'a) -> Self {
+        self.switches.push(Box::new(switch));
+        self
+    }
 }
 
 impl<T: Clone> WriteValue for WriteSet<'_, T> {
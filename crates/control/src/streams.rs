@@ -1,11 +1,15 @@
+use crate::aggregate::{Aggregation, Windowed};
 use crate::button::ButtonPressStream;
 use crate::ButtonEvent;
 use futures::{Stream, StreamExt};
 use pin_project::pin_project;
+use std::collections::VecDeque;
 use std::future::ready;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
 use thiserror::Error;
+use tokio::time::Instant;
 
 /// some helpers provided as extensions to stream since streams are quite useful as input for
 /// automations
@@ -29,6 +33,15 @@ pub trait StreamCustomExt: Stream + Sized {
         self.filter(move |v| ready(value.eq(v)))
     }
 
+    /// filter out any values for which the condition is false, eg: to only act on a sensor while
+    /// another condition holds, this is used by the `automation!` macro's `when`
+    fn filter_when<F>(self, condition: F) -> impl Stream<Item = Self::Item>
+    where
+        F: Fn(&Self::Item) -> bool,
+    {
+        self.filter(move |value| ready(condition(value)))
+    }
+
     /// next_eq wait for the next value in the stream which equals the given value,
     /// eg: waiting for a certain value from an enum sensor like a button
     fn next_eq(&mut self, value: Self::Item) -> impl Future<Output = Option<Self::Item>>
@@ -67,6 +80,74 @@ pub trait StreamCustomExt: Stream + Sized {
     {
         ButtonPressStream::new(self)
     }
+
+    /// Emits the rate of change of a numeric stream in units per second, measured over the given
+    /// window, eg: a temperature sensor would yield °C/s, multiply by 60 for °C/min.
+    ///
+    /// The rate is calculated between the newest value and the last value received before the
+    /// window began, or the oldest value if none is that old, so the rate spans at least the whole
+    /// window once enough values have been received. No value is emitted until at least two values
+    /// have been received
+    fn derivative(self, window: Duration) -> impl Stream<Item = f64>
+    where
+        Self::Item: Into<f64>,
+    {
+        Derivative {
+            stream: self,
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Emits an aggregate of the numeric values received over a sliding window of time, eg: the
+    /// average temperature over the last hour, a new aggregate is emitted for each value received.
+    ///
+    /// To expose the aggregate as a [Sensor](crate::Sensor), see [AggregateSensor](crate::AggregateSensor)
+    fn aggregate(self, window: Duration, aggregation: Aggregation) -> impl Stream<Item = f64>
+    where
+        Self::Item: Into<f64>,
+    {
+        Windowed::new(self, window, aggregation)
+    }
+}
+
+#[pin_project]
+struct Derivative<S: Stream> {
+    #[pin]
+    stream: S,
+    window: Duration,
+    samples: VecDeque<(Instant, f64)>,
+}
+
+impl<S: Stream> Stream for Derivative<S>
+where
+    S::Item: Into<f64>,
+{
+    type Item = f64;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+            let now = Instant::now();
+            let value = item.into();
+            // keep a single sample from before the window so the rate always spans the window
+            while this.samples.len() > 1
+                && this.samples.get(1).is_some_and(|(time, _)| now.duration_since(*time) >= *this.window)
+            {
+                this.samples.pop_front();
+            }
+            this.samples.push_back((now, value));
+            if let Some((first_time, first_value)) = this.samples.front() {
+                let elapsed = now.duration_since(*first_time).as_secs_f64();
+                if elapsed > 0.0 {
+                    return Poll::Ready(Some((value - first_value) / elapsed));
+                }
+            }
+        }
+    }
 }
 
 #[pin_project]
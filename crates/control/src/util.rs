@@ -0,0 +1,38 @@
+//! Helpers shared by the integrations and the code generated by the macros, these are not part of
+//! the public API
+
+use futures::stream::{BoxStream, unfold};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Lock the mutex, recovering the data from a poisoned lock, a poisoned lock only means another
+/// thread panicked mid-update, the data is still usable
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Send the value to every current subscriber of the channel, sending only fails when nobody is
+/// subscribed, which is not an error, so it's ignored
+pub fn send_broadcast<T>(sender: &broadcast::Sender<T>, value: T) {
+    let _ = sender.send(value);
+}
+
+/// The values received on a broadcast channel as a stream, eg: to implement [Sensor](crate::Sensor),
+/// a receiver which falls behind skips the values it missed with a warning, the stream ends once
+/// every sender has been dropped
+pub fn broadcast_stream<T>(receiver: broadcast::Receiver<T>) -> BoxStream<'static, T>
+where
+    T: Clone + Send + 'static,
+{
+    Box::pin(unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(value) => return Some((value, receiver)),
+                Err(RecvError::Lagged(missed)) => warn!("missed {missed} broadcast values"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }))
+}
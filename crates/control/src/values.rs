@@ -50,6 +50,17 @@ pub trait ToggleValue: WriteValue {
 
 impl<T> dyn ToggleValue<Item = T> {}
 
+/// ColorLight represents a light which supports setting its colour temperature, this allows
+/// lights from different integrations to be controlled together by helpers such as
+/// [circadian](crate::recipes::circadian)
+pub trait ColorLight {
+    /// Returns true if the light is currently on
+    fn is_on(&self) -> BoxFuture<'_, anyhow::Result<bool>>;
+    /// Sets the colour temperature in kelvin along with the brightness, this must not change
+    /// whether the light is on or off
+    fn set_white(&self, kelvin: u16, brightness: Percentage) -> BoxFuture<'_, anyhow::Result<()>>;
+}
+
 /// Group can be used to group multiple writable values together to write to each in a single call
 pub struct Group<'a, T>(Vec<&'a T>);
 
@@ -109,6 +120,18 @@ where
     V: WriteValue,
     <V as ReadValue>::Item: Not<Output = <V as WriteValue>::Item>;
 
+impl<V> FakeToggle<V>
+where
+    V: ReadValue + Sync,
+    V: WriteValue,
+    <V as ReadValue>::Item: Not<Output = <V as WriteValue>::Item>,
+{
+    /// Wrap the given value to emulate a toggle
+    pub fn new(value: V) -> Self {
+        Self(value)
+    }
+}
+
 impl<V> WriteValue for FakeToggle<V>
 where
     V: ReadValue + Sync,
@@ -0,0 +1,133 @@
+//! Buffers items and writes them in batches, sensors which report every second would
+//! otherwise cause a write per reading
+
+use crate::{Backend, Error};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::spawn;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::time::interval;
+use tracing::{error, warn};
+
+/// Controls how items are batched
+#[derive(Debug, Clone, Copy)]
+pub struct Batching {
+    /// Items are written as soon as this many are buffered
+    pub size: usize,
+    /// Buffered items are written at least this often
+    pub interval: Duration,
+    /// The most items kept while the backend is failing, the oldest are dropped first
+    pub max_buffered: usize,
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self {
+            size: 500,
+            interval: Duration::from_secs(1),
+            max_buffered: 10_000,
+        }
+    }
+}
+
+/// Buffers items and writes them to a [Backend] in the background, failed writes are retried
+/// on each interval
+pub struct Writer<B: Backend> {
+    backend: Arc<B>,
+    batching: Batching,
+    /// the background task is started on first use since the runtime may not be running yet
+    sender: OnceLock<UnboundedSender<B::Item>>,
+}
+
+impl<B: Backend> Writer<B> {
+    /// Create a new writer, nothing is written until the first item
+    pub fn new(backend: B, batching: Batching) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            batching,
+            sender: OnceLock::new(),
+        }
+    }
+
+    /// The backend items are written to
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Buffer the item to be written, failures to write are logged rather than returned since
+    /// the item is written later
+    pub fn write(&self, item: B::Item) -> Result<(), Error> {
+        let sender = self.sender.get_or_init(|| {
+            let (sender, receiver) = unbounded_channel();
+            spawn(run(self.backend.clone(), self.batching, receiver));
+            sender
+        });
+        sender.send(item).map_err(|_| Error::Stopped)
+    }
+}
+
+async fn run<B: Backend>(backend: Arc<B>, batching: Batching, mut items: UnboundedReceiver<B::Item>) {
+    let mut buffer = Buffer {
+        backend,
+        batching,
+        items: Vec::new(),
+        failing: false,
+    };
+    let mut ticks = interval(batching.interval);
+    loop {
+        tokio::select! {
+            item = items.recv() => {
+                let Some(item) = item else {
+                    // every writer has been dropped
+                    buffer.flush().await;
+                    break;
+                };
+                buffer.items.push(item);
+                // while the backend is failing, writes are only retried on each tick
+                if buffer.items.len() >= batching.size && !buffer.failing {
+                    buffer.flush().await;
+                }
+            }
+            _ = ticks.tick() => buffer.flush().await,
+        }
+    }
+}
+
+struct Buffer<B: Backend> {
+    backend: Arc<B>,
+    batching: Batching,
+    items: Vec<B::Item>,
+    failing: bool,
+}
+
+impl<B: Backend> Buffer<B> {
+    async fn flush(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        match self.backend.write(&self.items).await {
+            Ok(()) => {
+                if self.failing {
+                    warn!("writing to {} recovered", self.backend.name());
+                }
+                self.failing = false;
+                self.items.clear();
+            }
+            Err(error) => {
+                if !self.failing {
+                    error!(
+                        "failed to write {} items to {}, retrying: {error:#}",
+                        self.items.len(),
+                        self.backend.name()
+                    );
+                }
+                self.failing = true;
+                if self.items.len() > self.batching.max_buffered {
+                    let excess = self.items.len() - self.batching.max_buffered;
+                    warn!("dropping {excess} items which could not be written");
+                    self.items.drain(..excess);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Reducing the number of samples recorded from sensors which update often
+
+use control::reflect::value::Value;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How updates from an attribute are reduced before being recorded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Downsample {
+    /// Record the mean of each window of the given length
+    Mean(Duration),
+    /// Record the minimum of each window of the given length
+    Min(Duration),
+    /// Record the maximum of each window of the given length
+    Max(Duration),
+    /// Record only values which differ from the last one recorded, at most once per interval, if
+    /// the value changes again within the interval the latest value is recorded once it passes
+    OnChange {
+        /// The shortest time between two recorded values
+        min_interval: Duration,
+        /// Numbers which differ from the last one recorded by less than this are not a change, eg:
+        /// to ignore the noise of a sensor, zero records any change
+        threshold: f64,
+        /// The last value is recorded again after this long without a change, so a sensor which
+        /// doesn't change still shows up in the history
+        max_interval: Option<Duration>,
+    },
+}
+
+/// Applies a [Downsample] rule to the updates of a single attribute, values which are not numbers
+/// can't be aggregated so are recorded on change when a window rule is used
+#[derive(Debug, Default)]
+pub(crate) struct Sampler {
+    rule: Option<Downsample>,
+    window: Option<Window>,
+    /// The last value recorded and when
+    last: Option<(Instant, Value)>,
+    /// A changed value waiting for the minimum interval to pass
+    pending: Option<Value>,
+}
+
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    count: u32,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Sampler {
+    pub(crate) fn new(rule: Option<Downsample>) -> Self {
+        Self {
+            rule,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the value to record for this update, if any
+    pub(crate) fn update(&mut self, value: Value, now: Instant) -> Option<Value> {
+        let (period, min_interval, threshold) = match self.rule {
+            None => return Some(value),
+            Some(Downsample::OnChange { min_interval, threshold, .. }) => (None, min_interval, threshold),
+            Some(Downsample::Mean(period) | Downsample::Min(period) | Downsample::Max(period)) => {
+                (Some(period), Duration::ZERO, 0.0)
+            }
+        };
+        let Some(number) = period.and(number(&value)) else {
+            return self.changed(value, min_interval, threshold, now);
+        };
+        // a window is only recorded once it has ended, the new value starts the next one
+        let ended = self.tick(now);
+        let window = self.window.get_or_insert(Window {
+            start: now,
+            count: 0,
+            sum: 0.0,
+            min: number,
+            max: number,
+        });
+        window.count += 1;
+        window.sum += number;
+        window.min = window.min.min(number);
+        window.max = window.max.max(number);
+        ended
+    }
+
+    /// Returns the value to record once time has passed without an update, eg: the end of a window
+    pub(crate) fn tick(&mut self, now: Instant) -> Option<Value> {
+        match self.rule? {
+            Downsample::OnChange { min_interval, max_interval, .. } => {
+                let since = self.last.as_ref().map(|(at, _)| now.duration_since(*at));
+                if since.is_some_and(|since| since < min_interval) {
+                    return None;
+                }
+                let value = match self.pending.take() {
+                    Some(value) => value,
+                    None if since.zip(max_interval).is_some_and(|(since, max)| since >= max) => {
+                        self.last.as_ref()?.1.clone()
+                    }
+                    None => return None,
+                };
+                self.last = Some((now, value.clone()));
+                Some(value)
+            }
+            Downsample::Mean(period) | Downsample::Min(period) | Downsample::Max(period) => {
+                if self.window.as_ref()?.start + period > now {
+                    return None;
+                }
+                self.finish()
+            }
+        }
+    }
+
+    /// Returns the value of any partial window or pending change, eg: when recording stops
+    pub(crate) fn finish(&mut self) -> Option<Value> {
+        if let Some(value) = self.pending.take() {
+            return Some(value);
+        }
+        let window = self.window.take()?;
+        let value = match self.rule? {
+            Downsample::Mean(_) => window.sum / f64::from(window.count),
+            Downsample::Min(_) => window.min,
+            Downsample::Max(_) => window.max,
+            Downsample::OnChange { .. } => return None,
+        };
+        Some(Value::Float(value))
+    }
+
+    fn changed(
+        &mut self,
+        value: Value,
+        min_interval: Duration,
+        threshold: f64,
+        now: Instant,
+    ) -> Option<Value> {
+        match &self.last {
+            Some((_, last)) if unchanged(last, &value, threshold) => {
+                // changed back before the pending value was recorded
+                self.pending = None;
+                None
+            }
+            Some((at, _)) if now.duration_since(*at) < min_interval => {
+                self.pending = Some(value);
+                None
+            }
+            _ => {
+                self.pending = None;
+                self.last = Some((now, value.clone()));
+                Some(value)
+            }
+        }
+    }
+}
+
+/// Returns true if the value is the same as the last one recorded, or a number within the
+/// threshold of it
+fn unchanged(last: &Value, value: &Value, threshold: f64) -> bool {
+    match (number(last), number(value)) {
+        (Some(last), Some(value)) => (last - value).abs() < threshold || last == value,
+        _ => last == value,
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        #[allow(clippy::cast_precision_loss, reason = "sensor readings are small enough")]
+        Value::Int(value) => Some(*value as f64),
+        Value::Float(value) => Some(*value),
+        _ => None,
+    }
+}
@@ -0,0 +1,106 @@
+//! Exporting the history of an attribute to files, eg: for offline analysis in a spreadsheet
+
+use crate::{Error, Query};
+use control::reflect::value::Value;
+use std::io::Write;
+use std::ops::Range;
+use std::time::SystemTime;
+
+/// The format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A CSV file with `timestamp` and `value` columns, timestamps are in RFC 3339
+    Csv,
+    /// A Parquet file with `timestamp`, `value` and `text` columns, numbers and booleans are in
+    /// `value` and any other values in `text`
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Write the samples of an attribute recorded in the given time range, oldest first, returns the
+/// number of samples written
+pub async fn export<Q: Query + ?Sized>(
+    history: &Q,
+    device: &str,
+    attribute: &str,
+    range: Range<SystemTime>,
+    format: Format,
+    output: impl Write + Send,
+) -> Result<usize, Error> {
+    let samples = history.range(device, attribute, range).await?;
+    match format {
+        Format::Csv => csv(&samples, output)?,
+        #[cfg(feature = "parquet")]
+        Format::Parquet => parquet(&samples, output)?,
+    }
+    Ok(samples.len())
+}
+
+fn csv(samples: &[(SystemTime, Value)], mut output: impl Write) -> Result<(), Error> {
+    writeln!(output, "timestamp,value")?;
+    for (timestamp, value) in samples {
+        write!(output, "{},", humantime::format_rfc3339_millis(*timestamp))?;
+        match value {
+            Value::Bool(value) => write!(output, "{value}")?,
+            Value::Int(value) => write!(output, "{value}")?,
+            Value::Float(value) => write!(output, "{value}")?,
+            Value::String(value) => write!(output, "\"{}\"", value.replace('"', "\"\""))?,
+            Value::None => {}
+        }
+        writeln!(output)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn parquet(samples: &[(SystemTime, Value)], output: impl Write + Send) -> Result<(), Error> {
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("value", DataType::Float64, true),
+        Field::new("text", DataType::Utf8, true),
+    ]));
+    let timestamps: TimestampMillisecondArray = samples
+        .iter()
+        .map(|(timestamp, _)| crate::millis(*timestamp))
+        .collect::<Vec<_>>()
+        .into();
+    #[allow(clippy::cast_precision_loss, reason = "sensor readings are small enough")]
+    let values: Float64Array = samples
+        .iter()
+        .map(|(_, value)| match value {
+            Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+            Value::Int(value) => Some(*value as f64),
+            Value::Float(value) => Some(*value),
+            Value::String(_) | Value::None => None,
+        })
+        .collect();
+    let texts: StringArray = samples
+        .iter()
+        .map(|(_, value)| match value {
+            Value::String(value) => Some(value.as_str()),
+            _ => None,
+        })
+        .collect();
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(timestamps.with_timezone("UTC")) as ArrayRef,
+            Arc::new(values),
+            Arc::new(texts),
+        ],
+    )?;
+    let mut writer = ArrowWriter::try_new(output, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
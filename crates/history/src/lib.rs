@@ -0,0 +1,129 @@
+#![doc = include_str!("../README.md")]
+
+pub mod batch;
+pub mod downsample;
+pub mod export;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod recorder;
+pub mod restore;
+pub mod retention;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use control::reflect::value::Value;
+use futures::future::BoxFuture;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::SystemTime;
+use thiserror::Error;
+
+pub use batch::{Batching, Writer};
+pub use downsample::Downsample;
+pub use export::Format;
+pub use recorder::Recorder;
+pub use restore::LastValue;
+pub use retention::{Prune, Retention};
+#[cfg(feature = "postgres")]
+pub use postgres::Postgres;
+#[cfg(feature = "sqlite")]
+pub use sqlite::Sqlite;
+
+/// A store which items are written to in batches, see [Writer]
+pub trait Backend: Send + Sync + 'static {
+    /// The type of item written
+    type Item: Send + 'static;
+
+    /// The name of the backend, used in logs
+    fn name(&self) -> String;
+
+    /// Write the given items, if this fails the same items are written again later along with any
+    /// new ones
+    fn write<'a>(&'a self, items: &'a [Self::Item]) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// A backend which can be queried for the samples recorded to it
+pub trait Query: Send + Sync {
+    /// Returns the samples of an attribute recorded in the given time range, oldest first
+    fn range<'a>(
+        &'a self,
+        device: &'a str,
+        attribute: &'a str,
+        range: Range<SystemTime>,
+    ) -> BoxFuture<'a, Result<Vec<(SystemTime, Value)>, Error>>;
+
+    /// Returns the most recent sample of an attribute, if any
+    fn latest<'a>(
+        &'a self,
+        device: &'a str,
+        attribute: &'a str,
+    ) -> BoxFuture<'a, Result<Option<(SystemTime, Value)>, Error>>;
+
+    /// Returns a [ReadValue](control::ReadValue) of the last recorded value of an attribute, eg:
+    /// to restore a setpoint after a restart
+    fn last_value<T>(&self, device: &str, attribute: &str) -> LastValue<'_, Self, T>
+    where
+        Self: Sized,
+    {
+        LastValue::new(self, device, attribute)
+    }
+}
+
+/// A single recorded update of a device's attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// When the update was recorded
+    pub timestamp: SystemTime,
+    /// The name of the device
+    pub device: String,
+    /// The name of the attribute, eg: `temperature`
+    pub attribute: String,
+    /// The tags of the device, sorted by key
+    pub tags: Arc<Vec<(String, String)>>,
+    /// The new value, this is never [Value::None]
+    pub value: Value,
+}
+
+/// Errors recording or querying history
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The background writer has stopped, this only happens if the runtime is shutting down
+    #[error("writer has stopped")]
+    Stopped,
+    /// A query of the SQLite database failed
+    #[cfg(feature = "sqlite")]
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    /// A query of the Postgres database failed
+    #[cfg(feature = "postgres")]
+    #[error("postgres error: {0}")]
+    Postgres(#[from] tokio_postgres::Error),
+    /// An export could not be written
+    #[error("failed to write export: {0}")]
+    Io(#[from] std::io::Error),
+    /// The samples could not be converted to Arrow for a Parquet export
+    #[cfg(feature = "parquet")]
+    #[error("arrow error: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    /// A Parquet export could not be written
+    #[cfg(feature = "parquet")]
+    #[error("parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    /// A blocking database task panicked or was cancelled
+    #[error("database task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Returns the time in milliseconds since the unix epoch
+#[cfg(any(feature = "sqlite", feature = "parquet"))]
+pub(crate) fn millis(time: SystemTime) -> i64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map(|since| i64::try_from(since.as_millis()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
+
+/// Returns the time from milliseconds since the unix epoch
+#[cfg(feature = "sqlite")]
+pub(crate) fn from_millis(millis: i64) -> SystemTime {
+    std::time::UNIX_EPOCH + std::time::Duration::from_millis(millis.try_into().unwrap_or_default())
+}
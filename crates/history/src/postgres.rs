@@ -0,0 +1,209 @@
+//! A Postgres database, optionally with the [TimescaleDB](https://www.timescale.com/) extension
+
+use crate::{Backend, Error, Query, Sample};
+use control::reflect::value::Value;
+use futures::future::BoxFuture;
+use std::ops::Range;
+use std::time::SystemTime;
+use tokio::spawn;
+use tokio_postgres::{Client, NoTls, Row};
+use tracing::{debug, error};
+
+/// Each migration is applied once, in order, the applied versions are kept in
+/// `history_migrations`, new migrations must only ever be appended
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE samples (
+        time TIMESTAMPTZ NOT NULL,
+        device TEXT NOT NULL,
+        attribute TEXT NOT NULL,
+        tags JSONB NOT NULL,
+        kind TEXT NOT NULL,
+        number DOUBLE PRECISION,
+        text TEXT
+    );
+    CREATE INDEX samples_by_attribute ON samples (device, attribute, time DESC);
+"];
+
+/// A history stored in a Postgres database, samples are kept in a single `samples` table, numeric
+/// and boolean values in the `number` column and textual values in the `text` column
+pub struct Postgres {
+    client: Client,
+}
+
+impl Postgres {
+    /// Connect to the database, eg: `host=localhost user=home dbname=home`, and apply any new
+    /// migrations, the connection is not encrypted
+    ///
+    /// If `timescale` is true the `samples` table is converted to a hypertable, this requires the
+    /// TimescaleDB extension
+    pub async fn connect(config: &str, timescale: bool) -> Result<Self, Error> {
+        let (mut client, connection) = tokio_postgres::connect(config, NoTls).await?;
+        spawn(async move {
+            if let Err(error) = connection.await {
+                error!("postgres connection failed: {error}");
+            }
+        });
+        migrate(&mut client).await?;
+        if timescale {
+            client
+                .execute(
+                    "SELECT create_hypertable('samples', 'time', if_not_exists => TRUE)",
+                    &[],
+                )
+                .await?;
+        }
+        Ok(Self { client })
+    }
+
+    /// Returns the samples of an attribute recorded in the given time range, oldest first
+    pub async fn range(
+        &self,
+        device: &str,
+        attribute: &str,
+        range: Range<SystemTime>,
+    ) -> Result<Vec<(SystemTime, Value)>, Error> {
+        let rows = self
+            .client
+            .query(
+                "SELECT time, kind, number, text FROM samples
+                 WHERE device = $1 AND attribute = $2 AND time >= $3 AND time < $4
+                 ORDER BY time",
+                &[&device, &attribute, &range.start, &range.end],
+            )
+            .await?;
+        rows.iter().map(sample).collect()
+    }
+
+    /// Returns the most recent sample of an attribute, if any
+    pub async fn latest(
+        &self,
+        device: &str,
+        attribute: &str,
+    ) -> Result<Option<(SystemTime, Value)>, Error> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT time, kind, number, text FROM samples
+                 WHERE device = $1 AND attribute = $2
+                 ORDER BY time DESC LIMIT 1",
+                &[&device, &attribute],
+            )
+            .await?;
+        row.as_ref().map(sample).transpose()
+    }
+}
+
+impl Query for Postgres {
+    fn range<'a>(
+        &'a self,
+        device: &'a str,
+        attribute: &'a str,
+        range: Range<SystemTime>,
+    ) -> BoxFuture<'a, Result<Vec<(SystemTime, Value)>, Error>> {
+        Box::pin(Postgres::range(self, device, attribute, range))
+    }
+
+    fn latest<'a>(
+        &'a self,
+        device: &'a str,
+        attribute: &'a str,
+    ) -> BoxFuture<'a, Result<Option<(SystemTime, Value)>, Error>> {
+        Box::pin(Postgres::latest(self, device, attribute))
+    }
+}
+
+impl Backend for Postgres {
+    type Item = Sample;
+
+    fn name(&self) -> String {
+        "postgres".to_string()
+    }
+
+    fn write<'a>(&'a self, samples: &'a [Sample]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            // each column is sent as an array so a batch is a single statement
+            let mut times = Vec::with_capacity(samples.len());
+            let mut devices = Vec::with_capacity(samples.len());
+            let mut attributes = Vec::with_capacity(samples.len());
+            let mut tags = Vec::with_capacity(samples.len());
+            let mut kinds = Vec::with_capacity(samples.len());
+            let mut numbers = Vec::with_capacity(samples.len());
+            let mut texts = Vec::with_capacity(samples.len());
+            for sample in samples {
+                let (kind, number, text) = encode(&sample.value);
+                times.push(sample.timestamp);
+                devices.push(sample.device.as_str());
+                attributes.push(sample.attribute.as_str());
+                tags.push(serde_json::Value::Object(
+                    sample
+                        .tags
+                        .iter()
+                        .map(|(key, value)| (key.clone(), value.clone().into()))
+                        .collect(),
+                ));
+                kinds.push(kind);
+                numbers.push(number);
+                texts.push(text);
+            }
+            self.client
+                .execute(
+                    "INSERT INTO samples (time, device, attribute, tags, kind, number, text)
+                     SELECT * FROM unnest($1::timestamptz[], $2::text[], $3::text[], $4::jsonb[],
+                                          $5::text[], $6::float8[], $7::text[])",
+                    &[&times, &devices, &attributes, &tags, &kinds, &numbers, &texts],
+                )
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+async fn migrate(client: &mut Client) -> Result<(), Error> {
+    client
+        .batch_execute("CREATE TABLE IF NOT EXISTS history_migrations (version INTEGER PRIMARY KEY)")
+        .await?;
+    let transaction = client.transaction().await?;
+    let applied: i64 = transaction
+        .query_one("SELECT count(*) FROM history_migrations", &[])
+        .await?
+        .get(0);
+    let applied = usize::try_from(applied).unwrap_or_default();
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        debug!("applying migration {}", index + 1);
+        transaction.batch_execute(migration).await?;
+        let version = i32::try_from(index + 1).unwrap_or(i32::MAX);
+        transaction
+            .execute("INSERT INTO history_migrations (version) VALUES ($1)", &[&version])
+            .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Returns the kind of value along with the `number` and `text` columns
+fn encode(value: &Value) -> (&'static str, Option<f64>, Option<&str>) {
+    match value {
+        Value::Bool(value) => ("bool", Some(if *value { 1.0 } else { 0.0 }), None),
+        #[allow(clippy::cast_precision_loss, reason = "sensor readings fit easily")]
+        Value::Int(value) => ("int", Some(*value as f64), None),
+        Value::Float(value) => ("float", Some(*value), None),
+        Value::String(value) => ("string", None, Some(value.as_str())),
+        Value::None => ("none", None, None),
+    }
+}
+
+fn sample(row: &Row) -> Result<(SystemTime, Value), Error> {
+    let time: SystemTime = row.try_get(0)?;
+    let kind: &str = row.try_get(1)?;
+    let number: Option<f64> = row.try_get(2)?;
+    let text: Option<String> = row.try_get(3)?;
+    #[allow(clippy::cast_possible_truncation, reason = "ints were stored as floats")]
+    let value = match (kind, number, text) {
+        ("bool", Some(number), _) => Value::Bool(number != 0.0),
+        ("int", Some(number), _) => Value::Int(number as i64),
+        (_, Some(number), _) => Value::Float(number),
+        (_, None, Some(text)) => Value::String(text),
+        (_, None, None) => Value::None,
+    };
+    Ok((time, value))
+}
@@ -0,0 +1,227 @@
+//! Records every update from a set of devices without an automation per sensor
+
+use crate::batch::{Batching, Writer};
+use crate::downsample::{Downsample, Sampler};
+use crate::export::{Format, export};
+use crate::{Backend, Error, Query, Sample};
+use bon::bon;
+use control::Sensor;
+use control::reflect;
+use control::reflect::value::Value;
+use control::util::lock;
+use futures::StreamExt;
+use futures::stream::{BoxStream, select_all};
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::time::{Instant, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error};
+
+/// Records the updates of sensors to a [Backend], each update is written as a [Sample] with the
+/// device and attribute it came from along with the device's tags
+pub struct Recorder<B: Backend<Item = Sample>> {
+    writer: Writer<B>,
+    rules: HashMap<(String, String), Downsample>,
+    default_rule: Option<Downsample>,
+}
+
+/// How often windows and pending changes are checked when downsampling
+const TICK: Duration = Duration::from_secs(1);
+
+#[bon]
+impl<B: Backend<Item = Sample>> Recorder<B> {
+    /// Create a new recorder
+    #[builder]
+    pub fn new(
+        #[builder(field)] rules: HashMap<(String, String), Downsample>,
+        /// Where updates are written
+        backend: B,
+        /// How updates are batched before being written
+        #[builder(default)]
+        batching: Batching,
+        /// How updates are downsampled when an attribute has no rule of its own, by default every
+        /// update is recorded
+        default_rule: Option<Downsample>,
+    ) -> Self {
+        Self {
+            writer: Writer::new(backend, batching),
+            rules,
+            default_rule,
+        }
+    }
+}
+
+impl<B: Backend<Item = Sample>, S: recorder_builder::State> RecorderBuilder<B, S> {
+    /// Downsample the updates of an attribute of a device, eg: a power sensor reporting every second
+    pub fn downsample(mut self, device: &str, attribute: &str, rule: Downsample) -> Self {
+        self.rules
+            .insert((device.to_string(), attribute.to_string()), rule);
+        self
+    }
+}
+
+/// The source of updates from a single attribute of a device
+struct Source {
+    device: String,
+    attribute: String,
+    tags: Arc<Vec<(String, String)>>,
+    sampler: Mutex<Sampler>,
+}
+
+impl<B: Backend<Item = Sample>> Recorder<B> {
+    /// The backend updates are written to, eg: to query the history
+    pub fn backend(&self) -> &B {
+        self.writer.backend()
+    }
+
+    /// Record every field which can be subscribed to on each of the given devices until cancelled,
+    /// eg: all the devices in a `DeviceSet`
+    pub async fn record<'a>(
+        &self,
+        devices: impl IntoIterator<Item = &'a dyn reflect::Device>,
+        token: CancellationToken,
+    ) {
+        let mut sources = Vec::new();
+        let mut streams: Vec<BoxStream<'a, (Arc<Source>, Value)>> = Vec::new();
+        for device in devices {
+            let info = device.info();
+            for field in device.fields() {
+                if !field.operations.subscribe {
+                    continue;
+                }
+                let stream = match device.subscribe(&field.name) {
+                    Ok(stream) => stream.await,
+                    Err(error) => {
+                        error!("failed to subscribe to {}: {error}", field.name);
+                        continue;
+                    }
+                };
+                let source = Arc::new(Source {
+                    sampler: Mutex::new(Sampler::new(self.rule(&info.name, &field.name))),
+                    device: info.name.clone(),
+                    attribute: field.name,
+                    tags: Arc::new(tags(&info)),
+                });
+                sources.push(source.clone());
+                streams.push(Box::pin(
+                    stream.map(move |value| (source.clone(), value)),
+                ));
+            }
+        }
+        debug!("recording {} attributes", streams.len());
+        self.write_all(sources, select_all(streams), token).await;
+    }
+
+    /// Record each reading of the given sensor until cancelled, this is useful for sensors which
+    /// are not part of a device, eg: a computed value
+    pub async fn record_sensor<S>(
+        &self,
+        device: &str,
+        attribute: &str,
+        sensor: &S,
+        token: CancellationToken,
+    ) where
+        S: Sensor + ?Sized,
+        S::Item: Into<Value>,
+    {
+        let source = Arc::new(Source {
+            device: device.to_string(),
+            attribute: attribute.to_string(),
+            tags: Arc::default(),
+            sampler: Mutex::new(Sampler::new(self.rule(device, attribute))),
+        });
+        let updates = sensor
+            .subscribe()
+            .map(|value| (source.clone(), value.into()));
+        self.write_all(vec![source.clone()], updates, token).await;
+    }
+
+    fn rule(&self, device: &str, attribute: &str) -> Option<Downsample> {
+        self.rules
+            .get(&(device.to_string(), attribute.to_string()))
+            .copied()
+            .or(self.default_rule)
+    }
+
+    async fn write_all(
+        &self,
+        sources: Vec<Arc<Source>>,
+        updates: impl futures::Stream<Item = (Arc<Source>, Value)>,
+        token: CancellationToken,
+    ) {
+        let mut updates = std::pin::pin!(updates);
+        let mut ticks = interval(TICK);
+        loop {
+            tokio::select! {
+                () = token.cancelled() => break,
+                update = updates.next() => {
+                    let Some((source, value)) = update else {
+                        break;
+                    };
+                    // absent values are not recorded
+                    if value == Value::None {
+                        continue;
+                    }
+                    let value = lock(&source.sampler).update(value, Instant::now());
+                    self.write(&source, value);
+                }
+                _ = ticks.tick() => {
+                    for source in &sources {
+                        let value = lock(&source.sampler).tick(Instant::now());
+                        self.write(source, value);
+                    }
+                }
+            }
+        }
+        // record whatever is left of any windows
+        for source in &sources {
+            let value = lock(&source.sampler).finish();
+            self.write(source, value);
+        }
+    }
+
+    fn write(&self, source: &Source, value: Option<Value>) {
+        let Some(value) = value else {
+            return;
+        };
+        let sample = Sample {
+            timestamp: SystemTime::now(),
+            device: source.device.clone(),
+            attribute: source.attribute.clone(),
+            tags: source.tags.clone(),
+            value,
+        };
+        if let Err(error) = self.writer.write(sample) {
+            error!("failed to record update: {error}");
+        }
+    }
+}
+
+impl<B: Backend<Item = Sample> + Query> Recorder<B> {
+    /// Export the history of an attribute in the given time range, eg: to a file for offline
+    /// analysis, returns the number of samples exported
+    pub async fn export(
+        &self,
+        device: &str,
+        attribute: &str,
+        range: Range<SystemTime>,
+        format: Format,
+        output: impl Write + Send,
+    ) -> Result<usize, Error> {
+        export(self.backend(), device, attribute, range, format, output).await
+    }
+}
+
+fn tags(info: &reflect::DeviceInfo) -> Vec<(String, String)> {
+    let mut tags: Vec<_> = info
+        .tags
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    // samples are written with tags in a stable order
+    tags.sort();
+    tags
+}
@@ -0,0 +1,47 @@
+//! Restoring state from the recorded history after a restart
+
+use crate::Query;
+use control::ReadValue;
+use control::reflect::value::{Value, ValueReadError};
+use futures::future::BoxFuture;
+use std::marker::PhantomData;
+
+/// Reads the last recorded value of an attribute, this is useful on startup to restore state which
+/// would otherwise be lost, eg: the last setpoint of a thermostat, the value is [None] if the
+/// attribute has never been recorded
+pub struct LastValue<'a, Q: ?Sized, T> {
+    history: &'a Q,
+    device: String,
+    attribute: String,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'a, Q: Query + ?Sized, T> LastValue<'a, Q, T> {
+    /// Create a new reader of the last value of the given attribute
+    pub fn new(history: &'a Q, device: &str, attribute: &str) -> Self {
+        Self {
+            history,
+            device: device.to_string(),
+            attribute: attribute.to_string(),
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<Q, T> ReadValue for LastValue<'_, Q, T>
+where
+    Q: Query + ?Sized,
+    T: TryFrom<Value, Error = ValueReadError>,
+{
+    type Item = Option<T>;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Option<T>>> {
+        Box::pin(async move {
+            let latest = self.history.latest(&self.device, &self.attribute).await?;
+            match latest {
+                Some((_, value)) => Ok(Some(T::try_from(value)?)),
+                None => Ok(None),
+            }
+        })
+    }
+}
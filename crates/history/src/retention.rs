@@ -0,0 +1,40 @@
+//! Pruning old samples so a long-running history doesn't fill the disk, eg: the SD card of a
+//! single board computer
+
+use crate::Error;
+use futures::future::BoxFuture;
+use std::time::Duration;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error};
+
+/// How long samples are kept, both limits apply if both are set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Samples older than this are deleted
+    pub max_age: Option<Duration>,
+    /// Only this many of the most recent samples of each attribute are kept
+    pub max_rows: Option<usize>,
+}
+
+/// A backend which old samples can be deleted from
+pub trait Prune: Send + Sync {
+    /// Delete the samples outside of the retention policy, returns the number deleted
+    fn prune(&self, retention: Retention) -> BoxFuture<'_, Result<usize, Error>>;
+}
+
+/// Prune the backend every `period` until cancelled
+pub async fn prune_periodically<P: Prune + ?Sized>(
+    backend: &P,
+    retention: Retention,
+    period: Duration,
+    token: CancellationToken,
+) {
+    let mut ticks = interval(period);
+    while token.run_until_cancelled(ticks.tick()).await.is_some() {
+        match backend.prune(retention).await {
+            Ok(deleted) => debug!("pruned {deleted} samples"),
+            Err(error) => error!("failed to prune history: {error}"),
+        }
+    }
+}
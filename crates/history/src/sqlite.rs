@@ -0,0 +1,250 @@
+//! An embedded SQLite database, useful for small installs without a database server
+
+use crate::retention::{Prune, Retention};
+use crate::{Backend, Error, Query, Sample, from_millis, millis};
+use control::reflect::value::Value;
+use control::util::lock;
+use futures::future::BoxFuture;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, params};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::task::spawn_blocking;
+use tracing::debug;
+
+/// Each migration is applied once, in order, the number applied is kept in `user_version`, new
+/// migrations must only ever be appended
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE samples (
+        timestamp INTEGER NOT NULL,
+        device TEXT NOT NULL,
+        attribute TEXT NOT NULL,
+        tags TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        value
+    );
+    CREATE INDEX samples_by_attribute ON samples (device, attribute, timestamp);
+"];
+
+/// A history stored in a SQLite database, samples are kept in a single `samples` table with
+/// timestamps in milliseconds since the unix epoch
+#[derive(Clone)]
+pub struct Sqlite {
+    name: String,
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Sqlite {
+    /// Open the database at the given path, it is created if it doesn't exist and any new
+    /// migrations are applied
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path: PathBuf = path.as_ref().into();
+        Self::new(path.display().to_string(), Connection::open(path)?)
+    }
+
+    /// Open a new database in memory, nothing is persisted
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::new("memory".to_string(), Connection::open_in_memory()?)
+    }
+
+    fn new(name: String, mut connection: Connection) -> Result<Self, Error> {
+        migrate(&mut connection)?;
+        Ok(Self {
+            name,
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Returns the samples of an attribute recorded in the given time range, oldest first
+    pub async fn range(
+        &self,
+        device: &str,
+        attribute: &str,
+        range: Range<SystemTime>,
+    ) -> Result<Vec<(SystemTime, Value)>, Error> {
+        let (device, attribute) = (device.to_string(), attribute.to_string());
+        self.blocking(move |connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT timestamp, kind, value FROM samples
+                 WHERE device = ?1 AND attribute = ?2 AND timestamp >= ?3 AND timestamp < ?4
+                 ORDER BY timestamp",
+            )?;
+            let rows = statement.query_map(
+                params![device, attribute, millis(range.start), millis(range.end)],
+                row,
+            )?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    /// Returns the most recent sample of an attribute, if any
+    pub async fn latest(
+        &self,
+        device: &str,
+        attribute: &str,
+    ) -> Result<Option<(SystemTime, Value)>, Error> {
+        let (device, attribute) = (device.to_string(), attribute.to_string());
+        self.blocking(move |connection| {
+            let mut statement = connection.prepare_cached(
+                "SELECT timestamp, kind, value FROM samples
+                 WHERE device = ?1 AND attribute = ?2
+                 ORDER BY timestamp DESC LIMIT 1",
+            )?;
+            let mut rows = statement.query_map(params![device, attribute], row)?;
+            Ok(rows.next().transpose()?)
+        })
+        .await
+    }
+
+    /// Run a query on a blocking thread, since SQLite blocks on disk IO
+    async fn blocking<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&mut Connection) -> Result<T, Error> + Send + 'static,
+    ) -> Result<T, Error> {
+        let connection = self.connection.clone();
+        spawn_blocking(move || query(&mut lock(&connection))).await?
+    }
+}
+
+impl Query for Sqlite {
+    fn range<'a>(
+        &'a self,
+        device: &'a str,
+        attribute: &'a str,
+        range: Range<SystemTime>,
+    ) -> BoxFuture<'a, Result<Vec<(SystemTime, Value)>, Error>> {
+        Box::pin(Sqlite::range(self, device, attribute, range))
+    }
+
+    fn latest<'a>(
+        &'a self,
+        device: &'a str,
+        attribute: &'a str,
+    ) -> BoxFuture<'a, Result<Option<(SystemTime, Value)>, Error>> {
+        Box::pin(Sqlite::latest(self, device, attribute))
+    }
+}
+
+impl Prune for Sqlite {
+    /// Deleted rows are reused by later samples rather than shrinking the file, so the database
+    /// stops growing once the retention limit is reached
+    fn prune(&self, retention: Retention) -> BoxFuture<'_, Result<usize, Error>> {
+        Box::pin(self.blocking(move |connection| {
+            let mut deleted = 0;
+            if let Some(max_age) = retention.max_age {
+                let oldest = SystemTime::now()
+                    .checked_sub(max_age)
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                deleted += connection
+                    .prepare_cached("DELETE FROM samples WHERE timestamp < ?1")?
+                    .execute(params![millis(oldest)])?;
+            }
+            if let Some(max_rows) = retention.max_rows {
+                deleted += connection
+                    .prepare_cached(
+                        "DELETE FROM samples WHERE rowid IN (
+                            SELECT rowid FROM (
+                                SELECT rowid, row_number() OVER (
+                                    PARTITION BY device, attribute ORDER BY timestamp DESC
+                                ) AS newest
+                                FROM samples
+                            )
+                            WHERE newest > ?1
+                        )",
+                    )?
+                    .execute(params![max_rows])?;
+            }
+            Ok(deleted)
+        }))
+    }
+}
+
+impl Backend for Sqlite {
+    type Item = Sample;
+
+    fn name(&self) -> String {
+        format!("sqlite ({})", self.name)
+    }
+
+    fn write<'a>(&'a self, samples: &'a [Sample]) -> BoxFuture<'a, anyhow::Result<()>> {
+        let rows: Vec<_> = samples
+            .iter()
+            .map(|sample| {
+                let (kind, value) = encode(&sample.value);
+                let tags: serde_json::Map<_, _> = sample
+                    .tags
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone().into()))
+                    .collect();
+                (
+                    millis(sample.timestamp),
+                    sample.device.clone(),
+                    sample.attribute.clone(),
+                    serde_json::Value::Object(tags).to_string(),
+                    kind,
+                    value,
+                )
+            })
+            .collect();
+        Box::pin(async move {
+            self.blocking(move |connection| {
+                let transaction = connection.transaction()?;
+                {
+                    let mut statement = transaction.prepare_cached(
+                        "INSERT INTO samples (timestamp, device, attribute, tags, kind, value)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    )?;
+                    for (timestamp, device, attribute, tags, kind, value) in rows {
+                        statement.execute(params![timestamp, device, attribute, tags, kind, value])?;
+                    }
+                }
+                transaction.commit()?;
+                Ok(())
+            })
+            .await?;
+            Ok(())
+        })
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), Error> {
+    let applied: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let transaction = connection.transaction()?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        debug!("applying migration {}", index + 1);
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Values are stored natively with their kind alongside, since SQLite has no boolean type
+fn encode(value: &Value) -> (&'static str, SqlValue) {
+    match value {
+        Value::Bool(value) => ("bool", SqlValue::Integer((*value).into())),
+        Value::Int(value) => ("int", SqlValue::Integer(*value)),
+        Value::Float(value) => ("float", SqlValue::Real(*value)),
+        Value::String(value) => ("string", SqlValue::Text(value.clone())),
+        Value::None => ("none", SqlValue::Null),
+    }
+}
+
+fn decode(kind: &str, value: SqlValue) -> Value {
+    match (kind, value) {
+        ("bool", SqlValue::Integer(value)) => Value::Bool(value != 0),
+        (_, SqlValue::Integer(value)) => Value::Int(value),
+        (_, SqlValue::Real(value)) => Value::Float(value),
+        (_, SqlValue::Text(value)) => Value::String(value),
+        (_, SqlValue::Null | SqlValue::Blob(_)) => Value::None,
+    }
+}
+
+fn row(row: &rusqlite::Row<'_>) -> rusqlite::Result<(SystemTime, Value)> {
+    let timestamp: i64 = row.get(0)?;
+    let kind: String = row.get(1)?;
+    Ok((from_millis(timestamp), decode(&kind, row.get(2)?)))
+}
@@ -0,0 +1,166 @@
+//! The HTTP client used to write points
+
+use crate::Error;
+use crate::line::Point;
+use futures::future::BoxFuture;
+use history::Backend;
+use std::fmt::Write;
+use tracing::{debug, trace};
+
+/// The connection details of an InfluxDB server
+#[derive(Debug, Clone)]
+pub enum Connection {
+    /// An InfluxDB 1.x server, 2.x servers also support this API if a database mapping is set up
+    V1 {
+        /// The base URL of the server, eg: `http://localhost:8086`
+        url: String,
+        /// The database to write to
+        database: String,
+        /// The username and password, if authentication is enabled
+        credentials: Option<(String, String)>,
+    },
+    /// An InfluxDB 2.x server or InfluxDB Cloud
+    V2 {
+        /// The base URL of the server, eg: `http://localhost:8086`
+        url: String,
+        /// The name of the organisation which owns the bucket
+        org: String,
+        /// The bucket to write to
+        bucket: String,
+        /// An API token with permission to write to the bucket
+        token: String,
+    },
+}
+
+impl Connection {
+    /// Connect to an InfluxDB 1.x database
+    pub fn v1(url: impl Into<String>, database: impl Into<String>) -> Self {
+        Self::V1 {
+            url: url.into(),
+            database: database.into(),
+            credentials: None,
+        }
+    }
+
+    /// Connect to an InfluxDB 2.x bucket, eg: on InfluxDB Cloud
+    pub fn v2(
+        url: impl Into<String>,
+        org: impl Into<String>,
+        bucket: impl Into<String>,
+        token: impl Into<String>,
+    ) -> Self {
+        Self::V2 {
+            url: url.into(),
+            org: org.into(),
+            bucket: bucket.into(),
+            token: token.into(),
+        }
+    }
+
+    /// Authenticate to an InfluxDB 1.x database with a username and password, this has no effect
+    /// on a 2.x connection, which is authenticated with its token
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        if let Self::V1 { credentials, .. } = &mut self {
+            *credentials = Some((username.into(), password.into()));
+        }
+        self
+    }
+
+    /// Returns the name of the database or bucket points are written to
+    pub fn target(&self) -> &str {
+        match self {
+            Self::V1 { database, .. } => database,
+            Self::V2 { bucket, .. } => bucket,
+        }
+    }
+}
+
+/// Writes points to a single database
+#[derive(Debug, Clone)]
+pub(crate) struct Client {
+    http: reqwest::Client,
+    connection: Connection,
+}
+
+impl Client {
+    pub(crate) fn new(connection: Connection) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            connection,
+        }
+    }
+
+    /// Write the given points in a single request
+    pub(crate) async fn write(&self, points: &[Point]) -> Result<(), Error> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        let mut body = String::new();
+        for point in points {
+            // InfluxDB rejects the whole request if any point has no fields
+            if point.fields.is_empty() {
+                debug!("skipping point in {} without any fields", point.measurement);
+                continue;
+            }
+            if !point.is_writable() {
+                debug!("skipping point in {} with only NaN or infinite fields", point.measurement);
+                continue;
+            }
+            // writing to a String can't fail
+            let _ = writeln!(body, "{point}");
+        }
+        if body.is_empty() {
+            return Ok(());
+        }
+        trace!("writing {} points to {}", points.len(), self.connection.target());
+
+        let request = match &self.connection {
+            Connection::V1 {
+                url,
+                database,
+                credentials,
+            } => {
+                let request = self
+                    .http
+                    .post(format!("{}/write", url.trim_end_matches('/')))
+                    .query(&[("db", database.as_str()), ("precision", "ns")]);
+                match credentials {
+                    Some((username, password)) => request.basic_auth(username, Some(password)),
+                    None => request,
+                }
+            }
+            Connection::V2 {
+                url,
+                org,
+                bucket,
+                token,
+            } => self
+                .http
+                .post(format!("{}/api/v2/write", url.trim_end_matches('/')))
+                .query(&[("org", org.as_str()), ("bucket", bucket.as_str()), ("precision", "ns")])
+                .header(reqwest::header::AUTHORIZATION, format!("Token {token}")),
+        };
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::Status {
+                status: status.as_u16(),
+                body,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Backend for Client {
+    type Item = Point;
+
+    fn name(&self) -> String {
+        format!("InfluxDB ({})", self.connection.target())
+    }
+
+    fn write<'a>(&'a self, points: &'a [Point]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move { Ok(Client::write(self, points).await?) })
+    }
+}
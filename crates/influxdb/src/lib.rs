@@ -0,0 +1,245 @@
+#![doc = include_str!("../README.md")]
+
+mod client;
+pub mod line;
+mod recording;
+
+use bon::bon;
+use client::Client;
+use control::WriteValue;
+use control::device::Device;
+use control::reflect;
+use control::reflect::value::{Value, ValueReadError, ValueType};
+use control::reflect::{DeviceInfo, Field, Operation, Operations, SetError};
+use futures::future::{BoxFuture, ready};
+use futures::stream::BoxStream;
+use history::Writer;
+use thiserror::Error;
+
+pub use client::Connection;
+pub use line::{FieldValue, Point};
+pub use history::Batching;
+pub use recording::History;
+
+/// The configuration data for an [InfluxDBData] device
+#[derive(Debug, Clone)]
+pub struct InfluxDBConfig {
+    /// The server and database or bucket to write to
+    pub connection: Connection,
+    /// The measurement each value is written to
+    pub measurement: String,
+    /// The name of the field each value is written to
+    pub field: String,
+    /// Tags added to every point, eg: the room a sensor is in
+    pub tags: Vec<(String, String)>,
+    /// How points are batched before being written
+    pub batching: Batching,
+}
+
+/// A measurement in an InfluxDB database, each value written to this device is recorded as a
+/// point timestamped with the time it was written, points are buffered and written in batches
+pub struct InfluxDBData {
+    info: DeviceInfo,
+    writer: Writer<Client>,
+    config: InfluxDBConfig,
+}
+
+#[bon]
+impl InfluxDBData {
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    #[builder]
+    pub async fn create(
+        manager: &mut (),
+        info: DeviceInfo,
+        /// The server and database or bucket to write to, eg: `Connection::v1("http://localhost:8086", "home")`
+        connection: Connection,
+        /// The measurement each value is written to
+        measurement: String,
+        /// The name of the field each value is written to, defaults to `value`
+        #[builder(default = "value".to_string())]
+        field: String,
+        /// Tags added to every point
+        #[builder(default)]
+        tags: Vec<(String, String)>,
+        /// How points are batched before being written
+        #[builder(default)]
+        batching: Batching,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_args(
+            manager,
+            info,
+            InfluxDBConfig {
+                connection,
+                measurement,
+                field,
+                tags,
+                batching,
+            },
+        )
+        .await
+    }
+}
+
+impl InfluxDBData {
+    /// Write a value to the configured field, the value is buffered and written with the next
+    /// batch so errors writing it are logged rather than returned
+    pub fn write(&self, value: impl Into<FieldValue>) -> Result<(), Error> {
+        let point = self.point().field(self.config.field.as_str(), value);
+        self.write_buffered(point)
+    }
+
+    /// Write a point with any fields, the point is written to the configured measurement with
+    /// the configured tags in addition to its own
+    pub fn write_point(&self, point: Point) -> Result<(), Error> {
+        let mut base = self.point().timestamp(point.timestamp);
+        base.tags.extend(point.tags);
+        base.fields = point.fields;
+        self.write_buffered(base)
+    }
+
+    fn write_buffered(&self, point: Point) -> Result<(), Error> {
+        if point.fields.is_empty() {
+            return Err(Error::NoFields(point.measurement));
+        }
+        self.writer
+            .write(point)
+            .map_err(|_| Error::Stopped)
+    }
+
+    fn point(&self) -> Point {
+        let mut point = Point::new(self.config.measurement.as_str());
+        point.tags.clone_from(&self.config.tags);
+        point
+    }
+}
+
+impl WriteValue for InfluxDBData {
+    type Item = f64;
+
+    fn set(&self, value: f64) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(ready(self.write(value).map_err(Into::into)))
+    }
+}
+
+impl Device for InfluxDBData {
+    type Args = InfluxDBConfig;
+    type Manager = ();
+
+    fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    async fn new_with_args(
+        _: &mut Self::Manager,
+        info: DeviceInfo,
+        config: InfluxDBConfig,
+    ) -> anyhow::Result<Self> {
+        if config.measurement.is_empty() {
+            return Err(Error::EmptyMeasurement.into());
+        }
+        Ok(InfluxDBData {
+            info,
+            writer: Writer::new(Client::new(config.connection.clone()), config.batching),
+            config,
+        })
+    }
+}
+
+impl reflect::Device for InfluxDBData {
+    fn info(&self) -> DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field {
+                name: "value".to_string(),
+                description: "Each value written to this field is recorded in the database, any type of value may be written".to_string(),
+                operations: Operations {
+                    subscribe: false,
+                    get: false,
+                    set: true,
+                    toggle: false,
+                },
+                value_type: ValueType::Float,
+            }
+        ]
+    }
+
+    fn subscribe(&self, field: &str) -> Result<BoxFuture<'_, BoxStream<'_, Value>>, reflect::Error> {
+        Err(self.unsupported(field, Operation::Subscribe))
+    }
+
+    fn get(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<Value>>, reflect::Error> {
+        Err(self.unsupported(field, Operation::Get))
+    }
+
+    fn set(&self, field: &str, value: Value) -> Result<BoxFuture<'_, anyhow::Result<()>>, SetError> {
+        if field == "value" {
+            let value = match value {
+                Value::Bool(value) => FieldValue::Boolean(value),
+                Value::Int(value) => FieldValue::Integer(value),
+                Value::Float(value) => FieldValue::Float(value),
+                Value::String(value) => FieldValue::String(value),
+                Value::None => {
+                    return Err(ValueReadError::WrongType {
+                        expected_type: ValueType::Float,
+                        actual_type: value.value_type(),
+                    }.into());
+                }
+            };
+            Ok(Box::pin(ready(self.write(value).map_err(Into::into))))
+        } else {
+            Err(self.unsupported(field, Operation::Set).into())
+        }
+    }
+
+    fn toggle(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<()>>, reflect::Error> {
+        Err(self.unsupported(field, Operation::Toggle))
+    }
+}
+
+impl InfluxDBData {
+    fn unsupported(&self, field: &str, operation: Operation) -> reflect::Error {
+        if field == "value" {
+            reflect::Error::OperationNotSupported {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+                operation,
+            }
+        } else {
+            reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            }
+        }
+    }
+}
+
+/// Errors writing to InfluxDB
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The measurement name was empty
+    #[error("measurement must not be empty")]
+    EmptyMeasurement,
+    /// A point had no fields, InfluxDB rejects these
+    #[error("point in {0} has no fields")]
+    NoFields(String),
+    /// The request could not be sent
+    #[error("failed to send request: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server rejected the write
+    #[error("server responded with {status}: {body}")]
+    Status {
+        /// The HTTP status code
+        status: u16,
+        /// The body of the response, this usually describes the error
+        body: String,
+    },
+    /// The background writer has stopped, this only happens if the runtime is shutting down
+    #[error("writer has stopped")]
+    Stopped,
+}
@@ -0,0 +1,182 @@
+//! Points and their encoding in the InfluxDB [line protocol](https://docs.influxdata.com/influxdb/v1/write_protocols/line_protocol_reference/)
+
+use std::fmt::{Display, Formatter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The value of a single field in a [Point]
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// A floating point number
+    Float(f64),
+    /// A signed integer
+    Integer(i64),
+    /// A boolean
+    Boolean(bool),
+    /// A string
+    String(String),
+}
+
+impl From<f64> for FieldValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<f32> for FieldValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value.into())
+    }
+}
+
+impl From<bool> for FieldValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+macro_rules! impl_integer {
+    ($($int:ty),*) => {
+        $(
+        impl From<$int> for FieldValue {
+            fn from(value: $int) -> Self {
+                Self::Integer(value.into())
+            }
+        }
+        )*
+    };
+}
+
+impl_integer!(i8, i16, i32, i64, u8, u16, u32);
+
+impl FieldValue {
+    /// Returns false for values the line protocol can't represent, ie: NaN and infinite floats,
+    /// these are left out when a point is written
+    pub fn is_writable(&self) -> bool {
+        match self {
+            FieldValue::Float(value) => value.is_finite(),
+            _ => true,
+        }
+    }
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Float(value) => write!(f, "{value}"),
+            FieldValue::Integer(value) => write!(f, "{value}i"),
+            FieldValue::Boolean(value) => write!(f, "{value}"),
+            FieldValue::String(value) => {
+                f.write_char('"')?;
+                for c in value.chars() {
+                    if c == '"' || c == '\\' {
+                        f.write_char('\\')?;
+                    }
+                    f.write_char(c)?;
+                }
+                f.write_char('"')
+            }
+        }
+    }
+}
+
+/// A single data point, a measurement with tags, fields and a timestamp
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    /// The name of the measurement
+    pub measurement: String,
+    /// Tags are indexed and used to filter and group points
+    pub tags: Vec<(String, String)>,
+    /// The values recorded, a point must have at least one field, fields which are not
+    /// [writable](FieldValue::is_writable) are skipped
+    pub fields: Vec<(String, FieldValue)>,
+    /// Nanoseconds since the unix epoch
+    pub timestamp: i64,
+}
+
+impl Point {
+    /// Create a new point for the measurement, timestamped now
+    pub fn new(measurement: impl Into<String>) -> Self {
+        Self {
+            measurement: measurement.into(),
+            tags: vec![],
+            fields: vec![],
+            timestamp: now(),
+        }
+    }
+
+    /// Add a tag to the point
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Add a field to the point
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<FieldValue>) -> Self {
+        self.fields.push((key.into(), value.into()));
+        self
+    }
+
+    /// Returns true if the point has a field which can be written, a point without one is skipped
+    pub fn is_writable(&self) -> bool {
+        self.fields.iter().any(|(_, value)| value.is_writable())
+    }
+
+    /// Set the timestamp of the point in nanoseconds since the unix epoch
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+}
+
+impl Display for Point {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        escape(f, &self.measurement, &[',', ' '])?;
+        for (key, value) in &self.tags {
+            // empty tag values are not allowed, they are omitted instead
+            if value.is_empty() {
+                continue;
+            }
+            f.write_char(',')?;
+            escape(f, key, &[',', '=', ' '])?;
+            f.write_char('=')?;
+            escape(f, value, &[',', '=', ' '])?;
+        }
+        let fields = self.fields.iter().filter(|(_, value)| value.is_writable());
+        for (i, (key, value)) in fields.enumerate() {
+            f.write_char(if i == 0 { ' ' } else { ',' })?;
+            escape(f, key, &[',', '=', ' '])?;
+            write!(f, "={value}")?;
+        }
+        write!(f, " {}", self.timestamp)
+    }
+}
+
+fn escape(f: &mut Formatter<'_>, value: &str, special: &[char]) -> std::fmt::Result {
+    for c in value.chars() {
+        if special.contains(&c) {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    Ok(())
+}
+
+/// Returns the current time in nanoseconds since the unix epoch
+pub(crate) fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| i64::try_from(since.as_nanos()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
@@ -0,0 +1,74 @@
+//! Recording the history of devices to InfluxDB with a [history::Recorder]
+
+use crate::client::{Client, Connection};
+use crate::line::{FieldValue, Point};
+use bon::bon;
+use control::reflect::value::Value;
+use futures::future::BoxFuture;
+use history::{Backend, Sample};
+use std::time::UNIX_EPOCH;
+
+/// A [Backend] writing each sample as a point in a single measurement, tagged with the `device`
+/// and `attribute` it came from along with the device's tags
+pub struct History {
+    client: Client,
+    measurement: String,
+}
+
+#[bon]
+impl History {
+    /// Create a new backend
+    #[builder]
+    pub fn new(
+        /// The server and database or bucket to write to
+        connection: Connection,
+        /// The measurement every sample is written to, defaults to `state`
+        #[builder(default = "state".to_string())]
+        measurement: String,
+    ) -> Self {
+        Self {
+            client: Client::new(connection),
+            measurement,
+        }
+    }
+}
+
+impl History {
+    fn point(&self, sample: &Sample) -> Option<Point> {
+        let value = match &sample.value {
+            Value::Bool(value) => FieldValue::Boolean(*value),
+            Value::Int(value) => FieldValue::Integer(*value),
+            Value::Float(value) => FieldValue::Float(*value),
+            Value::String(value) => FieldValue::String(value.clone()),
+            Value::None => return None,
+        };
+        let timestamp = sample
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|since| i64::try_from(since.as_nanos()).unwrap_or(i64::MAX))
+            .unwrap_or_default();
+        let mut point = Point::new(self.measurement.as_str())
+            .tag("device", sample.device.as_str())
+            .tag("attribute", sample.attribute.as_str())
+            .field("value", value)
+            .timestamp(timestamp);
+        point.tags.extend(sample.tags.iter().cloned());
+        Some(point)
+    }
+}
+
+impl Backend for History {
+    type Item = Sample;
+
+    fn name(&self) -> String {
+        Backend::name(&self.client)
+    }
+
+    fn write<'a>(&'a self, samples: &'a [Sample]) -> BoxFuture<'a, anyhow::Result<()>> {
+        let points: Vec<_> = samples
+            .iter()
+            .filter_map(|sample| self.point(sample))
+            .collect();
+        Box::pin(async move { Ok(self.client.write(&points).await?) })
+    }
+}
@@ -0,0 +1,63 @@
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{Expr, Ident, Token};
+
+/// The input of `automation!`, a set of `key: expression` pairs in any order
+pub struct AutomationInput {
+    name: Expr,
+    trigger: Expr,
+    when: Option<Expr>,
+    then: Expr,
+}
+
+impl Parse for AutomationInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut name = None;
+        let mut trigger = None;
+        let mut when = None;
+        let mut then = None;
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![:]>()?;
+            let value: Expr = input.parse()?;
+            let slot = match key.to_string().as_str() {
+                "name" => &mut name,
+                "trigger" => &mut trigger,
+                "when" => &mut when,
+                "then" => &mut then,
+                other => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("unknown key: '{other}', expected one of name, trigger, when or then"),
+                    ))
+                }
+            };
+            if slot.replace(value).is_some() {
+                return Err(syn::Error::new(key.span(), format!("{key} is given more than once")));
+            }
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+        let missing = |key: &str| syn::Error::new(Span::call_site(), format!("missing key: {key}"));
+        Ok(Self {
+            name: name.ok_or_else(|| missing("name"))?,
+            trigger: trigger.ok_or_else(|| missing("trigger"))?,
+            when,
+            then: then.ok_or_else(|| missing("then"))?,
+        })
+    }
+}
+
+pub fn automation(input: AutomationInput) -> TokenStream {
+    let AutomationInput { name, trigger, when, then } = input;
+    let trigger = match when {
+        Some(when) => quote! { ::home_control::StreamCustomExt::filter_when(#trigger, #when) },
+        None => quote! { #trigger },
+    };
+    quote! {
+        ::home_control::automation::Automation::new(#name, #trigger, #then)
+    }
+}
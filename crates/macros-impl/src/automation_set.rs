@@ -1,40 +1,56 @@
-use proc_macro2::{Ident, Span, TokenStream};
+use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{parse_quote, ExprField, Index, Member};
+use syn::spanned::Spanned;
+use syn::{parse_quote, Data, DeriveInput, Lifetime, Member, Type, TypePath};
 
-pub fn automation_sets(max: usize) -> TokenStream {
-    (0..=max).map(automation_set).collect()
-}
-
-fn automation_set(size:usize) -> TokenStream {
-    let types: Vec<_> = (1..=size).map(|x| Ident::new(&format!("A{x}"), Span::call_site())).collect();
-    let assertions = types.iter().map(|ty| quote! { #ty: AutomationSet });
-    let indices: Vec<_> = (0..size).map(|i| {
-        let mut index: ExprField = parse_quote! { self.0 };
-        index.member = Member::Unnamed(Index {
-            index: i as u32,
-            span: Span::call_site(),
-        });
-        index
-    }).collect();
-    quote! {
+pub fn automation_set(input: DeriveInput) -> syn::Result<TokenStream> {
+    let input_span = input.span();
+    let name = input.ident;
+    let Data::Struct(data) = input.data else {
+        return Err(syn::Error::new(
+            input_span,
+            "can only derive AutomationSet for structs",
+        ));
+    };
+    // the lifetime of the automations, they can only borrow devices if the struct has a lifetime
+    let lifetime: Lifetime = input
+        .generics
+        .lifetimes()
+        .next()
+        .map_or_else(|| parse_quote!('static), |param| param.lifetime.clone());
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let automations = data.fields.iter().enumerate().map(|(i, field)| {
+        let member = if let Some(name) = field.ident.clone() {
+            Member::Named(name)
+        } else {
+            Member::Unnamed(i.into())
+        };
+        if is_automation(&field.ty) {
+            quote! { automations.push(self.#member); }
+        } else {
+            // anything else yielding automations, eg: a Vec, an Option or a nested set
+            quote! { automations.extend(self.#member); }
+        }
+    });
+    Ok(quote! {
+        impl #impl_generics ::home_control::automation::AutomationSet<#lifetime> for #name #ty_generics #where_clause {}
 
-impl<#(#types),*> AutomationSet for (#(#types,)*)
-where
-        #(#assertions),*
-{
-    fn futures<'a>(&'a mut self, futures: &mut Vec<BoxFuture<'a, ()>>) {
-        #(
-            #indices.futures(futures);
-        )*;
-    }
+        impl #impl_generics IntoIterator for #name #ty_generics #where_clause {
+            type Item = ::home_control::automation::Automation<#lifetime>;
+            type IntoIter = std::vec::IntoIter<::home_control::automation::Automation<#lifetime>>;
 
-    fn size(&self) -> usize {
-        let mut count = 0;
-        #(count+=#indices.size();)*
-        count
-    }
+            fn into_iter(self) -> Self::IntoIter {
+                let mut automations = Vec::new();
+                #(#automations)*
+                automations.into_iter()
+            }
+        }
+    })
 }
 
-    }
-}
\ No newline at end of file
+fn is_automation(ty: &Type) -> bool {
+    let Type::Path(TypePath { qself: None, path }) = ty else {
+        return false;
+    };
+    path.segments.last().is_some_and(|segment| segment.ident == "Automation")
+}
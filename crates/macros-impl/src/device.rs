@@ -1,7 +1,9 @@
+mod exposes;
 mod input;
+mod mock;
 mod output;
 use proc_macro2::Ident;
-use syn::{LitInt, LitStr, Path};
+use syn::{ImplItem, LitInt, LitStr, Path};
 
 
 #[derive(Clone, Debug)]
@@ -10,6 +12,16 @@ pub struct Device {
     url: LitStr,
     name: Ident,
     values: Vec<Value>,
+    /// Commands taking several parameters, eg: a preset with a duration
+    commands: Vec<Command>,
+    /// Items written in an `impl { ... }` block, emitted as is into an impl of the device
+    impl_items: Vec<ImplItem>,
+    /// The exposes file values were generated from, if any
+    exposes: Option<LitStr>,
+    /// Enums generated for values read from an exposes file
+    enums: Vec<GeneratedEnum>,
+    /// Whether to generate tests checking each enum and bool mapping converts both ways
+    mapping_tests: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -17,8 +29,28 @@ struct Value {
     docs: Vec<LitStr>,
     mode: Mode,
     attribute_name: LitStr,
+    /// Other names the attribute is reported under, eg: by older firmware
+    aliases: Vec<LitStr>,
     value_name: Option<Ident>,
     value_type: Type,
+    /// A module with `serialize` and `deserialize` functions used instead of the default conversions
+    with: Option<Path>,
+}
+
+/// A command sent as a single set request, eg: `command "warning" => warn(mode: ..., duration: u16)`
+#[derive(Clone, Debug)]
+struct Command {
+    docs: Vec<LitStr>,
+    /// The attribute the parameters are nested under, if any, otherwise each parameter is set directly
+    attribute_name: Option<LitStr>,
+    name: Ident,
+    params: Vec<Param>,
+}
+
+#[derive(Clone, Debug)]
+struct Param {
+    name: Ident,
+    value_type: Type,
 }
 
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
@@ -111,7 +143,13 @@ enum Type {
         kind: NumericKind,
         range: Option<(LitInt, LitInt)>
     },
-    Bool(Option<[LitStr; 2]>)
+    Float,
+    Bool(Option<[LitStr; 2]>),
+    /// A set of enum values, reported as an array of strings, eg: the supported color modes
+    Set {
+        path: Path,
+        variants: Vec<Variant>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -133,3 +171,10 @@ struct Variant {
     zigbee: syn::LitStr,
     rust: Ident,
 }
+
+#[derive(Clone, Debug)]
+struct GeneratedEnum {
+    docs: Vec<LitStr>,
+    name: Ident,
+    variants: Vec<Variant>,
+}
@@ -0,0 +1,259 @@
+//! Generating the values of a device from the `exposes` JSON zigbee2mqtt publishes for a model
+//!
+//! The JSON can be the `exposes` array itself, the `definition` of a device or a whole device
+//! entry from the `zigbee2mqtt/bridge/devices` topic
+
+use super::{GeneratedEnum, Mode, NumericKind, Type, Value, Variant};
+use convert_case::{Case, Casing};
+use proc_macro2::{Ident, Span};
+use serde_json::Value as Json;
+use std::path::Path;
+use syn::{LitInt, LitStr};
+
+/// The access bit set when zigbee2mqtt publishes the property
+const PUBLISHED: u64 = 1;
+/// The access bit set when the property can be set
+const SET: u64 = 2;
+/// The access bit set when the property can be requested with a get
+const GET: u64 = 4;
+
+/// Read an exposes file, relative to the root of the crate calling the macro, and generate a value
+/// for each property along with an enum for each enum property
+pub(super) fn generate(path: &LitStr, device: &Ident) -> syn::Result<(Vec<Value>, Vec<GeneratedEnum>)> {
+    let span = path.span();
+    let error = |message: String| syn::Error::new(span, message);
+    let root = std::env::var("CARGO_MANIFEST_DIR").map_err(|_| error("CARGO_MANIFEST_DIR is not set".to_string()))?;
+    let file = Path::new(&root).join(path.value());
+    let contents = std::fs::read_to_string(&file).map_err(|err| error(format!("failed to read {}: {err}", file.display())))?;
+    let json: Json = serde_json::from_str(&contents).map_err(|err| error(format!("failed to parse {}: {err}", file.display())))?;
+    let exposes = if json.is_array() {
+        &json
+    } else {
+        json.pointer("/exposes")
+            .or_else(|| json.pointer("/definition/exposes"))
+            .ok_or_else(|| error("expected an exposes array, a definition or a device".to_string()))?
+    };
+    let Json::Array(exposes) = exposes else {
+        return Err(error("exposes should be an array".to_string()));
+    };
+    let mut generator = Generator {
+        device,
+        span,
+        values: Vec::new(),
+        enums: Vec::new(),
+    };
+    for expose in exposes {
+        generator.expose(expose);
+    }
+    Ok((generator.values, generator.enums))
+}
+
+struct Generator<'a> {
+    device: &'a Ident,
+    span: Span,
+    values: Vec<Value>,
+    enums: Vec<GeneratedEnum>,
+}
+
+impl Generator<'_> {
+    fn expose(&mut self, expose: &Json) {
+        if let Some(features) = expose.get("features").and_then(Json::as_array) {
+            // a composite with a property is published as a single object, which is not supported
+            if expose.get("property").is_some() {
+                return;
+            }
+            for feature in features {
+                self.expose(feature);
+            }
+            return;
+        }
+        let Some(property) = expose.get("property").and_then(Json::as_str) else {
+            return;
+        };
+        if self.values.iter().any(|value| value.attribute_name.value() == property) {
+            return;
+        }
+        let access = expose.get("access").and_then(Json::as_u64).unwrap_or(0);
+        let stream = access & PUBLISHED != 0;
+        let get = access & GET != 0;
+        let set = access & SET != 0;
+        let (value_type, toggle) = match expose.get("type").and_then(Json::as_str) {
+            Some("binary") => match self.binary(expose) {
+                Some(binary) => binary,
+                None => return,
+            },
+            Some("numeric") => (self.numeric(expose), false),
+            Some("enum") => match self.enumeration(property, expose) {
+                Some(value_type) => (value_type, false),
+                None => return,
+            },
+            // only lists of enums have a matching type
+            Some("list") => match self.list(property, expose) {
+                Some(value_type) => (value_type, false),
+                None => return,
+            },
+            // text has no matching type
+            _ => return,
+        };
+        let mode = match (stream, get, set, toggle && set) {
+            (_, true, true, true) => Mode::StreamGetSetToggle,
+            (_, true, true, false) => Mode::StreamGetSet,
+            (_, true, false, _) => Mode::StreamGet,
+            (true, false, true, _) => Mode::StreamSet,
+            (true, false, false, _) => Mode::Stream,
+            (false, false, true, true) => Mode::SetToggle,
+            (false, false, true, false) => Mode::Set,
+            (false, false, false, _) => return,
+        };
+        let docs = expose
+            .get("description")
+            .and_then(Json::as_str)
+            .map(|description| LitStr::new(&format!(" {description}"), self.span))
+            .into_iter()
+            .collect();
+        let name = field_name(property, self.span);
+        self.values.push(Value {
+            docs,
+            mode,
+            attribute_name: LitStr::new(property, self.span),
+            aliases: Vec::new(),
+            value_name: name,
+            value_type,
+            with: None,
+        });
+    }
+
+    /// Returns the type of a binary property and whether it can be toggled
+    fn binary(&self, expose: &Json) -> Option<(Type, bool)> {
+        match (expose.get("value_on")?, expose.get("value_off")?) {
+            (Json::String(on), Json::String(off)) => {
+                let toggle = expose.get("value_toggle").is_some_and(Json::is_string);
+                let variants = [LitStr::new(off, self.span), LitStr::new(on, self.span)];
+                Some((Type::Bool(Some(variants)), toggle))
+            }
+            (Json::Bool(true), Json::Bool(false)) => Some((Type::Bool(None), false)),
+            _ => None,
+        }
+    }
+
+    fn numeric(&self, expose: &Json) -> Type {
+        let min = expose.get("value_min").and_then(Json::as_i64);
+        let max = expose.get("value_max").and_then(Json::as_i64);
+        let fractional = expose
+            .get("value_step")
+            .and_then(Json::as_f64)
+            .is_some_and(|step| step.fract() != 0.0);
+        match (min, max) {
+            (Some(min), Some(max)) if !fractional => Type::Number {
+                kind: integer_kind(min, max),
+                range: Some((
+                    LitInt::new(&min.to_string(), self.span),
+                    LitInt::new(&max.to_string(), self.span),
+                )),
+            },
+            // readings without a range can have a fractional part, eg: a temperature
+            _ => Type::Float,
+        }
+    }
+
+    fn list(&mut self, property: &str, expose: &Json) -> Option<Type> {
+        let item = expose.get("item_type")?;
+        if item.get("type").and_then(Json::as_str) != Some("enum") {
+            return None;
+        }
+        match self.enumeration(property, item)? {
+            Type::Enum { path, variants } => Some(Type::Set { path, variants }),
+            _ => None,
+        }
+    }
+
+    fn enumeration(&mut self, property: &str, expose: &Json) -> Option<Type> {
+        let values = expose.get("values")?.as_array()?;
+        let mut variants: Vec<Variant> = Vec::new();
+        for value in values {
+            let value = value.as_str()?;
+            let mut rust = pascal(value);
+            if !rust.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                rust.insert_str(0, "Value");
+            }
+            // values which only differ by case or punctuation still need distinct variants
+            if variants.iter().any(|variant| variant.rust == rust) {
+                rust = format!("{rust}{}", variants.len());
+            }
+            variants.push(Variant {
+                zigbee: LitStr::new(value, self.span),
+                rust: Ident::new(&rust, self.span),
+            });
+        }
+        if variants.is_empty() {
+            return None;
+        }
+        let name = Ident::new(
+            &format!("{}{}", self.device, pascal(property)),
+            self.span,
+        );
+        self.enums.push(GeneratedEnum {
+            docs: vec![LitStr::new(
+                &format!(" The values of `{property}` on a [{}]", self.device),
+                self.span,
+            )],
+            name: name.clone(),
+            variants: variants.clone(),
+        });
+        Some(Type::Enum {
+            path: name.into(),
+            variants,
+        })
+    }
+}
+
+/// Returns the field name to use for a property if it can't be used as an identifier as is
+fn field_name(property: &str, span: Span) -> Option<Ident> {
+    if syn::parse_str::<Ident>(property).is_ok() {
+        return None;
+    }
+    let mut name: String = property
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    if syn::parse_str::<Ident>(&name).is_ok() {
+        Some(Ident::new(&name, span))
+    } else {
+        // keywords, eg: `type`
+        Some(Ident::new(&format!("{name}_"), span))
+    }
+}
+
+/// Converts a zigbee2mqtt name to pascal case, dropping anything which can't be in an identifier
+fn pascal(name: &str) -> String {
+    name.to_case(Case::Pascal)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect()
+}
+
+fn integer_kind(min: i64, max: i64) -> NumericKind {
+    let fits = |low: i64, high: i64| min >= low && max <= high;
+    if min >= 0 {
+        if fits(0, u8::MAX.into()) {
+            NumericKind::U8
+        } else if fits(0, u16::MAX.into()) {
+            NumericKind::U16
+        } else if fits(0, u32::MAX.into()) {
+            NumericKind::U32
+        } else {
+            NumericKind::U64
+        }
+    } else if fits(i8::MIN.into(), i8::MAX.into()) {
+        NumericKind::I8
+    } else if fits(i16::MIN.into(), i16::MAX.into()) {
+        NumericKind::I16
+    } else if fits(i32::MIN.into(), i32::MAX.into()) {
+        NumericKind::I32
+    } else {
+        NumericKind::I64
+    }
+}
@@ -1,19 +1,27 @@
-use crate::device::{Mode, NumericKind, Type, Value, Variant};
+use crate::device::exposes::generate;
+use crate::device::{Command, Mode, NumericKind, Param, Type, Value, Variant};
 use crate::*;
 use proc_macro2::Span;
 use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
 use syn::token::Brace;
-use syn::{braced, parse_quote, Attribute, Expr, ExprLit, Ident, Lit, LitBool, LitStr, Meta, MetaNameValue, Path, Token};
+use syn::{braced, parenthesized, parse_quote, ImplItem, Attribute, Expr, ExprLit, ExprPath, Ident, Lit, LitBool, LitStr, Meta, MetaNameValue, Path, Token};
 
 mod kw {
     use syn::custom_keyword;
     custom_keyword!(bool);
+    custom_keyword!(f64);
+    custom_keyword!(set);
+    custom_keyword!(command);
+    custom_keyword!(exposes);
 }
 
 impl Parse for Device {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let docs = parse_docs(&input)?;
+        let Attrs { docs, with, mapping_tests } = parse_attrs(&input)?;
+        if let Some(with) = with {
+            return Err(syn::Error::new(with.span(), "with can only be used on values"))
+        }
         input.parse::<Token![pub]>()?;
         let name: Ident = input.parse()?;
 
@@ -22,54 +30,187 @@ impl Parse for Device {
 
         let url: LitStr = values_content.parse()?;
         if !url.value().starts_with("https://www.zigbee2mqtt.io/devices/") {
-            return Err(syn::Error::new(url.span(), "URL should be formatted as https://www.zigbee2mqtt.io/devices/<deviceID>"))
+            return Err(syn::Error::new(url.span(), "URL should be formatted as https://www.zigbee2mqtt.io/devices/<deviceID>.html"))
         }
         values_content.parse::<Token![,]>()?;
-        let values = values_content.parse_terminated(Value::parse, Token![,])?;
+        let mut exposes = None;
+        let (mut values, enums) = if values_content.peek(kw::exposes) {
+            values_content.parse::<kw::exposes>()?;
+            let path: LitStr = values_content.parse()?;
+            if !values_content.is_empty() {
+                values_content.parse::<Token![,]>()?;
+            }
+            let generated = generate(&path, &name)?;
+            exposes = Some(path);
+            generated
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let written = values_content.parse_terminated(Entry::parse, Token![,])?;
         drop(values_content);
-        let values = values.into_iter().collect();
-        Ok(Self { docs, url, name, values })
+        // values written out replace any generated for the same attribute, eg: to use an existing enum
+        let generated = values.len();
+        let mut commands = Vec::new();
+        let mut impl_items = Vec::new();
+        for entry in written {
+            let value = match entry {
+                Entry::Value(value) => value,
+                Entry::Command(command) => {
+                    commands.push(command);
+                    continue
+                }
+                Entry::Impl(items) => {
+                    impl_items.extend(items);
+                    continue
+                }
+            };
+            let existing = values[..generated].iter_mut().find(|existing| existing.attribute_name.value() == value.attribute_name.value());
+            match existing {
+                Some(existing) => *existing = value,
+                None => values.push(value),
+            }
+        }
+        let used: Vec<_> = values.iter().filter_map(|value| match &value.value_type {
+            Type::Enum { path, .. } | Type::Set { path, .. } => path.get_ident().cloned(),
+            _ => None,
+        }).collect();
+        let enums = enums.into_iter().filter(|generated| used.contains(&generated.name)).collect();
+        Ok(Self { docs, url, name, values, commands, impl_items, exposes, enums, mapping_tests: mapping_tests.is_some() })
     }
 }
 
-fn parse_docs(input: &ParseStream) -> syn::Result<Vec<LitStr>> {
+/// The attributes allowed on a device or a value
+struct Attrs {
+    docs: Vec<LitStr>,
+    /// `#[with = path::to::module]`, only allowed on values
+    with: Option<Path>,
+    /// `#[mapping_tests]`, only allowed on devices
+    mapping_tests: Option<Span>,
+}
+
+fn parse_attrs(input: &ParseStream) -> syn::Result<Attrs> {
     let attrs = input.call(Attribute::parse_outer)?;
-    let docs = attrs.into_iter().map(|attr| {
+    let mut parsed = Attrs {
+        docs: Vec::new(),
+        with: None,
+        mapping_tests: None,
+    };
+    for attr in attrs {
         let span = attr.span();
-        let Meta::NameValue(MetaNameValue { path, eq_token: _, value }) = attr.meta else {
-            return Err(syn::Error::new(span, "only doc comment attributes allowed here"))
+        let (path, value) = match attr.meta {
+            Meta::Path(path) if path == parse_quote!(mapping_tests) => {
+                parsed.mapping_tests = Some(span);
+                continue;
+            }
+            Meta::NameValue(MetaNameValue { path, eq_token: _, value }) => (path, value),
+            _ => return Err(syn::Error::new(span, "only doc comment, with and mapping_tests attributes allowed here")),
         };
+        if path == parse_quote!(with) {
+            let Expr::Path(ExprPath { attrs: _, qself: None, path }) = value else {
+                return Err(syn::Error::new(span, "with should be the path of a module, eg: #[with = path::to::module]"))
+            };
+            parsed.with = Some(path);
+            continue;
+        }
         if path != parse_quote!(doc) {
-            return Err(syn::Error::new(span, "only doc comment attributes allowed here"))
+            return Err(syn::Error::new(span, "only doc comment, with and mapping_tests attributes allowed here"))
         }
         let Expr::Lit(ExprLit { attrs: _ , lit: Lit::Str(doc) }) = value else {
-            return Err(syn::Error::new(span, "only doc comment attributes allowed here"))
+            return Err(syn::Error::new(span, "only doc comment, with and mapping_tests attributes allowed here"))
+        };
+        parsed.docs.push(doc);
+    }
+    Ok(parsed)
+}
+
+/// An entry in the body of a device, a value, a command or an impl block
+enum Entry {
+    Value(Value),
+    Command(Command),
+    /// `impl { ... }`, eg: wrappers around the generated methods
+    Impl(Vec<ImplItem>),
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![impl]) {
+            input.parse::<Token![impl]>()?;
+            let content;
+            braced!(content in input);
+            let mut items = Vec::new();
+            while !content.is_empty() {
+                items.push(content.parse()?);
+            }
+            return Ok(Self::Impl(items))
+        }
+        let fork = input.fork();
+        fork.call(Attribute::parse_outer)?;
+        if fork.peek(kw::command) {
+            input.parse().map(Self::Command)
+        } else {
+            input.parse().map(Self::Value)
+        }
+    }
+}
+
+impl Parse for Command {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let Attrs { docs, with, mapping_tests } = parse_attrs(&input)?;
+        if let Some(with) = with {
+            return Err(syn::Error::new(with.span(), "with cannot be used on commands"))
+        }
+        if let Some(span) = mapping_tests {
+            return Err(syn::Error::new(span, "mapping_tests can only be used on devices"))
+        }
+        input.parse::<kw::command>()?;
+        let attribute_name = if input.peek(LitStr) {
+            let attribute_name = input.parse()?;
+            input.parse::<Token![=>]>()?;
+            Some(attribute_name)
+        } else {
+            None
         };
-        Ok(doc)
-    }).collect::<Result<_, _>>()?;
-    Ok(docs)
+        let name: Ident = input.parse()?;
+        let params;
+        parenthesized!(params in input);
+        let params: Vec<Param> = params.parse_terminated(Param::parse, Token![,])?.into_iter().collect();
+        if params.is_empty() {
+            return Err(syn::Error::new(name.span(), "a command needs at least one parameter, use set for values without parameters"))
+        }
+        Ok(Self { docs, attribute_name, name, params })
+    }
+}
+
+impl Parse for Param {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let value_type = input.parse()?;
+        Ok(Self { name, value_type })
+    }
 }
 
 impl Parse for Value {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let docs = parse_docs(&input)?;
-        let mut modifier_span = Option::<Span>::None;
+        let Attrs { docs, with, mapping_tests } = parse_attrs(&input)?;
+        if let Some(span) = mapping_tests {
+            return Err(syn::Error::new(span, "mapping_tests can only be used on devices"))
+        }
+        let mut first_modifier = Option::<Span>::None;
         let mut stream_span = None;
         let mut get = false;
         let mut set = false;
         let mut toggle = false;
-        let mut correct_order = true;
+        let mut toggle_span = None;
+        // the first modifier written after one which should follow it
+        let mut misplaced = Option::<Ident>::None;
         while input.peek(Ident) {
             let ident: Ident = input.parse()?;
-            if let Some(span) = modifier_span {
-                modifier_span = span.join(ident.span());
-            }
-            match ident.to_string().as_str() {
+            first_modifier.get_or_insert(ident.span());
+            let out_of_order = match ident.to_string().as_str() {
                 "stream" => {
-                    if get || set || toggle {
-                        correct_order = false;
-                    }
                     stream_span = Some(ident.span());
+                    get || set || toggle
                 }
                 "get" => {
                     if let Some(stream) = stream_span {
@@ -78,49 +219,55 @@ impl Parse for Value {
                             "stream is implied by get, both modifiers cannot be used together",
                         ));
                     }
-                    if set || toggle {
-                        correct_order = false;
-                    }
                     get = true;
+                    set || toggle
                 }
                 "set" => {
-                    if toggle {
-                        correct_order = false;
-                    }
                     set = true;
+                    toggle
                 }
                 "toggle" => {
-                    toggle = true
+                    toggle = true;
+                    toggle_span = Some(ident.span());
+                    false
                 },
                 s => {
                     return Err(syn::Error::new(
                         ident.span(),
-                        format!("unknown modifier: '{s}'"),
+                        format!("unknown modifier: '{s}', expected one of stream, get, set or toggle"),
                     ));
                 }
+            };
+            if out_of_order && misplaced.is_none() {
+                misplaced = Some(ident);
             }
         }
         let stream = stream_span.is_some();
-        if !correct_order {
-            let mut correct = String::new();
+        if let Some(misplaced) = misplaced {
+            let mut correct = Vec::new();
             if stream {
-                correct.push_str("stream ")
+                correct.push("stream")
             }
             if get {
-                correct.push_str("get ")
+                correct.push("get")
             }
             if set {
-                correct.push_str("set ")
+                correct.push("set")
             }
             if toggle {
-                correct.push_str("toggle ")
+                correct.push("toggle")
             }
-            let span = modifier_span.unwrap_or_else(Span::call_site);
             return Err(syn::Error::new(
-                span,
-                format!("modifiers out of order, use: '{correct}'"),
+                misplaced.span(),
+                format!("modifiers out of order, '{misplaced}' should come earlier, use: '{}'", correct.join(" ")),
             ));
         }
+        let Some(first_modifier) = first_modifier else {
+            return Err(syn::Error::new(input.span(), "expected at least one of the stream, get, set or toggle modifiers"))
+        };
+        if let Some(toggle) = toggle_span && !set {
+            return Err(syn::Error::new(toggle, "toggle cannot be used without set, add set before it"))
+        }
         let mode = match (stream, get, set, toggle) {
             (true, false, false, false) => Mode::Stream,
             (true, false, true, false) => Mode::StreamSet,
@@ -129,16 +276,18 @@ impl Parse for Value {
             (_, true, true, true) => Mode::StreamGetSetToggle,
             (false, false, true, false) => Mode::Set,
             (false, false, true, true) => Mode::SetToggle,
-            tuple => return Err(syn::Error::new(modifier_span.unwrap_or_else(Span::call_site), format!("unanticipated modifier combination: {tuple:?}")))
+            (true, false, true, true) => {
+                return Err(syn::Error::new(toggle_span.unwrap_or(first_modifier), "toggle requires get rather than stream, use: 'get set toggle'"))
+            }
+            tuple => return Err(syn::Error::new(first_modifier, format!("unanticipated modifier combination: {tuple:?}")))
         };
-        if toggle && !set {
-            return Err(syn::Error::new(modifier_span.unwrap_or_else(Span::call_site), "toggle cannot be used without set"))
-        }
-        if !stream && !get && !set && !toggle {
-            return Err(syn::Error::new(Span::call_site(), "expected on of stream, get,set or toggle modifiers"))
-        }
 
         let attribute_name = input.parse()?;
+        let mut aliases = Vec::new();
+        while input.peek(Token![|]) {
+            input.parse::<Token![|]>()?;
+            aliases.push(input.parse()?);
+        }
         input.parse::<Token![=>]>()?;
 
         let mut value_name = None;
@@ -147,12 +296,17 @@ impl Parse for Value {
             input.parse::<Token![:]>()?;
         }
         let value_type = input.parse()?;
+        if let Some(with) = &with && toggle {
+            return Err(syn::Error::new(with.span(), "toggle cannot be used with a custom serializer"))
+        }
         Ok(Self {
             docs,
             mode,
             attribute_name,
+            aliases,
             value_name,
             value_type,
+            with,
         })
     }
 }
@@ -160,13 +314,15 @@ impl Parse for Value {
 impl Parse for Type {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         if input.peek(Token![enum]) {
-            input.parse::<Token![enum]>()?;
-            let path: Path = input.parse()?;
-            let variants;
-            braced!(variants in input);
-            let variants = variants.parse_terminated(Variant::parse, Token![,])?;
-            let variants = variants.into_iter().collect();
+            let (path, variants) = parse_enum(input)?;
             Ok(Self::Enum { path, variants })
+        } else if input.peek(kw::set) {
+            let set = input.parse::<kw::set>()?;
+            if !input.peek(Token![enum]) {
+                return Err(syn::Error::new(set.span, "a set should be of an enum, eg: set enum ColorMode { \"xy\" => Xy }"))
+            }
+            let (path, variants) = parse_enum(input)?;
+            Ok(Self::Set { path, variants })
         } else if input.peek(kw::bool) {
             input.parse::<kw::bool>()?;
             let variants = if input.peek(Brace) {
@@ -193,6 +349,9 @@ impl Parse for Type {
                 None
             };
             Ok(Self::Bool(variants))
+        } else if input.peek(kw::f64) {
+            input.parse::<kw::f64>()?;
+            Ok(Self::Float)
         } else {
             let kind = input.parse()?;
             let range = if input.peek(Token![<]) {
@@ -213,6 +372,16 @@ impl Parse for Type {
     }
 }
 
+/// Parses `enum Path { "zigbee" => Variant, ... }`
+fn parse_enum(input: ParseStream) -> syn::Result<(Path, Vec<Variant>)> {
+    input.parse::<Token![enum]>()?;
+    let path: Path = input.parse()?;
+    let variants;
+    braced!(variants in input);
+    let variants = variants.parse_terminated(Variant::parse, Token![,])?;
+    Ok((path, variants.into_iter().collect()))
+}
+
 impl Parse for Variant {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let zigbee = input.parse()?;
@@ -236,7 +405,10 @@ impl Parse for NumericKind {
             "i32" => Self::I32,
             "i64" => Self::I64,
             "i128" => Self::I128,
-            _ => return Err(syn::Error::new(number_type.span(), "unknown/unsupported integer type")),
+            _ => return Err(syn::Error::new(
+                number_type.span(),
+                "unknown/unsupported type, expected bool, f64, an integer type, enum or set enum",
+            )),
         })
     }
 }
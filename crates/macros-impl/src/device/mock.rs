@@ -0,0 +1,154 @@
+//! Generating a mock of each device for the testing crate, the mock keeps the raw JSON value of
+//! each attribute, publishing values as the device would and responding to set and get requests
+
+use super::{Device, Type, Value};
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+
+impl Device {
+    pub(super) fn mock(&self) -> TokenStream {
+        let name = &self.name;
+        let mock = Ident::new(&format!("Mock{name}"), name.span());
+        let mod_name = self.mod_name();
+        let methods = self.values.iter().map(|value| value.mock_methods(&mod_name));
+        // values sharing an attribute are toggled once
+        let mut toggled = Vec::new();
+        let mut toggles = Vec::new();
+        for value in &self.values {
+            let attribute = &value.attribute_name;
+            if !value.mode.allow_toggle() || !matches!(value.value_type, Type::Bool(_)) || toggled.contains(&attribute.value()) {
+                continue;
+            }
+            toggled.push(attribute.value());
+            let field = value.field_name();
+            let raw = value.raw_ident();
+            toggles.push(quote! {
+                #attribute => Some(Self::#raw(!self.#field()?))
+            });
+        }
+        let doc = format!(" A mock of a [{name}] for tests, it publishes values as the device would and responds to set and get requests");
+        quote! {
+            #[cfg(feature = "mock")]
+            #[doc = #doc]
+            pub struct #mock {
+                device: ::testing::MockDevice,
+                // the raw value of each attribute, by attribute name
+                state: ::std::sync::Mutex<::std::collections::HashMap<String, ::serde_json::Value>>,
+            }
+
+            #[cfg(feature = "mock")]
+            impl #mock {
+                /// Create the mock, it responds to requests until the connection is closed
+                pub async fn new(connection: &::testing::Connection, name: &str) -> ::std::sync::Arc<Self> {
+                    let (device, requests) = ::testing::MockDevice::connect(connection, name).await;
+                    let mock = ::std::sync::Arc::new(Self {
+                        device,
+                        state: ::std::sync::Mutex::default(),
+                    });
+                    ::tokio::spawn(::std::sync::Arc::clone(&mock).respond(requests));
+                    mock
+                }
+
+                async fn respond(self: ::std::sync::Arc<Self>, mut requests: ::testing::MockRequests) {
+                    while let Some(request) = requests.next().await {
+                        let attributes: Vec<String> = match request {
+                            ::testing::MockRequest::Set(values) => values.into_iter().map(|(attribute, value)| {
+                                let value = if value == "TOGGLE" {
+                                    self.toggled(&attribute).unwrap_or(value)
+                                } else {
+                                    value
+                                };
+                                self.attributes().insert(attribute.clone(), value);
+                                attribute
+                            }).collect(),
+                            ::testing::MockRequest::Get(values) => values.into_iter().map(|(attribute, _)| attribute).collect(),
+                        };
+                        // zigbee2mqtt publishes the state of the attributes which were requested
+                        let payload: ::serde_json::Map<String, ::serde_json::Value> = {
+                            let state = self.attributes();
+                            attributes.into_iter().filter_map(|attribute| {
+                                let value = state.get(&attribute)?.clone();
+                                Some((attribute, value))
+                            }).collect()
+                        };
+                        if !payload.is_empty() {
+                            self.device.publish(::serde_json::Value::Object(payload)).await;
+                        }
+                    }
+                }
+
+                fn toggled(&self, attribute: &str) -> Option<::serde_json::Value> {
+                    match attribute {
+                        #(#toggles,)*
+                        _ => None,
+                    }
+                }
+
+                fn attributes(&self) -> ::std::sync::MutexGuard<'_, ::std::collections::HashMap<String, ::serde_json::Value>> {
+                    ::control::util::lock(&self.state)
+                }
+
+                async fn store_and_publish(&self, attribute: &str, value: ::serde_json::Value) {
+                    self.attributes().insert(attribute.to_string(), value.clone());
+                    self.device.publish(::serde_json::json!({ attribute: value })).await;
+                }
+
+                #(#methods)*
+            }
+        }
+    }
+}
+
+impl Value {
+    fn mock_methods(&self, mod_name: &Ident) -> TokenStream {
+        let attribute = &self.attribute_name;
+        let field = self.field_name();
+        let publish = Ident::new(&format!("publish_{field}"), field.span());
+        let raw = self.raw_ident();
+        let ty = &self.value_type;
+        let publish_doc = format!(" Publish a new value of `{}` as the device", attribute.value());
+        let get_doc = format!(" The current value of `{}`, if it has been published or set", attribute.value());
+        let deserialize = Ident::new(&format!("deserialize_{field}"), field.span());
+        let (to_raw, from_raw) = match &self.value_type {
+            _ if self.with.is_some() => {
+                let with = &self.with;
+                (
+                    quote! { #with::serialize(&value, ::serde_json::value::Serializer).unwrap_or(::serde_json::Value::Null) },
+                    quote! { #mod_name::#deserialize(value).ok()?.value() },
+                )
+            }
+            Type::Enum { .. } | Type::Bool(Some(_)) | Type::Set { .. } => {
+                let convert = self.convert_ident();
+                (
+                    quote! { ::serde_json::json!(#mod_name::#convert(value)) },
+                    quote! { #mod_name::#deserialize(value).ok()?.value() },
+                )
+            }
+            Type::Number { .. } | Type::Float | Type::Bool(None) => (
+                quote! { ::serde_json::json!(value) },
+                quote! { ::serde_json::from_value(value).ok() },
+            ),
+        };
+        quote! {
+            #[doc = #publish_doc]
+            pub async fn #publish(&self, value: #ty) {
+                self.store_and_publish(#attribute, Self::#raw(value)).await;
+            }
+
+            #[doc = #get_doc]
+            pub fn #field(&self) -> Option<#ty> {
+                let value = self.attributes().get(#attribute)?.clone();
+                #from_raw
+            }
+
+            fn #raw(value: #ty) -> ::serde_json::Value {
+                #to_raw
+            }
+        }
+    }
+
+    fn raw_ident(&self) -> Ident {
+        let name = self.field_name();
+        Ident::new(&format!("raw_{name}"), name.span())
+    }
+}
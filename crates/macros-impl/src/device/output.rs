@@ -1,7 +1,8 @@
-use super::{Device, Mode, NumericKind, SubPub, Type, Value, Variant};
+use super::{Command, Device, GeneratedEnum, Mode, NumericKind, Param, SubPub, Type, Value, Variant};
 use proc_macro2::{Group, Ident, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
 use std::collections::HashMap;
+use syn::ext::IdentExt;
 use syn::{parse_str, LitStr, Path};
 
 macro_rules! imports {
@@ -65,7 +66,30 @@ impl ToTokens for Device {
             };
             (ident, path.into_token_stream())
         }).collect();
-        import_idents(self.build_token_stream(), &imports)
+        // generated enums are not imported since their variants could be named after an import
+        let enums = self.enums.iter().map(GeneratedEnum::definition).collect::<Vec<_>>();
+        // rebuild the device when the exposes file changes
+        let exposes = self.exposes.as_ref().map(|path| quote! {
+            const _: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/", #path));
+        });
+        // written by hand, so it's left to use the imports of the calling module
+        let name = &self.name;
+        let impl_items = &self.impl_items;
+        let impl_block = (!impl_items.is_empty()).then(|| quote! {
+            impl #name {
+                #(#impl_items)*
+            }
+        });
+        // the mock only uses full paths
+        let mock = self.mock();
+        let device = import_idents(self.build_token_stream(), &imports);
+        quote! {
+            #device
+            #(#enums)*
+            #exposes
+            #impl_block
+            #mock
+        }
     }
 }
 
@@ -176,14 +200,20 @@ impl Device {
             url,
             name,
             values,
+            commands,
+            impl_items: _,
+            exposes: _,
+            enums: _,
+            mapping_tests: _,
         } = self;
         let update = Ident::new(&format!("{name}Update"), name.span());
         let fields = values.iter().map(|value| value.field(&update));
         let methods = values.iter().map(Value::method);
+        let command_methods = commands.iter().map(Command::method);
         let values_set = values.iter().map(|value| value.set(&update, &mod_name));
-        let (publish, set_publish, define_publish) = if values.iter().any(Value::requires_publish) {
+        let (publish, set_publish, define_publish) = if values.iter().any(Value::requires_publish) || !commands.is_empty() {
             (
-                Some(quote! { publish: tokio::sync::mpsc::Sender<crate::publish::Publish>, }),
+                Some(quote! { publish: crate::publish::Publisher, }),
                 Some(quote! { publish, }),
                 Some(quote! { let publish = manager.outgoing_publishes(); }),
             )
@@ -231,6 +261,7 @@ impl Device {
                 }
 
                 async fn new_with_args(manager: &mut crate::Manager, info: ::control::reflect::DeviceInfo, _: ()) -> Result<Self, anyhow::Error> {
+                    manager.register_device(info.name.clone());
                     #define_publish
                     #define_updates
                     Ok(Self {
@@ -243,9 +274,26 @@ impl Device {
             }
 
         impl #name {
+                        /// Returns the name of this device, eg: to identify it in logs
+                        pub fn name(&self) -> &str {
+                            &self.info.name
+                        }
+
                         #(#methods)*
+
+                        #(#command_methods)*
                     }
 
+        // the channels are left out, they only identify the manager's internals
+        impl ::std::fmt::Debug for #name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.debug_struct(stringify!(#name))
+                    .field("id", &self.info.id)
+                    .field("name", &self.info.name)
+                    .finish_non_exhaustive()
+            }
+        }
+
         #updates
 
         #reflect
@@ -253,27 +301,40 @@ impl Device {
     }
 
     fn updates(self) -> impl ToTokens {
+        let tests = self.mapping_tests.then(|| self.mapping_tests());
         let mod_name = self.mod_name();
         let name = self.name;
         let update = Ident::new(&format!("{name}Update"), name.span());
         let enum_fn = self.values.clone().into_iter().map(|value| {
             let name = value.field_name();
             let fn_name = Ident::new(&format!("deserialize_{name}"), name.span());
+            if let Some(with) = &value.with {
+                let ty = &value.value_type;
+                return quote! {
+                pub(super) fn #fn_name<'de, D>(deserializer: D) -> Result<crate::Reported<#ty>, D::Error> where D: Deserializer<'de> {
+                    use serde::de::Error;
+                    match <Option<::serde_json::Value> as Deserialize>::deserialize(deserializer)? {
+                        Some(value) => #with::deserialize(value).map(crate::Reported::Value).map_err(D::Error::custom),
+                        None => Ok(crate::Reported::Null),
+                    }
+                }
+            }
+            }
             match &value.value_type {
                 Type::Enum { path, variants } => {
                     let ty = &value.value_type;
                     let variants = variants.iter().map(|Variant { zigbee, rust }| {
                         quote! {
-                            Some(#zigbee) => Some(#path::#rust)
+                            Some(#zigbee) => crate::Reported::Value(#path::#rust)
                         }
                     });
                     quote! {
-                pub(super) fn #fn_name<'de, D>(deserializer: D) -> Result<Option<#ty>, D::Error> where D: Deserializer<'de> {
+                pub(super) fn #fn_name<'de, D>(deserializer: D) -> Result<crate::Reported<#ty>, D::Error> where D: Deserializer<'de> {
                     use serde::de::Error;
                     Ok(match <Option<String> as Deserialize>::deserialize(deserializer)?.as_deref() {
                         #(#variants,)*
                         Some(unknown) => return Err(D::Error::custom(format!("unknown value for {}: {}", stringify!(#name), unknown))),
-                        None => None
+                        None => crate::Reported::Null
                     })
                 }
             }
@@ -281,54 +342,142 @@ impl Device {
                 Type::Bool(Some([false_value, true_value])) => {
                     let ty = &value.value_type;
                     quote! {
-                pub(super) fn #fn_name<'de, D>(deserializer: D) -> Result<Option<#ty>, D::Error> where D: Deserializer<'de> {
+                pub(super) fn #fn_name<'de, D>(deserializer: D) -> Result<crate::Reported<#ty>, D::Error> where D: Deserializer<'de> {
                     use serde::de::Error;
                     Ok(match <Option<String> as Deserialize>::deserialize(deserializer)?.as_deref() {
-                        Some(#false_value) => Some(false),
-                        Some(#true_value) => Some(true),
+                        Some(#false_value) => crate::Reported::Value(false),
+                        Some(#true_value) => crate::Reported::Value(true),
                         Some(unknown) => return Err(D::Error::custom(format!("unknown value for {}: {}", stringify!(#name), unknown))),
-                        None => None
+                        None => crate::Reported::Null
                     })
                 }
             }
                 }
-                Type::Number { .. } | Type::Bool(None) => quote! {}
+                Type::Set { path, variants } => {
+                    let ty = &value.value_type;
+                    let variants = variants.iter().map(|Variant { zigbee, rust }| {
+                        quote! {
+                            #zigbee => #path::#rust
+                        }
+                    });
+                    quote! {
+                pub(super) fn #fn_name<'de, D>(deserializer: D) -> Result<crate::Reported<#ty>, D::Error> where D: Deserializer<'de> {
+                    use serde::de::Error;
+                    let Some(values) = <Option<Vec<String>> as Deserialize>::deserialize(deserializer)? else {
+                        return Ok(crate::Reported::Null)
+                    };
+                    values.iter().map(|value| Ok(match value.as_str() {
+                        #(#variants,)*
+                        unknown => return Err(D::Error::custom(format!("unknown value for {}: {}", stringify!(#name), unknown))),
+                    })).collect::<Result<#ty, D::Error>>().map(crate::Reported::Value)
+                }
+            }
+                }
+                Type::Number { .. } | Type::Float | Type::Bool(None) => quote! {}
             }
         });
-        let fields = self.values.clone().into_iter().map(|value| {
+        let attribute_names: Vec<_> = self.values.iter().map(|value| value.attribute_name.value()).collect();
+        let is_shared = |attribute: &String| attribute_names.iter().filter(|other| *other == attribute).count() > 1;
+        let fields = self.values.iter().enumerate().filter_map(|(index, value)| {
+            let attribute = value.attribute_name.value();
+            if is_shared(&attribute) {
+                // an attribute shared by several values is deserialized once, then by each getter
+                if attribute_names[..index].contains(&attribute) {
+                    return None;
+                }
+                let shared = value.shared_ident();
+                let attribute = &value.attribute_name;
+                let mut aliases: Vec<_> = self.values.iter()
+                    .filter(|other| other.attribute_name.value() == attribute.value())
+                    .flat_map(|other| other.aliases.iter().map(LitStr::value))
+                    .collect();
+                aliases.sort();
+                aliases.dedup();
+                return Some(quote! {
+                    #[serde(default, rename = #attribute)]
+                    #(#[serde(alias = #aliases)])*
+                    #shared: crate::Reported<::serde_json::Value>
+                });
+            }
             let name = value.field_name();
-            let attr = if let Type::Enum { .. } | Type::Bool(Some(_)) = &value.value_type {
+            let rename = (name != attribute).then(|| {
+                let attribute = &value.attribute_name;
+                quote! { #[serde(rename = #attribute)] }
+            });
+            let aliases = &value.aliases;
+            let aliases = quote! { #(#[serde(alias = #aliases)])* };
+            // deserializers are only called for fields which are present, absent fields use the default
+            let attr = if value.has_deserializer() {
                 let deserialize_with =
                     LitStr::new(&format!("{mod_name}::deserialize_{name}"), name.span());
                 quote! {
-                    #[serde(deserialize_with=#deserialize_with)]
+                    #[serde(default, deserialize_with=#deserialize_with)]
                 }
             } else {
-                quote! {}
+                quote! {
+                    #[serde(default)]
+                }
             };
-            let ty = value.value_type;
-            let docs = value.docs;
-            quote! {
+            let ty = &value.value_type;
+            let docs = &value.docs;
+            Some(quote! {
                 #attr
+                #rename
+                #aliases
                 #(#[doc = #docs])*
                 ///
-                ///Will be None only if the value was not included in the received update
-                pub #name: Option<#ty>
-            }
-        });
-        let getters = self.values.clone().into_iter().map(|value| {
+                ///Distinguishes a value which was not included in the received update from one reported as `null`
+                pub #name: crate::Reported<#ty>
+            })
+        }).collect::<Vec<_>>();
+        let getters = self.values.iter().map(|value| {
             let name = value.field_name();
-            let ty = value.value_type;
+            let ty = &value.value_type;
+            if !is_shared(&value.attribute_name.value()) {
+                return quote! {
+                    fn #name(self) -> Option<#ty> {
+                        self.#name.value()
+                    }
+                };
+            }
+            let shared = value.shared_ident();
+            // a value of the attribute which is not this value's is left to the values sharing it
+            let convert = if value.has_deserializer() {
+                let deserialize = Ident::new(&format!("deserialize_{name}"), name.span());
+                quote! { #mod_name::#deserialize(value).ok()?.value() }
+            } else {
+                quote! { ::serde_json::from_value::<#ty>(value).ok() }
+            };
+            let docs = &value.docs;
             quote! {
-                fn #name(self) -> Option<#ty> {
-                    self.#name
+                #(#[doc = #docs])*
+                ///
+                ///Returns `None` if the attribute was not included in the update, was `null` or was a value of another value sharing it's attribute
+                pub fn #name(self) -> Option<#ty> {
+                    let value = self.#shared.value()?;
+                    #convert
                 }
             }
-        });
+        }).collect::<Vec<_>>();
         let convert_fn = self
             .values
             .into_iter()
             .map(|value| match &value.value_type {
+                // values which are never published have nothing to convert
+                _ if value.with.is_some() && value.requires_publish() => {
+                    let name = value.convert_ident();
+                    let field = value.field_name();
+                    let ty = &value.value_type;
+                    let with = &value.with;
+                    quote! {
+                        pub(super) fn #name(value: #ty) -> ::serde_json::Value {
+                            #with::serialize(&value, ::serde_json::value::Serializer).unwrap_or_else(|error| {
+                                ::tracing::error!("failed to serialize {}: {error}", stringify!(#field));
+                                ::serde_json::Value::Null
+                            })
+                        }
+                    }
+                }
                 Type::Enum { path, variants } => {
                     let variants = variants.iter().map(|Variant { zigbee, rust }| {
                         quote! {
@@ -344,6 +493,22 @@ impl Device {
                         }
                     }
                 }
+                Type::Set { path, variants } => {
+                    let ty = &value.value_type;
+                    let variants = variants.iter().map(|Variant { zigbee, rust }| {
+                        quote! {
+                            #path::#rust => #zigbee
+                        }
+                    });
+                    let name = value.convert_ident();
+                    quote! {
+                        pub(super) fn #name(value: #ty) -> Vec<String> {
+                            value.into_iter().map(|value| match value {
+                                #(#variants,)*
+                            }.to_string()).collect()
+                        }
+                    }
+                }
                 Type::Bool(Some([false_str, true_str])) => {
                     let name = value.convert_ident();
                     quote! {
@@ -356,10 +521,10 @@ impl Device {
                         }
                     }
                 }
-                Type::Number { .. } | Type::Bool(None) => quote! {},
+                Type::Number { .. } | Type::Float | Type::Bool(None) => quote! {},
             });
         quote! {
-            #[derive(Deserialize, Clone)]
+            #[derive(Deserialize, Clone, Debug)]
             #[doc = concat!("An update from a ", stringify!(#name), " device")]
             pub struct #update {
                 #(#fields),*
@@ -383,11 +548,64 @@ impl Device {
                 #(#enum_fn)*
 
                 #(#convert_fn)*
+
+                #tests
+            }
+        }
+    }
+
+    /// Tests checking each enum and bool mapping converts to it's zigbee string and back, eg: to
+    /// catch a typo in a mapping
+    fn mapping_tests(&self) -> TokenStream {
+        let tests = self.values.iter().filter(|value| value.with.is_none()).filter_map(|value| {
+            let pairs: Vec<_> = match &value.value_type {
+                Type::Enum { path, variants } => variants.iter().map(|Variant { zigbee, rust }| quote! { (#path::#rust, #zigbee) }).collect(),
+                Type::Bool(Some([false_str, true_str])) => vec![quote! { (false, #false_str) }, quote! { (true, #true_str) }],
+                // each value of a set is checked on it's own
+                Type::Set { path, variants } => variants.iter().map(|Variant { zigbee, rust }| {
+                    quote! { (::control::reflect::value::EnumSet::from_iter([#path::#rust]), #zigbee) }
+                }).collect(),
+                Type::Number { .. } | Type::Float | Type::Bool(None) => return None,
+            };
+            let (expected, json) = if matches!(value.value_type, Type::Set { .. }) {
+                (
+                    quote! { vec![zigbee.to_string()] },
+                    quote! { ::serde_json::json!([zigbee]) },
+                )
+            } else {
+                (
+                    quote! { zigbee },
+                    quote! { ::serde_json::Value::String(zigbee.to_string()) },
+                )
+            };
+            let name = value.field_name();
+            let test = Ident::new(&format!("{name}_mapping"), name.span());
+            let convert = value.convert_ident();
+            let deserialize = Ident::new(&format!("deserialize_{name}"), name.span());
+            Some(quote! {
+                #[test]
+                fn #test() {
+                    for (value, zigbee) in [#(#pairs),*] {
+                        assert_eq!(zigbee, zigbee.trim(), "{zigbee:?} has surrounding whitespace");
+                        assert_eq!(#convert(Clone::clone(&value)), #expected);
+                        let parsed = #deserialize(#json)
+                            .expect("failed to deserialize mapped value");
+                        assert_eq!(parsed, crate::Reported::Value(value));
+                    }
+                }
+            })
+        });
+        quote! {
+            #[cfg(test)]
+            mod tests {
+                use super::*;
+
+                #(#tests)*
             }
         }
     }
 
-    fn mod_name(&self) -> Ident {
+    pub(super) fn mod_name(&self) -> Ident {
         let name = &self.name;
         Ident::new(&format!("_{name}"), name.span())
     }
@@ -548,6 +766,68 @@ impl Device {
     }
 }
 
+impl Command {
+    fn method(&self) -> TokenStream {
+        let Self { docs, attribute_name, name, params } = self;
+        let command = name.unraw().to_string();
+        let args = params.iter().map(|Param { name, value_type }| quote! { #name: #value_type });
+        let converts = params.iter().map(Param::convert);
+        let keys = params.iter().map(|param| param.name.unraw().to_string());
+        let names = params.iter().map(|param| &param.name);
+        let payload = quote! {
+            ::serde_json::json!({ #(#keys: #names),* })
+        };
+        // the parameters are nested under the attribute, eg: {"warning": {"mode": ..., "duration": ...}}
+        let payload = match attribute_name {
+            Some(attribute) => quote! { ::serde_json::json!({ #attribute: #payload }) },
+            None => payload,
+        };
+        quote! {
+            #(#[doc = #docs])*
+            pub async fn #name(&self, #(#args),*) -> Result<(), anyhow::Error> {
+                use anyhow::Context;
+                #(#converts)*
+                let payload = #payload;
+                if self.publish.intercept(&self.info.name, #command, format_args!("{payload}")) {
+                    return Ok(());
+                }
+                let publish = crate::publish::Publish::new(format!("{}/set", self.info.name), payload)
+                    .context("serialize JSON")?;
+                self.publish.send(publish).await.context("publish command")
+            }
+        }
+    }
+}
+
+impl Param {
+    /// Converts the parameter to the value sent to zigbee2mqtt, shadowing it
+    fn convert(&self) -> TokenStream {
+        let name = &self.name;
+        match &self.value_type {
+            Type::Enum { path, variants } => {
+                let variants = variants.iter().map(|Variant { zigbee, rust }| quote! { #path::#rust => #zigbee });
+                quote! {
+                    let #name = match #name {
+                        #(#variants,)*
+                    };
+                }
+            }
+            Type::Bool(Some([false_str, true_str])) => quote! {
+                let #name = if #name { #true_str } else { #false_str };
+            },
+            Type::Set { path, variants } => {
+                let variants = variants.iter().map(|Variant { zigbee, rust }| quote! { #path::#rust => #zigbee });
+                quote! {
+                    let #name: Vec<&str> = #name.into_iter().map(|value| match value {
+                        #(#variants,)*
+                    }).collect();
+                }
+            }
+            Type::Number { .. } | Type::Float | Type::Bool(None) => quote! {},
+        }
+    }
+}
+
 impl Value {
     fn field(&self, update: &Ident) -> TokenStream {
         let name = self.field_name();
@@ -584,7 +864,16 @@ impl Value {
             #update::#getter
         };
         let (new, to_device) = match &self.value_type {
-            Type::Enum { .. } | Type::Bool(Some(_)) => {
+            _ if self.with.is_some() => {
+                let convert = self.convert_ident();
+                (
+                    quote! { new_mapped },
+                    Some(quote! {
+                        #mod_name::#convert
+                    }),
+                )
+            }
+            Type::Enum { .. } | Type::Bool(Some(_)) | Type::Set { .. } => {
                 let convert = self.convert_ident();
                 (
                     quote! { new_mapped },
@@ -593,7 +882,7 @@ impl Value {
                     }),
                 )
             }
-            Type::Number { .. } | Type::Bool(None) => (quote! { new }, None),
+            Type::Number { .. } | Type::Float | Type::Bool(None) => (quote! { new }, None),
         };
         match self.mode.sub_pub() {
             SubPub::SubOnly => {
@@ -626,12 +915,22 @@ impl Value {
 
     fn zigbee_type(&self) -> TokenStream {
         match &self.value_type {
+            _ if self.with.is_some() => {
+                quote! {
+                    ::serde_json::Value
+                }
+            }
             Type::Enum { .. } | Type::Bool(Some(_)) => {
                 quote! {
                     String
                 }
             }
-            Type::Number { .. } | Type::Bool(None) => self.value_type.to_token_stream(),
+            Type::Set { .. } => {
+                quote! {
+                    Vec<String>
+                }
+            }
+            Type::Number { .. } | Type::Float | Type::Bool(None) => self.value_type.to_token_stream(),
         }
     }
 
@@ -662,14 +961,14 @@ impl Value {
         }
     }
 
-    fn convert_ident(&self) -> Ident {
+    pub(super) fn convert_ident(&self) -> Ident {
         let name = self.field_name();
         Ident::new(&format!("convert_{name}"), name.span())
     }
 }
 
 impl Value {
-    fn field_name(&self) -> Ident {
+    pub(super) fn field_name(&self) -> Ident {
         self.value_name
             .clone()
             .unwrap_or_else(|| Ident::new(&self.attribute_name.value(), self.attribute_name.span()))
@@ -743,6 +1042,12 @@ impl ToTokens for Type {
                     }
                 }
             }
+            Type::Float => {
+                quote! {f64}
+            }
+            Type::Set { path, .. } => {
+                quote! { ::control::reflect::value::EnumSet<#path> }
+            }
             Type::Bool(_) => {
                 quote! {bool}
             }
@@ -751,6 +1056,18 @@ impl ToTokens for Type {
 }
 
 impl Value {
+    /// Returns true if the value is deserialized by a generated function, rather than it's type's
+    /// `Deserialize` implementation
+    fn has_deserializer(&self) -> bool {
+        self.with.is_some() || matches!(self.value_type, Type::Enum { .. } | Type::Bool(Some(_)) | Type::Set { .. })
+    }
+
+    /// The field of the update holding the raw JSON of an attribute shared by several values
+    fn shared_ident(&self) -> Ident {
+        let attribute = self.attribute_name.value().replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        Ident::new(&format!("shared_{attribute}"), self.attribute_name.span())
+    }
+
     fn requires_publish(&self) -> bool {
         self.mode.sub_pub() != SubPub::SubOnly
     }
@@ -759,3 +1076,25 @@ impl Value {
         self.mode.sub_pub() != SubPub::PubOnly
     }
 }
+
+impl GeneratedEnum {
+    fn definition(&self) -> TokenStream {
+        let Self { docs, name, variants } = self;
+        let definitions = variants.iter().map(|Variant { zigbee, rust }| {
+            quote! {
+                #[doc = concat!("`", #zigbee, "`")]
+                #rust
+            }
+        });
+        let values = variants.iter().map(|Variant { zigbee, rust }| quote! { #zigbee => #rust });
+        quote! {
+            #(#[doc = #docs])*
+            #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+            pub enum #name {
+                #(#definitions),*
+            }
+
+            ::control::reflect::enum_value!(#name, #(#values),*);
+        }
+    }
+}
@@ -2,19 +2,16 @@ use proc_macro2::{Ident, TokenStream};
 use quote::quote;
 use syn::parse::{Parse, ParseStream, Parser};
 use syn::spanned::Spanned;
-use syn::{braced, parse_quote, Attribute, Data, DeriveInput, Expr, ExprAssign, ExprLit, ExprPath, Lit, Member, Meta, MetaList, MetaNameValue, Token};
+use syn::{braced, parse_quote, Attribute, Data, DeriveInput, Expr, ExprAssign, ExprLit, ExprPath, GenericArgument, Lit, Member, Meta, MetaList, MetaNameValue, PathArguments, Token, Type, TypeArray, TypePath};
 
 pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
-    let input_span = input.span();
     let name = input.ident;
-    let Data::Struct(data) = input.data else {
-        return Err(syn::Error::new(
-            input_span,
-            "can only derive DeviceSet for structs",
-        ));
+    let data = match input.data {
+        Data::Struct(data) => data,
+        Data::Enum(data) => return Err(syn::Error::new(data.enum_token.span(), "can only derive DeviceSet for structs")),
+        Data::Union(data) => return Err(syn::Error::new(data.union_token.span(), "can only derive DeviceSet for structs")),
     };
-    let members = data.fields.members();
-    let member_count = data.fields.len();
+    let mut devices = Vec::new();
     let fields = data
         .fields
         .iter()
@@ -27,8 +24,16 @@ pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
             let mut device_name = None;
             let mut description = None;
             let mut tags_map = None;
+            let mut names = None;
+            let mut name_from_env = None;
+            let mut profiles = None;
+            let mut flags = Vec::new();
             let args: Vec<_> = extra_args.into_iter().filter_map(|arg| {
                 match arg {
+                    Arg::Flag(flag) => {
+                        flags.push(flag);
+                        None
+                    }
                     Arg::Normal(name, expr) => match name.to_string().as_str() {
                         "id" => {
                             id = Some(expr);
@@ -42,6 +47,18 @@ pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
                             description = Some(expr);
                             None
                         }
+                        "names" => {
+                            names = Some(expr);
+                            None
+                        }
+                        "name_from_env" => {
+                            name_from_env = Some(expr);
+                            None
+                        }
+                        "profiles" => {
+                            profiles = Some(expr);
+                            None
+                        }
                         _ => Some(quote! {
                             .#name(#expr)
                         })
@@ -60,17 +77,41 @@ pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
                     }
                 }
             }).collect();
-            let id = match (id, &field.ident) {
-                (None, None) => {
-                    return Err(syn::Error::new(span, "explicit id param required for unnamed fields"))
-                }
-                (None, Some(name)) => {
-                    let name = name.to_string();
-                    parse_quote!(#name)
+            let mut nested = None;
+            let mut skip = None;
+            for flag in flags {
+                match flag.to_string().as_str() {
+                    "set" => nested = Some(flag),
+                    "skip" => skip = Some(flag),
+                    _ => return Err(syn::Error::new(flag.span(), format!("unknown flag: '{flag}', expected set or skip"))),
                 }
-                (Some(id), _) => id
+            }
+            let member = if let Some(name) = field.ident.clone() {
+                Member::Named(name)
+            } else {
+                Member::Unnamed(i.into())
             };
-            let device_name = device_name.unwrap_or_else(|| id.clone());
+            let ty = field.ty;
+            let has_params = id.is_some() || device_name.is_some() || name_from_env.is_some() || description.is_some() || tags_map.is_some() || names.is_some() || profiles.is_some() || !args.is_empty();
+            if let Some(flag) = skip {
+                // not a device, eg: helper state, so it's neither created nor iterated over
+                if has_params || nested.is_some() {
+                    return Err(syn::Error::new(flag.span(), "a skipped field cannot have any other params"))
+                }
+                return Ok(quote! {
+                    #member: ::core::default::Default::default()
+                })
+            }
+            if let Some(flag) = nested {
+                // a nested set creates its own devices, so device params are meaningless here
+                if has_params {
+                    return Err(syn::Error::new(flag.span(), "a nested device set cannot have any other params"))
+                }
+                devices.push(quote! { devices.extend(self.#member); });
+                return Ok(quote! {
+                    #member: <#ty as ::home_control::device::DeviceSet>::new(manager).await?
+                })
+            }
             let description = description.unwrap_or_else(|| if docs.is_empty() {
                 parse_quote!(None)
             } else {
@@ -81,15 +122,8 @@ pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
             let tags = tags_map.unwrap_or_else(|| parse_quote! {
                 std::collections::HashMap::<String, String>::default()
             });
-
-            let member = if let Some(name) = field.ident {
-                Member::Named(name)
-            } else {
-                Member::Unnamed(i.into())
-            };
-            let ty = field.ty;
-            Ok(quote! {
-                #member: #ty::create()
+            let create = |ty: &Type, id: &Expr, device_name: &Expr| quote! {
+                #ty::create()
                     .manager(manager.device_manager()?)
                     .info(::home_control::reflect::DeviceInfo {
                         id: #id.to_string(),
@@ -99,7 +133,98 @@ pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
                     })
                     #(#args)*
                     .call()
-                    .await?
+                    .await
+            };
+
+            let profiles = match profiles {
+                None => None,
+                Some(Expr::Array(profiles)) if option_inner(&ty).is_some() => Some(profiles.elems),
+                Some(Expr::Array(profiles)) => {
+                    return Err(syn::Error::new(profiles.span(), "profiles can only be used with Option fields, they are None in other profiles"))
+                }
+                Some(profiles) => {
+                    return Err(syn::Error::new(profiles.span(), "profiles should be an array of profile names, eg: [\"home\"]"))
+                }
+            };
+
+            if let Some(elem) = collection_element(&ty) {
+                let Some(names) = names else {
+                    return Err(syn::Error::new(span, "a names param is required for Vec and array fields"))
+                };
+                let Expr::Array(names) = &names else {
+                    return Err(syn::Error::new(names.span(), "names should be an array of device ids"))
+                };
+                if let Some(param) = id.or(device_name).or(name_from_env) {
+                    return Err(syn::Error::new(param.span(), "each device is named by the names param, id and name cannot be used"))
+                }
+                // each element is created with it's own id, any other params are shared
+                let elements = names.elems.iter().map(|id| {
+                    let create = create(elem, id, id);
+                    quote! { #create? }
+                });
+                let elements = if let Type::Array(_) = &ty {
+                    quote! { [#(#elements),*] }
+                } else {
+                    quote! { vec![#(#elements),*] }
+                };
+                devices.push(quote! {
+                    devices.extend(self.#member.into_iter().map(|device| Box::new(device) as Box<dyn ::home_control::reflect::Device>));
+                });
+                return Ok(quote! {
+                    #member: #elements
+                })
+            }
+            if let Some(names) = names {
+                return Err(syn::Error::new(names.span(), "names can only be used with Vec and array fields"))
+            }
+
+            let id = match (id, &field.ident) {
+                (None, None) => {
+                    return Err(syn::Error::new(span, "explicit id param required for unnamed fields"))
+                }
+                (None, Some(name)) => {
+                    let name = name.to_string();
+                    parse_quote!(#name)
+                }
+                (Some(id), _) => id
+            };
+            let device_name = match (device_name, name_from_env) {
+                (Some(_), Some(env)) => {
+                    return Err(syn::Error::new(env.span(), "name and name_from_env cannot be used together"))
+                }
+                (Some(device_name), None) => device_name,
+                // resolved at runtime, eg: to use different names with a test broker
+                (None, Some(env)) => parse_quote! {
+                    ::std::env::var(#env).unwrap_or_else(|_| #id.to_string())
+                },
+                (None, None) => id.clone(),
+            };
+            if let Some(inner) = option_inner(&ty) {
+                // a device which failed to be created is left out rather than failing the whole set
+                let create = create(inner, &id, &device_name);
+                devices.push(quote! {
+                    devices.extend(self.#member.map(|device| Box::new(device) as Box<dyn ::home_control::reflect::Device>));
+                });
+                let optional = quote! {
+                    ::home_control::device::optional_device(&#id.to_string(), #create)
+                };
+                let Some(profiles) = profiles else {
+                    return Ok(quote! {
+                        #member: #optional
+                    })
+                };
+                return Ok(quote! {
+                    #member: if manager.profile().is_none_or(|profile| [#profiles].contains(&profile)) {
+                        #optional
+                    } else {
+                        None
+                    }
+                })
+            }
+            devices.push(quote! { devices.push(Box::new(self.#member)); });
+            let create = create(&ty, &id, &device_name);
+            Ok(quote! {
+                #member: #create?
             })
         })
         .collect::<syn::Result<Vec<_>>>()?;
@@ -114,19 +239,48 @@ pub fn device_set(input: DeriveInput) -> syn::Result<TokenStream> {
 
         impl IntoIterator for #name {
             type Item = Box<dyn ::home_control::reflect::Device>;
-            type IntoIter = std::array::IntoIter<Box<dyn ::home_control::reflect::Device>, #member_count>;
+            type IntoIter = std::vec::IntoIter<Box<dyn ::home_control::reflect::Device>>;
 
             fn into_iter(self) -> Self::IntoIter {
-                [
-                    #(
-                    Box::new(self.#members) as Box<dyn ::home_control::reflect::Device>
-                    ),*
-                ].into_iter()
+                let mut devices: Vec<Box<dyn ::home_control::reflect::Device>> = Vec::new();
+                #(#devices)*
+                devices.into_iter()
             }
         }
     })
 }
 
+/// Returns the element type if the type is a `Vec` or an array of devices
+pub(crate) fn collection_element(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Array(TypeArray { elem, .. }) => Some(elem),
+        _ => generic_argument(ty, "Vec"),
+    }
+}
+
+/// Returns the device type if the type is an `Option` of a device
+pub(crate) fn option_inner(ty: &Type) -> Option<&Type> {
+    generic_argument(ty, "Option")
+}
+
+/// Returns the only generic argument of the type if it's named `name`, eg: `Vec<T>`
+fn generic_argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let Type::Path(TypePath { qself: None, path }) = ty else {
+        return None;
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != name {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
 fn extra_args(attrs: Vec<Attribute>) -> Result<(Vec<Arg>, String), syn::Error> {
     let mut args = Vec::new();
     let mut docs = Vec::new();
@@ -198,6 +352,7 @@ fn extra_args(attrs: Vec<Attribute>) -> Result<(Vec<Arg>, String), syn::Error> {
 }
 
 enum Arg {
+    Flag(Ident),
     Normal(Ident, Expr),
     Tags(Vec<[Expr; 2]>)
 }
@@ -205,6 +360,9 @@ enum Arg {
 impl Parse for Arg {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let name: Ident = input.parse()?;
+        if !input.peek(Token![=]) {
+            return Ok(Self::Flag(name))
+        }
         input.parse::<Token![=]>()?;
         if name != "tags" {
             let expr = input.parse()?;
@@ -5,15 +5,17 @@ use proc_macro2::TokenStream;
 use quote::quote;
 pub use crate::device::Device;
 
+mod automation;
 mod device;
 mod device_set;
 mod automation_set;
-// mod tagged;
+mod tagged;
 
 pub fn device(input: Device) -> TokenStream {
     quote! { #input }
 }
 
 pub use device_set::device_set;
-pub use automation_set::automation_sets;
-// pub use tagged::tagged;
\ No newline at end of file
+pub use automation_set::automation_set;
+pub use tagged::tagged;
+pub use automation::{automation, AutomationInput};
\ No newline at end of file
@@ -1,14 +1,11 @@
+use crate::device_set::{collection_element, option_inner};
 use convert_case::{Case, Casing};
-use proc_macro2::{Ident, Span, TokenStream};
-use quote::{quote, ToTokens, TokenStreamExt};
-use std::collections::{HashMap, HashSet};
-use syn::parse::{ParseStream, Parser};
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
-use syn::{
-    Attribute, Data, DeriveInput, Expr, ExprStruct, FieldValue, Fields, ImplItem, ImplItemFn,
-    Index, Item, ItemImpl, ItemStruct, Member, Meta, MetaList, MetaNameValue, Path, Token, Type,
-    TypePath, Visibility, parse_quote,
-};
+use syn::{Attribute, Index, ItemStruct, Member, Meta, MetaList, Token, Type};
 
 pub fn tagged(args: TokenStream, input: ItemStruct) -> TokenStream {
     if !args.is_empty() {
@@ -16,207 +13,127 @@ pub fn tagged(args: TokenStream, input: ItemStruct) -> TokenStream {
             .to_compile_error();
     }
     match tagged_impl(input) {
-        Ok(items) => items.into_iter().map(ToTokens::into_token_stream).collect(),
+        Ok(tokens) => tokens,
         Err(error) => error.into_compile_error(),
     }
 }
 
-pub fn tagged_impl(mut input: ItemStruct) -> syn::Result<Vec<Item>> {
-    // let Fields::Named(fields) = input.fields else {
-    //     return Err(syn::Error::new(input.fields.span(), "Only named fields are supported."))
-    // };
-    let mut tag_sets = HashMap::new();
+fn tagged_impl(mut input: ItemStruct) -> syn::Result<TokenStream> {
+    // tags are kept in the order they first appear so the generated code is stable
+    let mut tags: Vec<Tag> = Vec::new();
     for (i, field) in input.fields.iter_mut().enumerate() {
-        for tag in tags(&mut field.attrs)? {
-            let fields = &mut tag_sets
-                .entry(tag.to_string().to_lowercase())
-                .or_insert(Tag::new(tag.to_string(), &input.vis))
-                .fields;
-            fields.push(Field {
-                name: field.ident.clone(),
-                member: match &field.ident {
-                    None => Member::Unnamed(Index {
-                        index: fields.len() as u32,
-                        span: field.span(),
-                    }),
-                    Some(name) => Member::Named(name.clone()),
-                },
-                original_member: match &field.ident {
-                    None => Member::Unnamed(Index {
-                        index: i as u32,
-                        span: field.span(),
-                    }),
-                    Some(name) => Member::Named(name.clone()),
-                },
-                ty: field.ty.clone(),
-            });
+        let member = match &field.ident {
+            None => Member::Unnamed(Index::from(i)),
+            Some(name) => Member::Named(name.clone()),
+        };
+        for tag in take_tags(&mut field.attrs)? {
+            let snake_name = tag.to_string().to_case(Case::Snake);
+            let index = match tags.iter().position(|existing| existing.snake_name == snake_name) {
+                Some(index) => index,
+                None => {
+                    tags.push(Tag {
+                        pascal_name: Ident::new(&tag.to_string().to_case(Case::Pascal), tag.span()),
+                        snake_name,
+                        method: Ident::new(&tag.to_string().to_case(Case::Snake), tag.span()),
+                        fields: Vec::new(),
+                    });
+                    tags.len() - 1
+                }
+            };
+            if !tags[index].fields.iter().any(|(existing, _)| *existing == member) {
+                tags[index].fields.push((member.clone(), field.ty.clone()));
+            }
         }
     }
 
-    let tag_type = {
-        let vis = &input.vis;
-        let tag_type = Ident::new(format!("{}Tag", input.ident).as_str(), input.ident.span());
-        let variants = tag_sets.values().map(|tag| tag.pascal_name);
-        parse_quote! {
+    let vis = &input.vis;
+    let name = &input.ident;
+    let tag_type = Ident::new(&format!("{name}Tag"), name.span());
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let variants = tags.iter().map(|tag| {
+        let variant = &tag.pascal_name;
+        let doc = format!("Devices tagged with `{}`", tag.snake_name);
+        quote! {
+            #[doc = #doc]
+            #variant
+        }
+    });
+    let methods = tags.iter().map(|tag| {
+        let method = &tag.method;
+        let doc = format!("Returns the devices tagged with `{}`", tag.snake_name);
+        let pushes = tag.fields.iter().map(|(member, ty)| push(member, ty));
+        quote! {
+            #[doc = #doc]
+            #vis fn #method(&self) -> Vec<&dyn ::home_control::reflect::Device> {
+                let mut devices: Vec<&dyn ::home_control::reflect::Device> = Vec::new();
+                #(#pushes)*
+                devices
+            }
+        }
+    });
+    let arms = tags.iter().map(|tag| {
+        let variant = &tag.pascal_name;
+        let method = &tag.method;
+        quote! { #tag_type::#variant => self.#method() }
+    });
+    let doc = format!("The tags of the devices in [{name}]");
+    Ok(quote! {
+        #input
+
+        #[doc = #doc]
+        #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
         #vis enum #tag_type {
             #(#variants),*
         }
-    }
-    };
 
-    let impl_block = {
-        let tag_type = tag_type.ident;
-        let name = &input.ident;
-        parse_quote! {
-            impl #name {
-                fn with_tag<'a, T>(&'a self, tags: #tag_type) -> impl IntoIterator<Item = T> {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#methods)*
 
+            /// Returns the devices with the given tag, eg: to operate on a whole area
+            #vis fn with_tag(&self, tag: #tag_type) -> Vec<&dyn ::home_control::reflect::Device> {
+                match tag {
+                    #(#arms,)*
                 }
             }
         }
-    };
-    let mut items = vec![Item::Struct(input), Item::Enum(tag_type), Item::Impl(impl_block)];
-    items.extend(tag_sets.values().flat_map(Tag::items));
-    Ok(items)
-}
-
-fn tags(attrs: &mut Vec<Attribute>) -> syn::Result<HashSet<Ident>> {
-    let mut tags = HashSet::new();
-    *attrs = attrs
-        .into_iter()
-        .filter_map(|attr| {
-            match &attr.meta {
-                Meta::Path(_) => {
-                    return Some(Err(syn::Error::new(attr.span(), "tag must be specified")));
-                }
-                Meta::List(MetaList { path, tokens, .. }) => {
-                    if !path.is_ident("tag") {
-                        return Some(Ok(attr.clone()));
-                    }
-                    let parser = |input: ParseStream| -> syn::Result<Vec<Ident>> {
-                        let idents =
-                            syn::punctuated::Punctuated::<Ident, Token![,]>::parse_terminated(
-                                input,
-                            )?;
-                        Ok(idents.into_iter().collect())
-                    };
-                    let idents = match parser.parse2(tokens.clone()) {
-                        Ok(idents) => idents,
-                        Err(error) => return Some(Err(error)),
-                    };
-                    tags.extend(idents);
-                }
-                Meta::NameValue(MetaNameValue { path, value, .. }) => {
-                    if !path.is_ident("tag") {
-                        return Some(Ok(attr.clone()));
-                    }
-                    let Expr::Path(value) = value else {
-                        return Some(Err(syn::Error::new(
-                            value.span(),
-                            "tag must be an identifier",
-                        )));
-                    };
-                    tags.insert(
-                        match value.path.require_ident() {
-                            Ok(ident) => ident,
-                            Err(error) => return Some(Err(error)),
-                        }
-                            .clone(),
-                    );
-                }
-            }
-            None
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(tags)
-}
-
-struct Tag {
-    visibility: Visibility,
-    pascal_name: String,
-    snake_name: String,
-    fields: Vec<Field>,
+    })
 }
 
-#[derive(Debug, Clone)]
-struct Field {
-    name: Option<Ident>,
-    member: Member,
-    original_member: Member,
-    ty: Type,
-}
-
-impl Tag {
-    fn new(name: String, visibility: &Visibility) -> Self {
-        Self {
-            visibility: visibility.clone(),
-            pascal_name: name.to_case(Case::Pascal),
-            snake_name: name.to_case(Case::Snake),
-            fields: vec![],
+/// Pushes the device, or each device, of the field
+fn push(member: &Member, ty: &Type) -> TokenStream {
+    if collection_element(ty).is_some() || option_inner(ty).is_some() {
+        quote! {
+            devices.extend(self.#member.iter().map(|device| device as &dyn ::home_control::reflect::Device));
+        }
+    } else {
+        quote! {
+            devices.push(&self.#member);
         }
     }
+}
 
-    fn items(&self) -> Vec<Item> {
-        let vis = &self.visibility;
-        let name = Ident::new(&self.pascal_name, Span::call_site());
-        let fields = self.fields.iter().map(|field| -> syn::Field {
-            let ty = &field.ty;
-            let name = field.name.iter();
-            parse_quote! { #(#name: )*&'a #ty }
-        });
-        let struc = parse_quote! {
-            #[derive(Clone, Copy)]
-            #vis struct #name<'a> {
-                #(#fields),*
-            }
-        };
-        let members = self.fields.iter().map(|field| &field.member).collect::<Vec<_>>();
-        let clauses = self.fields.iter().map(|field| {
-            let ty = &field.ty;
-            quote! {
-                &'a #ty: Into<T>
-            }
-        }).collect::<Vec<_>>();
-        let impl_block = parse_quote! {
-            impl<'a> #name<'a> {
-                fn all<T>(self) -> impl IntoIterator<Item = T>
-                where
-                    #(#clauses),*
-                {
-                    [
-                        #(self.#members.into()),*
-                    ]
-                }
-            }
+/// Removes the `#[tag(...)]` attributes of a field, returning the tags
+fn take_tags(attrs: &mut Vec<Attribute>) -> syn::Result<Vec<Ident>> {
+    let mut tags = Vec::new();
+    let mut kept = Vec::new();
+    for attr in attrs.drain(..) {
+        if !attr.path().is_ident("tag") {
+            kept.push(attr);
+            continue;
+        }
+        let Meta::List(MetaList { tokens, .. }) = &attr.meta else {
+            return Err(syn::Error::new(attr.span(), "tags should be listed, eg: #[tag(downstairs, lights)]"));
         };
-        vec![Item::Struct(struc), Item::Impl(impl_block)]
+        let idents = Punctuated::<Ident, Token![,]>::parse_terminated.parse2(tokens.clone())?;
+        tags.extend(idents);
     }
+    *attrs = kept;
+    Ok(tags)
+}
 
-    fn function(&self) -> ImplItemFn {
-        let vis = &self.visibility;
-        let ty = Ident::new(&self.pascal_name, Span::call_site());
-        let name = Ident::new(&self.snake_name, Span::call_site());
-        let members = self
-            .fields
-            .iter()
-            .enumerate()
-            .map(|(i, field)| -> FieldValue {
-                let original = &field.original_member;
-                let member = match original {
-                    Member::Unnamed(_) => Member::Unnamed(Index {
-                        index: i as u32,
-                        span: Span::call_site(),
-                    }),
-                    Member::Named(name) => Member::Named(name.clone()),
-                };
-                parse_quote!(#member : &self.#member)
-            });
-        parse_quote! {
-            #vis fn #name(&self) -> #ty<'_> {
-                #ty {
-                    #(#members),*
-                }
-            }
-        }
-    }
+struct Tag {
+    pascal_name: Ident,
+    snake_name: String,
+    method: Ident,
+    fields: Vec<(Member, Type)>,
 }
@@ -0,0 +1,102 @@
+impl ::home_control::device::DeviceSet for Devices {
+    async fn new(
+        manager: &mut ::home_control::Manager<'_>,
+    ) -> Result<Self, ::home_control::device::CreateDeviceError> {
+        Ok(Self {
+            bedroom_light: Light::create()
+                .manager(manager.device_manager()?)
+                .info(::home_control::reflect::DeviceInfo {
+                    id: "bedroom_light".to_string(),
+                    name: "bedroom_bulb".to_string(),
+                    description: Some(String::from("The bulb in the bedroom")),
+                    tags: {
+                        let mut tags = std::collections::HashMap::<
+                            String,
+                            String,
+                        >::new();
+                        tags.insert(
+                            stringify!(room).to_string(),
+                            Room::Bedroom.to_string(),
+                        );
+                        tags
+                    },
+                })
+                .ip(Ipv4Addr::new(192, 168, 1, 62))
+                .call()
+                .await?,
+            kitchen_sensor: TemperatureAndHumiditySensor::create()
+                .manager(manager.device_manager()?)
+                .info(::home_control::reflect::DeviceInfo {
+                    id: "kitchen_sensor".to_string(),
+                    name: ::std::env::var("KITCHEN_SENSOR")
+                        .unwrap_or_else(|_| "kitchen_sensor".to_string())
+                        .to_string(),
+                    description: None,
+                    tags: std::collections::HashMap::<String, String>::default(),
+                })
+                .call()
+                .await?,
+            office_light: if manager
+                .profile()
+                .is_none_or(|profile| ["home"].contains(&profile))
+            {
+                ::home_control::device::optional_device(
+                    &"office_light".to_string(),
+                    Light::create()
+                        .manager(manager.device_manager()?)
+                        .info(::home_control::reflect::DeviceInfo {
+                            id: "office_light".to_string(),
+                            name: "office_light".to_string(),
+                            description: None,
+                            tags: std::collections::HashMap::<String, String>::default(),
+                        })
+                        .call()
+                        .await,
+                )
+            } else {
+                None
+            },
+            doors: vec![
+                ContactSensor::create().manager(manager.device_manager() ?)
+                .info(::home_control::reflect::DeviceInfo { id : "door_1".to_string(),
+                name : "door_1".to_string(), description : None, tags :
+                std::collections::HashMap:: < String, String > ::default(), }).call().
+                await ?, ContactSensor::create().manager(manager.device_manager() ?)
+                .info(::home_control::reflect::DeviceInfo { id : "door_2".to_string(),
+                name : "door_2".to_string(), description : None, tags :
+                std::collections::HashMap:: < String, String > ::default(), }).call().
+                await ?
+            ],
+            outside: <OutsideDevices as ::home_control::device::DeviceSet>::new(manager)
+                .await?,
+        })
+    }
+}
+impl IntoIterator for Devices {
+    type Item = Box<dyn ::home_control::reflect::Device>;
+    type IntoIter = std::vec::IntoIter<Box<dyn ::home_control::reflect::Device>>;
+    fn into_iter(self) -> Self::IntoIter {
+        let mut devices: Vec<Box<dyn ::home_control::reflect::Device>> = Vec::new();
+        devices.push(Box::new(self.bedroom_light));
+        devices.push(Box::new(self.kitchen_sensor));
+        devices
+            .extend(
+                self
+                    .office_light
+                    .map(|device| {
+                        Box::new(device) as Box<dyn ::home_control::reflect::Device>
+                    }),
+            );
+        devices
+            .extend(
+                self
+                    .doors
+                    .into_iter()
+                    .map(|device| {
+                        Box::new(device) as Box<dyn ::home_control::reflect::Device>
+                    }),
+            );
+        devices.extend(self.outside);
+        devices.into_iter()
+    }
+}
@@ -0,0 +1,38 @@
+impl ::home_control::device::DeviceSet for Devices {
+    async fn new(
+        manager: &mut ::home_control::Manager<'_>,
+    ) -> Result<Self, ::home_control::device::CreateDeviceError> {
+        Ok(Self {
+            hallway_button: HueSmartButton::create()
+                .manager(manager.device_manager()?)
+                .info(::home_control::reflect::DeviceInfo {
+                    id: "hallway_button".to_string(),
+                    name: "hallway_button".to_string(),
+                    description: None,
+                    tags: std::collections::HashMap::<String, String>::default(),
+                })
+                .call()
+                .await?,
+            hallway_light: Light::create()
+                .manager(manager.device_manager()?)
+                .info(::home_control::reflect::DeviceInfo {
+                    id: "hallway_light".to_string(),
+                    name: "hallway_light".to_string(),
+                    description: None,
+                    tags: std::collections::HashMap::<String, String>::default(),
+                })
+                .call()
+                .await?,
+        })
+    }
+}
+impl IntoIterator for Devices {
+    type Item = Box<dyn ::home_control::reflect::Device>;
+    type IntoIter = std::vec::IntoIter<Box<dyn ::home_control::reflect::Device>>;
+    fn into_iter(self) -> Self::IntoIter {
+        let mut devices: Vec<Box<dyn ::home_control::reflect::Device>> = Vec::new();
+        devices.push(Box::new(self.hallway_button));
+        devices.push(Box::new(self.hallway_light));
+        devices.into_iter()
+    }
+}
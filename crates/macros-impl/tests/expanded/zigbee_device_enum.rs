@@ -0,0 +1,382 @@
+#[derive(Clone)]
+/// Wireless Button
+///
+#[doc = concat!(
+    "See [zigbee2mqtt.io](", "https://www.zigbee2mqtt.io/devices/SNZB-01.html",
+    ") for more information"
+)]
+pub struct WirelessButton {
+    info: ::control::reflect::DeviceInfo,
+    updates: crate::Updates<WirelessButtonUpdate>,
+    action: crate::attribute::SubscribeAttr<WirelessButtonUpdate, ButtonAction>,
+}
+#[::bon::bon]
+impl WirelessButton {
+    #[builder]
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    pub async fn create(
+        manager: &mut crate::Manager,
+        info: ::control::reflect::DeviceInfo,
+    ) -> Result<Self, anyhow::Error> {
+        <Self as ::control::device::Device>::new(manager, info).await
+    }
+}
+impl ::control::device::Device for WirelessButton {
+    type Args = ();
+    type Manager = crate::Manager;
+    fn info(&self) -> &::control::reflect::DeviceInfo {
+        &self.info
+    }
+    async fn new_with_args(
+        manager: &mut crate::Manager,
+        info: ::control::reflect::DeviceInfo,
+        _: (),
+    ) -> Result<Self, anyhow::Error> {
+        manager.register_device(info.name.clone());
+        let updates = manager.subscribe(info.name.clone());
+        Ok(Self {
+            action: crate::attribute::SubscribeAttr::new(
+                updates.clone(),
+                WirelessButtonUpdate::action,
+            ),
+            updates,
+            info,
+        })
+    }
+}
+impl WirelessButton {
+    /// Returns the name of this device, eg: to identify it in logs
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+    /// detected action from the button
+    pub fn action<'a>(
+        &'a self,
+    ) -> &'a (impl ::control::Sensor<
+        Item = ButtonAction,
+    > + Sync + Send + Sync + Clone + use<>) {
+        &self.action
+    }
+}
+impl ::std::fmt::Debug for WirelessButton {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        f.debug_struct(stringify!(WirelessButton))
+            .field("id", &self.info.id)
+            .field("name", &self.info.name)
+            .finish_non_exhaustive()
+    }
+}
+#[derive(::serde::Deserialize, Clone, Debug)]
+#[doc = concat!("An update from a ", stringify!(WirelessButton), " device")]
+pub struct WirelessButtonUpdate {
+    #[serde(default, deserialize_with = "_WirelessButton::deserialize_action")]
+    /// detected action from the button
+    ///
+    ///Distinguishes a value which was not included in the received update from one reported as `null`
+    pub action: crate::Reported<ButtonAction>,
+}
+impl WirelessButtonUpdate {
+    fn action(self) -> Option<ButtonAction> {
+        self.action.value()
+    }
+}
+impl WirelessButton {
+    /// Returns a stream of updates as they are received from the device
+    pub fn updates(
+        &self,
+    ) -> impl ::futures::stream::Stream<Item = WirelessButtonUpdate> {
+        self.updates.subscribe()
+    }
+}
+#[allow(non_snake_case)]
+mod _WirelessButton {
+    use super::*;
+    pub(super) fn deserialize_action<'de, D>(
+        deserializer: D,
+    ) -> Result<crate::Reported<ButtonAction>, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+        Ok(
+            match <Option<String> as ::serde::Deserialize>::deserialize(deserializer)?
+                .as_deref()
+            {
+                Some("single") => crate::Reported::Value(ButtonAction::Single),
+                Some("double") => crate::Reported::Value(ButtonAction::Double),
+                Some("long") => crate::Reported::Value(ButtonAction::Long),
+                Some(unknown) => {
+                    return Err(
+                        D::Error::custom(
+                            format!(
+                                "unknown value for {}: {}", stringify!(action), unknown
+                            ),
+                        ),
+                    );
+                }
+                None => crate::Reported::Null,
+            },
+        )
+    }
+    pub(super) fn convert_action(value: ButtonAction) -> String {
+        match value {
+            ButtonAction::Single => "single",
+            ButtonAction::Double => "double",
+            ButtonAction::Long => "long",
+        }
+            .to_string()
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        #[test]
+        fn action_mapping() {
+            for (value, zigbee) in [
+                (ButtonAction::Single, "single"),
+                (ButtonAction::Double, "double"),
+                (ButtonAction::Long, "long"),
+            ] {
+                assert_eq!(
+                    zigbee, zigbee.trim(), "{zigbee:?} has surrounding whitespace"
+                );
+                assert_eq!(convert_action(Clone::clone(& value)), zigbee);
+                let parsed = deserialize_action(
+                        ::serde_json::Value::String(zigbee.to_string()),
+                    )
+                    .expect("failed to deserialize mapped value");
+                assert_eq!(parsed, crate ::Reported::Value(value));
+            }
+        }
+    }
+}
+impl ::control::reflect::Device for WirelessButton {
+    fn info(&self) -> ::control::reflect::DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<::control::reflect::Field> {
+        vec![
+            ::control::reflect::Field { name : "action".to_string(), description :
+            " detected action from the button".to_string(), operations :
+            ::control::reflect::Operations { subscribe : true, get : false, set : false,
+            toggle : false, }, value_type :
+            ::control::reflect::value::ValueType::from_type:: < ButtonAction > (), },
+        ]
+    }
+    fn subscribe(
+        &self,
+        field: &str,
+    ) -> Result<
+        ::futures::future::BoxFuture<
+            ::futures::stream::BoxStream<'_, ::control::reflect::value::Value>,
+        >,
+        ::control::reflect::Error,
+    > {
+        use ::control::Sensor;
+        use ::futures::stream::StreamExt;
+        match field {
+            "action" => {
+                Ok(
+                    Box::pin(
+                        ::futures::future::ready(
+                            Box::pin(
+                                self
+                                    .action
+                                    .subscribe()
+                                    .map(::control::reflect::value::Value::from),
+                            ) as ::futures::stream::BoxStream<_>,
+                        ),
+                    ),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+    fn get(
+        &self,
+        field: &str,
+    ) -> Result<
+        ::futures::future::BoxFuture<
+            '_,
+            anyhow::Result<::control::reflect::value::Value>,
+        >,
+        ::control::reflect::Error,
+    > {
+        use ::control::ReadValue;
+        use ::futures::future::FutureExt;
+        match field {
+            "action" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Get,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+    fn set(
+        &self,
+        field: &str,
+        value: ::control::reflect::value::Value,
+    ) -> Result<
+        ::futures::future::BoxFuture<'_, anyhow::Result<()>>,
+        ::control::reflect::SetError,
+    > {
+        use ::control::WriteValue;
+        match field {
+            "action" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Set,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(
+                    ::control::reflect::Error::FieldNotFound {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                    }
+                        .into(),
+                )
+            }
+        }
+    }
+    fn toggle(
+        &self,
+        field: &str,
+    ) -> Result<
+        futures::future::BoxFuture<'_, anyhow::Result<()>>,
+        ::control::reflect::Error,
+    > {
+        use ::control::ToggleValue;
+        match field {
+            "action" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Toggle,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+}
+#[cfg(feature = "mock")]
+/// A mock of a [WirelessButton] for tests, it publishes values as the device would and responds to set and get requests
+pub struct MockWirelessButton {
+    device: ::testing::MockDevice,
+    state: ::std::sync::Mutex<::std::collections::HashMap<String, ::serde_json::Value>>,
+}
+#[cfg(feature = "mock")]
+impl MockWirelessButton {
+    /// Create the mock, it responds to requests until the connection is closed
+    pub async fn new(
+        connection: &::testing::Connection,
+        name: &str,
+    ) -> ::std::sync::Arc<Self> {
+        let (device, requests) = ::testing::MockDevice::connect(connection, name).await;
+        let mock = ::std::sync::Arc::new(Self {
+            device,
+            state: ::std::sync::Mutex::default(),
+        });
+        ::tokio::spawn(::std::sync::Arc::clone(&mock).respond(requests));
+        mock
+    }
+    async fn respond(
+        self: ::std::sync::Arc<Self>,
+        mut requests: ::testing::MockRequests,
+    ) {
+        while let Some(request) = requests.next().await {
+            let attributes: Vec<String> = match request {
+                ::testing::MockRequest::Set(values) => {
+                    values
+                        .into_iter()
+                        .map(|(attribute, value)| {
+                            let value = if value == "TOGGLE" {
+                                self.toggled(&attribute).unwrap_or(value)
+                            } else {
+                                value
+                            };
+                            self.attributes().insert(attribute.clone(), value);
+                            attribute
+                        })
+                        .collect()
+                }
+                ::testing::MockRequest::Get(values) => {
+                    values.into_iter().map(|(attribute, _)| attribute).collect()
+                }
+            };
+            let payload: ::serde_json::Map<String, ::serde_json::Value> = {
+                let state = self.attributes();
+                attributes
+                    .into_iter()
+                    .filter_map(|attribute| {
+                        let value = state.get(&attribute)?.clone();
+                        Some((attribute, value))
+                    })
+                    .collect()
+            };
+            if !payload.is_empty() {
+                self.device.publish(::serde_json::Value::Object(payload)).await;
+            }
+        }
+    }
+    fn toggled(&self, attribute: &str) -> Option<::serde_json::Value> {
+        match attribute {
+            _ => None,
+        }
+    }
+    fn attributes(
+        &self,
+    ) -> ::std::sync::MutexGuard<
+        '_,
+        ::std::collections::HashMap<String, ::serde_json::Value>,
+    > {
+        ::control::util::lock(&self.state)
+    }
+    async fn store_and_publish(&self, attribute: &str, value: ::serde_json::Value) {
+        self.attributes().insert(attribute.to_string(), value.clone());
+        self.device.publish(::serde_json::json!({ attribute : value })).await;
+    }
+    /// Publish a new value of `action` as the device
+    pub async fn publish_action(&self, value: ButtonAction) {
+        self.store_and_publish("action", Self::raw_action(value)).await;
+    }
+    /// The current value of `action`, if it has been published or set
+    pub fn action(&self) -> Option<ButtonAction> {
+        let value = self.attributes().get("action")?.clone();
+        _WirelessButton::deserialize_action(value).ok()?.value()
+    }
+    fn raw_action(value: ButtonAction) -> ::serde_json::Value {
+        ::serde_json::json!(_WirelessButton::convert_action(value))
+    }
+}
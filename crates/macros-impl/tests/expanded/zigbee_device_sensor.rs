@@ -0,0 +1,423 @@
+#[derive(Clone)]
+/// A Door/window contact sensor
+///
+#[doc = concat!(
+    "See [zigbee2mqtt.io](", "https://www.zigbee2mqtt.io/devices/SNZB-04.html",
+    ") for more information"
+)]
+pub struct ContactSensor {
+    info: ::control::reflect::DeviceInfo,
+    publish: crate::publish::Publisher,
+    updates: crate::Updates<ContactSensorUpdate>,
+    battery: crate::attribute::SubscribePublishAttr<
+        ::light_ranged_integers::RangedU8<0, 100>,
+        ContactSensorUpdate,
+        ::light_ranged_integers::RangedU8<0, 100>,
+    >,
+    contact: crate::attribute::SubscribeAttr<ContactSensorUpdate, bool>,
+}
+#[::bon::bon]
+impl ContactSensor {
+    #[builder]
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    pub async fn create(
+        manager: &mut crate::Manager,
+        info: ::control::reflect::DeviceInfo,
+    ) -> Result<Self, anyhow::Error> {
+        <Self as ::control::device::Device>::new(manager, info).await
+    }
+}
+impl ::control::device::Device for ContactSensor {
+    type Args = ();
+    type Manager = crate::Manager;
+    fn info(&self) -> &::control::reflect::DeviceInfo {
+        &self.info
+    }
+    async fn new_with_args(
+        manager: &mut crate::Manager,
+        info: ::control::reflect::DeviceInfo,
+        _: (),
+    ) -> Result<Self, anyhow::Error> {
+        manager.register_device(info.name.clone());
+        let publish = manager.outgoing_publishes();
+        let updates = manager.subscribe(info.name.clone());
+        Ok(Self {
+            battery: crate::attribute::SubscribePublishAttr::new(
+                updates.clone(),
+                publish.clone(),
+                info.name.clone(),
+                "battery",
+                ContactSensorUpdate::battery,
+            ),
+            contact: crate::attribute::SubscribeAttr::new(
+                updates.clone(),
+                ContactSensorUpdate::contact,
+            ),
+            publish,
+            updates,
+            info,
+        })
+    }
+}
+impl ContactSensor {
+    /// Returns the name of this device, eg: to identify it in logs
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+    /// Battery level of the sensor as a percentage
+    pub fn battery<'a>(
+        &'a self,
+    ) -> &'a (impl ::control::Sensor<
+        Item = ::light_ranged_integers::RangedU8<0, 100>,
+    > + ::control::ReadValue<
+        Item = ::light_ranged_integers::RangedU8<0, 100>,
+    > + Sync + Send + Sync + Clone + use<>) {
+        &self.battery
+    }
+    /// true if the contact sensor is in contact
+    pub fn contact<'a>(
+        &'a self,
+    ) -> &'a (impl ::control::Sensor<Item = bool> + Sync + Send + Sync + Clone + use<>) {
+        &self.contact
+    }
+}
+impl ::std::fmt::Debug for ContactSensor {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        f.debug_struct(stringify!(ContactSensor))
+            .field("id", &self.info.id)
+            .field("name", &self.info.name)
+            .finish_non_exhaustive()
+    }
+}
+#[derive(::serde::Deserialize, Clone, Debug)]
+#[doc = concat!("An update from a ", stringify!(ContactSensor), " device")]
+pub struct ContactSensorUpdate {
+    #[serde(default)]
+    /// Battery level of the sensor as a percentage
+    ///
+    ///Distinguishes a value which was not included in the received update from one reported as `null`
+    pub battery: crate::Reported<::light_ranged_integers::RangedU8<0, 100>>,
+    #[serde(default)]
+    /// true if the contact sensor is in contact
+    ///
+    ///Distinguishes a value which was not included in the received update from one reported as `null`
+    pub contact: crate::Reported<bool>,
+}
+impl ContactSensorUpdate {
+    fn battery(self) -> Option<::light_ranged_integers::RangedU8<0, 100>> {
+        self.battery.value()
+    }
+    fn contact(self) -> Option<bool> {
+        self.contact.value()
+    }
+}
+impl ContactSensor {
+    /// Returns a stream of updates as they are received from the device
+    pub fn updates(&self) -> impl ::futures::stream::Stream<Item = ContactSensorUpdate> {
+        self.updates.subscribe()
+    }
+}
+#[allow(non_snake_case)]
+mod _ContactSensor {
+    use super::*;
+}
+impl ::control::reflect::Device for ContactSensor {
+    fn info(&self) -> ::control::reflect::DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<::control::reflect::Field> {
+        vec![
+            ::control::reflect::Field { name : "battery".to_string(), description :
+            " Battery level of the sensor as a percentage".to_string(), operations :
+            ::control::reflect::Operations { subscribe : true, get : true, set : false,
+            toggle : false, }, value_type :
+            ::control::reflect::value::ValueType::from_type:: <
+            ::light_ranged_integers::RangedU8 < 0, 100 > > (), },
+            ::control::reflect::Field { name : "contact".to_string(), description :
+            " true if the contact sensor is in contact".to_string(), operations :
+            ::control::reflect::Operations { subscribe : true, get : false, set : false,
+            toggle : false, }, value_type :
+            ::control::reflect::value::ValueType::from_type:: < bool > (), },
+        ]
+    }
+    fn subscribe(
+        &self,
+        field: &str,
+    ) -> Result<
+        ::futures::future::BoxFuture<
+            ::futures::stream::BoxStream<'_, ::control::reflect::value::Value>,
+        >,
+        ::control::reflect::Error,
+    > {
+        use ::control::Sensor;
+        use ::futures::stream::StreamExt;
+        match field {
+            "battery" => {
+                Ok(
+                    Box::pin(
+                        ::futures::future::ready(
+                            Box::pin(
+                                self
+                                    .battery
+                                    .subscribe()
+                                    .map(::control::reflect::value::Value::from),
+                            ) as ::futures::stream::BoxStream<_>,
+                        ),
+                    ),
+                )
+            }
+            "contact" => {
+                Ok(
+                    Box::pin(
+                        ::futures::future::ready(
+                            Box::pin(
+                                self
+                                    .contact
+                                    .subscribe()
+                                    .map(::control::reflect::value::Value::from),
+                            ) as ::futures::stream::BoxStream<_>,
+                        ),
+                    ),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+    fn get(
+        &self,
+        field: &str,
+    ) -> Result<
+        ::futures::future::BoxFuture<
+            '_,
+            anyhow::Result<::control::reflect::value::Value>,
+        >,
+        ::control::reflect::Error,
+    > {
+        use ::control::ReadValue;
+        use ::futures::future::FutureExt;
+        match field {
+            "battery" => {
+                Ok(
+                    Box::pin(
+                        self
+                            .battery
+                            .get()
+                            .map(|result| {
+                                result.map(::control::reflect::value::Value::from)
+                            }),
+                    ),
+                )
+            }
+            "contact" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Get,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+    fn set(
+        &self,
+        field: &str,
+        value: ::control::reflect::value::Value,
+    ) -> Result<
+        ::futures::future::BoxFuture<'_, anyhow::Result<()>>,
+        ::control::reflect::SetError,
+    > {
+        use ::control::WriteValue;
+        match field {
+            "battery" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Set,
+                    }
+                        .into(),
+                )
+            }
+            "contact" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Set,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(
+                    ::control::reflect::Error::FieldNotFound {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                    }
+                        .into(),
+                )
+            }
+        }
+    }
+    fn toggle(
+        &self,
+        field: &str,
+    ) -> Result<
+        futures::future::BoxFuture<'_, anyhow::Result<()>>,
+        ::control::reflect::Error,
+    > {
+        use ::control::ToggleValue;
+        match field {
+            "battery" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Toggle,
+                    }
+                        .into(),
+                )
+            }
+            "contact" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Toggle,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+}
+#[cfg(feature = "mock")]
+/// A mock of a [ContactSensor] for tests, it publishes values as the device would and responds to set and get requests
+pub struct MockContactSensor {
+    device: ::testing::MockDevice,
+    state: ::std::sync::Mutex<::std::collections::HashMap<String, ::serde_json::Value>>,
+}
+#[cfg(feature = "mock")]
+impl MockContactSensor {
+    /// Create the mock, it responds to requests until the connection is closed
+    pub async fn new(
+        connection: &::testing::Connection,
+        name: &str,
+    ) -> ::std::sync::Arc<Self> {
+        let (device, requests) = ::testing::MockDevice::connect(connection, name).await;
+        let mock = ::std::sync::Arc::new(Self {
+            device,
+            state: ::std::sync::Mutex::default(),
+        });
+        ::tokio::spawn(::std::sync::Arc::clone(&mock).respond(requests));
+        mock
+    }
+    async fn respond(
+        self: ::std::sync::Arc<Self>,
+        mut requests: ::testing::MockRequests,
+    ) {
+        while let Some(request) = requests.next().await {
+            let attributes: Vec<String> = match request {
+                ::testing::MockRequest::Set(values) => {
+                    values
+                        .into_iter()
+                        .map(|(attribute, value)| {
+                            let value = if value == "TOGGLE" {
+                                self.toggled(&attribute).unwrap_or(value)
+                            } else {
+                                value
+                            };
+                            self.attributes().insert(attribute.clone(), value);
+                            attribute
+                        })
+                        .collect()
+                }
+                ::testing::MockRequest::Get(values) => {
+                    values.into_iter().map(|(attribute, _)| attribute).collect()
+                }
+            };
+            let payload: ::serde_json::Map<String, ::serde_json::Value> = {
+                let state = self.attributes();
+                attributes
+                    .into_iter()
+                    .filter_map(|attribute| {
+                        let value = state.get(&attribute)?.clone();
+                        Some((attribute, value))
+                    })
+                    .collect()
+            };
+            if !payload.is_empty() {
+                self.device.publish(::serde_json::Value::Object(payload)).await;
+            }
+        }
+    }
+    fn toggled(&self, attribute: &str) -> Option<::serde_json::Value> {
+        match attribute {
+            _ => None,
+        }
+    }
+    fn attributes(
+        &self,
+    ) -> ::std::sync::MutexGuard<
+        '_,
+        ::std::collections::HashMap<String, ::serde_json::Value>,
+    > {
+        ::control::util::lock(&self.state)
+    }
+    async fn store_and_publish(&self, attribute: &str, value: ::serde_json::Value) {
+        self.attributes().insert(attribute.to_string(), value.clone());
+        self.device.publish(::serde_json::json!({ attribute : value })).await;
+    }
+    /// Publish a new value of `battery` as the device
+    pub async fn publish_battery(
+        &self,
+        value: ::light_ranged_integers::RangedU8<0, 100>,
+    ) {
+        self.store_and_publish("battery", Self::raw_battery(value)).await;
+    }
+    /// The current value of `battery`, if it has been published or set
+    pub fn battery(&self) -> Option<::light_ranged_integers::RangedU8<0, 100>> {
+        let value = self.attributes().get("battery")?.clone();
+        ::serde_json::from_value(value).ok()
+    }
+    fn raw_battery(
+        value: ::light_ranged_integers::RangedU8<0, 100>,
+    ) -> ::serde_json::Value {
+        ::serde_json::json!(value)
+    }
+    /// Publish a new value of `contact` as the device
+    pub async fn publish_contact(&self, value: bool) {
+        self.store_and_publish("contact", Self::raw_contact(value)).await;
+    }
+    /// The current value of `contact`, if it has been published or set
+    pub fn contact(&self) -> Option<bool> {
+        let value = self.attributes().get("contact")?.clone();
+        ::serde_json::from_value(value).ok()
+    }
+    fn raw_contact(value: bool) -> ::serde_json::Value {
+        ::serde_json::json!(value)
+    }
+}
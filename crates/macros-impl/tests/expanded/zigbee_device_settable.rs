@@ -0,0 +1,327 @@
+#[derive(Clone)]
+/// A smart plug
+///
+#[doc = concat!(
+    "See [zigbee2mqtt.io](", "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+    ") for more information"
+)]
+pub struct Plug {
+    info: ::control::reflect::DeviceInfo,
+    publish: crate::publish::Publisher,
+    updates: crate::Updates<PlugUpdate>,
+    state: crate::attribute::SubscribePublishAttr<bool, PlugUpdate, bool>,
+}
+#[::bon::bon]
+impl Plug {
+    #[builder]
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    pub async fn create(
+        manager: &mut crate::Manager,
+        info: ::control::reflect::DeviceInfo,
+    ) -> Result<Self, anyhow::Error> {
+        <Self as ::control::device::Device>::new(manager, info).await
+    }
+}
+impl ::control::device::Device for Plug {
+    type Args = ();
+    type Manager = crate::Manager;
+    fn info(&self) -> &::control::reflect::DeviceInfo {
+        &self.info
+    }
+    async fn new_with_args(
+        manager: &mut crate::Manager,
+        info: ::control::reflect::DeviceInfo,
+        _: (),
+    ) -> Result<Self, anyhow::Error> {
+        manager.register_device(info.name.clone());
+        let publish = manager.outgoing_publishes();
+        let updates = manager.subscribe(info.name.clone());
+        Ok(Self {
+            state: crate::attribute::SubscribePublishAttr::new(
+                updates.clone(),
+                publish.clone(),
+                info.name.clone(),
+                "state",
+                PlugUpdate::state,
+            ),
+            publish,
+            updates,
+            info,
+        })
+    }
+}
+impl Plug {
+    /// Returns the name of this device, eg: to identify it in logs
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+    /// On/off state of the switch
+    pub fn state<'a>(
+        &'a self,
+    ) -> &'a (impl ::control::Sensor<
+        Item = bool,
+    > + ::control::ReadValue<
+        Item = bool,
+    > + ::control::WriteValue<Item = bool> + Sync + Send + Sync + Clone + use<>) {
+        &self.state
+    }
+}
+impl ::std::fmt::Debug for Plug {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        f.debug_struct(stringify!(Plug))
+            .field("id", &self.info.id)
+            .field("name", &self.info.name)
+            .finish_non_exhaustive()
+    }
+}
+#[derive(::serde::Deserialize, Clone, Debug)]
+#[doc = concat!("An update from a ", stringify!(Plug), " device")]
+pub struct PlugUpdate {
+    #[serde(default)]
+    /// On/off state of the switch
+    ///
+    ///Distinguishes a value which was not included in the received update from one reported as `null`
+    pub state: crate::Reported<bool>,
+}
+impl PlugUpdate {
+    fn state(self) -> Option<bool> {
+        self.state.value()
+    }
+}
+impl Plug {
+    /// Returns a stream of updates as they are received from the device
+    pub fn updates(&self) -> impl ::futures::stream::Stream<Item = PlugUpdate> {
+        self.updates.subscribe()
+    }
+}
+#[allow(non_snake_case)]
+mod _Plug {
+    use super::*;
+}
+impl ::control::reflect::Device for Plug {
+    fn info(&self) -> ::control::reflect::DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<::control::reflect::Field> {
+        vec![
+            ::control::reflect::Field { name : "state".to_string(), description :
+            " On/off state of the switch".to_string(), operations :
+            ::control::reflect::Operations { subscribe : true, get : true, set : true,
+            toggle : false, }, value_type :
+            ::control::reflect::value::ValueType::from_type:: < bool > (), },
+        ]
+    }
+    fn subscribe(
+        &self,
+        field: &str,
+    ) -> Result<
+        ::futures::future::BoxFuture<
+            ::futures::stream::BoxStream<'_, ::control::reflect::value::Value>,
+        >,
+        ::control::reflect::Error,
+    > {
+        use ::control::Sensor;
+        use ::futures::stream::StreamExt;
+        match field {
+            "state" => {
+                Ok(
+                    Box::pin(
+                        ::futures::future::ready(
+                            Box::pin(
+                                self
+                                    .state
+                                    .subscribe()
+                                    .map(::control::reflect::value::Value::from),
+                            ) as ::futures::stream::BoxStream<_>,
+                        ),
+                    ),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+    fn get(
+        &self,
+        field: &str,
+    ) -> Result<
+        ::futures::future::BoxFuture<
+            '_,
+            anyhow::Result<::control::reflect::value::Value>,
+        >,
+        ::control::reflect::Error,
+    > {
+        use ::control::ReadValue;
+        use ::futures::future::FutureExt;
+        match field {
+            "state" => {
+                Ok(
+                    Box::pin(
+                        self
+                            .state
+                            .get()
+                            .map(|result| {
+                                result.map(::control::reflect::value::Value::from)
+                            }),
+                    ),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+    fn set(
+        &self,
+        field: &str,
+        value: ::control::reflect::value::Value,
+    ) -> Result<
+        ::futures::future::BoxFuture<'_, anyhow::Result<()>>,
+        ::control::reflect::SetError,
+    > {
+        use ::control::WriteValue;
+        match field {
+            "state" => {
+                let value = value.try_into()?;
+                Ok(Box::pin(self.state.set(value)))
+            }
+            _ => {
+                Err(
+                    ::control::reflect::Error::FieldNotFound {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                    }
+                        .into(),
+                )
+            }
+        }
+    }
+    fn toggle(
+        &self,
+        field: &str,
+    ) -> Result<
+        futures::future::BoxFuture<'_, anyhow::Result<()>>,
+        ::control::reflect::Error,
+    > {
+        use ::control::ToggleValue;
+        match field {
+            "state" => {
+                Err(
+                    ::control::reflect::Error::OperationNotSupported {
+                        device: self.info.name.to_owned(),
+                        field: field.to_owned(),
+                        operation: ::control::reflect::Operation::Toggle,
+                    }
+                        .into(),
+                )
+            }
+            _ => {
+                Err(::control::reflect::Error::FieldNotFound {
+                    device: self.info.name.to_owned(),
+                    field: field.to_owned(),
+                })
+            }
+        }
+    }
+}
+#[cfg(feature = "mock")]
+/// A mock of a [Plug] for tests, it publishes values as the device would and responds to set and get requests
+pub struct MockPlug {
+    device: ::testing::MockDevice,
+    state: ::std::sync::Mutex<::std::collections::HashMap<String, ::serde_json::Value>>,
+}
+#[cfg(feature = "mock")]
+impl MockPlug {
+    /// Create the mock, it responds to requests until the connection is closed
+    pub async fn new(
+        connection: &::testing::Connection,
+        name: &str,
+    ) -> ::std::sync::Arc<Self> {
+        let (device, requests) = ::testing::MockDevice::connect(connection, name).await;
+        let mock = ::std::sync::Arc::new(Self {
+            device,
+            state: ::std::sync::Mutex::default(),
+        });
+        ::tokio::spawn(::std::sync::Arc::clone(&mock).respond(requests));
+        mock
+    }
+    async fn respond(
+        self: ::std::sync::Arc<Self>,
+        mut requests: ::testing::MockRequests,
+    ) {
+        while let Some(request) = requests.next().await {
+            let attributes: Vec<String> = match request {
+                ::testing::MockRequest::Set(values) => {
+                    values
+                        .into_iter()
+                        .map(|(attribute, value)| {
+                            let value = if value == "TOGGLE" {
+                                self.toggled(&attribute).unwrap_or(value)
+                            } else {
+                                value
+                            };
+                            self.attributes().insert(attribute.clone(), value);
+                            attribute
+                        })
+                        .collect()
+                }
+                ::testing::MockRequest::Get(values) => {
+                    values.into_iter().map(|(attribute, _)| attribute).collect()
+                }
+            };
+            let payload: ::serde_json::Map<String, ::serde_json::Value> = {
+                let state = self.attributes();
+                attributes
+                    .into_iter()
+                    .filter_map(|attribute| {
+                        let value = state.get(&attribute)?.clone();
+                        Some((attribute, value))
+                    })
+                    .collect()
+            };
+            if !payload.is_empty() {
+                self.device.publish(::serde_json::Value::Object(payload)).await;
+            }
+        }
+    }
+    fn toggled(&self, attribute: &str) -> Option<::serde_json::Value> {
+        match attribute {
+            _ => None,
+        }
+    }
+    fn attributes(
+        &self,
+    ) -> ::std::sync::MutexGuard<
+        '_,
+        ::std::collections::HashMap<String, ::serde_json::Value>,
+    > {
+        ::control::util::lock(&self.state)
+    }
+    async fn store_and_publish(&self, attribute: &str, value: ::serde_json::Value) {
+        self.attributes().insert(attribute.to_string(), value.clone());
+        self.device.publish(::serde_json::json!({ attribute : value })).await;
+    }
+    /// Publish a new value of `state` as the device
+    pub async fn publish_state(&self, value: bool) {
+        self.store_and_publish("state", Self::raw_state(value)).await;
+    }
+    /// The current value of `state`, if it has been published or set
+    pub fn state(&self) -> Option<bool> {
+        let value = self.attributes().get("state")?.clone();
+        ::serde_json::from_value(value).ok()
+    }
+    fn raw_state(value: bool) -> ::serde_json::Value {
+        ::serde_json::json!(value)
+    }
+}
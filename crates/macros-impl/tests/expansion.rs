@@ -0,0 +1,135 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Compares the expansion of the macros for representative inputs against the golden files in
+//! `tests/expanded`, so a change to the generated code shows up in review as a diff of those files
+//!
+//! Run with `UPDATE_EXPANDED=1` to write the golden files after an intended change to the generated
+//! code, a missing golden file fails the test otherwise
+
+use macros_impl::Device;
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use syn::{DeriveInput, parse_quote};
+
+#[test]
+fn zigbee_device_sensor() {
+    let device: Device = syn::parse2(quote! {
+        /// A Door/window contact sensor
+        pub ContactSensor {
+            "https://www.zigbee2mqtt.io/devices/SNZB-04.html",
+            /// Battery level of the sensor as a percentage
+            get "battery" => u8<0, 100>,
+            /// true if the contact sensor is in contact
+            stream "contact" => bool,
+        }
+    })
+    .expect("failed to parse device");
+    assert_expansion("zigbee_device_sensor", macros_impl::device(device));
+}
+
+#[test]
+fn zigbee_device_enum() {
+    let device: Device = syn::parse2(quote! {
+        /// Wireless Button
+        #[mapping_tests]
+        pub WirelessButton {
+            "https://www.zigbee2mqtt.io/devices/SNZB-01.html",
+            /// detected action from the button
+            stream "action" => enum ButtonAction {
+                "single" => Single,
+                "double" => Double,
+                "long" => Long,
+            }
+        }
+    })
+    .expect("failed to parse device");
+    assert_expansion("zigbee_device_enum", macros_impl::device(device));
+}
+
+#[test]
+fn zigbee_device_settable() {
+    let device: Device = syn::parse2(quote! {
+        /// A smart plug
+        pub Plug {
+            "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+            /// On/off state of the switch
+            get set "state" => bool,
+        }
+    })
+    .expect("failed to parse device");
+    assert_expansion("zigbee_device_settable", macros_impl::device(device));
+}
+
+#[test]
+fn device_set_plain() {
+    let input: DeriveInput = parse_quote! {
+        struct Devices {
+            hallway_button: HueSmartButton,
+            hallway_light: Light,
+        }
+    };
+    assert_expansion("device_set_plain", macros_impl::device_set(input).expect("failed to expand device set"));
+}
+
+#[test]
+fn device_set_params() {
+    let input: DeriveInput = parse_quote! {
+        struct Devices {
+            /// The bulb in the bedroom
+            #[device(
+                name = "bedroom_bulb",
+                ip = Ipv4Addr::new(192, 168, 1, 62),
+                tags = {
+                    room = Room::Bedroom
+                }
+            )]
+            bedroom_light: Light,
+            #[device(name_from_env = "KITCHEN_SENSOR")]
+            kitchen_sensor: TemperatureAndHumiditySensor,
+            #[device(profiles = ["home"])]
+            office_light: Option<Light>,
+            #[device(names = ["door_1", "door_2"])]
+            doors: Vec<ContactSensor>,
+            #[device(set)]
+            outside: OutsideDevices,
+        }
+    };
+    assert_expansion("device_set_params", macros_impl::device_set(input).expect("failed to expand device set"));
+}
+
+/// Compare the formatted expansion with the golden file of the given name
+fn assert_expansion(name: &str, tokens: TokenStream) {
+    let file = syn::parse2(tokens).expect("the expansion should be a valid file");
+    let expanded = prettyplease::unparse(&file);
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/expanded")
+        .join(format!("{name}.rs"));
+    if env::var_os("UPDATE_EXPANDED").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &expanded).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|error| {
+        panic!("failed to read {}: {error}, run with UPDATE_EXPANDED=1 to write it", path.display())
+    });
+    if expected != expanded {
+        let diff: Vec<String> = expected
+            .lines()
+            .zip(expanded.lines())
+            .enumerate()
+            .filter(|(_, (expected, actual))| expected != actual)
+            .take(10)
+            .map(|(line, (expected, actual))| format!("line {}:\n  - {expected}\n  + {actual}", line + 1))
+            .collect();
+        panic!(
+            "the expansion of {name} differs from {} ({} lines expected, {} lines expanded), \
+            run with UPDATE_EXPANDED=1 if this was intended\n{}",
+            path.display(),
+            expected.lines().count(),
+            expanded.lines().count(),
+            diff.join("\n"),
+        );
+    }
+}
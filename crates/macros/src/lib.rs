@@ -1,9 +1,9 @@
 //! An internal crate for procedural macros, any public macro should be re-exported
 
-use macros_impl::Device;
+use macros_impl::{AutomationInput, Device};
 use proc_macro::TokenStream;
 use syn::__private::ToTokens;
-use syn::{parse_macro_input, DeriveInput, LitInt};
+use syn::{parse_macro_input, DeriveInput, ItemStruct};
 
 /// an internal macro to define a zigbee device without having to write complicated boilerplate code
 #[proc_macro]
@@ -22,13 +22,29 @@ pub fn device_set(tokens: TokenStream) -> TokenStream {
     }
 }
 
-/// an internal helper macro for implementing AutomationSet for tuples
+/// a public derive macro for deriving home_control::automation::AutomationSet
+#[proc_macro_derive(AutomationSet)]
+pub fn automation_set(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    match macros_impl::automation_set(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// a public attribute macro which adds an accessor for each tag given to the fields of a device set
+/// with `#[tag(...)]`, it must be placed above `#[derive(DeviceSet)]`
+#[proc_macro_attribute]
+pub fn tagged(args: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ItemStruct);
+    macros_impl::tagged(args.into(), input).into()
+}
+
+/// a public macro for declaring an automation, eg:
+/// `automation! { name: "...", trigger: stream, when: |event| condition, then: async |event| action }`,
+/// `when` is optional and gates the trigger
 #[proc_macro]
-pub fn automation_sets(tokens: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(tokens as LitInt);
-    let input = match input.base10_parse() {
-        Ok(value) => value,
-        Err(err) => return err.to_compile_error().into(),
-    };
-    macros_impl::automation_sets(input).into()
+pub fn automation(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as AutomationInput);
+    macros_impl::automation(input).into()
 }
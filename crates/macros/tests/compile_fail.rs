@@ -0,0 +1,7 @@
+//! Checks misuse of the macros is reported at the offending tokens with a useful message
+
+#[test]
+fn compile_fail() {
+    let tests = trybuild::TestCases::new();
+    tests.compile_fail("tests/ui/*.rs");
+}
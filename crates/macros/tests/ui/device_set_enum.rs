@@ -0,0 +1,10 @@
+//! A device set can only be derived for a struct
+
+#[derive(macros::DeviceSet)]
+enum Devices {
+    Home,
+}
+
+fn main() {
+    let _ = Devices::Home;
+}
@@ -0,0 +1,14 @@
+//! Flags other than set and skip are rejected
+
+#[derive(macros::DeviceSet)]
+struct Devices {
+    #[device(nested)]
+    kitchen: Kitchen,
+}
+
+struct Kitchen;
+
+fn main() {
+    let devices = Devices { kitchen: Kitchen };
+    let _ = devices.kitchen;
+}
@@ -0,0 +1,10 @@
+//! The URL must link to the device on zigbee2mqtt.io
+
+macros::zigbee_device! {
+    pub Plug {
+        "https://example.com/S26R2ZB.html",
+        stream get set "state" => bool,
+    }
+}
+
+fn main() {}
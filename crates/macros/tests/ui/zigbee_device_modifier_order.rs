@@ -0,0 +1,10 @@
+//! Modifiers must be written in the order stream, get, set, toggle
+
+macros::zigbee_device! {
+    pub Plug {
+        "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+        set get "state" => bool,
+    }
+}
+
+fn main() {}
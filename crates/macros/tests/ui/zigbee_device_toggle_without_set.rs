@@ -0,0 +1,10 @@
+//! A value can only be toggled if it can be set
+
+macros::zigbee_device! {
+    pub Plug {
+        "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+        get toggle "state" => bool,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+//! Only the modifiers stream, get, set and toggle exist
+
+macros::zigbee_device! {
+    pub Plug {
+        "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+        subscribe "state" => bool,
+    }
+}
+
+fn main() {}
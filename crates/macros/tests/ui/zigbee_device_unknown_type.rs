@@ -0,0 +1,10 @@
+//! Only bool, f64, integers, enums and sets of enums are supported
+
+macros::zigbee_device! {
+    pub Plug {
+        "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+        stream "power" => f32,
+    }
+}
+
+fn main() {}
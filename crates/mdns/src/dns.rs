@@ -0,0 +1,271 @@
+//! Just enough of the DNS message format to read the names from mDNS responses and build queries
+
+/// Record type of an IPv4 host address
+pub(crate) const TYPE_A: u16 = 1;
+/// Record type of a domain name pointer, used by DNS-SD to list service instances
+pub(crate) const TYPE_PTR: u16 = 12;
+const TYPE_CNAME: u16 = 5;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const HEADER_LEN: usize = 12;
+/// Set in the flags of a response
+const FLAG_RESPONSE: u16 = 0x8000;
+/// Limits the number of compression pointers followed when reading a name, to prevent loops
+const MAX_POINTERS: usize = 16;
+
+/// Returns the names announced in the given mDNS response, this is the owner of each record along
+/// with the names pointed to by PTR, SRV and CNAME records. Goodbye records (with a TTL of 0) are
+/// ignored, returns None if the message is not a valid response
+pub(crate) fn announced_names(packet: &[u8]) -> Option<Vec<String>> {
+    let flags = read_u16(packet, 2)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return Some(vec![]);
+    }
+    let questions = read_u16(packet, 4)?;
+    let records = [6, 8, 10]
+        .into_iter()
+        .map(|offset| read_u16(packet, offset).map(u32::from))
+        .sum::<Option<u32>>()?;
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..questions {
+        let (_, next) = read_name(packet, offset)?;
+        // type and class
+        offset = next + 4;
+    }
+
+    let mut names = Vec::new();
+    for _ in 0..records {
+        let (name, next) = read_name(packet, offset)?;
+        let record_type = read_u16(packet, next)?;
+        let ttl = read_u32(packet, next + 4)?;
+        let data_len = usize::from(read_u16(packet, next + 8)?);
+        let data = next + 10;
+        offset = data + data_len;
+        if offset > packet.len() {
+            return None;
+        }
+        if ttl == 0 {
+            continue;
+        }
+        names.push(name);
+        match record_type {
+            TYPE_PTR | TYPE_CNAME => names.push(read_name(packet, data)?.0),
+            // priority, weight and port precede the target
+            TYPE_SRV => names.push(read_name(packet, data + 6)?.0),
+            _ => {}
+        }
+    }
+    Some(names)
+}
+
+/// Builds a query for records of the given type for the given name
+pub(crate) fn query(name: &str, record_type: u16) -> Vec<u8> {
+    let mut packet = vec![0u8; HEADER_LEN];
+    // one question
+    packet[5] = 1;
+    for label in name.trim_end_matches('.').split('.') {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label);
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// Reads a possibly compressed name, returns the name along with the offset following it
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *packet.get(offset)?;
+        match len & 0xC0 {
+            0x00 if len == 0 => {
+                let end = end.unwrap_or(offset + 1);
+                return Some((labels.join("."), end));
+            }
+            0x00 => {
+                let start = offset + 1;
+                let label = packet.get(start..start + usize::from(len))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset = start + usize::from(len);
+            }
+            0xC0 => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                let pointer = usize::from(read_u16(packet, offset)? & 0x3FFF);
+                end.get_or_insert(offset + 2);
+                offset = pointer;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    let bytes = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> Option<u32> {
+    let bytes = packet.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used, clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// The offset of the first name after the header
+    const FIRST_NAME: u8 = HEADER_LEN as u8;
+
+    fn header(flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+        let mut packet = vec![0u8; HEADER_LEN];
+        packet[2..4].copy_from_slice(&flags.to_be_bytes());
+        packet[4..6].copy_from_slice(&questions.to_be_bytes());
+        packet[6..8].copy_from_slice(&answers.to_be_bytes());
+        packet
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for label in name.split('.') {
+            encoded.push(label.len() as u8);
+            encoded.extend_from_slice(label.as_bytes());
+        }
+        encoded.push(0);
+        encoded
+    }
+
+    fn record(owner: &[u8], record_type: u16, ttl: u32, data: &[u8]) -> Vec<u8> {
+        let mut record = owner.to_vec();
+        record.extend_from_slice(&record_type.to_be_bytes());
+        record.extend_from_slice(&CLASS_IN.to_be_bytes());
+        record.extend_from_slice(&ttl.to_be_bytes());
+        record.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    #[test]
+    fn compressed_names() {
+        let mut packet = header(FLAG_RESPONSE, 1, 2);
+        packet.extend(name("phone.local"));
+        packet.extend(TYPE_A.to_be_bytes());
+        packet.extend(CLASS_IN.to_be_bytes());
+        // the owner points back to the question, the target adds a label before the pointer
+        packet.extend(record(&[0xC0, FIRST_NAME], TYPE_A, 120, &[192, 168, 1, 20]));
+        packet.extend(record(&[0xC0, FIRST_NAME], TYPE_PTR, 120, &[3, b'_', b't', b'v', 0xC0, FIRST_NAME]));
+
+        let names = announced_names(&packet).unwrap();
+        assert_eq!(names, ["phone.local", "phone.local", "_tv.phone.local"]);
+    }
+
+    #[test]
+    fn read_name_ends_after_first_pointer() {
+        let mut packet = header(FLAG_RESPONSE, 0, 0);
+        packet.extend(name("phone.local"));
+        let start = packet.len();
+        packet.extend([3, b'_', b't', b'v', 0xC0, FIRST_NAME]);
+        packet.extend(name("ignored"));
+
+        assert_eq!(read_name(&packet, start), Some(("_tv.phone.local".to_string(), start + 6)));
+        assert_eq!(read_name(&packet, HEADER_LEN), Some(("phone.local".to_string(), start)));
+    }
+
+    #[test]
+    fn pointer_loops() {
+        // a pointer to itself
+        let mut packet = header(FLAG_RESPONSE, 0, 0);
+        packet.extend([0xC0, FIRST_NAME]);
+        assert_eq!(read_name(&packet, HEADER_LEN), None);
+
+        // two pointers to each other, with a label in between
+        let mut packet = header(FLAG_RESPONSE, 0, 0);
+        packet.extend([1, b'a', 0xC0, FIRST_NAME + 4, 0xC0, FIRST_NAME]);
+        assert_eq!(read_name(&packet, HEADER_LEN), None);
+
+        let mut packet = header(FLAG_RESPONSE, 0, 1);
+        packet.extend(record(&[0xC0, FIRST_NAME], TYPE_A, 120, &[192, 168, 1, 20]));
+        assert_eq!(announced_names(&packet), None);
+    }
+
+    #[test]
+    fn out_of_bounds_offsets() {
+        // a pointer past the end of the packet
+        let mut packet = header(FLAG_RESPONSE, 0, 0);
+        packet.extend([0xFF, 0xFF]);
+        assert_eq!(read_name(&packet, HEADER_LEN), None);
+
+        // a label longer than the rest of the packet
+        let mut packet = header(FLAG_RESPONSE, 0, 0);
+        packet.extend([10, b'p', b'h', b'o', b'n', b'e']);
+        assert_eq!(read_name(&packet, HEADER_LEN), None);
+
+        // the reserved label types
+        for reserved in [0x40, 0x80] {
+            let mut packet = header(FLAG_RESPONSE, 0, 0);
+            packet.extend([reserved, 0]);
+            assert_eq!(read_name(&packet, HEADER_LEN), None);
+        }
+
+        // a PTR target pointing past the end of the packet
+        let mut packet = header(FLAG_RESPONSE, 0, 1);
+        packet.extend(record(&name("phone.local"), TYPE_PTR, 120, &[0xC0, 0xFF]));
+        assert_eq!(announced_names(&packet), None);
+    }
+
+    #[test]
+    fn truncated_packets() {
+        let mut packet = header(FLAG_RESPONSE, 1, 1);
+        packet.extend(name("phone.local"));
+        packet.extend(TYPE_A.to_be_bytes());
+        packet.extend(CLASS_IN.to_be_bytes());
+        packet.extend(record(&name("phone.local"), TYPE_A, 120, &[192, 168, 1, 20]));
+        assert!(announced_names(&packet).is_some());
+
+        for len in 0..packet.len() {
+            assert_eq!(announced_names(&packet[..len]), None, "truncated to {len} bytes");
+        }
+
+        // the record claims more data than the packet holds
+        let mut packet = header(FLAG_RESPONSE, 0, 1);
+        packet.extend(record(&name("phone.local"), TYPE_A, 120, &[192, 168, 1, 20]));
+        let data_len = packet.len() - 6;
+        packet[data_len..data_len + 2].copy_from_slice(&8u16.to_be_bytes());
+        assert_eq!(announced_names(&packet), None);
+    }
+
+    #[test]
+    fn goodbye_records_ignored() {
+        let mut packet = header(FLAG_RESPONSE, 0, 3);
+        packet.extend(record(&name("phone.local"), TYPE_A, 0, &[192, 168, 1, 20]));
+        packet.extend(record(&name("_companion-link._tcp.local"), TYPE_PTR, 0, &name("phone._companion-link._tcp.local")));
+        packet.extend(record(&name("tv.local"), TYPE_A, 120, &[192, 168, 1, 30]));
+
+        assert_eq!(announced_names(&packet).unwrap(), ["tv.local"]);
+    }
+
+    #[test]
+    fn srv_target() {
+        let mut data = vec![0, 0, 0, 0, 0x1F, 0x90];
+        data.extend(name("phone.local"));
+        let mut packet = header(FLAG_RESPONSE, 0, 1);
+        packet.extend(record(&name("phone._companion-link._tcp.local"), TYPE_SRV, 120, &data));
+
+        assert_eq!(announced_names(&packet).unwrap(), ["phone._companion-link._tcp.local", "phone.local"]);
+    }
+
+    #[test]
+    fn queries_are_not_read() {
+        let packet = query("phone.local", TYPE_A);
+        assert_eq!(announced_names(&packet), Some(vec![]));
+        assert_eq!(read_name(&packet, HEADER_LEN), Some(("phone.local".to_string(), packet.len() - 4)));
+    }
+}
@@ -0,0 +1,365 @@
+#![doc= include_str!("../README.md")]
+
+mod dns;
+
+use anyhow::Context;
+use bon::bon;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::future::ready;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::sync::watch::{Receiver, Sender, channel};
+use tokio::time::interval;
+use tokio_stream::wrappers::WatchStream;
+use tracing::{debug, error, trace};
+
+use control::device::Device;
+use control::device_manager::{DeviceManager, Supervisor};
+use control::reflect;
+use control::reflect::value::{Value, ValueType};
+use control::reflect::{DeviceInfo, Field, Operation, Operations, SetError};
+use thiserror::Error;
+use tokio::spawn;
+use tokio_util::sync::CancellationToken;
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+/// How often devices are checked for expiry and queries are sent
+const TICK: Duration = Duration::from_secs(1);
+
+/// The configuration data for an mDNS listener
+#[derive(Debug)]
+pub struct MdnsConfig {
+    /// The name of the target device (to be included in logs)
+    pub name: String,
+    /// The hostname announced by the device, eg: `Dylans-iPhone.local`, a record matches the
+    /// device if its name is this hostname or if its first label is the hostname without
+    /// `.local`, the latter matches service instances named after the device. This is not case
+    /// sensitive
+    pub hostname: String,
+    /// Service types to query for, eg: `_companion-link._tcp.local`, some devices only answer
+    /// queries for the services they offer
+    pub services: Vec<String>,
+    /// the length of time since the device was last heard from before deeming it absent
+    pub timeout: Duration,
+    /// the interval between each query for the device
+    pub query_interval: Duration,
+}
+
+impl MdnsConfig {
+    fn matches(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.');
+        let hostname = self.hostname.trim_end_matches('.');
+        let short = hostname.strip_suffix(".local").unwrap_or(hostname);
+        name.eq_ignore_ascii_case(hostname)
+            || name
+                .split('.')
+                .next()
+                .is_some_and(|label| label.eq_ignore_ascii_case(short))
+    }
+}
+
+/// A manager of mDNS listeners. Collects created listeners until ready to begin listening, all
+/// devices share a single socket
+#[derive(Default)]
+pub struct MdnsManager {
+    listeners: Vec<Listener>,
+}
+
+struct Listener {
+    config: MdnsConfig,
+    sender: Sender<bool>,
+    last_seen: Option<Instant>,
+    last_query: Option<Instant>,
+}
+
+impl DeviceManager for MdnsManager {
+    fn start(self: Box<Self>, token: CancellationToken) {
+        spawn(self.run(token));
+    }
+
+    fn supervise(self: Box<Self>, supervisor: &Supervisor) {
+        let token = supervisor.token();
+        // the listeners are kept across restarts, so a device is not deemed absent by a restart
+        let listeners = Arc::new(AsyncMutex::new(self.listeners));
+        supervisor.spawn("mdns", move || listen(listeners.clone(), token.clone()));
+    }
+}
+
+impl MdnsManager {
+    /// Create a new manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Listen for all devices until cancelled
+    pub async fn run(self, token: CancellationToken) {
+        if let Err(error) = listen(Arc::new(AsyncMutex::new(self.listeners)), token).await {
+            error!("{error:#}");
+        }
+    }
+}
+
+/// Listen for the devices until cancelled, an error is returned if the socket cannot be opened
+async fn listen(listeners: Arc<AsyncMutex<Vec<Listener>>>, token: CancellationToken) -> anyhow::Result<()> {
+    let socket = open_socket().context("Error opening mDNS socket")?;
+    let mut listeners = listeners.lock().await;
+    let destination = SocketAddr::V4(SocketAddrV4::new(MDNS_ADDR, MDNS_PORT));
+    let mut ticks = interval(TICK);
+    let mut buf = vec![0u8; 9000];
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = ticks.tick() => {
+                let now = Instant::now();
+                for listener in listeners.iter_mut() {
+                    listener.expire(now);
+                    for query in listener.queries(now) {
+                        if let Err(error) = socket.send_to(&query, destination).await {
+                            error!("Error sending mDNS query: {error}");
+                        }
+                    }
+                }
+            }
+            result = socket.recv_from(&mut buf) => {
+                let len = match result {
+                    Ok((len, _)) => len,
+                    Err(error) => {
+                        error!("Error receiving mDNS packet: {error}");
+                        continue;
+                    }
+                };
+                let Some(names) = buf.get(..len).and_then(dns::announced_names) else {
+                    trace!("Ignoring invalid mDNS packet");
+                    continue;
+                };
+                let now = Instant::now();
+                for listener in listeners.iter_mut() {
+                    if names.iter().any(|name| listener.config.matches(name)) {
+                        listener.seen(now);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Listener {
+    fn seen(&mut self, now: Instant) {
+        trace!("{} announced", self.config.name);
+        self.last_seen = Some(now);
+        self.sender.send_if_modified(|present| !std::mem::replace(present, true));
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let expired = self
+            .last_seen
+            .is_none_or(|seen| now.duration_since(seen) > self.config.timeout);
+        if expired && self.sender.send_if_modified(|present| std::mem::replace(present, false)) {
+            debug!("{} has not announced for {:?}", self.config.name, self.config.timeout);
+        }
+    }
+
+    /// Returns the queries to send if a query is due
+    fn queries(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let due = self
+            .last_query
+            .is_none_or(|query| now.duration_since(query) >= self.config.query_interval);
+        if !due {
+            return vec![];
+        }
+        self.last_query = Some(now);
+        let hostname = self.config.hostname.as_str();
+        let hostname = if hostname.ends_with(".local") {
+            hostname.to_string()
+        } else {
+            format!("{hostname}.local")
+        };
+        [dns::query(&hostname, dns::TYPE_A)]
+            .into_iter()
+            .chain(self.config.services.iter().map(|service| dns::query(service, dns::TYPE_PTR)))
+            .collect()
+    }
+}
+
+/// Open a socket bound to the mDNS port and joined to the mDNS group, the port is shared with
+/// any other mDNS responder on this machine
+fn open_socket() -> Result<UdpSocket, io::Error> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// An mDNS device, this represents a watched device and exposes some methods for getting current
+/// status and listening for changes
+pub struct MdnsDevice {
+    info: DeviceInfo,
+    receiver: Receiver<bool>,
+}
+
+#[bon]
+impl MdnsDevice {
+    #[allow(
+        missing_docs,
+        reason = "This item is hidden since it's only intended for use in macros"
+    )]
+    #[doc(hidden)]
+    #[builder]
+    pub async fn create(
+        manager: &mut MdnsManager,
+        info: DeviceInfo,
+        /// The hostname announced by the device
+        hostname: String,
+        /// Service types to query for
+        #[builder(default)]
+        services: Vec<String>,
+        /// the length of time since the device was last heard from before deeming it absent
+        timeout: Duration,
+        /// the interval between each query for the device
+        query_interval: Duration,
+    ) -> anyhow::Result<Self> {
+        let name = info.name.clone();
+        Self::new_with_args(
+            manager,
+            info,
+            MdnsConfig {
+                name,
+                hostname,
+                services,
+                timeout,
+                query_interval,
+            },
+        )
+        .await
+    }
+
+    /// Returns true if the device has been heard from within the timeout
+    pub fn online(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Returns a stream of changes to the online status of the device
+    pub fn online_changes(&self) -> impl Stream<Item = bool> {
+        WatchStream::from_changes(self.receiver.clone())
+    }
+}
+
+impl Device for MdnsDevice {
+    type Args = MdnsConfig;
+    type Manager = MdnsManager;
+
+    fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    async fn new_with_args(
+        manager: &mut Self::Manager,
+        info: DeviceInfo,
+        config: MdnsConfig,
+    ) -> anyhow::Result<Self> {
+        if config.hostname.is_empty() {
+            return Err(Error::EmptyHostname.into());
+        }
+        let (sender, receiver) = channel(false);
+        manager.listeners.push(Listener {
+            config,
+            sender,
+            last_seen: None,
+            last_query: None,
+        });
+        Ok(MdnsDevice { info, receiver })
+    }
+}
+
+impl reflect::Device for MdnsDevice {
+    fn info(&self) -> DeviceInfo {
+        self.info.clone()
+    }
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field {
+                name: "detected".to_string(),
+                description: "This value is true whenever the given device is announcing itself on the local network".to_string(),
+                operations: Operations {
+                    subscribe: true,
+                    get: true,
+                    set: false,
+                    toggle: false,
+                },
+                value_type: ValueType::Bool,
+            }
+        ]
+    }
+
+    fn subscribe(&self, field: &str) -> Result<BoxFuture<'_, BoxStream<'_, Value>>, reflect::Error> {
+        if field == "detected" {
+            Ok(Box::pin(ready(Box::pin(self.online_changes().map(Value::from)) as BoxStream<_>)))
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+
+    fn get(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<Value>>, reflect::Error> {
+        if field == "detected" {
+            Ok(Box::pin(ready(Ok(self.online().into()))))
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+
+    fn set(&self, field: &str, _: Value) -> Result<BoxFuture<'_, anyhow::Result<()>>, SetError> {
+        if field == "detected" {
+            Err(reflect::Error::OperationNotSupported {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+                operation: Operation::Set,
+            }.into())
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            }.into())
+        }
+    }
+
+    fn toggle(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<()>>, reflect::Error> {
+        if field == "detected" {
+            Err(reflect::Error::OperationNotSupported {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+                operation: Operation::Toggle,
+            })
+        } else {
+            Err(reflect::Error::FieldNotFound {
+                device: self.info.name.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+}
+
+/// Errors creating an mDNS device
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The hostname to listen for was empty
+    #[error("hostname must not be empty")]
+    EmptyHostname,
+}
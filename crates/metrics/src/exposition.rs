@@ -0,0 +1,86 @@
+//! The Prometheus [text exposition format](https://prometheus.io/docs/instrumenting/exposition_formats/)
+
+use std::fmt::{Display, Formatter, Write};
+
+/// All the samples of a single metric, these must be written together
+pub(crate) struct Family {
+    pub(crate) name: &'static str,
+    pub(crate) help: &'static str,
+    pub(crate) kind: Kind,
+    pub(crate) samples: Vec<Sample>,
+}
+
+/// The type of a metric
+#[derive(Clone, Copy)]
+pub(crate) enum Kind {
+    Gauge,
+    Counter,
+}
+
+/// A single value of a metric, identified by its labels
+pub(crate) struct Sample {
+    pub(crate) labels: Vec<(&'static str, String)>,
+    pub(crate) value: f64,
+}
+
+impl Family {
+    pub(crate) fn new(name: &'static str, help: &'static str, kind: Kind) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: vec![],
+        }
+    }
+
+    pub(crate) fn push(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+        self.samples.push(Sample { labels, value });
+    }
+}
+
+impl Display for Family {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // empty families are omitted entirely
+        if self.samples.is_empty() {
+            return Ok(());
+        }
+        let kind = match self.kind {
+            Kind::Gauge => "gauge",
+            Kind::Counter => "counter",
+        };
+        writeln!(f, "# HELP {} {}", self.name, self.help)?;
+        writeln!(f, "# TYPE {} {kind}", self.name)?;
+        for sample in &self.samples {
+            f.write_str(self.name)?;
+            for (i, (key, value)) in sample.labels.iter().enumerate() {
+                f.write_char(if i == 0 { '{' } else { ',' })?;
+                write!(f, "{key}=\"")?;
+                escape(f, value)?;
+                f.write_char('"')?;
+            }
+            if !sample.labels.is_empty() {
+                f.write_char('}')?;
+            }
+            f.write_char(' ')?;
+            match sample.value {
+                value if value == f64::INFINITY => f.write_str("+Inf")?,
+                value if value == f64::NEG_INFINITY => f.write_str("-Inf")?,
+                value => write!(f, "{value}")?,
+            }
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+fn escape(f: &mut Formatter<'_>, value: &str) -> std::fmt::Result {
+    for c in value.chars() {
+        match c {
+            '\\' => f.write_str("\\\\")?,
+            '"' => f.write_str("\\\"")?,
+            '\n' => f.write_str("\\n")?,
+            c => f.write_char(c)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,346 @@
+#![doc = include_str!("../README.md")]
+
+mod exposition;
+
+use anyhow::Context;
+use axum::Router;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use bon::Builder;
+use control::{Sensor, Service};
+use control::automation::{Automation, AutomationStats};
+use control::connection::ConnectionStats;
+use control::device::DeviceSet;
+use control::reflect::Device;
+use control::reflect::value::Value;
+use control::util::lock;
+use exposition::{Family, Kind};
+use futures::future::{Either, join_all, pending, select};
+use futures::stream::{BoxStream, select_all};
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::spawn;
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+/// How long a scrape waits for each field which can only be read, a device which doesn't answer in
+/// time is left out of the scrape rather than holding it up
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serves the state of devices and sensors and the counters of automations on `/metrics` in the
+/// Prometheus format, run it by adding it as a service
+#[derive(Builder)]
+#[builder(finish_fn = build)]
+pub struct Exporter<'a> {
+    #[builder(field)]
+    sensors: Vec<(String, BoxStream<'a, Value>)>,
+    #[builder(field)]
+    devices: Vec<Box<dyn Device>>,
+    #[builder(field)]
+    automations: Vec<(String, Arc<AutomationStats>)>,
+    #[builder(field)]
+    connections: Vec<(String, Arc<ConnectionStats>)>,
+    /// The address to listen on, defaults to all interfaces
+    #[builder(into)]
+    #[builder(default = "0.0.0.0")]
+    bind_address: String,
+    /// The port to listen on
+    port: u16,
+}
+
+impl<'a, S: exporter_builder::State> ExporterBuilder<'a, S> {
+    /// Export each value of the sensor under the given name, eg: a combined sensor which is not
+    /// a field of any device
+    pub fn add_sensor<T>(mut self, name: impl Into<String>, sensor: &'a T) -> Self
+    where
+        T: Sensor + ?Sized,
+        T::Item: Into<Value>,
+    {
+        self.sensors
+            .push((name.into(), Box::pin(sensor.subscribe().map(Into::into))));
+        self
+    }
+
+    /// Export the state of a device
+    pub fn add_device(mut self, device: impl Device + 'static) -> Self {
+        self.devices.push(Box::new(device));
+        self
+    }
+
+    /// Export the state of every device in a set
+    pub fn add_device_set(mut self, set: impl DeviceSet + 'static) -> Self {
+        self.devices.extend(set);
+        self
+    }
+
+    /// Export the counters of an automation, this must be called before the automation is
+    /// passed to the manager
+    pub fn add_automation(mut self, automation: &Automation<'_>) -> Self {
+        self.automations
+            .push((automation.name().to_string(), automation.stats()));
+        self
+    }
+
+    /// Export the state of a connection, eg: the MQTT connection of the zigbee manager from
+    /// `zigbee::Manager::connection`
+    pub fn add_connection(mut self, name: impl Into<String>, connection: Arc<ConnectionStats>) -> Self {
+        self.connections.push((name.into(), connection));
+        self
+    }
+}
+
+impl<'a> Service<'a> for Exporter<'a> {
+    fn name(&self) -> String {
+        "metrics".to_string()
+    }
+
+    async fn start(self) -> anyhow::Result<()> {
+        let listener = TcpListener::bind((self.bind_address, self.port))
+            .await
+            .context("failed to bind address")?;
+        let (sensors, streams): (Vec<_>, Vec<_>) = self
+            .sensors
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, stream))| (name, stream.map(move |value| (index, value))))
+            .unzip();
+        let shared = Arc::new(Shared {
+            sensors,
+            latest_sensors: Mutex::default(),
+            devices: self.devices,
+            automations: self.automations,
+            connections: self.connections,
+            latest: Mutex::default(),
+        });
+        let tracking = spawn(track(shared.clone()));
+        let router = Router::new()
+            .route("/metrics", get(metrics))
+            .with_state(shared.clone());
+        // the sensors borrow from the caller, so they are tracked by this future rather than a task
+        let serve = pin!(async { axum::serve(listener, router).await });
+        let result = match select(serve, pin!(track_sensors(shared, streams))).await {
+            Either::Left((result, _)) => result,
+            Either::Right((never, _)) => match never {},
+        };
+        tracking.abort();
+        result.context("metrics server failed")
+    }
+}
+
+struct Shared {
+    /// The names of the sensors, in the order they were added
+    sensors: Vec<String>,
+    /// The latest value of each sensor, by index
+    latest_sensors: Mutex<HashMap<usize, Value>>,
+    devices: Vec<Box<dyn Device>>,
+    automations: Vec<(String, Arc<AutomationStats>)>,
+    connections: Vec<(String, Arc<ConnectionStats>)>,
+    /// The latest value of each field which can be subscribed to, by device index and field name
+    latest: Mutex<HashMap<(usize, String), Value>>,
+}
+
+/// Keep the latest value of every field which can be subscribed to, fields which can only be read
+/// are read on each scrape instead
+async fn track(shared: Arc<Shared>) {
+    let mut streams = Vec::new();
+    for (index, device) in shared.devices.iter().enumerate() {
+        for field in device.fields() {
+            if !field.operations.subscribe {
+                continue;
+            }
+            let stream = match device.subscribe(&field.name) {
+                Ok(stream) => stream.await,
+                Err(error) => {
+                    warn!("failed to subscribe to {}: {error}", field.name);
+                    continue;
+                }
+            };
+            let name = field.name;
+            streams.push(stream.map(move |value| (index, name.clone(), value)));
+        }
+    }
+    debug!("tracking {} attributes", streams.len());
+    let mut updates = select_all(streams);
+    while let Some((index, field, value)) = updates.next().await {
+        lock(&shared.latest).insert((index, field), value);
+    }
+}
+
+/// Keep the latest value of every sensor, this never returns so the server keeps running once
+/// every sensor has ended
+async fn track_sensors(shared: Arc<Shared>, streams: Vec<impl Stream<Item = (usize, Value)> + Unpin>) -> Infallible {
+    let mut updates = select_all(streams);
+    while let Some((index, value)) = updates.next().await {
+        lock(&shared.latest_sensors).insert(index, value);
+    }
+    pending().await
+}
+
+async fn metrics(State(shared): State<Arc<Shared>>) -> impl IntoResponse {
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        shared.render().await,
+    )
+}
+
+impl Shared {
+    async fn render(&self) -> String {
+        let mut values = Family::new(
+            "tintean_device_value",
+            "The current value of each numeric or boolean device attribute",
+            Kind::Gauge,
+        );
+        let mut states = Family::new(
+            "tintean_device_state",
+            "The current state of each textual device attribute, the value is always 1",
+            Kind::Gauge,
+        );
+        let latest = lock(&self.latest).clone();
+        // the fields are read concurrently, so a scrape takes as long as the slowest device
+        let fields = join_all(self.devices.iter().enumerate().flat_map(|(index, device)| {
+            let latest = &latest;
+            device.fields().into_iter().map(move |field| async move {
+                let value = if field.operations.subscribe {
+                    latest.get(&(index, field.name.clone())).cloned()
+                } else if field.operations.get {
+                    read(device.as_ref(), &field.name).await
+                } else {
+                    None
+                };
+                (device.name(), field.name, value)
+            })
+        }))
+        .await;
+        for (name, field, value) in fields {
+            let labels = vec![("device", name), ("attribute", field)];
+            match value {
+                Some(Value::Bool(value)) => values.push(labels, if value { 1.0 } else { 0.0 }),
+                #[allow(clippy::cast_precision_loss, reason = "metrics are always floats")]
+                Some(Value::Int(value)) => values.push(labels, value as f64),
+                Some(Value::Float(value)) => values.push(labels, value),
+                Some(Value::String(value)) => {
+                    let mut labels = labels;
+                    labels.push(("state", value));
+                    states.push(labels, 1.0);
+                }
+                Some(Value::None) | None => {}
+            }
+        }
+
+        let mut sensor_values = Family::new(
+            "tintean_sensor_value",
+            "The current value of each numeric or boolean sensor",
+            Kind::Gauge,
+        );
+        let mut sensor_states = Family::new(
+            "tintean_sensor_state",
+            "The current state of each textual sensor, the value is always 1",
+            Kind::Gauge,
+        );
+        let latest_sensors = lock(&self.latest_sensors).clone();
+        for (index, name) in self.sensors.iter().enumerate() {
+            let labels = vec![("sensor", name.clone())];
+            match latest_sensors.get(&index).cloned() {
+                Some(Value::Bool(value)) => sensor_values.push(labels, if value { 1.0 } else { 0.0 }),
+                #[allow(clippy::cast_precision_loss, reason = "metrics are always floats")]
+                Some(Value::Int(value)) => sensor_values.push(labels, value as f64),
+                Some(Value::Float(value)) => sensor_values.push(labels, value),
+                Some(Value::String(value)) => {
+                    let mut labels = labels;
+                    labels.push(("state", value));
+                    sensor_states.push(labels, 1.0);
+                }
+                Some(Value::None) | None => {}
+            }
+        }
+
+        let mut triggers = Family::new(
+            "tintean_automation_triggers_total",
+            "The number of times each automation has been triggered",
+            Kind::Counter,
+        );
+        let mut runs = Family::new(
+            "tintean_automation_runs_total",
+            "The number of completed runs of each automation, by result",
+            Kind::Counter,
+        );
+        #[allow(clippy::cast_precision_loss, reason = "metrics are always floats")]
+        for (name, stats) in &self.automations {
+            triggers.push(vec![("automation", name.clone())], stats.triggered() as f64);
+            runs.push(
+                vec![("automation", name.clone()), ("result", "success".to_string())],
+                stats.succeeded() as f64,
+            );
+            runs.push(
+                vec![("automation", name.clone()), ("result", "failure".to_string())],
+                stats.failed() as f64,
+            );
+        }
+
+        let mut up = Family::new(
+            "tintean_connection_up",
+            "Whether each connection is currently established",
+            Kind::Gauge,
+        );
+        let mut connects = Family::new(
+            "tintean_connection_connects_total",
+            "The number of times each connection has been established",
+            Kind::Counter,
+        );
+        let mut disconnects = Family::new(
+            "tintean_connection_disconnects_total",
+            "The number of times each established connection has been lost",
+            Kind::Counter,
+        );
+        let mut reconnects = Family::new(
+            "tintean_connection_reconnect_attempts_total",
+            "The number of attempts to reconnect each connection",
+            Kind::Counter,
+        );
+        let mut errors = Family::new(
+            "tintean_connection_last_error",
+            "The most recent error of each connection which has had one, the value is always 1",
+            Kind::Gauge,
+        );
+        #[allow(clippy::cast_precision_loss, reason = "metrics are always floats")]
+        for (name, stats) in &self.connections {
+            let labels = vec![("connection", name.clone())];
+            up.push(labels.clone(), if stats.is_connected() { 1.0 } else { 0.0 });
+            connects.push(labels.clone(), stats.connects() as f64);
+            disconnects.push(labels.clone(), stats.disconnects() as f64);
+            reconnects.push(labels.clone(), stats.reconnect_attempts() as f64);
+            if let Some(error) = stats.last_error() {
+                let mut labels = labels;
+                labels.push(("error", error));
+                errors.push(labels, 1.0);
+            }
+        }
+
+        let mut body = String::new();
+        for family in [values, states, sensor_values, sensor_states, triggers, runs, up, connects, disconnects, reconnects, errors] {
+            // writing to a String can't fail
+            let _ = write!(body, "{family}");
+        }
+        body
+    }
+}
+
+async fn read(device: &dyn Device, field: &str) -> Option<Value> {
+    let result = match device.get(field) {
+        Ok(future) => timeout(READ_TIMEOUT, future)
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out after {READ_TIMEOUT:?}"))),
+        Err(error) => Err(error.into()),
+    };
+    result
+        .inspect_err(|error| debug!("failed to read {field}: {error}"))
+        .ok()
+}
@@ -0,0 +1,178 @@
+#![doc = include_str!("../README.md")]
+
+mod peer;
+
+pub use peer::{Peer, RemoteEntity, Zone};
+
+use bon::Builder;
+use control::Sensor;
+use control::Service;
+use control::automation::{Automation, AutomationStats};
+use control::reflect::Device;
+use control::reflect::value::Value;
+use futures::StreamExt;
+use futures::stream::{BoxStream, select_all};
+use rumqttc::{AsyncClient, ConnectionError, MqttOptions, QoS};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval, sleep};
+use tracing::{debug, error, warn};
+
+/// How long to wait before reconnecting after the connection fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Republishes sensors, device fields and automation counters to MQTT topics, run it by adding it
+/// as a service
+#[derive(Builder)]
+#[builder(finish_fn = build)]
+pub struct Republisher<'a> {
+    #[builder(field)]
+    sensors: Vec<BoxStream<'a, (String, Value)>>,
+    #[builder(field)]
+    devices: Vec<&'a dyn Device>,
+    #[builder(field)]
+    automations: Vec<(String, Arc<AutomationStats>)>,
+    /// The MQTT options used to establish a connection
+    mqtt_options: MqttOptions,
+    /// The prefix of the topics devices and automations are published on, defaults to `tintean`
+    #[builder(into)]
+    #[builder(default = "tintean")]
+    prefix: String,
+    /// Whether published values are retained by the broker, defaults to true
+    #[builder(default = true)]
+    retain: bool,
+    /// How often the counters of automations are published, defaults to once a minute
+    #[builder(default = Duration::from_secs(60))]
+    status_interval: Duration,
+}
+
+impl<'a, S: republisher_builder::State> RepublisherBuilder<'a, S> {
+    /// Publish each value of the sensor to the given topic, the topic is not prefixed
+    pub fn add_sensor<T>(mut self, topic: impl Into<String>, sensor: &'a T) -> Self
+    where
+        T: Sensor + ?Sized,
+        T::Item: Into<Value>,
+    {
+        let topic = topic.into();
+        self.sensors.push(Box::pin(
+            sensor
+                .subscribe()
+                .map(move |value| (topic.clone(), value.into())),
+        ));
+        self
+    }
+
+    /// Publish each field of the device which can be subscribed to on `{prefix}/{device}/{field}`
+    pub fn add_device(mut self, device: &'a dyn Device) -> Self {
+        self.devices.push(device);
+        self
+    }
+
+    /// Periodically publish the run counters of an automation on `{prefix}/automation/{name}`,
+    /// this must be called before the automation is passed to the manager
+    pub fn add_automation(mut self, automation: &Automation<'_>) -> Self {
+        self.automations
+            .push((automation.name().to_string(), automation.stats()));
+        self
+    }
+}
+
+impl<'a> Service<'a> for Republisher<'a> {
+    fn name(&self) -> String {
+        "mqtt-republisher".to_string()
+    }
+
+    async fn start(self) -> anyhow::Result<()> {
+        let (client, mut event_loop) = AsyncClient::new(self.mqtt_options, 100);
+        // publishes are only sent while the event loop is polled
+        let connection = async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(_) => {}
+                    // every client has been dropped so nothing more will be published
+                    Err(ConnectionError::RequestsDone) => break,
+                    Err(error) => {
+                        warn!("Error from connection: {error}");
+                        sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        };
+
+        let mut streams = self.sensors;
+        for device in self.devices {
+            let name = device.name();
+            for field in device.fields() {
+                if !field.operations.subscribe {
+                    continue;
+                }
+                let stream = match device.subscribe(&field.name) {
+                    Ok(stream) => stream.await,
+                    Err(error) => {
+                        error!("failed to subscribe to {}: {error}", field.name);
+                        continue;
+                    }
+                };
+                let topic = format!("{}/{name}/{}", self.prefix, field.name);
+                streams.push(Box::pin(stream.map(move |value| (topic.clone(), value))));
+            }
+        }
+        debug!("republishing {} entities", streams.len());
+        let publisher = Publisher {
+            client: &client,
+            retain: self.retain,
+        };
+        let updates = select_all(streams).for_each(|(topic, value)| async move {
+            publisher.publish(topic, &value).await;
+        });
+
+        let prefix = &self.prefix;
+        let automations = &self.automations;
+        let status_interval = self.status_interval;
+        let status = async move {
+            let mut ticks = interval(status_interval);
+            loop {
+                ticks.tick().await;
+                for (name, stats) in automations {
+                    let status = json!({
+                        "triggered": stats.triggered(),
+                        "succeeded": stats.succeeded(),
+                        "failed": stats.failed(),
+                    });
+                    publisher
+                        .publish(format!("{prefix}/automation/{name}"), &status)
+                        .await;
+                }
+            }
+        };
+
+        tokio::join!(connection, updates, status);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Publisher<'a> {
+    client: &'a AsyncClient,
+    retain: bool,
+}
+
+impl Publisher<'_> {
+    async fn publish(self, topic: String, value: &impl serde::Serialize) {
+        let payload = match serde_json::to_vec(value) {
+            Ok(payload) => payload,
+            Err(error) => {
+                error!("Failed to serialize payload for {topic}: {error}");
+                return;
+            }
+        };
+        if let Err(error) = self
+            .client
+            .publish(&topic, QoS::AtLeastOnce, self.retain, payload)
+            .await
+        {
+            error!("Failed to publish to {topic}: {error}");
+        }
+    }
+}
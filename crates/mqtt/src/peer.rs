@@ -0,0 +1,408 @@
+//! Sharing entities between controllers over MQTT, each controller publishes the sensors added to
+//! it's [Peer] and subscribes to the values published by the others
+
+use bon::Builder;
+use control::reflect::Device;
+use control::reflect::value::Value;
+use control::{ReadValue, Sensor, Service};
+use control::util::{broadcast_stream, lock, send_broadcast};
+use futures::future::{BoxFuture, ready};
+use futures::stream::{BoxStream, select_all, unfold};
+use futures::{Stream, StreamExt};
+use rumqttc::{AsyncClient, ConnectionError, Event, Incoming, LastWill, MqttOptions, QoS};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, watch};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use crate::{Publisher, RECONNECT_DELAY};
+
+/// The status published by a controller while it is connected
+const ONLINE: &str = "online";
+/// The status published by a controller once it disconnects, this is also it's last will
+const OFFLINE: &str = "offline";
+/// The number of remote updates buffered for each subscriber
+const UPDATE_CAPACITY: usize = 256;
+
+/// Shares state with other controllers over MQTT, eg: between the main house and an outbuilding
+/// which each run their own controller, run it by adding it as a service
+///
+/// Each controller publishes the entities added to it on `{prefix}/peer/{controller}/state/{entity}`
+/// and it's status on `{prefix}/peer/{controller}/status`, the status is set to `offline` by the
+/// broker as the controller's last will, so the other controllers notice when it goes down, how
+/// quickly depends on the keep alive of the MQTT options
+#[derive(Builder)]
+#[builder(finish_fn = build)]
+pub struct Peer<'a> {
+    #[builder(field)]
+    entities: Vec<BoxStream<'a, (String, Value)>>,
+    #[builder(field)]
+    devices: Vec<&'a dyn Device>,
+    /// The MQTT options used to establish a connection, the last will is replaced
+    mqtt_options: MqttOptions,
+    /// The name of this controller, this must be unique among the controllers
+    #[builder(into)]
+    controller: String,
+    /// The prefix of the topics used, defaults to `tintean`
+    #[builder(into)]
+    #[builder(default = "tintean")]
+    prefix: String,
+    #[builder(skip = Arc::new(Shared::new(&controller, &prefix)))]
+    shared: Arc<Shared>,
+}
+
+impl<'a, S: peer_builder::State> PeerBuilder<'a, S> {
+    /// Share each value of the sensor with the other controllers as the given entity, eg: whether
+    /// anyone is home
+    pub fn add_sensor<T>(mut self, entity: impl Into<String>, sensor: &'a T) -> Self
+    where
+        T: Sensor + ?Sized,
+        T::Item: Into<Value>,
+    {
+        let entity = entity.into();
+        self.entities.push(Box::pin(
+            sensor
+                .subscribe()
+                .map(move |value| (entity.clone(), value.into())),
+        ));
+        self
+    }
+
+    /// Share each field of the device which can be subscribed to as the entity `{device}/{field}`
+    pub fn add_device(mut self, device: &'a dyn Device) -> Self {
+        self.devices.push(device);
+        self
+    }
+}
+
+impl Peer<'_> {
+    /// An entity shared by another controller, values which cannot be converted to `T` are
+    /// skipped
+    pub fn remote<T>(&self, controller: impl Into<String>, entity: impl Into<String>) -> RemoteEntity<T> {
+        RemoteEntity {
+            controller: controller.into(),
+            entity: entity.into(),
+            shared: self.shared.clone(),
+            _t: PhantomData,
+        }
+    }
+
+    /// A zone of automations which runs on one controller at a time, `controllers` are the
+    /// controllers able to run it in order of preference, the zone is owned by the first of them
+    /// which is online
+    pub fn zone<C>(&self, name: impl Into<String>, controllers: impl IntoIterator<Item = C>) -> Zone
+    where
+        C: Into<String>,
+    {
+        Zone {
+            name: name.into(),
+            controllers: controllers.into_iter().map(Into::into).collect(),
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/peer/{}/{name}", self.prefix, self.controller)
+    }
+}
+
+impl<'a> Service<'a> for Peer<'a> {
+    fn name(&self) -> String {
+        "mqtt-peer".to_string()
+    }
+
+    async fn start(self) -> anyhow::Result<()> {
+        let status_topic = self.topic("status");
+        let state_topic = self.topic("state");
+        let mut mqtt_options = self.mqtt_options;
+        mqtt_options.set_last_will(LastWill::new(&status_topic, OFFLINE, QoS::AtLeastOnce, true));
+        let (client, mut event_loop) = AsyncClient::new(mqtt_options, 100);
+        let shared = &self.shared;
+        let subscriptions = [
+            format!("{}+/status", shared.topic_prefix),
+            format!("{}+/state/#", shared.topic_prefix),
+        ];
+
+        let connection = async {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                        // the session may be clean, so subscribe and announce on each connection
+                        debug!("connected, announcing {} as online", shared.controller);
+                        for topic in &subscriptions {
+                            if let Err(error) = client.subscribe(topic, QoS::AtLeastOnce).await {
+                                error!("Failed to subscribe to {topic}: {error}");
+                            }
+                        }
+                        if let Err(error) = client
+                            .publish(&status_topic, QoS::AtLeastOnce, true, ONLINE)
+                            .await
+                        {
+                            error!("Failed to publish status: {error}");
+                        }
+                    }
+                    Ok(Event::Incoming(Incoming::Publish(publish))) => {
+                        shared.receive(&publish.topic, &publish.payload);
+                    }
+                    Ok(_) => {}
+                    Err(ConnectionError::RequestsDone) => break,
+                    Err(error) => {
+                        // the status of the other controllers is kept while disconnected, so no
+                        // zone is taken over just because this controller lost the broker
+                        warn!("Error from connection: {error}");
+                        sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        };
+
+        let mut streams = self.entities;
+        for device in self.devices {
+            let name = device.name();
+            for field in device.fields() {
+                if !field.operations.subscribe {
+                    continue;
+                }
+                let stream = match device.subscribe(&field.name) {
+                    Ok(stream) => stream.await,
+                    Err(error) => {
+                        error!("failed to subscribe to {}: {error}", field.name);
+                        continue;
+                    }
+                };
+                let entity = format!("{name}/{}", field.name);
+                streams.push(Box::pin(stream.map(move |value| (entity.clone(), value))));
+            }
+        }
+        debug!("sharing {} entities", streams.len());
+        let publisher = Publisher {
+            client: &client,
+            retain: true,
+        };
+        let state_topic = &state_topic;
+        let updates = select_all(streams).for_each(|(entity, value)| async move {
+            publisher.publish(format!("{state_topic}/{entity}"), &value).await;
+        });
+
+        tokio::join!(connection, updates);
+        Ok(())
+    }
+}
+
+/// The state received from the other controllers
+struct Shared {
+    controller: String,
+    /// The prefix of every peer topic, eg: `tintean/peer/`
+    topic_prefix: String,
+    /// Whether each controller is online, by name, this controller is only marked online once it's
+    /// own status has been received back
+    online: watch::Sender<BTreeMap<String, bool>>,
+    /// The latest value of each remote entity, by controller and entity
+    latest: Mutex<HashMap<(String, String), Value>>,
+    /// Every value received from the other controllers
+    updates: broadcast::Sender<RemoteUpdate>,
+}
+
+#[derive(Debug, Clone)]
+struct RemoteUpdate {
+    controller: String,
+    entity: String,
+    value: Value,
+}
+
+impl Shared {
+    fn new(controller: &str, prefix: &str) -> Self {
+        Self {
+            controller: controller.to_string(),
+            topic_prefix: format!("{prefix}/peer/"),
+            online: watch::Sender::default(),
+            latest: Mutex::default(),
+            updates: broadcast::channel(UPDATE_CAPACITY).0,
+        }
+    }
+
+    /// Handle a publish on one of the peer topics
+    fn receive(&self, topic: &str, payload: &[u8]) {
+        let Some((controller, kind)) = topic
+            .strip_prefix(&self.topic_prefix)
+            .and_then(|topic| topic.split_once('/'))
+        else {
+            return;
+        };
+        if kind == "status" {
+            let online = payload == ONLINE.as_bytes();
+            if controller != self.controller {
+                info!(controller, "controller is {}", if online { ONLINE } else { OFFLINE });
+            }
+            // this controller's own status arrives after the retained status of every other
+            // controller, so receiving it means the status of the others is known
+            self.online.send_if_modified(|controllers| {
+                controllers.insert(controller.to_string(), online) != Some(online)
+            });
+            return;
+        }
+        if controller == self.controller {
+            return;
+        }
+        let Some(entity) = kind.strip_prefix("state/") else {
+            return;
+        };
+        let value = match serde_json::from_slice::<Value>(payload) {
+            Ok(value) => value,
+            Err(error) => {
+                warn!(controller, "failed to parse value of {entity}: {error}");
+                return;
+            }
+        };
+        lock(&self.latest).insert((controller.to_string(), entity.to_string()), value.clone());
+        send_broadcast(&self.updates, RemoteUpdate {
+            controller: controller.to_string(),
+            entity: entity.to_string(),
+            value,
+        });
+    }
+
+    /// The first of the controllers which is online, this controller is always online, until the
+    /// status of the others is known they are assumed to be online so nothing is taken over on
+    /// startup
+    fn owner<'c>(&self, controllers: &'c [String]) -> Option<&'c str> {
+        let online = self.online.borrow();
+        let synced = online.get(&self.controller) == Some(&true);
+        controllers
+            .iter()
+            .find(|controller| {
+                **controller == self.controller
+                    || online.get(*controller).copied().unwrap_or(!synced)
+            })
+            .map(String::as_str)
+    }
+}
+
+/// An entity shared by another controller, get it from [Peer::remote]
+pub struct RemoteEntity<T> {
+    controller: String,
+    entity: String,
+    shared: Arc<Shared>,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> RemoteEntity<T>
+where
+    T: TryFrom<Value>,
+    T::Error: Display,
+{
+    fn convert(&self, value: Value) -> Option<T> {
+        match T::try_from(value) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!(controller = self.controller, "invalid value of {}: {error}", self.entity);
+                None
+            }
+        }
+    }
+}
+
+impl<T> Sensor for RemoteEntity<T>
+where
+    T: TryFrom<Value> + Send,
+    T::Error: Display,
+{
+    type Item = T;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        let updates = broadcast_stream(self.shared.updates.subscribe());
+        Box::pin(updates.filter_map(move |update| {
+            let value = (update.controller == self.controller && update.entity == self.entity)
+                .then(|| self.convert(update.value))
+                .flatten();
+            ready(value)
+        }))
+    }
+}
+
+impl<T> ReadValue for RemoteEntity<T>
+where
+    T: TryFrom<Value> + Send,
+    T::Error: Display,
+{
+    type Item = T;
+
+    /// The latest value shared by the other controller
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        let latest = lock(&self.shared.latest)
+            .get(&(self.controller.clone(), self.entity.clone()))
+            .cloned();
+        let result = match latest {
+            Some(value) => T::try_from(value).map_err(|error| anyhow::anyhow!("invalid value: {error}")),
+            None => Err(anyhow::anyhow!("{} has not shared {} yet", self.controller, self.entity)),
+        };
+        Box::pin(ready(result))
+    }
+}
+
+/// A group of automations which should only run on one controller at a time, eg: the automations
+/// of an outbuilding which the main house takes over while the outbuilding's controller is down,
+/// get it from [Peer::zone]
+///
+/// The zone is a sensor of whether this controller currently owns it, gate the triggers of it's
+/// automations with [gate](Self::gate)
+#[derive(Clone)]
+pub struct Zone {
+    name: String,
+    controllers: Vec<String>,
+    shared: Arc<Shared>,
+}
+
+impl Zone {
+    /// The name of this zone
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The controller which currently owns this zone, None if none of them are online
+    pub fn owner(&self) -> Option<String> {
+        self.shared.owner(&self.controllers).map(str::to_string)
+    }
+
+    /// True if this controller currently owns the zone
+    pub fn is_active(&self) -> bool {
+        self.shared.owner(&self.controllers) == Some(self.shared.controller.as_str())
+    }
+
+    /// Only pass on the items of the stream while this controller owns the zone, eg: the trigger
+    /// of an automation
+    pub fn gate<'s, S>(&self, stream: S) -> impl Stream<Item = S::Item> + Send + 's
+    where
+        S: Stream + Send + 's,
+        S::Item: Send,
+    {
+        let zone = self.clone();
+        stream.filter(move |_| ready(zone.is_active()))
+    }
+}
+
+impl Sensor for Zone {
+    type Item = bool;
+
+    /// Each change of whether this controller owns the zone
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        let state = (self.shared.online.subscribe(), self.is_active());
+        Box::pin(unfold(state, move |(mut online, active)| async move {
+            loop {
+                online.changed().await.ok()?;
+                let now = self.is_active();
+                if now != active {
+                    if now {
+                        info!(zone = self.name, "taking over the zone");
+                    } else {
+                        info!(zone = self.name, "handing the zone back");
+                    }
+                    return Some((now, (online, now)));
+                }
+            }
+        }))
+    }
+}
@@ -209,6 +209,145 @@ where
     }
 }
 
+/// A set of enum values, eg: the color modes supported by a light, each value is held at most once
+///
+/// When accessed dynamically the set is a comma separated string of the enum's values
+#[derive(Debug, Clone)]
+pub struct EnumSet<T>(Vec<T>);
+
+impl<T> EnumSet<T> {
+    /// Creates an empty set
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Returns the number of values in the set
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the set has no values
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an iterator over the values, in the order they were inserted
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.0.iter()
+    }
+}
+
+impl<T: PartialEq> EnumSet<T> {
+    /// Adds a value to the set, returning false if it was already present
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.contains(&value) {
+            false
+        } else {
+            self.0.push(value);
+            true
+        }
+    }
+
+    /// Removes a value from the set, returning false if it was not present
+    pub fn remove(&mut self, value: &T) -> bool {
+        let len = self.0.len();
+        self.0.retain(|existing| existing != value);
+        self.0.len() != len
+    }
+
+    /// Returns true if the set contains the value
+    pub fn contains(&self, value: &T) -> bool {
+        self.0.contains(value)
+    }
+}
+
+impl<T> Default for EnumSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// sets are equal regardless of the order values were inserted
+impl<T: PartialEq> PartialEq for EnumSet<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|value| other.contains(value))
+    }
+}
+
+impl<T: Eq> Eq for EnumSet<T> {}
+
+impl<T: PartialEq> FromIterator<T> for EnumSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T: PartialEq> Extend<T> for EnumSet<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<T> IntoIterator for EnumSet<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a EnumSet<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl<T> AsValueType for EnumSet<T> {
+    fn value_type() -> ValueType {
+        ValueType::String { values: None }
+    }
+}
+
+impl<T: Into<Value>> From<EnumSet<T>> for Value {
+    fn from(set: EnumSet<T>) -> Self {
+        let values: Vec<_> = set.into_iter().filter_map(|value| match value.into() {
+            Value::String(value) => Some(value),
+            _ => None,
+        }).collect();
+        Value::String(values.join(","))
+    }
+}
+
+impl<T> TryFrom<Value> for EnumSet<T>
+where
+    T: TryFrom<Value, Error=ValueReadError> + PartialEq
+{
+    type Error = ValueReadError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let Value::String(values) = value else {
+            return Err(ValueReadError::WrongType {
+                expected_type: ValueType::String { values: None },
+                actual_type: value.value_type(),
+            })
+        };
+        values
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| T::try_from(Value::String(value.to_string())))
+            .collect()
+    }
+}
+
 #[derive(Debug, Error, Serialize, Deserialize, Clone)]
 /// An error in converting a [Value] to the correct type for a certain field
 pub enum ValueReadError {
@@ -428,8 +567,37 @@ impl From<f64> for Value {
     }
 }
 
+impl TryFrom<Value> for f64 {
+    type Error = ValueReadError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(value) => Ok(value),
+            // whole numbers may have been stored as ints
+            #[allow(clippy::cast_precision_loss, reason = "values are small enough in practice")]
+            Value::Int(value) => Ok(value as f64),
+            value => Err(ValueReadError::WrongType {
+                expected_type: ValueType::Float,
+                actual_type: value.value_type(),
+            }),
+        }
+    }
+}
+
 impl From<String> for Value {
     fn from(value: String) -> Self {
         Value::String(value)
     }
 }
+
+impl TryFrom<Value> for String {
+    type Error = ValueReadError;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let Value::String(value) = value else {
+            return Err(ValueReadError::WrongType {
+                expected_type: ValueType::String { values: None },
+                actual_type: value.value_type(),
+            })
+        };
+        Ok(value)
+    }
+}
@@ -0,0 +1,150 @@
+// A minimal control panel on top of the REST API and the event stream, kept dependency-free so it
+// can be embedded in the binary
+
+/** The number of automation runs shown */
+const MAX_RUNS = 20;
+
+/** The devices by id, as returned by /api/devices */
+const devices = new Map();
+
+async function request(method, path, body) {
+    const headers = body === undefined ? {} : {"Content-Type": "application/json"};
+    const token = localStorage.getItem("token");
+    if (token !== null) {
+        headers["Authorization"] = `Bearer ${token}`;
+    }
+    const response = await fetch(path, {
+        method,
+        headers,
+        body: body === undefined ? undefined : JSON.stringify(body),
+    });
+    // the server requires a token for changes, ask for it and try again
+    if (response.status === 401) {
+        const token = prompt("Token");
+        if (token !== null) {
+            localStorage.setItem("token", token);
+            return request(method, path, body);
+        }
+    }
+    if (!response.ok) {
+        const error = await response.json().catch(() => ({error: response.statusText}));
+        throw new Error(error.error);
+    }
+    return response.status === 204 ? null : response.json();
+}
+
+async function loadDevices() {
+    for (const device of await request("GET", "/api/devices")) {
+        devices.set(device.id, device);
+    }
+    renderDevices();
+}
+
+async function loadAutomations() {
+    const automations = await request("GET", "/api/automations");
+    const body = document.getElementById("automations");
+    body.replaceChildren(...automations.map(automation => {
+        const row = document.createElement("tr");
+        for (const value of [automation.name, automation.triggered, automation.succeeded, automation.failed]) {
+            const cell = document.createElement("td");
+            cell.textContent = value;
+            row.append(cell);
+        }
+        return row;
+    }));
+}
+
+function renderDevices() {
+    const container = document.getElementById("devices");
+    container.replaceChildren(...[...devices.values()].map(renderDevice));
+}
+
+function renderDevice(device) {
+    const card = document.createElement("div");
+    card.className = "card";
+    const title = document.createElement("h3");
+    title.textContent = device.name;
+    card.append(title);
+    const tags = Object.entries(device.tags).map(([key, value]) => `${key}: ${value}`).join(", ");
+    if (tags) {
+        const line = document.createElement("div");
+        line.className = "tags";
+        line.textContent = tags;
+        card.append(line);
+    }
+    for (const field of device.fields) {
+        card.append(renderField(device, field));
+    }
+    return card;
+}
+
+function renderField(device, field) {
+    const row = document.createElement("div");
+    row.className = "field";
+    row.title = field.description;
+    const name = document.createElement("span");
+    name.textContent = field.name;
+    row.append(name);
+    const value = device.values[field.name];
+    if (field.value_type.type === "Bool" && (field.operations.toggle || field.operations.set)) {
+        const button = document.createElement("button");
+        button.textContent = value === undefined ? "?" : value ? "on" : "off";
+        button.className = value ? "on" : "";
+        button.onclick = () => {
+            const change = field.operations.toggle
+                ? request("POST", `/api/devices/${device.id}/${field.name}/toggle`)
+                : request("PUT", `/api/devices/${device.id}/${field.name}`, !value);
+            change.catch(error => alert(error.message));
+        };
+        row.append(button);
+    } else {
+        const text = document.createElement("span");
+        text.textContent = value === undefined || value === null ? "-" : String(value);
+        row.append(text);
+    }
+    return row;
+}
+
+function addRun(event) {
+    const runs = document.getElementById("runs");
+    const item = document.createElement("li");
+    item.className = event.event;
+    const time = new Date().toLocaleTimeString();
+    item.textContent = `${time} ${event.automation} ${event.event}${event.error ? `: ${event.error}` : ""}`;
+    runs.prepend(item);
+    while (runs.children.length > MAX_RUNS) {
+        runs.lastChild.remove();
+    }
+}
+
+function connect() {
+    const status = document.getElementById("connection");
+    const protocol = location.protocol === "https:" ? "wss:" : "ws:";
+    const socket = new WebSocket(`${protocol}//${location.host}/api/events`);
+    socket.onopen = () => {
+        status.textContent = "connected";
+        status.className = "connected";
+    };
+    socket.onmessage = message => {
+        const event = JSON.parse(message.data);
+        if (event.type === "device_update") {
+            const device = devices.get(event.device);
+            if (device) {
+                device.values[event.field] = event.value;
+                renderDevices();
+            }
+        } else if (event.type === "automation_run") {
+            addRun(event);
+            loadAutomations();
+        }
+    };
+    socket.onclose = () => {
+        status.textContent = "disconnected";
+        status.className = "disconnected";
+        // the controller may be restarting, keep trying
+        setTimeout(connect, 2000);
+    };
+}
+
+loadDevices().then(connect);
+loadAutomations();
@@ -0,0 +1,23 @@
+//! The dashboard served on `/`, a minimal control panel built on the API, the assets are embedded
+//! in the binary so nothing needs to be deployed alongside it
+
+use axum::Router;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+
+const INDEX: &str = include_str!("../dashboard/index.html");
+const SCRIPT: &str = include_str!("../dashboard/dashboard.js");
+const STYLE: &str = include_str!("../dashboard/dashboard.css");
+
+/// The routes of the dashboard's assets
+pub(crate) fn routes<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    Router::new()
+        .route("/", get(async || asset("text/html; charset=utf-8", INDEX)))
+        .route("/dashboard.js", get(async || asset("text/javascript; charset=utf-8", SCRIPT)))
+        .route("/dashboard.css", get(async || asset("text/css; charset=utf-8", STYLE)))
+}
+
+fn asset(content_type: &'static str, contents: &'static str) -> impl IntoResponse {
+    ([(CONTENT_TYPE, content_type)], contents)
+}
@@ -0,0 +1,580 @@
+#![doc = include_str!("../README.md")]
+
+#[cfg(feature = "dashboard")]
+mod dashboard;
+
+use anyhow::Context;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use bon::Builder;
+use control::Service;
+use control::automation::{Automation, AutomationStats, ReloadTrigger, Reloaded, RunEvent};
+use control::device::DeviceSet;
+use control::health::Health;
+use control::reflect::value::Value;
+use control::reflect::{self, Device, DeviceInfo, Field, SetError};
+use control::util::lock;
+use futures::StreamExt;
+use futures::future::ready;
+use futures::stream::select_all;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::spawn;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tracing::{debug, warn};
+
+/// The number of events buffered for each WebSocket client, a client which falls further behind
+/// misses the oldest events
+const EVENT_CAPACITY: usize = 256;
+
+/// Serves the REST API for the devices and automations added to it, run it by adding it as a
+/// service
+#[derive(Builder)]
+#[builder(finish_fn = build)]
+pub struct Server {
+    #[builder(field)]
+    devices: Vec<Box<dyn Device>>,
+    #[builder(field)]
+    automations: Vec<(String, Arc<AutomationStats>)>,
+    /// The address to listen on, defaults to localhost only, eg: use `0.0.0.0` to listen on all
+    /// interfaces, which requires a [token](ServerBuilder::token)
+    #[builder(into)]
+    #[builder(default = "127.0.0.1")]
+    bind_address: String,
+    /// The bearer token required by the routes which change anything, ie: setting or toggling a
+    /// field and reloading the automations. The server refuses to start without a token unless it
+    /// listens on a loopback address, as these routes would be open to the whole network
+    #[builder(into)]
+    token: Option<String>,
+    /// The port to listen on
+    port: u16,
+    /// Serve `/healthz` and `/readyz` from the health of the manager, see
+    /// [Manager::health](control::Manager::health)
+    health: Option<Health>,
+    /// Reload the automations on `POST /api/reload`, the automations listed are replaced by the
+    /// reloaded ones
+    reload: Option<ReloadTrigger>,
+}
+
+impl<S: server_builder::State> ServerBuilder<S> {
+    /// Expose a device
+    pub fn add_device(mut self, device: impl Device + 'static) -> Self {
+        self.devices.push(Box::new(device));
+        self
+    }
+
+    /// Expose every device in a set
+    pub fn add_device_set(mut self, set: impl DeviceSet + 'static) -> Self {
+        self.devices.extend(set);
+        self
+    }
+
+    /// Expose the counters of an automation, this must be called before the automation is passed
+    /// to the manager
+    pub fn add_automation(mut self, automation: &Automation<'_>) -> Self {
+        self.automations
+            .push((automation.name().to_string(), automation.stats()));
+        self
+    }
+}
+
+impl Service<'static> for Server {
+    fn name(&self) -> String {
+        "rest-api".to_string()
+    }
+
+    async fn start(self) -> anyhow::Result<()> {
+        let listener = TcpListener::bind((self.bind_address, self.port))
+            .await
+            .context("failed to bind address")?;
+        let addr = listener.local_addr().context("failed to read the bound address")?;
+        if self.token.is_none() && !addr.ip().is_loopback() {
+            anyhow::bail!(
+                "refusing to serve the rest api on {addr} without a token, anyone on the network could change devices, set a token or listen on a loopback address"
+            );
+        }
+        let shared = Arc::new(Shared {
+            devices: self.devices,
+            automations: Mutex::default(),
+            forwarding: Mutex::default(),
+            latest: Mutex::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            reload: self.reload,
+            token: self.token,
+        });
+        shared.set_automations(self.automations);
+        let tracking = spawn(track(shared.clone()));
+        let router = Router::new()
+            .route("/api/devices", get(devices))
+            .route("/api/devices/{id}", get(device))
+            .route("/api/devices/{id}/{field}", get(get_field).put(set_field))
+            .route("/api/devices/{id}/{field}/toggle", post(toggle_field))
+            .route("/api/automations", get(automations))
+            .route("/api/automations/{name}", get(automation))
+            .route("/api/reload", post(reload))
+            .route("/api/events", get(events));
+        #[cfg(feature = "dashboard")]
+        let router = router.merge(dashboard::routes());
+        let router = match self.health {
+            Some(health) => router
+                .route("/healthz", get(healthz).with_state(health.clone()))
+                .route("/readyz", get(readyz).with_state(health)),
+            None => router,
+        };
+        let result = axum::serve(listener, router.with_state(shared.clone())).await;
+        tracking.abort();
+        if let Some(forwarding) = lock(&shared.forwarding).take() {
+            forwarding.abort();
+        }
+        result.context("rest api server failed")
+    }
+}
+
+struct Shared {
+    devices: Vec<Box<dyn Device>>,
+    automations: Mutex<Reloaded>,
+    /// The task forwarding the run events of the current automations
+    forwarding: Mutex<Option<JoinHandle<()>>>,
+    /// The latest value of each field which can be subscribed to, by device index and field name
+    latest: Mutex<HashMap<(usize, String), Value>>,
+    /// Every device update and automation run, sent to the WebSocket clients
+    events: broadcast::Sender<Event>,
+    reload: Option<ReloadTrigger>,
+    /// The bearer token required by the routes which change anything
+    token: Option<String>,
+}
+
+impl Shared {
+    /// Replace the automations listed and forward the run events of the new ones instead
+    fn set_automations(self: &Arc<Self>, automations: Reloaded) {
+        let forwarding = spawn(forward_runs(self.clone(), automations.clone()));
+        *lock(&self.automations) = automations;
+        if let Some(previous) = lock(&self.forwarding).replace(forwarding) {
+            previous.abort();
+        }
+    }
+
+    /// Find a device by id, along with it's index
+    fn device(&self, id: &str) -> Result<(usize, &dyn Device), ApiError> {
+        self.devices
+            .iter()
+            .enumerate()
+            .find(|(_, device)| device.info().id == id)
+            .map(|(index, device)| (index, device.as_ref()))
+            .ok_or_else(|| ApiError::DeviceNotFound(id.to_string()))
+    }
+
+    /// Check the request carries the bearer token, if one is required
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), ApiError> {
+        let Some(token) = &self.token else {
+            return Ok(());
+        };
+        let given = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match given {
+            Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err(ApiError::Unauthorized),
+        }
+    }
+
+    fn describe(&self, index: usize, device: &dyn Device) -> DeviceResponse {
+        let latest = lock(&self.latest);
+        let fields = device.fields();
+        let values = fields
+            .iter()
+            .filter_map(|field| {
+                let value = latest.get(&(index, field.name.clone()))?;
+                Some((field.name.clone(), value.clone()))
+            })
+            .collect();
+        DeviceResponse {
+            info: device.info(),
+            fields,
+            values,
+        }
+    }
+}
+
+/// A device along with the latest value of each field which has reported one
+#[derive(Serialize)]
+struct DeviceResponse {
+    #[serde(flatten)]
+    info: DeviceInfo,
+    fields: Vec<Field>,
+    values: HashMap<String, Value>,
+}
+
+/// An automation and the counters of it's runs
+#[derive(Serialize)]
+struct AutomationResponse {
+    name: String,
+    triggered: u64,
+    succeeded: u64,
+    failed: u64,
+}
+
+impl AutomationResponse {
+    fn new(name: &str, stats: &AutomationStats) -> Self {
+        Self {
+            name: name.to_string(),
+            triggered: stats.triggered(),
+            succeeded: stats.succeeded(),
+            failed: stats.failed(),
+        }
+    }
+}
+
+/// An event sent to the clients of `/api/events`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    /// A field of a device reported a new value
+    DeviceUpdate {
+        device: String,
+        field: String,
+        value: Value,
+    },
+    /// An automation was triggered or a run finished
+    AutomationRun {
+        automation: String,
+        #[serde(flatten)]
+        run: Run,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Run {
+    Triggered,
+    Succeeded,
+    Failed { error: String },
+}
+
+impl From<RunEvent> for Run {
+    fn from(event: RunEvent) -> Self {
+        match event {
+            RunEvent::Triggered => Self::Triggered,
+            RunEvent::Succeeded => Self::Succeeded,
+            RunEvent::Failed(error) => Self::Failed { error },
+        }
+    }
+}
+
+/// Keep the latest value of every field which can be subscribed to, so fields which cannot be read
+/// still have a current value, each update is also sent to the WebSocket clients
+async fn track(shared: Arc<Shared>) {
+    let mut streams = Vec::new();
+    for (index, device) in shared.devices.iter().enumerate() {
+        for field in device.fields() {
+            if !field.operations.subscribe {
+                continue;
+            }
+            let stream = match device.subscribe(&field.name) {
+                Ok(stream) => stream.await,
+                Err(error) => {
+                    warn!("failed to subscribe to {}: {error}", field.name);
+                    continue;
+                }
+            };
+            let name = field.name;
+            streams.push(stream.map(move |value| (index, name.clone(), value)));
+        }
+    }
+    debug!("tracking {} attributes", streams.len());
+    let ids: Vec<_> = shared.devices.iter().map(|device| device.info().id).collect();
+    let mut updates = select_all(streams);
+    while let Some((index, field, value)) = updates.next().await {
+        // sending only fails when no client is connected
+        let _ = shared.events.send(Event::DeviceUpdate {
+            device: ids[index].clone(),
+            field: field.clone(),
+            value: value.clone(),
+        });
+        lock(&shared.latest).insert((index, field), value);
+    }
+}
+
+/// Send the events of every automation's runs to the WebSocket clients
+async fn forward_runs(shared: Arc<Shared>, automations: Reloaded) {
+    let runs = automations.into_iter().map(|(name, stats)| {
+        BroadcastStream::new(stats.subscribe()).filter_map(move |event| {
+            let event = match event {
+                Ok(event) => Some(Event::AutomationRun {
+                    automation: name.clone(),
+                    run: event.into(),
+                }),
+                Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                    warn!("missed {missed} run events of automation {name}");
+                    None
+                }
+            };
+            ready(event)
+        })
+    });
+    let mut runs = select_all(runs);
+    while let Some(event) = runs.next().await {
+        let _ = shared.events.send(event);
+    }
+}
+
+async fn events(State(shared): State<Arc<Shared>>, upgrade: WebSocketUpgrade) -> Response {
+    let events = shared.events.subscribe();
+    upgrade.on_upgrade(move |socket| stream_events(socket, events))
+}
+
+/// Send each event to the client as JSON until it disconnects
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<Event>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("websocket client fell behind, {missed} events were dropped");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                let json = match serde_json::to_string(&event) {
+                    Ok(json) => json,
+                    Err(error) => {
+                        warn!("failed to serialize event: {error}");
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(json.into())).await.is_err() {
+                    debug!("websocket client disconnected");
+                    return;
+                }
+            }
+            message = socket.recv() => match message {
+                None | Some(Err(_) | Ok(Message::Close(_))) => {
+                    debug!("websocket client disconnected");
+                    return;
+                }
+                // clients have nothing to send, pings are answered by axum
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+}
+
+async fn devices(State(shared): State<Arc<Shared>>) -> Json<Vec<DeviceResponse>> {
+    Json(
+        shared
+            .devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| shared.describe(index, device.as_ref()))
+            .collect(),
+    )
+}
+
+async fn device(State(shared): State<Arc<Shared>>, Path(id): Path<String>) -> Result<Json<DeviceResponse>, ApiError> {
+    let (index, device) = shared.device(&id)?;
+    Ok(Json(shared.describe(index, device)))
+}
+
+/// Read the field if it can be read, otherwise return the latest value it reported
+async fn get_field(
+    State(shared): State<Arc<Shared>>,
+    Path((id, field)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let (index, device) = shared.device(&id)?;
+    let operations = device
+        .fields()
+        .into_iter()
+        .find(|f| f.name == field)
+        .map(|f| f.operations);
+    if let Some(operations) = operations
+        && !operations.get
+        && operations.subscribe
+    {
+        let latest = lock(&shared.latest).get(&(index, field.clone())).cloned();
+        return latest.map(Json).ok_or(ApiError::NoValue { device: id, field });
+    }
+    Ok(Json(device.get(&field)?.await?))
+}
+
+async fn set_field(
+    State(shared): State<Arc<Shared>>,
+    Path((id, field)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(value): Json<Value>,
+) -> Result<StatusCode, ApiError> {
+    shared.authorize(&headers)?;
+    let (_, device) = shared.device(&id)?;
+    device.set(&field, value)?.await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn toggle_field(
+    State(shared): State<Arc<Shared>>,
+    Path((id, field)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    shared.authorize(&headers)?;
+    let (_, device) = shared.device(&id)?;
+    device.toggle(&field)?.await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn automations(State(shared): State<Arc<Shared>>) -> Json<Vec<AutomationResponse>> {
+    Json(
+        lock(&shared.automations)
+            .iter()
+            .map(|(name, stats)| AutomationResponse::new(name, stats))
+            .collect(),
+    )
+}
+
+async fn automation(
+    State(shared): State<Arc<Shared>>,
+    Path(name): Path<String>,
+) -> Result<Json<AutomationResponse>, ApiError> {
+    lock(&shared.automations)
+        .iter()
+        .find(|(automation, _)| *automation == name)
+        .map(|(name, stats)| Json(AutomationResponse::new(name, stats)))
+        .ok_or(ApiError::AutomationNotFound(name))
+}
+
+/// Reload the automations, returning the automations which were started
+async fn reload(
+    State(shared): State<Arc<Shared>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<AutomationResponse>>, ApiError> {
+    shared.authorize(&headers)?;
+    let trigger = shared.reload.as_ref().ok_or(ApiError::ReloadDisabled)?;
+    let reloaded = trigger.reload().await.map_err(ApiError::Reload)?;
+    let response = reloaded
+        .iter()
+        .map(|(name, stats)| AutomationResponse::new(name, stats))
+        .collect();
+    shared.set_automations(reloaded);
+    Ok(Json(response))
+}
+
+/// The health of the system and each of it's components
+#[derive(Serialize)]
+struct HealthResponse {
+    healthy: bool,
+    components: BTreeMap<String, ComponentResponse>,
+}
+
+#[derive(Serialize)]
+struct ComponentResponse {
+    healthy: bool,
+    detail: String,
+}
+
+async fn healthz(State(health): State<Health>) -> impl IntoResponse {
+    let healthy = health.is_healthy();
+    let components = health
+        .components()
+        .into_iter()
+        .map(|(name, component)| {
+            (name, ComponentResponse {
+                healthy: component.healthy,
+                detail: component.detail,
+            })
+        })
+        .collect();
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(HealthResponse { healthy, components }))
+}
+
+async fn readyz(State(health): State<Health>) -> impl IntoResponse {
+    if health.is_ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    }
+}
+
+/// An error returned by the API as `{"error": "..."}`
+#[derive(Debug, Error)]
+enum ApiError {
+    #[error("a valid bearer token is required")]
+    Unauthorized,
+    #[error("device '{0}' not found")]
+    DeviceNotFound(String),
+    #[error("automation '{0}' not found")]
+    AutomationNotFound(String),
+    #[error("field '{field}' of device '{device}' has not reported a value yet")]
+    NoValue { device: String, field: String },
+    #[error(transparent)]
+    Field(#[from] reflect::Error),
+    #[error(transparent)]
+    Set(#[from] SetError),
+    #[error("device operation failed: {0:#}")]
+    Failed(#[from] anyhow::Error),
+    #[error("reloading automations is not enabled")]
+    ReloadDisabled,
+    #[error("failed to reload automations: {0}")]
+    Reload(String),
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::DeviceNotFound(_)
+            | Self::AutomationNotFound(_)
+            | Self::ReloadDisabled
+            | Self::NoValue { .. }
+            | Self::Field(reflect::Error::FieldNotFound { .. })
+            | Self::Set(SetError::Error(reflect::Error::FieldNotFound { .. })) => StatusCode::NOT_FOUND,
+            Self::Field(reflect::Error::OperationNotSupported { .. })
+            | Self::Set(SetError::Error(reflect::Error::OperationNotSupported { .. })) => {
+                StatusCode::METHOD_NOT_ALLOWED
+            }
+            Self::Set(SetError::ParseError(_)) => StatusCode::BAD_REQUEST,
+            // the device itself failed, eg: it did not respond
+            Self::Failed(_) => StatusCode::BAD_GATEWAY,
+            Self::Reload(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            warn!("request failed: {self}");
+        }
+        let body = ErrorResponse {
+            error: self.to_string(),
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Compare two byte strings in a time which depends only on their lengths, so the token cannot be
+/// guessed a byte at a time from how long a request takes to be rejected
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
@@ -0,0 +1,84 @@
+//! Mocks of common devices built with [MockDevice::builder], they mirror the zigbee2mqtt
+//! definitions in the zigbee crate, update their readings with [MockDevice::update], eg:
+//! ```ignore
+//! let door = mock_contact_sensor(&connection, "front_door", true).await;
+//! door.update("contact", false).await;
+//! ```
+
+use crate::{Connection, MockAttribute, MockDevice};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// An attribute which can be read with a get request but not set, eg: a battery level
+fn reading(value: impl Into<Value>) -> MockAttribute {
+    MockAttribute::builder().value(value).set(false).build()
+}
+
+/// An attribute which is only ever published by the device, eg: a detected action
+fn event(value: impl Into<Value>) -> MockAttribute {
+    MockAttribute::builder().value(value).get(false).set(false).build()
+}
+
+/// An attribute which can be read and set, eg: a configuration option
+fn setting(value: impl Into<Value>) -> MockAttribute {
+    MockAttribute::builder().value(value).build()
+}
+
+/// Mock a Sonoff door/window contact sensor (SNZB-04), `contact` is true while the door/window is
+/// closed
+pub async fn mock_contact_sensor(connection: &Connection, name: &str, contact: bool) -> Arc<MockDevice> {
+    MockDevice::builder()
+        .attribute("battery", reading(100))
+        .attribute("voltage", reading(3000))
+        .attribute("contact", event(contact))
+        .attribute("battery_low", event(false))
+        .start(connection, name)
+        .await
+}
+
+/// Mock a Sonoff temperature and humidity sensor (SNZB-02D), the temperature is in Celsius and
+/// the humidity is a percentage
+pub async fn mock_temperature_sensor(
+    connection: &Connection,
+    name: &str,
+    temperature: i32,
+    humidity: u8,
+) -> Arc<MockDevice> {
+    MockDevice::builder()
+        .attribute("battery", reading(100))
+        .attribute("temperature", reading(temperature))
+        .attribute("humidity", reading(humidity))
+        .attribute("comfort_temperature_min", setting(19))
+        .attribute("comfort_temperature_max", setting(27))
+        .attribute("comfort_humidity_min", setting(40))
+        .attribute("comfort_humidity_max", setting(60))
+        .attribute("temperature_units", setting("celsius"))
+        .attribute("temperature_calibration", setting(0))
+        .attribute("humidity_calibration", setting(0))
+        .start(connection, name)
+        .await
+}
+
+/// Mock an Aqara roller shade driver (ZNJLBL01LM), the `"command"` attribute moves the shade
+/// straight to the requested state, without reporting the motor as opening or closing first
+pub async fn mock_roller_shade(connection: &Connection, name: &str, open: bool) -> Arc<MockDevice> {
+    let state = if open { "OPEN" } else { "CLOSE" };
+    let command = MockAttribute::builder()
+        .on_set("OPEN", json!({"state": "OPEN", "motor_state": "stopped", "running": false}))
+        .on_set("CLOSE", json!({"state": "CLOSE", "motor_state": "stopped", "running": false}))
+        .on_set("STOP", json!({"motor_state": "stopped", "running": false}))
+        .value("STOP")
+        .get(false)
+        .build();
+    MockDevice::builder()
+        .attribute("state", reading(state))
+        .attribute("command", command)
+        .attribute("battery", reading(100))
+        .attribute("device_temperature", event(20))
+        .attribute("charging_status", reading(false))
+        .attribute("motor_state", event("stopped"))
+        .attribute("running", event(false))
+        .attribute("motor_speed", setting("medium"))
+        .start(connection, name)
+        .await
+}
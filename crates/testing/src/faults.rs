@@ -0,0 +1,127 @@
+//! Injecting network misbehaviour into the publishes passing through a [Connection](crate::Connection),
+//! the faults are random but seeded, so a failing test fails the same way every run, eg:
+//! ```ignore
+//! connection.set_faults(Faults::builder().drop(0.2).duplicate(0.1).build());
+//! ```
+
+use crate::Publish;
+use bon::Builder;
+use control::util::lock;
+use log::debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// The seed used when none is given
+const DEFAULT_SEED: u64 = 0x5EED_F00D;
+
+/// Which publishes faults are applied to
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Direction {
+    /// Both the publishes of the mocks and the requests sent to them
+    #[default]
+    Both,
+    /// Only the publishes of the mocks, ie: the state reported by the devices
+    FromDevices,
+    /// Only the publishes received by the mocks, ie: the set and get requests
+    ToDevices,
+}
+
+/// The misbehaviour to inject, the probabilities are between 0 and 1
+#[derive(Debug, Clone, Builder)]
+pub struct Faults {
+    /// The publishes affected
+    #[builder(default)]
+    direction: Direction,
+    /// The probability a publish is dropped
+    #[builder(default)]
+    drop: f64,
+    /// Delay every publish, publishes stay in order
+    delay: Option<Duration>,
+    /// The probability a publish is delivered twice
+    #[builder(default)]
+    duplicate: f64,
+    /// The probability a publish is held back and delivered after the next publish
+    #[builder(default)]
+    reorder: f64,
+    /// The seed of the random faults
+    #[builder(default = DEFAULT_SEED)]
+    seed: u64,
+}
+
+impl Faults {
+    fn applies_to(&self, direction: Direction) -> bool {
+        self.direction == Direction::Both || self.direction == direction
+    }
+}
+
+/// The faults currently applied to a connection, shared by both directions
+pub(crate) type SharedFaults = Arc<Mutex<Option<Faults>>>;
+
+/// Applies the faults to the publishes passing in one direction
+pub(crate) struct Injector {
+    faults: SharedFaults,
+    direction: Direction,
+    /// the seed the random state was derived from, the state is reset when the faults change
+    seed: Option<u64>,
+    state: u64,
+    /// a publish held back to be delivered after the next
+    held: Option<Publish>,
+}
+
+impl Injector {
+    pub(crate) fn new(faults: SharedFaults, direction: Direction) -> Self {
+        Self {
+            faults,
+            direction,
+            seed: None,
+            state: 0,
+            held: None,
+        }
+    }
+
+    /// Returns the publishes to deliver in place of the given publish, in order
+    pub(crate) async fn pass(&mut self, publish: Publish) -> Vec<Publish> {
+        let Some(faults) = lock(&self.faults).clone().filter(|faults| faults.applies_to(self.direction)) else {
+            return self.held.take().into_iter().chain([publish]).collect();
+        };
+        if self.seed != Some(faults.seed) {
+            self.seed = Some(faults.seed);
+            // xorshift must not start from zero
+            self.state = faults.seed.max(1);
+        }
+        if let Some(delay) = faults.delay {
+            sleep(delay).await;
+        }
+        if self.chance(faults.drop) {
+            debug!("dropping publish to {}", publish.topic);
+            return Vec::new();
+        }
+        if self.held.is_none() && self.chance(faults.reorder) {
+            debug!("holding back publish to {}", publish.topic);
+            self.held = Some(publish);
+            return Vec::new();
+        }
+        let mut delivered = vec![publish.clone()];
+        if self.chance(faults.duplicate) {
+            debug!("duplicating publish to {}", publish.topic);
+            delivered.push(publish);
+        }
+        delivered.extend(self.held.take());
+        delivered
+    }
+
+    /// Returns true with the given probability
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // xorshift64, good enough for picking which publishes misbehave
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        #[allow(clippy::cast_precision_loss, reason = "only the leading bits matter")]
+        let sample = (self.state >> 11) as f64 / (1u64 << 53) as f64;
+        sample < probability
+    }
+}
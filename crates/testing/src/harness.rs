@@ -0,0 +1,164 @@
+//! A harness wiring up everything an integration test needs, the test broker, the mocks, a
+//! [Manager] connected to the broker and the devices created by it, eg:
+//! ```ignore
+//! let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+//!     .mocks(async |conn: &Connection| (MockHueSmartButton::new(conn, "button").await, MockLight::new(conn, "light").await))
+//!     .start()
+//!     .await;
+//! let (button, light) = &harness.mocks;
+//! ```
+//!
+//! A device manager which needs more than the connection to the broker, eg: devices to rename,
+//! can be created by the test with [TestHarness::builder] instead
+//!
+//! Each harness has it's own client id and base topic on the shared test broker, so tests using
+//! the same device names can run in parallel
+//!
+//! Once the automations under test are created, [run] starts the manager with them and runs the
+//! test alongside it, eg:
+//! ```ignore
+//! run(harness.manager, [automation], async {
+//!     button.publish_switch(true).await;
+//!     light_on.await;
+//! })
+//! .await;
+//! ```
+
+use crate::{CancelGuard, Connection, start_mqtt_broker};
+use bon::bon;
+use control::Manager;
+use control::automation::Automation;
+use control::device::DeviceSet;
+use control::device_manager::DeviceManager;
+use rumqttc::MqttOptions;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::join;
+use tokio::time::{sleep, timeout};
+
+/// Gives each harness a unique client id and base topic, so tests can run in parallel
+static NEXT_CLIENT: AtomicUsize = AtomicUsize::new(0);
+
+/// How long [run] waits for the test to finish and the manager to stop before failing the test
+pub const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long [run] waits after starting the manager before starting the test, so the devices have
+/// subscribed to the broker
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+/// A device manager which can be connected to the test broker, so a harness can create it without
+/// being told how, see [TestHarness::with_devices]
+pub trait TestDeviceManager: DeviceManager {
+    /// Create the manager from the options for connecting to the test broker and the base topic of
+    /// the mocks
+    fn connect(mqtt: MqttOptions, base_topic: &str) -> Self;
+}
+
+/// Everything an integration test needs, see [TestHarness::with_devices]
+pub struct TestHarness<'a, D, M> {
+    /// The devices created by the manager
+    pub devices: D,
+    /// The mocks created by [mocks](TestHarnessBuilder::mocks)
+    pub mocks: M,
+    /// The manager the devices were created with, start it with the automations under test
+    pub manager: Manager<'a>,
+    /// The connection to the test broker
+    pub connection: Connection,
+    _guard: CancelGuard,
+}
+
+#[bon]
+impl<'a, D: DeviceSet, M> TestHarness<'a, D, M> {
+    /// Start the test broker, create the mocks, then create the devices with a manager connected
+    /// to the broker
+    #[builder(finish_fn = start)]
+    pub async fn new<F, G, A>(
+        /// Creates the device manager from the options for connecting to the test broker and the
+        /// base topic of the mocks, eg:
+        /// `|mqtt, base_topic| zigbee::Manager::builder().mqtt_options(mqtt).base_topic(base_topic).build()`
+        device_manager: G,
+        /// Creates the mocks once the broker has started, the mocks are created before the devices
+        /// so they can answer the requests made while the devices are created
+        mocks: A,
+        /// The profile to create devices for
+        #[builder(into)]
+        profile: Option<String>,
+        /// Pause tokio's clock once the devices are created, see [pause_time](crate::pause_time),
+        /// this requires a `current_thread` runtime
+        #[builder(default)]
+        pause_time: bool,
+        /// Log writes to devices rather than making them, see [dry_run](control::dry_run)
+        #[builder(default)]
+        dry_run: bool,
+    ) -> Self
+    where
+        F: DeviceManager,
+        G: FnOnce(MqttOptions, &str) -> F,
+        A: AsyncFnOnce(&Connection) -> M,
+    {
+        let client = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+        let (connection, guard) = start_mqtt_broker();
+        let connection = connection.with_base_topic(format!("test-harness-{client}"));
+        let mocks = mocks(&connection).await;
+
+        let mqtt = connection.mqtt_options(format!("test-harness-{client}"));
+        let mut manager = Manager::builder()
+            .add_device_manager(device_manager(mqtt, connection.base_topic()))
+            .maybe_profile(profile)
+            .dry_run(dry_run)
+            .build();
+        let devices = manager.create().await.expect("failed to create devices");
+        if pause_time {
+            crate::pause_time();
+        }
+        Self {
+            devices,
+            mocks,
+            manager,
+            connection,
+            _guard: guard,
+        }
+    }
+
+    /// Start the test broker, create the mocks, then create the devices with a manager of type `F`
+    /// connected to the broker, see [builder](Self::builder) for the options
+    #[builder(finish_fn = start)]
+    pub async fn with_devices<F, A>(
+        mocks: A,
+        #[builder(into)] profile: Option<String>,
+        #[builder(default)] pause_time: bool,
+        #[builder(default)] dry_run: bool,
+    ) -> Self
+    where
+        F: TestDeviceManager,
+        A: AsyncFnOnce(&Connection) -> M,
+    {
+        Self::builder()
+            .device_manager(F::connect)
+            .mocks(mocks)
+            .maybe_profile(profile)
+            .pause_time(pause_time)
+            .dry_run(dry_run)
+            .start()
+            .await
+    }
+}
+
+/// Start the manager with the automations and run the test alongside it on the same task, the
+/// manager is shut down with it's [shutdown_token](Manager::shutdown_token) once the test returns.
+/// Fails if the test has not finished and the manager stopped within [RUN_TIMEOUT]
+pub async fn run<'a>(
+    manager: Manager<'a>,
+    automations: impl IntoIterator<Item = Automation<'a>>,
+    test: impl Future<Output = ()>,
+) {
+    let shutdown = manager.shutdown_token();
+    let stopped = timeout(RUN_TIMEOUT, async {
+        join!(manager.start(automations), async {
+            sleep(SETTLE_TIME).await;
+            test.await;
+            shutdown.cancel();
+        })
+    });
+    assert!(stopped.await.is_ok(), "the test did not finish or the manager did not stop once shut down");
+}
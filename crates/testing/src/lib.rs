@@ -1,39 +1,90 @@
 //! A crate with utilities useful for testing
 #![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
 
+mod devices;
+mod faults;
+mod harness;
+#[cfg(feature = "arp")]
+mod network;
+mod record;
+mod simulation;
+mod wiz;
+
+pub use devices::*;
+pub use faults::{Direction, Faults};
+pub use harness::{RUN_TIMEOUT, TestDeviceManager, TestHarness, run};
+#[cfg(feature = "arp")]
+pub use network::MockNetwork;
+pub use record::{RecordedPublish, Recording};
+pub use simulation::{Report, Script, SimulatedSensor, SimulatedValue, SimulatedWrite, Simulation};
+pub use wiz::{MockWizBulb, WizRequest};
+
+use bon::{bon, Builder};
+use control::util::lock;
+use faults::{Injector, SharedFaults};
 use futures::StreamExt;
 use log::{debug, info, warn};
-use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS};
-use serde_json::{json, Value};
+use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS, matches};
+use serde_json::{json, Map, Value};
+use std::cell::Cell;
 use std::pin::pin;
-use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
 use std::thread::{sleep};
-use std::time::Duration;
-use tokio::process::{Child, Command};
+use std::time::{Duration, Instant};
 use tokio::spawn;
 use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::mpsc::Sender;
 use tokio::task::JoinHandle;
+use tokio::time::interval;
 use tokio_stream::wrappers::BroadcastStream;
 
-const CONFIG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/rumqttd.test.toml");
+/// The broker config, the port it listens on is replaced with an ephemeral port
+const CONFIG: &str = include_str!("../rumqttd.test.toml");
+/// How long to wait for the broker to accept connections
+const BROKER_TIMEOUT: Duration = Duration::from_secs(5);
 
-/// Start a local MQTT broker at `localhost:1883`
-pub fn start_mqtt_broker() -> (Connection, CancelGuard) {
-    let broker = Command::new("rumqttd")
-        .args(["--config", CONFIG])
-        .spawn()
-        .expect("failed to start mqtt broker");
+/// How many times [settle] yields, enough for a chain of a few woken tasks to run
+const SETTLE_YIELDS: usize = 10;
 
-    // allow time for the broker to start
-    sleep(Duration::from_secs(1));
+/// The port of the broker started by this process
+static BROKER: OnceLock<u16> = OnceLock::new();
+/// Gives each connection a unique client id, the broker disconnects a client when another
+/// connects with the same id
+static NEXT_CONNECTION: AtomicUsize = AtomicUsize::new(0);
 
+/// The base topic of the mock devices unless set with [Connection::with_base_topic]
+const DEFAULT_BASE_TOPIC: &str = "zigbee2mqtt";
+
+thread_local! {
+    /// Whether [pause_time] paused the clock, tokio does not expose whether the clock is paused
+    /// and the clock is per runtime, which is a single thread when it can be paused
+    static PAUSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Start a local MQTT broker on an ephemeral port and connect to it, the port is available from
+/// [Connection::port] and [Connection::mqtt_options] gives the options to connect to the broker
+///
+/// The broker runs in-process and is started by the first call, it is ready to accept connections
+/// once this returns, each test binary runs it's own broker on a different port so the test
+/// binaries can run in parallel. Every test within a binary shares the broker and so the topics
+/// on it, tests running in parallel must use different device names or give each connection it's
+/// own base topic with [Connection::with_base_topic], as [TestHarness] does
+pub fn start_mqtt_broker() -> (Connection, CancelGuard) {
+    let port = *BROKER.get_or_init(start_broker);
+
+    let id = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
     let (client, event_loop) =
-        AsyncClient::new(MqttOptions::new("testing", "localhost", 1883), 10);
+        AsyncClient::new(MqttOptions::new(format!("testing-{id}"), "localhost", port), 10);
     let client = Arc::new(client);
     let (incoming_send, incoming_recv) = tokio::sync::broadcast::channel::<Publish>(10);
     let (outgoing_send, mut outgoing_recv) = tokio::sync::mpsc::channel::<Publish>(10);
+    let faults = SharedFaults::default();
+    let mut incoming_faults = Injector::new(faults.clone(), Direction::ToDevices);
+    let mut outgoing_faults = Injector::new(faults.clone(), Direction::FromDevices);
     let incoming_job = spawn(async move {
         let events = futures::stream::unfold(event_loop, |mut event_loop| async {
             match event_loop.poll().await {
@@ -62,21 +113,25 @@ pub fn start_mqtt_broker() -> (Connection, CancelGuard) {
                 payload,
             };
             info!("Received: {publish:?}");
-            incoming_send
-                .send(publish)
-                .expect("failed to send incoming publish");
+            for publish in incoming_faults.pass(publish).await {
+                incoming_send
+                    .send(publish)
+                    .expect("failed to send incoming publish");
+            }
         }
     });
     let outgoing_job = spawn({
         let client = client.clone();
         async move {
             while let Some(publish) = outgoing_recv.recv().await {
-                info!("Publishing: {publish:?}");
-                let payload = serde_json::to_vec(&publish.payload).expect("could not serialize outgoing publish");
-                client
-                    .publish(publish.topic, QoS::AtLeastOnce, false, payload)
-                    .await
-                    .expect("failed to publish");
+                for publish in outgoing_faults.pass(publish).await {
+                    info!("Publishing: {publish:?}");
+                    let payload = serde_json::to_vec(&publish.payload).expect("could not serialize outgoing publish");
+                    client
+                        .publish(publish.topic, QoS::AtLeastOnce, false, payload)
+                        .await
+                        .expect("failed to publish");
+                }
             }
         }
     });
@@ -85,16 +140,47 @@ pub fn start_mqtt_broker() -> (Connection, CancelGuard) {
         client,
         receiver: incoming_recv,
         sender: outgoing_send,
+        faults,
+        port,
+        base_topic: DEFAULT_BASE_TOPIC.to_string(),
     }, CancelGuard {
-        broker,
         incoming_job,
         outgoing_job,
     })
 }
 
+/// Start the broker on an ephemeral port, returning the port
+fn start_broker() -> u16 {
+    let mut config: rumqttd::Config = toml::from_str(CONFIG).expect("failed to parse broker config");
+    // the port is released again before the broker binds it, another process could take it in
+    // between but that is unlikely as the OS does not hand out the same ephemeral port again soon
+    let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .expect("failed to find a free port for the mqtt broker")
+        .port();
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    for server in config.v4.iter_mut().flat_map(|servers| servers.values_mut()) {
+        server.listen = address;
+    }
+    std::thread::Builder::new()
+        .name("mqtt-broker".to_string())
+        .spawn(move || {
+            if let Err(error) = rumqttd::Broker::new(config).start() {
+                warn!("mqtt broker stopped: {error}");
+            }
+        })
+        .expect("failed to start mqtt broker");
+    // the broker has no ready signal of it's own, so wait for it to accept connections
+    let start = Instant::now();
+    while TcpStream::connect(address).is_err() {
+        assert!(start.elapsed() < BROKER_TIMEOUT, "mqtt broker did not start within {BROKER_TIMEOUT:?}");
+        sleep(Duration::from_millis(10));
+    }
+    port
+}
+
 /// A guard which cancels background tasks when dropped
 pub struct CancelGuard {
-    broker: Child,
     incoming_job: JoinHandle<()>,
     outgoing_job: JoinHandle<()>,
 }
@@ -103,7 +189,6 @@ impl Drop for CancelGuard {
     fn drop(&mut self) {
         self.incoming_job.abort();
         self.outgoing_job.abort();
-        self.broker.start_kill().expect("failed to kill mqtt broker");
     }
 }
 
@@ -118,131 +203,479 @@ pub struct Connection {
     client: Arc<AsyncClient>,
     receiver: Receiver<Publish>,
     sender: Sender<Publish>,
+    faults: SharedFaults,
+    port: u16,
+    base_topic: String,
 }
 
 impl Connection {
+    /// Use a different base topic for the mock devices created on this connection, the device
+    /// manager under test must be configured with the same base topic, eg: to keep the devices of
+    /// tests running in parallel apart
+    pub fn with_base_topic(mut self, base_topic: impl Into<String>) -> Self {
+        self.base_topic = base_topic.into();
+        self
+    }
+
+    /// The base topic of the mock devices, defaults to `zigbee2mqtt`
+    pub fn base_topic(&self) -> &str {
+        &self.base_topic
+    }
+
+    /// The topic of a device under the base topic, eg: for [expect_publish](Self::expect_publish)
+    pub fn topic(&self, device: &str) -> String {
+        format!("{}/{device}", self.base_topic)
+    }
+
+    /// The port the test broker listens on at `localhost`
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The options to connect another client to the test broker, eg: the zigbee manager under
+    /// test, each client must have a different id
+    pub fn mqtt_options(&self, client_id: impl Into<String>) -> MqttOptions {
+        let mut options = MqttOptions::new(client_id, "localhost", self.port);
+        options.set_keep_alive(Duration::from_secs(5));
+        options
+    }
+
+    /// Inject faults into the publishes passing through this connection, replacing any faults
+    /// set before, see [Faults]
+    pub fn set_faults(&self, faults: Faults) {
+        *lock(&self.faults) = Some(faults);
+    }
+
+    /// Stop injecting faults, a publish held back to be reordered is delivered with the next
+    /// publish
+    pub fn clear_faults(&self) {
+        *lock(&self.faults) = None;
+    }
+
     async fn new_device(&self, name: &str) -> (Receiver<Publish>, Sender<Publish>) {
+        // requests are sent to the device's set and get topics
         self.client
-            .subscribe(format!("zigbee2mqtt/{name}"), QoS::AtLeastOnce)
+            .subscribe(format!("{}/+", self.topic(name)), QoS::AtLeastOnce)
             .await
             .expect("failed to subscribe to device");
         (self.receiver.resubscribe(), self.sender.clone())
     }
+
+    /// Expect a publish to a topic matching `topic`, which may contain MQTT wildcards, with a
+    /// payload matching `payload`, returning the matching payload
+    ///
+    /// Publishes are recorded from when this is called rather than when it is awaited, so the
+    /// expectation can be created before triggering the publish, eg:
+    /// ```ignore
+    /// let toggled = connection.expect_publish(&connection.topic("light/set"), json!({"state": "TOGGLE"}), TIMEOUT);
+    /// button.publish_action(Action::Press).await;
+    /// toggled.await;
+    /// ```
+    ///
+    /// # Panics
+    /// If no matching publish is seen within `timeout`, the panic lists the publishes which were
+    /// seen instead, with a diff against the expected payload for those on a matching topic
+    pub fn expect_publish<M: PayloadMatcher>(
+        &self,
+        topic: &str,
+        payload: M,
+        timeout: Duration,
+    ) -> impl Future<Output = Value> + use<M> {
+        let mut receiver = self.receiver.resubscribe();
+        self.client
+            .try_subscribe(topic, QoS::AtLeastOnce)
+            .expect("failed to subscribe to expected topic");
+        let topic = topic.to_string();
+        async move {
+            let mut seen = Vec::new();
+            let matched = tokio::time::timeout(timeout, async {
+                loop {
+                    let publish = match receiver.recv().await {
+                        Ok(publish) => publish,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("expect_publish skipped {skipped} publishes");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    };
+                    if matches(&publish.topic, &topic) && payload.matches(&publish.payload) {
+                        return Some(publish.payload);
+                    }
+                    seen.push(publish);
+                }
+            })
+            .await;
+            if let Ok(Some(payload)) = matched {
+                return payload;
+            }
+            let mut message = format!(
+                "expected a publish to {topic} matching {} within {timeout:?}, ",
+                payload.describe()
+            );
+            if seen.is_empty() {
+                message.push_str("nothing was published");
+            } else {
+                message.push_str("saw:");
+                for publish in &seen {
+                    message.push_str(&format!("\n  {}: {}", publish.topic, publish.payload));
+                    if matches(&publish.topic, &topic) {
+                        for line in payload.diff(&publish.payload) {
+                            message.push_str(&format!("\n    {line}"));
+                        }
+                    }
+                }
+            }
+            panic!("{message}");
+        }
+    }
+}
+
+/// Matches the payload of a publish, see [Connection::expect_publish]
+///
+/// A [Value] matches a payload equal to it, except that an object only needs the keys it contains
+/// to match, eg: `json!({"state": "ON"})` matches `{"state": "ON", "brightness": 254}`, any
+/// `Fn(&Value) -> bool` can also be used as a matcher
+pub trait PayloadMatcher {
+    /// Whether the payload matches
+    fn matches(&self, payload: &Value) -> bool;
+
+    /// A description of the expected payload
+    fn describe(&self) -> String;
+
+    /// The differences between a payload which did not match and the expected payload, one per
+    /// line
+    fn diff(&self, _payload: &Value) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+impl PayloadMatcher for Value {
+    fn matches(&self, payload: &Value) -> bool {
+        match (self, payload) {
+            (Value::Object(expected), Value::Object(actual)) => expected
+                .iter()
+                .all(|(key, value)| actual.get(key).is_some_and(|actual| value.matches(actual))),
+            _ => self == payload,
+        }
+    }
+
+    fn describe(&self) -> String {
+        self.to_string()
+    }
+
+    fn diff(&self, payload: &Value) -> Vec<String> {
+        let (Value::Object(expected), Value::Object(actual)) = (self, payload) else {
+            return vec![format!("- {self}"), format!("+ {payload}")];
+        };
+        let mut lines = Vec::new();
+        for (key, value) in expected {
+            match actual.get(key) {
+                None => lines.push(format!("- {key}: {value} (missing)")),
+                Some(actual) if !value.matches(actual) => {
+                    lines.push(format!("- {key}: {value}"));
+                    lines.push(format!("+ {key}: {actual}"));
+                }
+                Some(_) => {}
+            }
+        }
+        lines
+    }
 }
 
-struct MockDevice {
-    name: &'static str,
+impl<F: Fn(&Value) -> bool> PayloadMatcher for F {
+    fn matches(&self, payload: &Value) -> bool {
+        self(payload)
+    }
+
+    fn describe(&self) -> String {
+        "a custom matcher".to_string()
+    }
+}
+
+/// A mock zigbee2mqtt device, it publishes the state of the device and receives the requests sent
+/// to it, the mocks generated by `zigbee_device!` are built on this
+///
+/// A device type without a generated mock can be mocked by configuring the behaviour of each
+/// attribute with [MockDevice::builder], eg:
+/// ```ignore
+/// let plug = MockDevice::builder()
+///     .attribute("state", MockAttribute::builder().value("OFF").toggle(["ON".into(), "OFF".into()]).build())
+///     .attribute("power", MockAttribute::builder().value(0).set(false).report_every(Duration::from_secs(1)).build())
+///     .start(&connection, "test_plug")
+///     .await;
+/// ```
+pub struct MockDevice {
+    name: String,
+    topic: String,
     sender: Sender<Publish>,
+    attributes: Mutex<HashMap<String, MockAttribute>>,
 }
 
+#[bon]
 impl MockDevice {
-    async fn new(connection: &Connection, name: &'static str) -> (MockDevice, Receiver<Publish>) {
+    /// Create a mock device which responds to requests with the configured attribute behaviours,
+    /// it responds until the connection is closed
+    #[builder(finish_fn = start)]
+    pub async fn new<'a>(
+        #[builder(field)] attributes: HashMap<String, MockAttribute>,
+        #[builder(finish_fn)] connection: &'a Connection,
+        #[builder(finish_fn)] name: &'a str,
+    ) -> Arc<Self> {
+        let (mut device, requests) = Self::connect(connection, name).await;
+        let reports: Vec<_> = attributes
+            .iter()
+            .filter_map(|(attribute, behaviour)| Some((attribute.clone(), behaviour.report_every?)))
+            .collect();
+        device.attributes = Mutex::new(attributes);
+        let device = Arc::new(device);
+        spawn(device.clone().respond(requests));
+        for (attribute, every) in reports {
+            spawn(device.clone().report(attribute, every));
+        }
+        device
+    }
+
+    /// Connect a mock device with the given name, returning it along with the requests sent to it,
+    /// the device has no attributes, so the requests must be handled by the caller
+    pub async fn connect(connection: &Connection, name: &str) -> (MockDevice, MockRequests) {
         let (receiver, sender) = connection.new_device(name).await;
-        (Self { name, sender }, receiver)
+        let topic = connection.topic(name);
+        let requests = MockRequests {
+            name: name.to_string(),
+            topic: topic.clone(),
+            receiver: BroadcastStream::new(receiver),
+        };
+        (Self { name: name.to_string(), topic, sender, attributes: Mutex::default() }, requests)
     }
-}
 
-impl MockDevice {
-    fn topic(&self) -> String {
-        format!("zigbee2mqtt/{}", self.name)
+    /// The name of the device
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
-    async fn publish(&self, payload: Value) {
-        let payload = serde_json::to_vec(&payload).expect("failed to serialize payload");
+    /// Publish a payload as the device, eg: `{"state": "ON"}`
+    pub async fn publish(&self, payload: Value) {
         self.sender
             .send(Publish {
-                topic: self.topic(),
-                payload: payload.into(),
+                topic: self.topic.clone(),
+                payload,
             })
             .await
             .expect("failed to send publish");
     }
 }
 
-/// Create a mock Hue Button
-pub async fn mock_philips_button(connection: &Connection, name: &'static str) -> MockHueButton {
-    MockHueButton(MockDevice::new(connection, name).await.0)
+impl<S: mock_device_builder::State> MockDeviceBuilder<'_, S> {
+    /// Add an attribute to the device
+    pub fn attribute(mut self, name: impl Into<String>, attribute: MockAttribute) -> Self {
+        self.attributes.insert(name.into(), attribute);
+        self
+    }
+}
+
+impl MockDevice {
+    /// The current value of an attribute configured with [MockDevice::builder]
+    pub fn value(&self, attribute: &str) -> Option<Value> {
+        Some(self.attributes().get(attribute)?.value.clone())
+    }
+
+    /// Update an attribute and publish the new value, eg: to simulate a reading changing
+    pub async fn update(&self, attribute: &str, value: impl Into<Value>) {
+        let value = value.into();
+        if let Some(behaviour) = self.attributes().get_mut(attribute) {
+            behaviour.value = value.clone();
+        }
+        self.publish(json!({ attribute: value })).await;
+    }
+
+    async fn respond(self: Arc<Self>, mut requests: MockRequests) {
+        while let Some(request) = requests.next().await {
+            let payload: Map<String, Value> = {
+                let mut attributes = self.attributes();
+                match request {
+                    MockRequest::Set(values) => {
+                        let mut payload = Map::new();
+                        for (attribute, value) in values {
+                            let Some(behaviour) = attributes.get_mut(&attribute) else {
+                                warn!("{} received a set request for unknown attribute {attribute}", self.name);
+                                continue;
+                            };
+                            let Some(value) = behaviour.apply(value) else {
+                                continue;
+                            };
+                            let effects = behaviour.effects(&value);
+                            payload.insert(attribute, value);
+                            for (attribute, value) in effects {
+                                if let Some(behaviour) = attributes.get_mut(&attribute) {
+                                    behaviour.value = value.clone();
+                                }
+                                payload.insert(attribute, value);
+                            }
+                        }
+                        payload
+                    }
+                    MockRequest::Get(values) => values
+                        .into_iter()
+                        .filter_map(|(attribute, _)| {
+                            let behaviour = attributes.get(&attribute).filter(|behaviour| behaviour.get)?;
+                            Some((attribute, behaviour.value.clone()))
+                        })
+                        .collect(),
+                }
+            };
+            // zigbee2mqtt publishes the state of the attributes which were requested
+            if !payload.is_empty() {
+                self.publish(Value::Object(payload)).await;
+            }
+        }
+    }
+
+    async fn report(self: Arc<Self>, attribute: String, every: Duration) {
+        let mut interval = interval(every);
+        loop {
+            interval.tick().await;
+            let Some(value) = self.value(&attribute) else {
+                return;
+            };
+            self.publish(json!({ &attribute: value })).await;
+        }
+    }
+
+    fn attributes(&self) -> MutexGuard<'_, HashMap<String, MockAttribute>> {
+        lock(&self.attributes)
+    }
+}
+
+/// The behaviour of an attribute of a [MockDevice]
+#[derive(Debug, Clone, Builder)]
+pub struct MockAttribute {
+    /// The other attributes updated when this is set to a value, see [MockAttributeBuilder::on_set]
+    #[builder(field)]
+    effects: Vec<(Value, Map<String, Value>)>,
+    /// The initial value of the attribute
+    #[builder(into)]
+    value: Value,
+    /// Whether get requests are answered with the current value, defaults to true
+    #[builder(default = true)]
+    get: bool,
+    /// Whether set requests are applied, defaults to true
+    #[builder(default = true)]
+    set: bool,
+    /// The values switched between when `"TOGGLE"` is set, eg: `["ON", "OFF"]`
+    toggle: Option<[Value; 2]>,
+    /// Publish the current value periodically, eg: a power reading
+    report_every: Option<Duration>,
 }
 
-/// A Mock hue button
-pub struct MockHueButton(MockDevice);
+impl MockAttribute {
+    /// Applies a set request, returning the new value if it was applied
+    fn apply(&mut self, value: Value) -> Option<Value> {
+        if !self.set {
+            return None;
+        }
+        self.value = match &self.toggle {
+            Some([first, second]) if value == "TOGGLE" => {
+                if self.value == *first { second.clone() } else { first.clone() }
+            }
+            _ => value,
+        };
+        Some(self.value.clone())
+    }
 
-impl MockHueButton {
-    /// trigger an action
-    pub async fn action(&mut self, action: &'static str) {
-        let payload = json! {{"action": action}};
-        self.0.publish(payload).await;
+    /// The other attributes updated by setting this to a value
+    fn effects(&self, value: &Value) -> Map<String, Value> {
+        self.effects
+            .iter()
+            .find(|(trigger, _)| trigger == value)
+            .map(|(_, effects)| effects.clone())
+            .unwrap_or_default()
     }
 }
 
-/// Create a mock hue light
-pub async fn mock_philips_light(
-    broker: &Connection,
-    name: &'static str,
-    state: bool,
-    brightness: u8,
-) -> Arc<MockLight> {
-    let (device, receiver) = MockDevice::new(broker, name).await;
-    let mock = MockLight {
-        device,
-        state: AtomicBool::new(state),
-        brightness: AtomicU8::new(brightness),
-    };
-    let mock = Arc::new(mock);
-    spawn(mock.clone().run(receiver));
-    mock
+impl<S: mock_attribute_builder::State> MockAttributeBuilder<S> {
+    /// Update other attributes when this is set to `value`, eg: a roller shade's `"command"`
+    /// updating it's `"state"`, the updates must be an object of attribute values
+    pub fn on_set(mut self, value: impl Into<Value>, updates: Value) -> Self {
+        let Value::Object(updates) = updates else {
+            panic!("updates to other attributes must be an object, got: {updates}");
+        };
+        self.effects.push((value.into(), updates));
+        self
+    }
+}
+
+/// A request sent to a mock device
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockRequest {
+    /// A request to set attributes, eg: `{"state": "TOGGLE"}`
+    Set(Map<String, Value>),
+    /// A request to publish the current value of attributes, eg: `{"state": ""}`
+    Get(Map<String, Value>),
 }
 
-/// A mock light
-pub struct MockLight {
-    device: MockDevice,
-    state: AtomicBool,
-    brightness: AtomicU8,
+/// The requests sent to a mock device
+pub struct MockRequests {
+    name: String,
+    topic: String,
+    receiver: BroadcastStream<Publish>,
 }
 
-impl MockLight {
-    async fn run(self: Arc<Self>, receiver: Receiver<Publish>) {
-        let mut receiver = BroadcastStream::new(receiver);
-        while let Some(result) = receiver.next().await {
+impl MockRequests {
+    /// Wait for the next request, returns `None` once the connection is closed
+    pub async fn next(&mut self) -> Option<MockRequest> {
+        let set = format!("{}/set", self.topic);
+        let get = format!("{}/get", self.topic);
+        while let Some(result) = self.receiver.next().await {
             let Ok(publish) = result else {
                 continue
             };
-            if publish.topic != self.device.topic() {
-                continue;
-            }
-            let value = publish.payload;
-            if value == json! {{"state": {}}} {
-                self.device.publish(json! {{"state": self.state}}).await
-            }
-            if value == json! {{"brightness": {}}} {
-                self
-                    .device
-                    .publish(json! {{"brightness": self.brightness}})
-                    .await
+            let Value::Object(payload) = publish.payload else {
+                warn!("request to {} is not an object", self.name);
+                continue
+            };
+            if publish.topic == set {
+                return Some(MockRequest::Set(payload));
             }
-            if let Value::Object(object) = value {
-                if let Some(Value::String(new_state)) = object.get("state") {
-                    match new_state.as_str() {
-                        "ON" => self.state.store(true, Ordering::Relaxed),
-                        "OFF" => self.state.store(false, Ordering::Relaxed),
-                        "TOGGLE" => self.state.store(!self.state.load(Ordering::Relaxed), Ordering::Relaxed),
-                        other => panic!("unknown state: {}", other),
-                    };
-                }
-                if let Some(Value::Number(brightness)) = object.get("brightness") {
-                    self.brightness.store(u8::try_from(brightness.as_u64().unwrap()).unwrap(), Ordering::Relaxed);
-                }
+            if publish.topic == get {
+                return Some(MockRequest::Get(payload));
             }
         }
+        None
     }
+}
 
-    /// Get current state
-    pub fn state(&self) -> bool {
-        self.state.load(Ordering::Relaxed)
-    }
+/// Pause the clock of the current runtime, timers then only fire once time is advanced with
+/// [advance], or automatically whenever the runtime has no other work to do
+///
+/// This must be called from a `current_thread` runtime, eg: `#[tokio::test]`, and allows
+/// multi-press and delay-off automations to be tested without real sleeps
+pub fn pause_time() {
+    tokio::time::pause();
+    PAUSED.set(true);
+}
+
+/// Resume the clock of the current runtime after [pause_time]
+pub fn resume_time() {
+    tokio::time::resume();
+    PAUSED.set(false);
+}
+
+/// Returns true while the clock is paused by [pause_time]
+pub fn is_time_paused() -> bool {
+    PAUSED.get()
+}
+
+/// Advance the paused clock, then yield so that the tasks woken by expired timers can run before
+/// this returns
+pub async fn advance(duration: Duration) {
+    tokio::time::advance(duration).await;
+    settle().await;
+}
 
-    /// Get current brightness
-    pub fn brightness(&self) -> u8 {
-        self.brightness.load(Ordering::Relaxed)
+/// Yield to the runtime a number of times, allowing spawned tasks and woken streams to make
+/// progress without advancing the clock
+pub async fn settle() {
+    for _ in 0..SETTLE_YIELDS {
+        tokio::task::yield_now().await;
     }
 }
@@ -0,0 +1,222 @@
+//! A mock network for the arp crate, it answers ARP requests for the configured devices so ARP
+//! scanning can be tested without root privileges or a real network interface, eg:
+//! ```ignore
+//! let network = MockNetwork::new(Ipv4Addr::new(10, 0, 0, 1));
+//! network.add_device(PHONE, Ipv4Addr::new(10, 0, 0, 7));
+//! let mut manager = ArpManager::with_datalink(network.clone());
+//! // create devices with the manager, start it, then
+//! network.set_online(PHONE, false);
+//! ```
+
+use arp::{DataLink, DataLinkReceiver, DataLinkSender, EthernetChannel, MacAddr, NetworkInterface, RECEIVE_TIMEOUT};
+use control::util::lock;
+use pnet::ipnetwork::{IpNetwork, Ipv4Network};
+use pnet::packet::Packet;
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex};
+
+/// The MAC address of the local machine on the mock network
+const LOCAL_MAC: MacAddr = MacAddr(0x02, 0, 0, 0, 0, 0x01);
+/// The prefix length of the subnet of the mock interface
+const PREFIX: u8 = 24;
+/// The size of an ethernet frame carrying an ARP packet
+const FRAME_SIZE: usize = EthernetPacket::minimum_packet_size() + ArpPacket::minimum_packet_size();
+
+/// A mock network with a single interface named `mock0`, it answers ARP requests for each online
+/// device, clones share the same devices
+#[derive(Clone)]
+pub struct MockNetwork {
+    network: Arc<Network>,
+}
+
+struct Network {
+    interface: NetworkInterface,
+    devices: Mutex<HashMap<MacAddr, MockHost>>,
+    /// the addresses asked about by each ARP request sent on the network
+    requests: Mutex<Vec<Ipv4Addr>>,
+}
+
+/// A device on a [MockNetwork]
+#[derive(Debug, Clone, Copy)]
+struct MockHost {
+    ip: Ipv4Addr,
+    online: bool,
+}
+
+impl MockNetwork {
+    /// Create a network where the local machine has the given address in a /24 subnet
+    pub fn new(local_ip: Ipv4Addr) -> Self {
+        let network = Ipv4Network::new(local_ip, PREFIX).expect("the prefix is valid");
+        let interface = NetworkInterface {
+            name: "mock0".to_string(),
+            description: "A mock network interface".to_string(),
+            index: 0,
+            mac: Some(LOCAL_MAC),
+            ips: vec![IpNetwork::V4(network)],
+            flags: 0,
+        };
+        Self {
+            network: Arc::new(Network {
+                interface,
+                devices: Mutex::default(),
+                requests: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Connect a device to the network with the given address
+    pub fn add_device(&self, mac: MacAddr, ip: Ipv4Addr) {
+        lock(&self.network.devices).insert(mac, MockHost { ip, online: true });
+    }
+
+    /// Connect or disconnect a device, a disconnected device does not answer ARP requests
+    pub fn set_online(&self, mac: MacAddr, online: bool) {
+        if let Some(host) = lock(&self.network.devices).get_mut(&mac) {
+            host.online = online;
+        }
+    }
+
+    /// Change the address of a device, eg: when it is given a new DHCP lease
+    pub fn move_device(&self, mac: MacAddr, ip: Ipv4Addr) {
+        if let Some(host) = lock(&self.network.devices).get_mut(&mac) {
+            host.ip = ip;
+        }
+    }
+
+    /// The address asked about by each ARP request sent on the network, in the order sent
+    pub fn requests(&self) -> Vec<Ipv4Addr> {
+        lock(&self.network.requests).clone()
+    }
+}
+
+impl DataLink for MockNetwork {
+    fn interfaces(&self) -> Vec<NetworkInterface> {
+        vec![self.network.interface.clone()]
+    }
+
+    fn channel(&self, interface: &NetworkInterface) -> io::Result<EthernetChannel> {
+        if interface.name != self.network.interface.name {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no mock interface {}", interface.name)));
+        }
+        let (replies, receiver) = channel();
+        let sender = MockSender {
+            network: self.network.clone(),
+            replies,
+        };
+        let receiver = MockReceiver {
+            receiver,
+            frame: Vec::new(),
+        };
+        Ok((Box::new(sender), Box::new(receiver)))
+    }
+}
+
+/// Answers the frames sent on a [MockNetwork]
+struct MockSender {
+    network: Arc<Network>,
+    replies: Sender<Vec<u8>>,
+}
+
+impl MockSender {
+    fn handle(&self, frame: &[u8]) {
+        let Some(ethernet) = EthernetPacket::new(frame) else {
+            return;
+        };
+        // pings and neighbor discovery are not answered
+        if ethernet.get_ethertype() != EtherTypes::Arp {
+            return;
+        }
+        let Some(request) = ArpPacket::new(ethernet.payload()) else {
+            return;
+        };
+        if request.get_operation() != ArpOperations::Request {
+            return;
+        }
+        let target = request.get_target_proto_addr();
+        lock(&self.network.requests).push(target);
+        let destination = ethernet.get_destination();
+        let answering: Vec<MacAddr> = lock(&self.network.devices)
+            .iter()
+            .filter(|(mac, host)| {
+                host.online && host.ip == target && (destination == MacAddr::broadcast() || destination == **mac)
+            })
+            .map(|(mac, _)| *mac)
+            .collect();
+        for mac in answering {
+            let reply = arp_reply((mac, target), (request.get_sender_hw_addr(), request.get_sender_proto_addr()));
+            // the receiver may have been dropped once the scanner stopped
+            let _ = self.replies.send(reply);
+        }
+    }
+}
+
+impl DataLinkSender for MockSender {
+    fn build_and_send(
+        &mut self,
+        num_packets: usize,
+        packet_size: usize,
+        func: &mut dyn FnMut(&mut [u8]),
+    ) -> Option<io::Result<()>> {
+        for _ in 0..num_packets {
+            let mut frame = vec![0; packet_size];
+            func(&mut frame);
+            self.handle(&frame);
+        }
+        Some(Ok(()))
+    }
+
+    fn send_to(&mut self, packet: &[u8], _dst: Option<NetworkInterface>) -> Option<io::Result<()>> {
+        self.handle(packet);
+        Some(Ok(()))
+    }
+}
+
+/// Receives the replies of the devices on a [MockNetwork]
+struct MockReceiver {
+    receiver: Receiver<Vec<u8>>,
+    /// the last frame received, the receiver lends it out until the next frame
+    frame: Vec<u8>,
+}
+
+impl DataLinkReceiver for MockReceiver {
+    fn next(&mut self) -> io::Result<&[u8]> {
+        match self.receiver.recv_timeout(RECEIVE_TIMEOUT) {
+            Ok(frame) => {
+                self.frame = frame;
+                Ok(&self.frame)
+            }
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+/// Build an ARP reply from the given device to the given requester
+fn arp_reply(source: (MacAddr, Ipv4Addr), target: (MacAddr, Ipv4Addr)) -> Vec<u8> {
+    let mut frame = vec![0; FRAME_SIZE];
+    {
+        let mut ethernet = MutableEthernetPacket::new(&mut frame).expect("buffer is large enough for EthernetPacket");
+        ethernet.set_destination(target.0);
+        ethernet.set_source(source.0);
+        ethernet.set_ethertype(EtherTypes::Arp);
+    }
+    {
+        let mut arp = MutableArpPacket::new(&mut frame[EthernetPacket::minimum_packet_size()..])
+            .expect("buffer is large enough for ArpPacket");
+        arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp.set_protocol_type(EtherTypes::Ipv4);
+        arp.set_hw_addr_len(6);
+        arp.set_proto_addr_len(4);
+        arp.set_operation(ArpOperations::Reply);
+        arp.set_sender_hw_addr(source.0);
+        arp.set_sender_proto_addr(source.1);
+        arp.set_target_hw_addr(target.0);
+        arp.set_target_proto_addr(target.1);
+    }
+    frame
+}
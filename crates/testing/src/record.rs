@@ -0,0 +1,123 @@
+//! Recording the traffic of a real zigbee2mqtt instance and replaying it through the test broker,
+//! so the quirks of real devices can be turned into regression tests, eg:
+//! ```ignore
+//! // once, against the real broker
+//! let recording = Recording::record(MqttOptions::new("recorder", "broker.local", 1883), "zigbee2mqtt/#", Duration::from_secs(60)).await?;
+//! recording.save("tests/recordings/hallway.jsonl")?;
+//!
+//! // in the test
+//! let recording = Recording::load("tests/recordings/hallway.jsonl")?;
+//! recording.replay(&connection, 10.0).await;
+//! ```
+
+use crate::{Connection, Publish};
+use log::{debug, warn};
+use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::{Instant, sleep_until, timeout_at};
+
+/// A publish captured by [Recording::record]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedPublish {
+    /// The time since the recording started, in milliseconds
+    pub at: u64,
+    /// The topic published to
+    pub topic: String,
+    /// The payload, payloads which are not JSON are recorded as a string
+    pub payload: Value,
+}
+
+/// A recording of the publishes on a broker, saved as one JSON publish per line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    /// The publishes in the order they were received
+    pub publishes: Vec<RecordedPublish>,
+}
+
+impl Recording {
+    /// Record the publishes to topics matching `filter` on the broker for the given duration,
+    /// eg: `zigbee2mqtt/#` for all zigbee2mqtt traffic
+    pub async fn record(options: MqttOptions, filter: &str, duration: Duration) -> io::Result<Self> {
+        let (client, mut event_loop) = AsyncClient::new(options, 100);
+        client
+            .subscribe(filter, QoS::AtLeastOnce)
+            .await
+            .map_err(io::Error::other)?;
+        let start = Instant::now();
+        let end = start + duration;
+        let mut publishes = Vec::new();
+        while let Ok(event) = timeout_at(end, event_loop.poll()).await {
+            let Event::Incoming(Incoming::Publish(publish)) = event.map_err(io::Error::other)? else {
+                continue;
+            };
+            let payload = serde_json::from_slice(&publish.payload)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&publish.payload).into_owned()));
+            debug!("recorded publish to {}", publish.topic);
+            publishes.push(RecordedPublish {
+                at: start.elapsed().as_millis().try_into().unwrap_or(u64::MAX),
+                topic: publish.topic,
+                payload,
+            });
+        }
+        if let Err(error) = client.disconnect().await {
+            warn!("failed to disconnect recorder: {error}");
+        }
+        Ok(Self { publishes })
+    }
+
+    /// Load a recording saved with [Recording::save]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut publishes = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            publishes.push(serde_json::from_str(&line)?);
+        }
+        Ok(Self { publishes })
+    }
+
+    /// Save the recording, one JSON publish per line
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for publish in &self.publishes {
+            serde_json::to_writer(&mut writer, publish)?;
+            writeln!(writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Only keep the publishes to topics starting with the given prefix, eg: to replay a single
+    /// device out of a recording of the whole network
+    pub fn only(mut self, prefix: &str) -> Self {
+        self.publishes.retain(|publish| publish.topic.starts_with(prefix));
+        self
+    }
+
+    /// Publish each recorded publish through the connection with the original timing divided by
+    /// `speed`, eg: a speed of 10 replays a minute of traffic in 6 seconds, the timing follows
+    /// tokio's clock so the replay also works with [pause_time](crate::pause_time)
+    pub async fn replay(&self, connection: &Connection, speed: f64) {
+        assert!(speed > 0.0, "the replay speed must be positive, got {speed}");
+        let start = Instant::now();
+        for publish in &self.publishes {
+            let offset = Duration::from_millis(publish.at).div_f64(speed);
+            sleep_until(start + offset).await;
+            connection
+                .sender
+                .send(Publish {
+                    topic: publish.topic.clone(),
+                    payload: publish.payload.clone(),
+                })
+                .await
+                .expect("failed to send replayed publish");
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Running automations against scripted or recorded sensor readings on an accelerated clock and
+//! reporting the writes they would have made, eg: to check heating logic against last winter's
+//! temperatures:
+//! ```ignore
+//! let samples = history.range("living_room", "temperature", last_winter).await?;
+//! let mut simulation = Simulation::new();
+//! let temperature = simulation.sensor(Script::from_samples(samples.into_iter().filter_map(|(at, value)| Some((at, value.as_f64()?)))));
+//! let boiler = simulation.value("boiler", false);
+//! let report = simulation.run([thermostat("heating", &temperature, &boiler, 20.0, 0.5)], Duration::from_secs(90 * 24 * 60 * 60)).await;
+//! for write in &report.writes {
+//!     println!("{write}");
+//! }
+//! ```
+//!
+//! The clock is paused for the simulation, so the runtime skips ahead to the next reading or
+//! timer whenever the automations are idle, this must run on a `current_thread` runtime, eg:
+//! `#[tokio::test]`, a clock already paused with [pause_time] is left paused afterwards
+
+use crate::{is_time_paused, pause_time, resume_time};
+use control::automation::{self, Automation};
+use control::{Manager, ReadValue, Sensor, ToggleValue, WriteValue};
+use control::util::lock;
+use futures::future::{BoxFuture, join_all, ready};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::mem::take;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::join;
+use tokio::sync::watch;
+use tokio::time::{Instant, sleep_until, timeout};
+use tokio_stream::wrappers::WatchStream;
+
+/// Readings of a simulated sensor at offsets from the start of the simulation
+#[derive(Debug, Clone)]
+pub struct Script<T> {
+    readings: Vec<(Duration, T)>,
+}
+
+impl<T> Default for Script<T> {
+    fn default() -> Self {
+        Self { readings: Vec::new() }
+    }
+}
+
+impl<T> Script<T> {
+    /// Create an empty script
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a reading at the given offset from the start of the simulation, readings may be added
+    /// in any order
+    pub fn at(mut self, offset: Duration, reading: T) -> Self {
+        self.readings.push((offset, reading));
+        self
+    }
+
+    /// A script of readings recorded at the given times, eg: samples queried from the history, the
+    /// earliest reading is at the start of the simulation
+    pub fn from_samples(samples: impl IntoIterator<Item = (SystemTime, T)>) -> Self {
+        let samples: Vec<_> = samples.into_iter().collect();
+        let Some(first) = samples.iter().map(|(at, _)| *at).min() else {
+            return Self::default();
+        };
+        Self {
+            readings: samples
+                .into_iter()
+                .map(|(at, reading)| (at.duration_since(first).unwrap_or_default(), reading))
+                .collect(),
+        }
+    }
+}
+
+/// Runs automations against simulated devices, see the [module docs](self)
+#[derive(Default)]
+pub struct Simulation {
+    shared: Arc<Shared>,
+    feeders: Vec<BoxFuture<'static, ()>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    start: OnceLock<Instant>,
+    writes: Mutex<Vec<SimulatedWrite>>,
+}
+
+impl Shared {
+    fn record(&self, device: &str, value: String) {
+        let at = self.start.get().map(Instant::elapsed).unwrap_or_default();
+        lock(&self.writes).push(SimulatedWrite {
+            at,
+            device: device.to_string(),
+            value,
+            automation: automation::current(),
+        });
+    }
+}
+
+impl Simulation {
+    /// Create a simulation without any devices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a sensor which reports the readings of the script as the simulation runs
+    pub fn sensor<T: Clone + Send + Sync + 'static>(&mut self, script: Script<T>) -> SimulatedSensor<T> {
+        let readings = Arc::new(watch::Sender::new(None));
+        let sender = readings.clone();
+        let mut script = script.readings;
+        script.sort_by_key(|(offset, _)| *offset);
+        self.feeders.push(Box::pin(async move {
+            let start = Instant::now();
+            for (offset, reading) in script {
+                sleep_until(start + offset).await;
+                sender.send_replace(Some(reading));
+            }
+        }));
+        SimulatedSensor { readings }
+    }
+
+    /// Create a value which records every write made to it, `initial` is the state it reports
+    /// before the first write
+    pub fn value<T: Clone + Send + Sync + 'static>(&mut self, name: impl Into<String>, initial: T) -> SimulatedValue<T> {
+        SimulatedValue {
+            name: name.into(),
+            state: Arc::new(watch::Sender::new(initial)),
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Run the automations for the given length of simulated time, returning the writes they made
+    pub async fn run<'a>(self, automations: impl IntoIterator<Item = Automation<'a>>, duration: Duration) -> Report {
+        // the clock is left as it was found, eg: paused by a test which goes on to advance it
+        let paused = is_time_paused();
+        if !paused {
+            pause_time();
+        }
+        let start = *self.shared.start.get_or_init(Instant::now);
+        let manager = Manager::builder().build();
+        let shutdown = manager.shutdown_token();
+        let feeders = join_all(self.feeders);
+        join!(manager.start(automations), async {
+            // a script may continue past the end of the simulation
+            let _ = timeout(duration, feeders).await;
+            sleep_until(start + duration).await;
+            shutdown.cancel();
+        });
+        if !paused {
+            resume_time();
+        }
+        Report {
+            writes: take(&mut *lock(&self.shared.writes)),
+        }
+    }
+}
+
+/// A sensor reporting the readings of a [Script], see [Simulation::sensor]
+pub struct SimulatedSensor<T> {
+    readings: Arc<watch::Sender<Option<T>>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> Sensor for SimulatedSensor<T> {
+    type Item = T;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(WatchStream::new(self.readings.subscribe()).filter_map(ready))
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> ReadValue for SimulatedSensor<T> {
+    type Item = T;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        let reading = self.readings.borrow().clone();
+        Box::pin(ready(reading.ok_or_else(|| anyhow::anyhow!("the script has no reading yet"))))
+    }
+}
+
+/// A value recording the writes made to it, it reports each write as it's new state, see
+/// [Simulation::value]
+pub struct SimulatedValue<T> {
+    name: String,
+    state: Arc<watch::Sender<T>>,
+    shared: Arc<Shared>,
+}
+
+impl<T> SimulatedValue<T> {
+    /// Change the state as if it was changed outside the automations, eg: a person flipping a
+    /// switch, this is not recorded as a write
+    pub fn change(&self, value: T) {
+        self.state.send_replace(value);
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Sensor for SimulatedValue<T> {
+    type Item = T;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(WatchStream::new(self.state.subscribe()))
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> ReadValue for SimulatedValue<T> {
+    type Item = T;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        Box::pin(ready(Ok(self.state.borrow().clone())))
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static> WriteValue for SimulatedValue<T> {
+    type Item = T;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        self.shared.record(&self.name, format!("{value:?}"));
+        self.state.send_replace(value);
+        Box::pin(ready(Ok(())))
+    }
+}
+
+impl ToggleValue for SimulatedValue<bool> {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        let mut value = false;
+        self.state.send_modify(|state| {
+            *state = !*state;
+            value = *state;
+        });
+        self.shared.record(&self.name, format!("{value:?}"));
+        Box::pin(ready(Ok(())))
+    }
+}
+
+/// The writes made during a simulation, see [Simulation::run]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Every write in the order they were made
+    pub writes: Vec<SimulatedWrite>,
+}
+
+impl Report {
+    /// The writes made to the named value
+    pub fn writes_to<'a>(&'a self, device: &'a str) -> impl Iterator<Item = &'a SimulatedWrite> {
+        self.writes.iter().filter(move |write| write.device == device)
+    }
+}
+
+/// A write made during a simulation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedWrite {
+    /// The simulated time since the start of the simulation
+    pub at: Duration,
+    /// The name of the value written to
+    pub device: String,
+    /// The value written, formatted for debugging, a toggle is recorded as the value it resulted in
+    pub value: String,
+    /// The automation which made the write, if any
+    pub automation: Option<String>,
+}
+
+impl Display for SimulatedWrite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {} = {}", self.at, self.device, self.value)?;
+        if let Some(automation) = &self.automation {
+            write!(f, " by {automation}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+//! A mock Wiz bulb, it answers requests on a loopback address like a real bulb so the wiz crate
+//! can be tested without hardware, eg:
+//! ```ignore
+//! let bulb = MockWizBulb::builder().start(Ipv4Addr::new(127, 0, 0, 2)).await;
+//! let light = Light::verify_new(&manager, info, bulb.addr()).await?;
+//! let turned_on = bulb.expect_request("setPilot", json!({"state": true}), TIMEOUT);
+//! light.turn_on().await?;
+//! turned_on.await;
+//! ```
+
+use crate::PayloadMatcher;
+use bon::bon;
+use control::util::lock;
+use log::{debug, warn};
+use serde_json::{Map, Value, json};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::spawn;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// The port bulbs listen for requests on
+const BULB_PORT: u16 = 38899;
+/// The port the wiz manager listens on, bulbs push their state to this port
+const LISTEN_PORT: u16 = 38900;
+/// The colour channels of the pilot, a bulb either shows a colour or a white temperature
+const COLOR_CHANNELS: [&str; 5] = ["r", "g", "b", "c", "w"];
+
+/// A request received by a [MockWizBulb]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WizRequest {
+    /// The method requested, eg: `setPilot`
+    pub method: String,
+    /// The parameters of the request
+    pub params: Value,
+}
+
+/// A mock Wiz bulb listening on `addr:38899`, any address in `127.0.0.0/8` can be used so each
+/// bulb in a test can have it's own address, the bulb stops when this is dropped
+///
+/// It answers `getPilot`, `setPilot`, `getSystemConfig`, `getModelConfig`, `getPower` and
+/// `registration`, after registration changes made with [MockWizBulb::change] are pushed to the
+/// registered listener with `syncPilot`
+pub struct MockWizBulb {
+    bulb: Arc<Bulb>,
+    job: JoinHandle<()>,
+}
+
+struct Bulb {
+    addr: Ipv4Addr,
+    socket: UdpSocket,
+    module: String,
+    /// the current state of the bulb, as returned by `getPilot`
+    pilot: Mutex<Map<String, Value>>,
+    /// the consumption in milliwatts
+    power: Mutex<u32>,
+    /// the address which registered for `syncPilot` messages
+    listener: Mutex<Option<Ipv4Addr>>,
+    requests: Mutex<Vec<WizRequest>>,
+    received: broadcast::Sender<WizRequest>,
+    responding: AtomicBool,
+}
+
+#[bon]
+impl MockWizBulb {
+    /// Start a mock bulb, which is turned off at full brightness and a warm white
+    #[builder(finish_fn = start)]
+    pub async fn new(
+        /// The address to listen on
+        #[builder(finish_fn)]
+        addr: Ipv4Addr,
+        /// The module name reported by `getSystemConfig`, this determines the features supported,
+        /// defaults to an RGB bulb
+        #[builder(into, default = "ESP01_SHRGB1C_31".to_string())]
+        module: String,
+        /// The initial state of the bulb, merged into the default state, eg: `json!({"state": true})`
+        pilot: Option<Value>,
+    ) -> MockWizBulb {
+        let socket = UdpSocket::bind((addr, BULB_PORT))
+            .await
+            .expect("failed to bind mock wiz bulb");
+        let mut initial = json!({"rssi": -60, "state": false, "dimming": 100, "temp": 2700});
+        if let Some(pilot) = pilot {
+            merge(&mut initial, pilot);
+        }
+        let Value::Object(pilot) = initial else {
+            panic!("the pilot of a wiz bulb must be an object");
+        };
+        let bulb = Arc::new(Bulb {
+            addr,
+            socket,
+            module,
+            pilot: Mutex::new(pilot),
+            power: Mutex::new(0),
+            listener: Mutex::default(),
+            requests: Mutex::default(),
+            received: broadcast::channel(16).0,
+            responding: AtomicBool::new(true),
+        });
+        let job = spawn(bulb.clone().respond());
+        MockWizBulb { bulb, job }
+    }
+}
+
+impl MockWizBulb {
+    /// The address of the bulb
+    pub fn addr(&self) -> Ipv4Addr {
+        self.bulb.addr
+    }
+
+    /// The current state of the bulb, as returned by `getPilot`
+    pub fn pilot(&self) -> Value {
+        Value::Object(lock(&self.bulb.pilot).clone())
+    }
+
+    /// Set the consumption reported by `getPower`, in milliwatts
+    pub fn set_power(&self, milliwatts: u32) {
+        *lock(&self.bulb.power) = milliwatts;
+    }
+
+    /// Stop answering requests, as if the bulb were switched off at the wall, requests are still
+    /// recorded
+    pub fn set_responding(&self, responding: bool) {
+        self.bulb.responding.store(responding, Ordering::Relaxed);
+    }
+
+    /// Change the state of the bulb as if it were changed from another source, eg: the Wiz app,
+    /// the new state is pushed to the registered listener
+    pub async fn change(&self, pilot: Value) {
+        let mut state = self.pilot();
+        merge(&mut state, pilot);
+        if let Value::Object(state) = state {
+            *lock(&self.bulb.pilot) = state;
+        }
+        self.bulb.push().await;
+    }
+
+    /// Every request received, in the order received
+    pub fn requests(&self) -> Vec<WizRequest> {
+        lock(&self.bulb.requests).clone()
+    }
+
+    /// The parameters of every request received with the given method
+    pub fn received(&self, method: &str) -> Vec<Value> {
+        lock(&self.bulb.requests)
+            .iter()
+            .filter(|request| request.method == method)
+            .map(|request| request.params.clone())
+            .collect()
+    }
+
+    /// Expect a request with the given method and parameters matching `params`, returning the
+    /// matching parameters
+    ///
+    /// Like [Connection::expect_publish](crate::Connection::expect_publish), requests are recorded
+    /// from when this is called rather than when it is awaited
+    ///
+    /// # Panics
+    /// If no matching request is received within `timeout`, the panic lists the requests which
+    /// were received instead
+    pub fn expect_request<M: PayloadMatcher>(
+        &self,
+        method: &str,
+        params: M,
+        timeout: Duration,
+    ) -> impl Future<Output = Value> + use<M> {
+        let mut receiver = self.bulb.received.subscribe();
+        let method = method.to_string();
+        let addr = self.bulb.addr;
+        async move {
+            let mut seen = Vec::new();
+            let matched = tokio::time::timeout(timeout, async {
+                loop {
+                    let request = match receiver.recv().await {
+                        Ok(request) => request,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("expect_request skipped {skipped} requests");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    };
+                    if request.method == method && params.matches(&request.params) {
+                        return Some(request.params);
+                    }
+                    seen.push(request);
+                }
+            })
+            .await;
+            if let Ok(Some(params)) = matched {
+                return params;
+            }
+            let mut message = format!(
+                "expected {addr} to receive {method} with {} within {timeout:?}, ",
+                params.describe()
+            );
+            if seen.is_empty() {
+                message.push_str("nothing was received");
+            } else {
+                message.push_str("received:");
+                for request in &seen {
+                    message.push_str(&format!("\n  {}: {}", request.method, request.params));
+                    if request.method == method {
+                        for line in params.diff(&request.params) {
+                            message.push_str(&format!("\n    {line}"));
+                        }
+                    }
+                }
+            }
+            panic!("{message}");
+        }
+    }
+}
+
+impl Drop for MockWizBulb {
+    fn drop(&mut self) {
+        self.job.abort();
+    }
+}
+
+impl Bulb {
+    async fn respond(self: Arc<Self>) {
+        let mut buf = [0; 4096];
+        loop {
+            let (len, source) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(error) => {
+                    warn!("mock wiz bulb {} failed to receive: {error}", self.addr);
+                    continue;
+                }
+            };
+            let request: Value = match serde_json::from_slice(&buf[..len]) {
+                Ok(request) => request,
+                Err(error) => {
+                    warn!("mock wiz bulb {} received invalid json: {error}", self.addr);
+                    continue;
+                }
+            };
+            let request = WizRequest {
+                method: request["method"].as_str().unwrap_or_default().to_string(),
+                params: request.get("params").cloned().unwrap_or(Value::Null),
+            };
+            debug!("mock wiz bulb {} received: {request:?}", self.addr);
+            lock(&self.requests).push(request.clone());
+            // nobody may be waiting for requests
+            let _ = self.received.send(request.clone());
+            if !self.responding.load(Ordering::Relaxed) {
+                continue;
+            }
+            let response = self.handle(&request, source);
+            self.send(&response, source).await;
+        }
+    }
+
+    fn handle(&self, request: &WizRequest, source: SocketAddr) -> Value {
+        let result = match request.method.as_str() {
+            "getPilot" => Value::Object(lock(&self.pilot).clone()),
+            "setPilot" => {
+                let mut pilot = lock(&self.pilot);
+                if let Value::Object(params) = &request.params {
+                    if COLOR_CHANNELS.iter().any(|channel| params.contains_key(*channel)) {
+                        pilot.remove("temp");
+                    }
+                    if params.contains_key("temp") {
+                        pilot.retain(|key, _| !COLOR_CHANNELS.contains(&key.as_str()));
+                    }
+                    pilot.extend(params.clone());
+                }
+                json!({"success": true})
+            }
+            "getSystemConfig" => json!({
+                "mac": format!("a8bb50{:06x}", u32::from(self.addr) & 0xffffff),
+                "moduleName": self.module,
+                "fwVersion": "1.25.0",
+            }),
+            "getModelConfig" => json!({"cctRange": [2200, 2700, 6500, 6500]}),
+            "getPower" => json!({"power": *lock(&self.power)}),
+            "registration" => {
+                let listener = request.params["phoneIp"]
+                    .as_str()
+                    .and_then(|ip| ip.parse().ok())
+                    .or(match source {
+                        SocketAddr::V4(source) => Some(*source.ip()),
+                        SocketAddr::V6(_) => None,
+                    });
+                *lock(&self.listener) = listener;
+                json!({"mac": "AAAAAAAAAAAA", "success": true})
+            }
+            method => {
+                return json!({
+                    "method": method,
+                    "env": "pro",
+                    "error": {"code": -32601, "message": "Method not found"},
+                });
+            }
+        };
+        json!({"method": request.method, "env": "pro", "result": result})
+    }
+
+    /// Push the current state to the registered listener
+    async fn push(&self) {
+        let Some(listener) = *lock(&self.listener) else {
+            return;
+        };
+        let params = Value::Object(lock(&self.pilot).clone());
+        let message = json!({"method": "syncPilot", "env": "pro", "params": params});
+        self.send(&message, (listener, LISTEN_PORT).into()).await;
+    }
+
+    async fn send(&self, message: &Value, to: SocketAddr) {
+        let message = serde_json::to_vec(message).expect("could not serialize wiz message");
+        if let Err(error) = self.socket.send_to(&message, to).await {
+            warn!("mock wiz bulb {} failed to send to {to}: {error}", self.addr);
+        }
+    }
+}
+
+/// Merge the fields of an object into another
+fn merge(target: &mut Value, source: Value) {
+    if let (Value::Object(target), Value::Object(source)) = (target, source) {
+        target.extend(source);
+    }
+}
@@ -3,6 +3,7 @@
 use simple_log::{Level, LogConfigBuilder};
 use control::reflect::{DeviceInfo, DeviceType};
 use wiz::light::Light;
+use wiz::Manager;
 
 #[allow(clippy::unwrap_used, clippy::expect_used, reason = "testing")]
 #[tokio::main]
@@ -13,7 +14,8 @@ async fn main() {
             .output_console()
             .build()
     ).unwrap();
-    let light = Light::verify_new(DeviceInfo {
+    let manager = Manager::builder().build();
+    let light = Light::verify_new(&manager, DeviceInfo {
         id: "test".to_string(),
         name: "Test Light".to_string(),
         description: None,
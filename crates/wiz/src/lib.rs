@@ -1,13 +1,17 @@
-#![cfg(target_os = "linux")]
 #![doc = include_str!("../README.md")]
 
 pub mod light;
+mod manager;
+pub mod room;
+pub mod system;
 
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use std::fmt::Debug;
-use std::net::{Ipv4Addr, UdpSocket};
-use tracing::debug;
-pub use light::Light;
+use std::net::Ipv4Addr;
+pub use light::{Color, Light};
+pub use manager::Manager;
+pub use room::{Room, RoomState};
+pub use system::{Kind, SystemConfig};
 
 #[derive(Debug, Clone, Deserialize)]
 #[allow(dead_code)]
@@ -17,52 +21,6 @@ struct Response<T> {
     result: T
 }
 
-async fn udp_request<Request, Data>(addr: Ipv4Addr, msg: Request) -> Result<Response<Data>, Error>
-where
-    Request: Serialize + Debug,
-    for<'de> Data: Deserialize<'de>,
-{
-    // dump the control message to string
-    debug!("sending request to {addr}: {msg:?}");
-    let msg = match serde_json::to_vec(&msg) {
-        Ok(v) => v,
-        Err(e) => return Err(Error::JsonSerialize(e)),
-    };
-
-    // get some udp socket from the os
-    let socket = match UdpSocket::bind("0.0.0.0:38899") {
-        Ok(s) => s,
-        Err(e) => return Err(Error::socket("bind", e)),
-    };
-
-    // connect to the remote bulb at their standard port
-    match socket.connect((addr, 38899)) {
-        Ok(_) => {}
-        Err(e) => return Err(Error::socket("connect", e)),
-    }
-
-    // send the control message
-    match socket.send(&msg) {
-        Ok(_) => {}
-        Err(e) => return Err(Error::socket("send", e)),
-    };
-
-    // declare a buffer of the max message size
-    let mut buffer = [0; 4096];
-    let bytes = match socket.recv(&mut buffer) {
-        Ok(b) => b,
-        Err(e) => return Err(Error::socket("receive", e)),
-    };
-
-    // create some JSON object from the string
-    debug!("received response from {addr}: {}", String::from_utf8(Vec::from(&buffer[..bytes]))
-        .unwrap_or_else(|_| "failed to decode".to_string()));
-    match serde_json::from_slice(&buffer[..bytes]) {
-        Ok(v) => Ok(v),
-        Err(e) => Err(Error::JsonSerialize(e)),
-    }
-}
-
 /// an Error that may occur while communicating with wiz devices
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -83,6 +41,28 @@ pub enum Error {
         err: std::io::Error
     },
 
+    /// The bulb did not respond to any attempt of a request
+    #[error("no response from {addr} after {attempts} attempts")]
+    Timeout {
+        /// The address of the bulb
+        addr: Ipv4Addr,
+        /// The number of times the request was sent
+        attempts: u32,
+    },
+
+    /// The manager stopped before the bulb responded
+    #[error("the wiz manager has stopped")]
+    Stopped,
+
+    /// Attempting to use a feature which the light does not support
+    #[error("light {ip} does not support {feature}")]
+    Unsupported {
+        /// The IP address of the light
+        ip: Ipv4Addr,
+        /// The feature which is not supported
+        feature: String,
+    },
+
     /// Attempting to look up or modify a light which doesn't exist
     #[error("light {light_id:?} not found")]
     LightNotFound {
@@ -116,6 +96,14 @@ impl Error {
         }
     }
 
+    /// Create a new unsupported feature error
+    pub fn unsupported(ip: &Ipv4Addr, feature: &str) -> Self {
+        Self::Unsupported {
+            ip: *ip,
+            feature: feature.to_string(),
+        }
+    }
+
     /// Create a new invalid IP error
     pub fn invalid_ip(ip: &Ipv4Addr, reason: &str) -> Self {
         Self::InvalidIP {
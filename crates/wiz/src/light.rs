@@ -1,21 +1,34 @@
 //! Wiz lights
 
-use crate::{udp_request, Error, Response};
+use crate::manager::Client;
+use crate::system::{Kind, RawModelConfig, SystemConfig};
+use crate::{Error, Manager, Response};
 use anyhow::Context;
 use bon::bon;
 use control::device::{Device};
+use control::{ColorLight, Percentage, ReadValue, Sensor, ToggleValue, WriteValue};
+use control::dry_run::DryRun;
 use control::reflect;
 use control::reflect::value::{Value, ValueType};
 use control::reflect::{DeviceInfo, Field, Operation, Operations, SetError};
+use control::util::lock;
 use futures::future::BoxFuture;
-use futures::stream::BoxStream;
+use futures::stream::{self, BoxStream};
 use futures::FutureExt;
 use light_ranged_integers::{RangedU16, RangedU8};
 use serde::de::Error as _;
 use serde::{Deserialize, Deserializer};
 use serde_json::json;
+use std::future::ready;
 use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch::{Receiver, Sender};
 use tokio::sync::Mutex;
+use tokio::time::{interval, sleep_until, Instant};
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, warn};
 
 /// A Wiz Light
 #[derive(Debug)]
@@ -25,34 +38,156 @@ where
 {
     info: DeviceInfo,
     addr: Ipv4Addr,
-    state: Mutex<State>,
+    client: Arc<Client>,
+    kind: Kind,
+    /// the last observed state, subscribers are notified of every change
+    state: Sender<State>,
+    /// false while the light is not answering requests
+    reachable: Receiver<bool>,
+    /// the state waiting to be sent
+    desired: std::sync::Mutex<Desired>,
+    /// held while sending a command, the time the last command was sent
+    last_sent: Mutex<Option<Instant>>,
+    dry_run: DryRun,
+}
+
+/// The minimum time between commands sent to a bulb
+pub const COMMAND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The state requested by calls to [Light::update_state] which has not been sent yet
+#[derive(Debug, Default)]
+struct Desired {
+    state: Option<State>,
+    /// incremented by each call
+    generation: u64,
+    /// the generation of the last state sent successfully
+    sent: u64,
 }
 
 impl Light {
     /// Create a new instance of `Light` and verify that it can be reached
-    pub async fn verify_new(info: DeviceInfo, addr: Ipv4Addr) -> Result<Self, anyhow::Error> {
-        let state = udp_request(addr, json! {{"method": "getPilot", "params": {}}})
+    pub async fn verify_new(manager: &Manager, info: DeviceInfo, addr: Ipv4Addr) -> Result<Self, anyhow::Error> {
+        let client = manager.client();
+        let state = client.request(addr, "getPilot", json! {{}})
             .await?
             .result;
-        let state = Mutex::new(state);
+        let kind = match system_config(&client, addr).await {
+            Ok(config) => config.kind(),
+            Err(error) => {
+                warn!("failed to read system config of {}, assuming all features are supported: {error}", info.name);
+                Kind::Unknown
+            }
+        };
+        let state = Sender::new(state);
+        client.watch(addr, state.clone()).await;
+        let reachable = client.reachability(addr);
         Ok(Self {
             info,
             addr,
+            client,
+            kind,
             state,
+            reachable,
+            desired: Default::default(),
+            last_sent: Mutex::new(None),
+            dry_run: manager.dry_run(),
         })
     }
 
-    /// update the tracked state and request to light to change state to match
+    /// Returns false if the light did not answer its last request, a light which is unreachable
+    /// is deemed reachable again as soon as it answers a request or pushes its state
+    pub fn online(&self) -> bool {
+        *self.reachable.borrow()
+    }
+
+    /// Returns a stream of changes to the reachability of the light
+    pub fn online_changes(&self) -> impl Stream<Item = bool> + use<> {
+        WatchStream::from_changes(self.reachable.clone())
+    }
+
+    /// Returns the kind of light, this was read when the light was created and determines which
+    /// features are supported
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// retrieve information about the hardware and firmware of the light
+    pub async fn system_config(&self) -> Result<SystemConfig, Error> {
+        system_config(&self.client, self.addr).await
+    }
+
+    /// update the tracked state and request to light to change state to match. Bulbs drop or
+    /// reorder commands sent in quick succession so commands are sent at most once per
+    /// [COMMAND_INTERVAL], calls made while waiting are collapsed into a single command with the
+    /// latest desired state. In dry-run mode the new state is logged instead, see [DryRun]
     pub async fn update_state(&self, f: impl FnOnce(&mut State)) -> Result<(), Error> {
-        let mut state = { *self.state.lock().await };
-        f(&mut state);
-        let msg = if state.state {
-            json! {{"method":"setPilot","params":{"dimming":state.brightness,"temp":state.temp,"state":true}}}
+        let generation = {
+            let mut desired = lock(&self.desired);
+            let mut state = desired.state.unwrap_or_else(|| *self.state.borrow());
+            f(&mut state);
+            if state.color.is_some() && !self.kind.supports_color() {
+                return Err(Error::unsupported(&self.addr, "colour"));
+            }
+            if state.temp.is_some() && !self.kind.supports_temp() {
+                return Err(Error::unsupported(&self.addr, "colour temperature"));
+            }
+            if self.dry_run.intercept(&self.info.id, "state", state) {
+                return Ok(());
+            }
+            desired.state = Some(state);
+            desired.generation += 1;
+            desired.generation
+        };
+
+        let mut last_sent = self.last_sent.lock().await;
+        let (state, latest) = {
+            let desired = lock(&self.desired);
+            if desired.sent >= generation {
+                // a later call already sent a state including this change
+                return Ok(());
+            }
+            let Some(state) = desired.state else {
+                return Ok(());
+            };
+            (state, desired.generation)
+        };
+        if let Some(last_sent) = *last_sent {
+            sleep_until(last_sent + COMMAND_INTERVAL).await;
+        }
+        let result = self.send_state(state).await;
+        *last_sent = Some(Instant::now());
+
+        let mut desired = lock(&self.desired);
+        if result.is_ok() {
+            desired.sent = latest;
+        }
+        if desired.generation == latest {
+            // nothing newer is waiting, later changes should start from the observed state, a
+            // state which failed to send was never applied so is not built upon either
+            desired.state = None;
+        }
+        result
+    }
+
+    async fn send_state(&self, state: State) -> Result<(), Error> {
+        let params = if state.state {
+            let mut params = json! {{"dimming":state.brightness,"state":true}};
+            // the bulb either shows a colour or a white temperature, the colour takes precedence
+            if let Some(color) = state.color {
+                params["r"] = color.r.into();
+                params["g"] = color.g.into();
+                params["b"] = color.b.into();
+                params["c"] = color.c.into();
+                params["w"] = color.w.into();
+            } else if let Some(temp) = state.temp {
+                params["temp"] = temp.inner().into();
+            }
+            params
         } else {
-            json! {{"method":"setPilot","params":{"state":false}}}
+            json! {{"state":false}}
         };
-        let _: Response<Success> = udp_request(self.addr, msg).await?;
-        *self.state.lock().await = state;
+        let _: Response<Success> = self.client.request(self.addr, "setPilot", params).await?;
+        self.state.send_replace(state);
         Ok(())
     }
 
@@ -83,22 +218,94 @@ impl Light {
         .await
     }
 
-    /// Returns the last observed state of the light, this is not guaranteed to be accurate since
-    /// the light can change state without notice if a command is sent from another source
+    /// set the colour of the light, this replaces any colour temperature
+    pub async fn set_color(&self, color: Color) -> Result<(), Error> {
+        self.update_state(|state| {
+            state.color = Some(color);
+            state.temp = None;
+        })
+        .await
+    }
+
+    /// set the colour temperature of the light, this replaces any colour
+    pub async fn set_temp(&self, temp: RangedU16<1000, 12000>) -> Result<(), Error> {
+        self.update_state(|state| {
+            state.temp = Some(temp);
+            state.color = None;
+        })
+        .await
+    }
+
+    /// Returns the on/off state of the light, this can be used anywhere a generic
+    /// switch is expected, eg: in a [Group](control::Group) or [ToggleSet](control::ToggleSet)
+    pub fn state(&self) -> Power<'_> {
+        Power(self)
+    }
+
+    /// Returns the brightness of the light
+    pub fn brightness(&self) -> Brightness<'_> {
+        Brightness(self)
+    }
+
+    /// Returns the colour temperature of the light, this is read as `None` while the light is
+    /// showing a colour
+    pub fn temperature(&self) -> Temperature<'_> {
+        Temperature(self)
+    }
+
+    /// Returns the state pushed by the light whenever it changes, this includes changes made
+    /// from other sources such as the Wiz app or a physical switch
+    pub fn updates(&self) -> Updates<'_> {
+        Updates(self)
+    }
+
+    /// Returns a stream of the tracked state, starting with the last observed state
+    fn states(&self) -> WatchStream<State> {
+        WatchStream::new(self.state.subscribe())
+    }
+
+    /// Returns the power consumption of the light, only some bulbs report their consumption
+    pub fn consumption(&self) -> Consumption<'_> {
+        Consumption(self)
+    }
+
+    /// retrieve the instantaneous power consumption of the light in watts, this fails for bulbs
+    /// which do not report their consumption
+    pub async fn power(&self) -> Result<f32, Error> {
+        let reading: PowerReading = self.client.request(self.addr, "getPower", json! {{}})
+            .await?
+            .result;
+        Ok(reading.power as f32 / 1000.0)
+    }
+
+    /// Returns the last observed state of the light, this is kept up to date by the state pushed
+    /// by the light but may be briefly outdated if the light was changed from another source
     pub async fn last_state(&self) -> State {
-        *self.state.lock().await
+        *self.state.borrow()
     }
 
     /// retrieve the current state from the light
     pub async fn get_state(&self) -> Result<State, Error> {
-        let state = udp_request(self.addr, json! {{"method": "getPilot", "params": {}}})
+        let state = self.client.request(self.addr, "getPilot", json! {{}})
             .await?
             .result;
-        *self.state.lock().await = state;
+        self.state.send_replace(state);
         Ok(state)
     }
 }
 
+async fn system_config(client: &Arc<Client>, addr: Ipv4Addr) -> Result<SystemConfig, Error> {
+    let system = client.request(addr, "getSystemConfig", json! {{}})
+        .await?
+        .result;
+    // older firmware does not support this
+    let model = client.request(addr, "getModelConfig", json! {{}})
+        .await
+        .ok()
+        .map(|response: Response<RawModelConfig>| response.result);
+    Ok(SystemConfig::new(system, model))
+}
+
 /// The state of the light
 #[derive(Debug, Clone, Copy, Deserialize)]
 pub struct State {
@@ -106,14 +313,56 @@ pub struct State {
     pub rssi: i8,
     /// true if the light is on
     pub state: bool,
-    /// The colour temperature of the light
-    #[serde(deserialize_with = "deserialize_temp")]
+    /// The colour temperature of the light, this is absent while the light is showing a colour
+    #[serde(default, deserialize_with = "deserialize_temp")]
     pub temp: Option<RangedU16<1000, 12000>>,
+    /// The colour of the light, this is absent while the light is white or showing a scene,
+    /// when set this takes precedence over [temp](Self::temp)
+    #[serde(flatten)]
+    pub color: Option<Color>,
     /// The brightness of the light as a percentage
     #[serde(rename = "dimming")]
     pub brightness: RangedU8<0, 100>,
 }
 
+/// A colour made up of the individual channels of the bulb
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct Color {
+    /// The red channel
+    pub r: u8,
+    /// The green channel
+    pub g: u8,
+    /// The blue channel
+    pub b: u8,
+    /// The cool white channel
+    #[serde(default)]
+    pub c: u8,
+    /// The warm white channel
+    #[serde(default)]
+    pub w: u8,
+}
+
+impl Color {
+    /// Create a colour from red, green and blue without any white
+    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, c: 0, w: 0 }
+    }
+
+    fn channel(&mut self, channel: &str) -> Option<&mut u8> {
+        match channel {
+            "red" => Some(&mut self.r),
+            "green" => Some(&mut self.g),
+            "blue" => Some(&mut self.b),
+            "cool_white" => Some(&mut self.c),
+            "warm_white" => Some(&mut self.w),
+            _ => None,
+        }
+    }
+}
+
+/// The names of the reflected colour channel fields
+const CHANNELS: [&str; 5] = ["red", "green", "blue", "cool_white", "warm_white"];
+
 fn deserialize_temp<'de, D: Deserializer<'de>>(
     deserializer: D,
 ) -> Result<Option<RangedU16<1000, 12000>>, D::Error> {
@@ -143,16 +392,175 @@ pub struct Success {
     success: bool,
 }
 
+/// The response to `getPower`
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct PowerReading {
+    /// The consumption in milliwatts
+    power: u32,
+}
+
+/// How often the power consumption is read while subscribed, bulbs do not push it
+const POWER_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The power consumption of a [Light] in watts
+#[derive(Debug, Clone, Copy)]
+pub struct Consumption<'a>(&'a Light);
+
+impl Sensor for Consumption<'_> {
+    type Item = f32;
+
+    fn subscribe(&self) -> BoxStream<'_, f32> {
+        let light = self.0;
+        Box::pin(stream::unfold(interval(POWER_POLL_INTERVAL), move |mut ticks| async move {
+            loop {
+                ticks.tick().await;
+                match light.power().await {
+                    Ok(power) => return Some((power, ticks)),
+                    Err(error) => debug!("failed to read power consumption of {}: {error}", light.info.name),
+                }
+            }
+        }))
+    }
+}
+
+impl ReadValue for Consumption<'_> {
+    type Item = f32;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<f32>> {
+        Box::pin(async { Ok(self.0.power().await?) })
+    }
+}
+
+/// The state pushed by a [Light] whenever it changes
+#[derive(Debug, Clone, Copy)]
+pub struct Updates<'a>(&'a Light);
+
+impl Sensor for Updates<'_> {
+    type Item = State;
+
+    fn subscribe(&self) -> BoxStream<'_, State> {
+        Box::pin(WatchStream::from_changes(self.0.state.subscribe()))
+    }
+}
+
+/// The on/off state of a [Light]
+#[derive(Debug, Clone, Copy)]
+pub struct Power<'a>(&'a Light);
+
+/// The brightness of a [Light] as a percentage
+#[derive(Debug, Clone, Copy)]
+pub struct Brightness<'a>(&'a Light);
+
+/// The colour temperature of a [Light]
+#[derive(Debug, Clone, Copy)]
+pub struct Temperature<'a>(&'a Light);
+
+impl Sensor for Power<'_> {
+    type Item = bool;
+
+    fn subscribe(&self) -> BoxStream<'_, bool> {
+        Box::pin(self.0.states().map(|state| state.state))
+    }
+}
+
+impl ReadValue for Power<'_> {
+    type Item = bool;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<bool>> {
+        Box::pin(async { Ok(self.0.get_state().await?.state) })
+    }
+}
+
+impl WriteValue for Power<'_> {
+    type Item = bool;
+
+    fn set(&self, value: bool) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move { Ok(self.0.update_state(|state| state.state = value).await?) })
+    }
+}
+
+impl ToggleValue for Power<'_> {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(self.0.toggle().await?) })
+    }
+}
+
+impl Sensor for Brightness<'_> {
+    type Item = RangedU8<0, 100>;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(self.0.states().map(|state| state.brightness))
+    }
+}
+
+impl ReadValue for Brightness<'_> {
+    type Item = RangedU8<0, 100>;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        Box::pin(async { Ok(self.0.get_state().await?.brightness) })
+    }
+}
+
+impl WriteValue for Brightness<'_> {
+    type Item = RangedU8<0, 100>;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move { Ok(self.0.update_state(|state| state.brightness = value).await?) })
+    }
+}
+
+impl Sensor for Temperature<'_> {
+    type Item = Option<RangedU16<1000, 12000>>;
+
+    fn subscribe(&self) -> BoxStream<'_, Self::Item> {
+        Box::pin(self.0.states().map(|state| state.temp))
+    }
+}
+
+impl ReadValue for Temperature<'_> {
+    type Item = Option<RangedU16<1000, 12000>>;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<Self::Item>> {
+        Box::pin(async { Ok(self.0.get_state().await?.temp) })
+    }
+}
+
+impl WriteValue for Temperature<'_> {
+    type Item = RangedU16<1000, 12000>;
+
+    fn set(&self, value: Self::Item) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move { Ok(self.0.set_temp(value).await?) })
+    }
+}
+
+impl ColorLight for Light {
+    fn is_on(&self) -> BoxFuture<'_, anyhow::Result<bool>> {
+        self.state().get()
+    }
+
+    fn set_white(&self, kelvin: u16, brightness: Percentage) -> BoxFuture<'_, anyhow::Result<()>> {
+        // the bulbs only support a narrower range, they clamp the temperature themselves
+        let temp = RangedU16::new(kelvin.clamp(1000, 12000));
+        Box::pin(async move {
+            Ok(self.update_state(|state| {
+                state.temp = Some(temp);
+                state.color = None;
+                state.brightness = brightness;
+            }).await?)
+        })
+    }
+}
+
 impl Device for Light {
     type Args = Ipv4Addr;
-    type Manager = ();
+    type Manager = Manager;
 
     fn info(&self) -> &DeviceInfo {
         &self.info
     }
 
-    async fn new_with_args(_: &mut Self::Manager, info: DeviceInfo, ip: Ipv4Addr) -> Result<Self, anyhow::Error> {
-        Self::verify_new(info, ip).await
+    async fn new_with_args(manager: &mut Self::Manager, info: DeviceInfo, ip: Ipv4Addr) -> Result<Self, anyhow::Error> {
+        Self::verify_new(manager, info, ip).await
     }
 }
 
@@ -166,7 +574,7 @@ impl Light {
     #[builder]
     #[allow(unused_variables, reason = "Cannot rename due to compatability issues")]
     pub async fn create(
-        manager: &mut (),
+        manager: &mut Manager,
         info: DeviceInfo,
         ip: Ipv4Addr,
     ) -> Result<Self, anyhow::Error> {
@@ -180,13 +588,13 @@ impl reflect::Device for Light {
     }
 
     fn fields(&self) -> Vec<Field> {
-        vec![
+        [
             Field {
                 name: "state".to_owned(),
                 description: "is true if the light is on".to_string(),
                 value_type: ValueType::from_type::<bool>(),
                 operations: Operations {
-                    subscribe: false,
+                    subscribe: true,
                     get: true,
                     set: true,
                     toggle: true,
@@ -197,10 +605,10 @@ impl reflect::Device for Light {
                 description: "The light's colour temperature".to_string(),
                 value_type: ValueType::from_type::<Option<RangedU16<1000, 12000>>>(),
                 operations: Operations {
-                    subscribe: false,
+                    subscribe: true,
                     get: true,
                     set: true,
-                    toggle: true,
+                    toggle: false,
                 }
             },
             Field {
@@ -208,27 +616,68 @@ impl reflect::Device for Light {
                 description: "The light's brightness".to_string(),
                 value_type: ValueType::from_type::<RangedU8<0, 100>>(),
                 operations: Operations {
-                    subscribe: false,
+                    subscribe: true,
                     get: true,
                     set: true,
-                    toggle: true,
+                    toggle: false,
+                }
+            },
+            Field {
+                name: "online".to_owned(),
+                description: "is false while the light is not answering requests".to_string(),
+                value_type: ValueType::from_type::<bool>(),
+                operations: Operations {
+                    subscribe: true,
+                    get: true,
+                    set: false,
+                    toggle: false,
+                }
+            },
+            Field {
+                name: "power".to_owned(),
+                description: "The light's power consumption in watts, only some bulbs report this".to_string(),
+                value_type: ValueType::from_type::<f64>(),
+                operations: Operations {
+                    subscribe: false,
+                    get: true,
+                    set: false,
+                    toggle: false,
                 }
             },
         ]
+        .into_iter()
+        .chain(CHANNELS.map(|channel| Field {
+            name: channel.to_owned(),
+            description: format!("The {} channel of the light's colour, absent while the light is white", channel.replace('_', " ")),
+            value_type: ValueType::from_type::<Option<u8>>(),
+            operations: Operations {
+                subscribe: false,
+                get: true,
+                set: true,
+                toggle: false,
+            }
+        }))
+        .collect()
     }
 
     fn subscribe(&self, field: &str) -> Result<BoxFuture<'_, BoxStream<'_, Value>>, reflect::Error> {
-        Err(match field {
-            "state" | "temp" | "brightness" => reflect::Error::OperationNotSupported {
+        // served from the last observed state, which the `syncPilot` messages pushed by the light update
+        let stream: BoxStream<_> = match field {
+            "state" => Box::pin(self.states().map(|state| Value::from(state.state))),
+            "temp" => Box::pin(self.states().map(|state| Value::from(state.temp))),
+            "brightness" => Box::pin(self.states().map(|state| Value::from(state.brightness))),
+            "online" => Box::pin(self.online_changes().map(Value::from)),
+            "power" | "red" | "green" | "blue" | "cool_white" | "warm_white" => return Err(reflect::Error::OperationNotSupported {
                 device: self.info.name.to_string(),
                 field: field.to_string(),
                 operation: Operation::Subscribe,
-            },
-            unknown => reflect::Error::FieldNotFound {
+            }),
+            unknown => return Err(reflect::Error::FieldNotFound {
                 device: self.info.name.to_string(),
                 field: unknown.to_string(),
-            },
-        })
+            }),
+        };
+        Ok(Box::pin(ready(stream)))
     }
 
     fn get(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<Value>>, reflect::Error> {
@@ -254,6 +703,24 @@ impl reflect::Device for Light {
                             .context("failed to fetch state from light")
                     }),
             )),
+            "online" => Ok(Box::pin(ready(Ok(self.online().into())))),
+            "power" => Ok(Box::pin(
+                self.power()
+                    .map(|result| {
+                        result.map(|power| f64::from(power).into())
+                            .context("failed to fetch power from light")
+                    }),
+            )),
+            channel if CHANNELS.contains(&channel) => {
+                let channel = channel.to_owned();
+                Ok(Box::pin(
+                    self.get_state()
+                        .map(move |result| {
+                            result.map(|state| state.color.and_then(|mut color| color.channel(&channel).copied()).into())
+                                .context("failed to fetch state from light")
+                        }),
+                ))
+            },
             unknown => Err(reflect::Error::FieldNotFound {
                 device: self.info.name.to_string(),
                 field: unknown.to_string(),
@@ -277,8 +744,13 @@ impl reflect::Device for Light {
             "temp" => {
                 let value = value.try_into()?;
                 Ok(Box::pin(
-                    self.update_state(move |state| state.temp = value)
-                        .map(|result| result.context("failed to update temp")),
+                    self.update_state(move |state| {
+                        state.temp = value;
+                        if value.is_some() {
+                            state.color = None;
+                        }
+                    })
+                    .map(|result| result.context("failed to update temp")),
                 ))
             },
             "brightness" => {
@@ -288,6 +760,26 @@ impl reflect::Device for Light {
                         .map(|result| result.context("failed to update brightness")),
                 ))
             },
+            "online" | "power" => Err(reflect::Error::OperationNotSupported {
+                device: self.info.name.to_string(),
+                field: field.to_string(),
+                operation: Operation::Set,
+            }.into()),
+            channel if CHANNELS.contains(&channel) => {
+                let value: Option<u8> = value.try_into()?;
+                let channel = channel.to_owned();
+                Ok(Box::pin(
+                    self.update_state(move |state| {
+                        let mut color = state.color.unwrap_or_default();
+                        if let Some(current) = color.channel(&channel) {
+                            *current = value.unwrap_or_default();
+                        }
+                        state.color = Some(color);
+                        state.temp = None;
+                    })
+                    .map(|result| result.context("failed to update colour")),
+                ))
+            },
             unknown => Err(reflect::Error::FieldNotFound {
                 device: self.info.name.to_string(),
                 field: unknown.to_string(),
@@ -303,7 +795,7 @@ impl reflect::Device for Light {
                         .map(|result| result.context("failed to update state")),
                 ))
             },
-            "temp" | "brightness" => {
+            "temp" | "brightness" | "online" | "power" | "red" | "green" | "blue" | "cool_white" | "warm_white" => {
                 Err(reflect::Error::OperationNotSupported {
                     device: self.info.name.to_string(),
                     field: field.to_string(),
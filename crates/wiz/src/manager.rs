@@ -0,0 +1,375 @@
+//! The [Manager] owns the single UDP socket used to talk to every bulb, responses are routed back
+//! to the waiting request by the address of the bulb and the `syncPilot` messages pushed by the
+//! bulbs are routed to the matching [Light](crate::Light)
+
+use crate::light::{State, Success};
+use crate::{Error, Response};
+use bon::bon;
+use control::device_manager::{DeviceManager, Supervisor};
+use control::dry_run::DryRun;
+use control::util::lock;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::spawn;
+use tokio::sync::OnceCell;
+use tokio::sync::oneshot;
+use tokio::sync::watch::{Receiver, Sender};
+use tokio::time::{interval, timeout};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, trace};
+
+/// The port bulbs listen for requests on
+const BULB_PORT: u16 = 38899;
+/// The port the socket is bound to, bulbs reply to the port a request was sent from and push
+/// `syncPilot` messages to this port
+const LISTEN_PORT: u16 = 38900;
+/// Bulbs forget listeners which do not renew their registration
+const REGISTRATION_INTERVAL: Duration = Duration::from_secs(20);
+/// Bulbs require a MAC address when registering but do not use it
+const LISTENER_MAC: &str = "AAAAAAAAAAAA";
+
+/// The default time to wait for a response before resending a request
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+/// The default number of times a request is resent before giving up
+const DEFAULT_RETRIES: u32 = 2;
+
+/// A manager of Wiz devices, all devices share a single socket
+pub struct Manager {
+    client: Arc<Client>,
+    dry_run: DryRun,
+}
+
+#[bon]
+impl Manager {
+    /// Create a new manager
+    #[builder]
+    pub fn new(
+        /// The time to wait for a response from a bulb before resending the request, UDP
+        /// datagrams may be dropped without notice
+        #[builder(default = DEFAULT_TIMEOUT)]
+        timeout: Duration,
+        /// The number of times a request is resent before giving up
+        #[builder(default = DEFAULT_RETRIES)]
+        retries: u32,
+    ) -> Self {
+        Self {
+            client: Arc::new(Client {
+                socket: OnceCell::new(),
+                pending: Mutex::default(),
+                lights: Mutex::default(),
+                reachable: Mutex::default(),
+                token: CancellationToken::new(),
+                next_id: AtomicU64::new(0),
+                timeout,
+                retries,
+            }),
+            dry_run: DryRun::default(),
+        }
+    }
+}
+
+impl Manager {
+    pub(crate) fn client(&self) -> Arc<Client> {
+        self.client.clone()
+    }
+
+    pub(crate) fn dry_run(&self) -> DryRun {
+        self.dry_run
+    }
+}
+
+impl DeviceManager for Manager {
+    fn start(self: Box<Self>, token: CancellationToken) {
+        self.supervise(&Supervisor::builder().token(token).build());
+    }
+
+    fn supervise(self: Box<Self>, supervisor: &Supervisor) {
+        let token = supervisor.token();
+        let client = self.client;
+        // the receive loop is stopped along with the manager, rather than by the renewal task, which
+        // may be restarted
+        spawn({
+            let client = client.clone();
+            let token = token.clone();
+            async move {
+                token.cancelled().await;
+                client.token.cancel();
+            }
+        });
+        supervisor.spawn("wiz", move || {
+            let (client, token) = (client.clone(), token.clone());
+            async move {
+                client.renew_registrations(token).await;
+                Ok(())
+            }
+        });
+    }
+
+    fn set_dry_run(&mut self, dry_run: DryRun) {
+        self.dry_run = dry_run;
+    }
+}
+
+/// A request waiting for a response from a bulb
+#[derive(Debug)]
+struct Pending {
+    id: u64,
+    method: String,
+    response: oneshot::Sender<Vec<u8>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    method: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Push {
+    params: State,
+}
+
+/// The shared state of a [Manager], this is held by each light
+#[derive(Debug)]
+pub(crate) struct Client {
+    /// the socket is opened on first use since devices are created before the manager is started
+    socket: OnceCell<Arc<UdpSocket>>,
+    pending: Mutex<HashMap<Ipv4Addr, VecDeque<Pending>>>,
+    lights: Mutex<HashMap<Ipv4Addr, Sender<State>>>,
+    /// whether each bulb answered its last request
+    reachable: Mutex<HashMap<Ipv4Addr, Sender<bool>>>,
+    /// cancelled when the manager is stopped, this stops the receive loop
+    token: CancellationToken,
+    /// identifies each pending request so an abandoned request can be removed
+    next_id: AtomicU64,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl Client {
+    /// Send a request to the bulb and wait for its response, the request is resent if no response
+    /// arrives within the timeout, once the manager has stopped this fails with [Error::Stopped]
+    pub(crate) async fn request<Data>(
+        self: &Arc<Self>,
+        addr: Ipv4Addr,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<Response<Data>, Error>
+    where
+        for<'de> Data: Deserialize<'de>,
+    {
+        let socket = self.socket().await?;
+        let msg = json! {{"method": method, "params": params}};
+        debug!("sending request to {addr}: {msg}");
+        let msg = serde_json::to_vec(&msg).map_err(Error::JsonSerialize)?;
+
+        for attempt in 0..=self.retries {
+            // nothing receives responses once the manager has stopped
+            if self.token.is_cancelled() {
+                return Err(Error::Stopped);
+            }
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let (sender, receiver) = oneshot::channel();
+            lock(&self.pending)
+                .entry(addr)
+                .or_default()
+                .push_back(Pending {
+                    id,
+                    method: method.to_string(),
+                    response: sender,
+                });
+            socket
+                .send_to(&msg, (addr, BULB_PORT))
+                .await
+                .map_err(|e| Error::socket("send", e))?;
+
+            let response = tokio::select! {
+                _ = self.token.cancelled() => return Err(Error::Stopped),
+                response = timeout(self.timeout, receiver) => response,
+            };
+            match response {
+                Ok(Ok(response)) => {
+                    self.set_reachable(addr, true);
+                    return serde_json::from_slice(&response).map_err(Error::JsonDeserialize);
+                }
+                Ok(Err(_)) => return Err(Error::Stopped),
+                Err(_) => {
+                    debug!("no response to {method} from {addr} (attempt {})", attempt + 1);
+                    if let Some(queue) = lock(&self.pending).get_mut(&addr) {
+                        queue.retain(|request| request.id != id);
+                    }
+                }
+            }
+        }
+        self.set_reachable(addr, false);
+        Err(Error::Timeout {
+            addr,
+            attempts: self.retries + 1,
+        })
+    }
+
+    /// Returns whether the bulb at the given address answered its last request, bulbs are
+    /// assumed to be reachable until a request times out
+    pub(crate) fn reachability(&self, addr: Ipv4Addr) -> Receiver<bool> {
+        lock(&self.reachable)
+            .entry(addr)
+            .or_insert_with(|| Sender::new(true))
+            .subscribe()
+    }
+
+    fn set_reachable(&self, addr: Ipv4Addr, reachable: bool) {
+        if let Some(sender) = lock(&self.reachable).get(&addr)
+            && sender.send_if_modified(|current| std::mem::replace(current, reachable) != reachable)
+        {
+            if reachable {
+                debug!("{addr} is reachable again");
+            } else {
+                debug!("{addr} is unreachable");
+            }
+        }
+    }
+
+    /// Route the state pushed by the bulb at the given address to the given sender and ask the
+    /// bulb to start pushing
+    pub(crate) async fn watch(self: &Arc<Self>, addr: Ipv4Addr, sender: Sender<State>) {
+        lock(&self.lights).insert(addr, sender);
+        if let Err(error) = self.register(addr).await {
+            error!("failed to register for updates from {addr}: {error}");
+        }
+    }
+
+    async fn socket(self: &Arc<Self>) -> Result<&Arc<UdpSocket>, Error> {
+        self.socket
+            .get_or_try_init(|| async {
+                let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, LISTEN_PORT))
+                    .await
+                    .map_err(|e| Error::socket("bind", e))?;
+                let socket = Arc::new(socket);
+                spawn(self.clone().receive(socket.clone()));
+                Ok(socket)
+            })
+            .await
+    }
+
+    /// Route each message received to the request waiting for it, or to the light which pushed
+    /// it, until the manager is stopped
+    async fn receive(self: Arc<Self>, socket: Arc<UdpSocket>) {
+        let mut buf = [0; 4096];
+        loop {
+            let (len, source) = tokio::select! {
+                _ = self.token.cancelled() => break,
+                result = socket.recv_from(&mut buf) => match result {
+                    Ok(received) => received,
+                    Err(error) => {
+                        error!("failed to receive wiz message: {error}");
+                        continue;
+                    }
+                },
+            };
+            let SocketAddr::V4(source) = source else {
+                continue;
+            };
+            let message = &buf[..len];
+            debug!("received message from {source}: {}", String::from_utf8_lossy(message));
+            let header: Header = match serde_json::from_slice(message) {
+                Ok(header) => header,
+                Err(error) => {
+                    trace!("ignoring invalid message from {source}: {error}");
+                    continue;
+                }
+            };
+            if header.method == "syncPilot" {
+                self.pushed(&socket, source.into(), message).await;
+            } else {
+                self.respond(*source.ip(), &header.method, message);
+            }
+        }
+        // wake any requests still waiting
+        lock(&self.pending).clear();
+    }
+
+    async fn pushed(&self, socket: &UdpSocket, source: SocketAddr, message: &[u8]) {
+        let SocketAddr::V4(addr) = source else {
+            return;
+        };
+        match serde_json::from_slice::<Push>(message) {
+            Ok(push) => {
+                self.set_reachable(*addr.ip(), true);
+                if let Some(sender) = lock(&self.lights).get(addr.ip()) {
+                    sender.send_replace(push.params);
+                }
+            }
+            Err(error) => trace!("ignoring invalid sync message from {source}: {error}"),
+        }
+        // bulbs expect each push to be acknowledged
+        let ack = json! {{"method": "syncPilot", "result": {"mac": LISTENER_MAC}}};
+        if let Err(error) = socket.send_to(ack.to_string().as_bytes(), source).await {
+            debug!("failed to acknowledge sync message from {source}: {error}");
+        }
+    }
+
+    /// Pass the response to the oldest request waiting for it
+    fn respond(&self, addr: Ipv4Addr, method: &str, message: &[u8]) {
+        let mut pending = lock(&self.pending);
+        let Some(queue) = pending.get_mut(&addr) else {
+            trace!("ignoring unexpected {method} response from {addr}");
+            return;
+        };
+        let Some(index) = queue.iter().position(|request| request.method == method) else {
+            trace!("ignoring unexpected {method} response from {addr}");
+            return;
+        };
+        if let Some(request) = queue.remove(index) {
+            // the request may have been abandoned
+            let _ = request.response.send(message.to_vec());
+        }
+    }
+
+    async fn renew_registrations(self: Arc<Self>, token: CancellationToken) {
+        let mut renew = interval(REGISTRATION_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = renew.tick() => {
+                    let addrs: Vec<Ipv4Addr> = lock(&self.lights).keys().copied().collect();
+                    for addr in addrs {
+                        if let Err(error) = self.register(addr).await {
+                            debug!("failed to renew registration with {addr}: {error}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ask the bulb to push its state to this machine
+    async fn register(self: &Arc<Self>, addr: Ipv4Addr) -> Result<(), Error> {
+        let local = local_ip(addr).await?;
+        let params = json! {{
+            "phoneIp": local.to_string(),
+            "phoneMac": LISTENER_MAC,
+            "register": true,
+        }};
+        let _: Response<Success> = self.request(addr, "registration", params).await?;
+        Ok(())
+    }
+}
+
+/// Returns the address of this machine on the route to the bulb
+async fn local_ip(addr: Ipv4Addr) -> Result<IpAddr, Error> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| Error::socket("bind", e))?;
+    // connecting a UDP socket sends nothing, it only selects the route
+    socket
+        .connect((addr, BULB_PORT))
+        .await
+        .map_err(|e| Error::socket("connect", e))?;
+    let local = socket.local_addr().map_err(|e| Error::socket("local address", e))?;
+    Ok(local.ip())
+}
@@ -0,0 +1,158 @@
+//! Rooms of Wiz lights
+
+use crate::light::{Color, State};
+use crate::{Error, Light};
+use control::{ReadValue, ToggleValue, WriteValue};
+use futures::future::{join_all, BoxFuture};
+use light_ranged_integers::{RangedU16, RangedU8};
+use std::future::Future;
+
+/// A set of lights which are controlled together, like a room in the Wiz app. Each command is
+/// sent to every light at once, a command succeeds only if it succeeds for every light
+#[derive(Debug, Clone)]
+pub struct Room<'a> {
+    lights: Vec<&'a Light>,
+}
+
+/// The combined state of the lights in a [Room]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomState {
+    /// The number of lights which are on
+    pub on: usize,
+    /// The number of lights in the room
+    pub total: usize,
+    /// The average brightness of the lights which are on, `None` if every light is off
+    pub brightness: Option<RangedU8<0, 100>>,
+}
+
+impl RoomState {
+    /// Returns true if any light in the room is on
+    pub fn any_on(&self) -> bool {
+        self.on > 0
+    }
+
+    /// Returns true if every light in the room is on
+    pub fn all_on(&self) -> bool {
+        self.on == self.total
+    }
+
+    fn from_states(states: &[State]) -> Self {
+        let on: Vec<u32> = states
+            .iter()
+            .filter(|state| state.state)
+            .map(|state| u32::from(state.brightness.inner()))
+            .collect();
+        let brightness = (!on.is_empty())
+            .then(|| on.iter().sum::<u32>() / on.len() as u32)
+            .and_then(|average| u8::try_from(average).ok())
+            .and_then(RangedU8::new_try);
+        Self {
+            on: on.len(),
+            total: states.len(),
+            brightness,
+        }
+    }
+}
+
+impl<'a> Room<'a> {
+    /// Create a new room of the given lights
+    pub fn new(lights: impl IntoIterator<Item = &'a Light>) -> Self {
+        Self {
+            lights: lights.into_iter().collect(),
+        }
+    }
+
+    /// Returns the lights in this room
+    pub fn lights(&self) -> &[&'a Light] {
+        &self.lights
+    }
+
+    /// Update the state of every light in the room, see [Light::update_state]
+    pub async fn update_state(&self, f: impl Fn(&mut State) + Clone) -> Result<(), Error> {
+        self.each(|light| light.update_state(f.clone())).await
+    }
+
+    /// turn on every light
+    pub async fn turn_on(&self) -> Result<(), Error> {
+        self.each(Light::turn_on).await
+    }
+
+    /// turn off every light
+    pub async fn turn_off(&self) -> Result<(), Error> {
+        self.each(Light::turn_off).await
+    }
+
+    /// Turn every light off if any light is on, otherwise turn every light on, this is based on
+    /// the last observed state of each light
+    pub async fn toggle(&self) -> Result<(), Error> {
+        let states: Vec<State> = join_all(self.lights.iter().map(|light| light.last_state())).await;
+        if RoomState::from_states(&states).any_on() {
+            self.turn_off().await
+        } else {
+            self.turn_on().await
+        }
+    }
+
+    /// set the brightness of every light without turning any on or off
+    pub async fn set_brightness(&self, brightness: RangedU8<0, 100>) -> Result<(), Error> {
+        self.update_state(move |state| state.brightness = brightness).await
+    }
+
+    /// set the colour of every light
+    pub async fn set_color(&self, color: Color) -> Result<(), Error> {
+        self.each(|light| light.set_color(color)).await
+    }
+
+    /// set the colour temperature of every light
+    pub async fn set_temp(&self, temp: RangedU16<1000, 12000>) -> Result<(), Error> {
+        self.each(|light| light.set_temp(temp)).await
+    }
+
+    /// retrieve the current state from every light and combine them
+    pub async fn get_state(&self) -> Result<RoomState, Error> {
+        let states = join_all(self.lights.iter().map(|light| light.get_state()))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(RoomState::from_states(&states))
+    }
+
+    async fn each<'l, F, Fut>(&'l self, f: F) -> Result<(), Error>
+    where
+        F: Fn(&'l Light) -> Fut,
+        Fut: Future<Output = Result<(), Error>> + 'l,
+    {
+        join_all(self.lights.iter().map(|light| f(*light)))
+            .await
+            .into_iter()
+            .collect()
+    }
+}
+
+impl ReadValue for Room<'_> {
+    type Item = bool;
+
+    fn get(&self) -> BoxFuture<'_, anyhow::Result<bool>> {
+        Box::pin(async { Ok(self.get_state().await?.any_on()) })
+    }
+}
+
+impl WriteValue for Room<'_> {
+    type Item = bool;
+
+    fn set(&self, value: bool) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            if value {
+                Ok(self.turn_on().await?)
+            } else {
+                Ok(self.turn_off().await?)
+            }
+        })
+    }
+}
+
+impl ToggleValue for Room<'_> {
+    fn toggle(&self) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async { Ok(Room::toggle(self).await?) })
+    }
+}
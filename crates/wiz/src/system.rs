@@ -0,0 +1,98 @@
+//! Information about the hardware and firmware of Wiz devices
+
+use serde::Deserialize;
+use std::ops::RangeInclusive;
+
+/// The response to `getSystemConfig`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RawSystemConfig {
+    mac: String,
+    module_name: String,
+    fw_version: String,
+}
+
+/// The response to `getModelConfig`, only newer firmware supports this
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RawModelConfig {
+    #[serde(default)]
+    cct_range: Vec<u16>,
+}
+
+/// Information about a device, useful for keeping an inventory of devices
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemConfig {
+    /// The MAC address of the device, eg: `a8bb50a1b2c3`
+    pub mac: String,
+    /// The firmware version, eg: `1.25.0`
+    pub firmware_version: String,
+    /// The name of the module in the device, eg: `ESP01_SHRGB1C_31`, this identifies which
+    /// features the device supports, see [kind](Self::kind)
+    pub module: String,
+    /// The range of colour temperatures supported, in kelvin, only reported by newer firmware
+    pub kelvin_range: Option<RangeInclusive<u16>>,
+}
+
+impl SystemConfig {
+    pub(crate) fn new(system: RawSystemConfig, model: Option<RawModelConfig>) -> Self {
+        let kelvin_range = model.and_then(|model| {
+            let min = model.cct_range.iter().min()?;
+            let max = model.cct_range.iter().max()?;
+            Some(*min..=*max)
+        });
+        Self {
+            mac: system.mac,
+            firmware_version: system.fw_version,
+            module: system.module_name,
+            kelvin_range,
+        }
+    }
+
+    /// Returns the kind of light, based on the module name
+    pub fn kind(&self) -> Kind {
+        Kind::from_module(&self.module)
+    }
+}
+
+/// The kinds of Wiz light, each supports a different set of features
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A light supporting colours as well as tunable white
+    Rgb,
+    /// A light supporting a range of colour temperatures but not colours
+    TunableWhite,
+    /// A light supporting only brightness
+    DimmableWhite,
+    /// The module name was not recognised, all features are assumed to be supported
+    Unknown,
+}
+
+impl Kind {
+    /// Returns the kind of light from its module name, eg: `ESP01_SHRGB1C_31`
+    pub fn from_module(module: &str) -> Self {
+        // the second part of the name describes the light, eg: SHRGB, SHTW, SHDW
+        let Some(light) = module.split('_').nth(1) else {
+            return Self::Unknown;
+        };
+        if light.contains("RGB") {
+            Self::Rgb
+        } else if light.contains("TW") {
+            Self::TunableWhite
+        } else if light.contains("DW") {
+            Self::DimmableWhite
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Returns true if the light can show colours
+    pub fn supports_color(self) -> bool {
+        matches!(self, Self::Rgb | Self::Unknown)
+    }
+
+    /// Returns true if the colour temperature of the light can be changed
+    pub fn supports_temp(self) -> bool {
+        !matches!(self, Self::DimmableWhite)
+    }
+}
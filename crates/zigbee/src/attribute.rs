@@ -7,7 +7,7 @@ use crate::Sensor;
 use crate::ToggleValue;
 use crate::WriteValue;
 use crate::get_request;
-use crate::publish::Publish;
+use crate::publish::{Publish, Publisher};
 use crate::{ReadValue, Updates};
 use anyhow::Result;
 use anyhow::{Context, Error};
@@ -18,8 +18,8 @@ use futures::stream::BoxStream;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::convert::identity;
-use tokio::sync::mpsc::Sender;
 use tokio_stream::StreamExt;
+use tracing::{Instrument, Span, debug, info_span};
 
 #[derive(Clone)]
 pub struct SubscribeAttr<Update, Item> {
@@ -51,7 +51,7 @@ where
 pub struct PublishAttr<Item, Zigbee> {
     attribute_name: &'static str,
     func: fn(Item) -> Zigbee,
-    publisher: Sender<Publish>,
+    publisher: Publisher,
     device_name: String,
 }
 
@@ -60,7 +60,7 @@ where
     for<'de> Item: Deserialize<'de>,
 {
     pub fn new(
-        publisher: Sender<Publish>,
+        publisher: Publisher,
         device_name: String,
         attribute_name: &'static str,
     ) -> Self {
@@ -78,7 +78,7 @@ where
     for<'de> Zigbee: Deserialize<'de>,
 {
     pub fn new_mapped(
-        publisher: Sender<Publish>,
+        publisher: Publisher,
         device_name: String,
         attribute_name: &'static str,
         func: fn(Item) -> Zigbee,
@@ -92,6 +92,13 @@ where
     }
 }
 
+impl<Item, Zigbee> PublishAttr<Item, Zigbee> {
+    /// The span which logs about this attribute are recorded in
+    fn span(&self) -> Span {
+        attribute_span(&self.device_name, self.attribute_name)
+    }
+}
+
 impl<Item, Zigbee> WriteValue for PublishAttr<Item, Zigbee>
 where
     Zigbee: Serialize,
@@ -100,15 +107,22 @@ where
 
     fn set(&self, value: Self::Item) -> BoxFuture<'_, Result<()>> {
         let key = self.attribute_name;
-        let value = (self.func)(value);
-        let publish = Publish::new(format!("{}/set", self.device_name), json!({key: value}));
+        let value = json!((self.func)(value));
 
-        Box::pin(async move {
-            self.publisher
-                .send(publish.context("serialize JSON")?)
-                .await
-                .context("publish set request")
-        })
+        Box::pin(
+            async move {
+                if self.publisher.intercept(&self.device_name, key, format_args!("{value}")) {
+                    return Ok(());
+                }
+                debug!("setting value");
+                let publish = Publish::new(format!("{}/set", self.device_name), json!({key: value}));
+                self.publisher
+                    .send(publish.context("serialize JSON")?)
+                    .await
+                    .context("publish set request")
+            }
+            .instrument(self.span()),
+        )
     }
 }
 
@@ -117,15 +131,22 @@ where
     Item: Serialize,
 {
     fn toggle(&self) -> BoxFuture<'_, Result<()>> {
-        Box::pin(async {
-            let key = self.attribute_name;
-            let publish = Publish::new(format!("{}/set", self.device_name), json!({key: "TOGGLE"}))
-                .context("serialize JSON")?;
-            self.publisher
-                .send(publish)
-                .await
-                .context("publish toggle request")
-        })
+        Box::pin(
+            async {
+                let key = self.attribute_name;
+                if self.publisher.intercept(&self.device_name, key, "TOGGLE") {
+                    return Ok(());
+                }
+                debug!("toggling value");
+                let publish = Publish::new(format!("{}/set", self.device_name), json!({key: "TOGGLE"}))
+                    .context("serialize JSON")?;
+                self.publisher
+                    .send(publish)
+                    .await
+                    .context("publish toggle request")
+            }
+            .instrument(self.span()),
+        )
     }
 }
 
@@ -138,7 +159,7 @@ where
     from_device: fn(Update) -> Option<Item>,
     to_device: fn(Item) -> Zigbee,
     updates: Updates<Update>,
-    publisher: Sender<Publish>,
+    publisher: Publisher,
     device_name: String,
 }
 
@@ -148,7 +169,7 @@ where
 {
     pub fn new(
         updates: Updates<Update>,
-        publisher: Sender<Publish>,
+        publisher: Publisher,
         device_name: String,
         attribute_name: &'static str,
         from_device: fn(Update) -> Option<Item>,
@@ -171,7 +192,7 @@ where
 {
     pub fn new_mapped(
         updates: Updates<Update>,
-        publisher: Sender<Publish>,
+        publisher: Publisher,
         device_name: String,
         attribute_name: &'static str,
         from_device: fn(Update) -> Option<Item>,
@@ -188,6 +209,16 @@ where
     }
 }
 
+impl<Item, Update, Zigbee> SubscribePublishAttr<Item, Update, Zigbee>
+where
+    for<'de> Update: Deserialize<'de>,
+{
+    /// The span which logs about this attribute are recorded in
+    fn span(&self) -> Span {
+        attribute_span(&self.device_name, self.attribute_name)
+    }
+}
+
 impl<Item, Update, Zigbee> Sensor for SubscribePublishAttr<Item, Update, Zigbee>
 where
     for<'de> Update: Deserialize<'de>,
@@ -217,14 +248,17 @@ where
         ).context("serialize JSON");
         let publisher = &self.publisher;
         let request = async move {
+            debug!("requesting value");
             publisher
                 .send(publish?)
                 .await
-                .context("publish toggle request")
+                .context("publish get request")
         };
 
         Box::pin(
-            join(request, response).map(|(_, value)| value.ok_or(Error::new(InputStreamClosed))),
+            join(request, response)
+                .map(|(_, value)| value.ok_or(Error::new(InputStreamClosed)))
+                .instrument(self.span()),
         )
     }
 }
@@ -238,15 +272,23 @@ where
 
     fn set(&self, value: Self::Item) -> BoxFuture<'_, Result<()>> {
         let key = self.attribute_name;
-        let value = (self.to_device)(value);
-        let publish = Publish::new(format!("{}/set", self.device_name), json!({key: value}));
+        let value = json!((self.to_device)(value));
+        let device = &self.device_name;
         let publisher = &self.publisher;
-        Box::pin(async {
-            publisher
-                .send(publish.context("serialize JSON")?)
-                .await
-                .context("publish set request")
-        })
+        Box::pin(
+            async move {
+                if publisher.intercept(device, key, format_args!("{value}")) {
+                    return Ok(());
+                }
+                debug!("setting value");
+                let publish = Publish::new(format!("{device}/set"), json!({key: value}));
+                publisher
+                    .send(publish.context("serialize JSON")?)
+                    .await
+                    .context("publish set request")
+            }
+            .instrument(self.span()),
+        )
     }
 }
 
@@ -259,12 +301,26 @@ where
             format!("{}/set", self.device_name),
             json!({self.attribute_name: "TOGGLE"}),
         );
+        let (device, key) = (&self.device_name, self.attribute_name);
         let publisher = &self.publisher;
-        Box::pin(async move {
-            publisher
-                .send(publish.context("serialize JSON")?)
-                .await
-                .context("publish toggle request")
-        })
+        Box::pin(
+            async move {
+                if publisher.intercept(device, key, "TOGGLE") {
+                    return Ok(());
+                }
+                debug!("toggling value");
+                publisher
+                    .send(publish.context("serialize JSON")?)
+                    .await
+                    .context("publish toggle request")
+            }
+            .instrument(self.span()),
+        )
     }
 }
+
+/// A span identifying a single attribute of a device, so the logs of one device can be filtered,
+/// eg: `RUST_LOG=[attribute{device=hallway_light}]=debug`
+fn attribute_span(device: &str, attribute: &'static str) -> Span {
+    info_span!("attribute", device, attribute)
+}
@@ -7,6 +7,7 @@ zigbee_device! {
     ///
     /// This switch has both a rocker which can trigger anything and a physical switch
     /// intended to control a non-smart light, these can be coupled on the device
+    #[mapping_tests]
     pub SmartWallSwitchSingle {
         "https://www.zigbee2mqtt.io/devices/QBKG04LM.html",
         /// The state of the physical switch
@@ -57,6 +58,7 @@ zigbee_device! {
     /// Aqara Roller Shade Driver E1
     ///
     /// A motorised driver for roller based blinds/shades
+    #[mapping_tests]
     pub RollerShadeDriver {
         "https://www.zigbee2mqtt.io/devices/ZNJLBL01LM.html",
         /// The current state of the blinds
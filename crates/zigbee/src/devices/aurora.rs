@@ -4,6 +4,7 @@ use macros::zigbee_device;
 // https://www.zigbee2mqtt.io/devices/AU-A1ZBDSS.html
 zigbee_device! {
     /// A Double Type G (UK) wall socket
+    #[mapping_tests]
     pub DoubleWallSocketTypeG {
         "https://www.zigbee2mqtt.io/devices/AU-A1ZBDSS.html",
         /// The state of the left switch
@@ -21,24 +22,23 @@ zigbee_device! {
         /// The power consumption of the right socket
         stream "power_right" => u32,
         /// The LED brightness of the switches
-        get set "brightness" => led_brightness: u8<0, 254>
-    }
-}
-
-impl DoubleWallSocketTypeG {
-    /// The left socket
-    pub fn left(&self) -> impl WallSocket<'_> {
-        WallSocketTypeG {
-            state: self.state_left(),
-            power: self.power_left(),
-        }
-    }
+        get set "brightness" => led_brightness: u8<0, 254>,
+        impl {
+            /// The left socket
+            pub fn left(&self) -> impl WallSocket<'_> {
+                WallSocketTypeG {
+                    state: self.state_left(),
+                    power: self.power_left(),
+                }
+            }
 
-    /// The right socket
-    pub fn right(&self) -> impl WallSocket<'_> {
-        WallSocketTypeG {
-            state: self.state_right(),
-            power: self.power_right(),
+            /// The right socket
+            pub fn right(&self) -> impl WallSocket<'_> {
+                WallSocketTypeG {
+                    state: self.state_right(),
+                    power: self.power_right(),
+                }
+            }
         }
     }
 }
@@ -1,8 +1,11 @@
-use control::ButtonEvent;
+use control::{ButtonEvent, ColorLight, Percentage, ReadValue, WriteValue};
+use futures::future::BoxFuture;
+use light_ranged_integers::{RangedU8, RangedU16};
 use macros::zigbee_device;
 
 zigbee_device!{
     /// A Philips Hue Smart Button
+    #[mapping_tests]
     pub HueSmartButton {
         "https://www.zigbee2mqtt.io/devices/8718699693985.html",
         /// The button events detected by the button
@@ -23,6 +26,7 @@ zigbee_device!{
 
 zigbee_device!{
     /// Hue white A60 bulb B22 1055lm with Bluetooth
+    #[mapping_tests]
     pub Light {
         "https://www.zigbee2mqtt.io/devices/9290024693.html#philips-9290024693",
         /// The current state of the bulb, on or off
@@ -31,6 +35,58 @@ zigbee_device!{
             "OFF" => false,
         },
         /// The current brightness of the bulb, expressed as a u8
-        get set "brightness" => u8<0, 254>
+        get set "brightness" => u8<0, 254>,
+        /// Fade to a brightness over the given number of seconds
+        command fade_brightness(brightness: u8<0, 254>, transition: f64),
     }
 }
+
+zigbee_device!{
+    /// Hue white ambiance A60 bulb E26/E27, with a tunable colour temperature
+    #[mapping_tests]
+    pub WhiteAmbianceLight {
+        "https://www.zigbee2mqtt.io/devices/9290022166.html",
+        /// The current state of the bulb, on or off
+        get set toggle "state" => bool {
+            "ON" => true,
+            "OFF" => false,
+        },
+        /// The current brightness of the bulb, expressed as a u8
+        get set "brightness" => u8<0, 254>,
+        /// The current colour temperature of the bulb, in mireds
+        get set "color_temp" => u16<153, 454>,
+        /// Set the colour temperature, in mireds, along with the brightness
+        command white(color_temp: u16<153, 454>, brightness: u8<0, 254>),
+    }
+}
+
+impl ColorLight for WhiteAmbianceLight {
+    fn is_on(&self) -> BoxFuture<'_, anyhow::Result<bool>> {
+        self.state().get()
+    }
+
+    fn set_white(&self, kelvin: u16, brightness: Percentage) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            let color_temp = mireds(kelvin);
+            // zigbee2mqtt turns a light on when it's brightness is set, so only the colour
+            // temperature is changed while the light is off
+            if self.state().get().await? {
+                self.white(color_temp, scale_brightness(brightness)).await
+            } else {
+                self.color_temp().set(color_temp).await
+            }
+        })
+    }
+}
+
+/// Converts a colour temperature in kelvin to mireds, clamped to the range the bulb supports
+fn mireds(kelvin: u16) -> RangedU16<153, 454> {
+    let mireds = 1_000_000 / u32::from(kelvin.max(1));
+    RangedU16::new(u16::try_from(mireds.clamp(153, 454)).unwrap_or(454))
+}
+
+/// Scales a percentage to the brightness range of zigbee lights
+fn scale_brightness(brightness: Percentage) -> RangedU8<0, 254> {
+    let scaled = u16::from(brightness.inner()) * 254 / 100;
+    RangedU8::new(u8::try_from(scaled).unwrap_or(254))
+}
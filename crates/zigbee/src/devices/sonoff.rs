@@ -20,6 +20,7 @@ zigbee_device! {
 
 zigbee_device! {
     /// Wireless Button
+    #[mapping_tests]
     pub WirelessButton {
         "https://www.zigbee2mqtt.io/devices/SNZB-01.html",
         /// Battery level as a percentage
@@ -55,6 +56,7 @@ enum_value!(ButtonAction,
 
 // https://www.zigbee2mqtt.io/devices/SNZB-02D.html
 zigbee_device! {
+    #[mapping_tests]
     pub TemperatureAndHumiditySensor {
         "https://www.zigbee2mqtt.io/devices/SNZB-02D.html",
         /// Battery level as a percentage
@@ -82,3 +84,12 @@ zigbee_device! {
         get set "humidity_calibration" => i8<-50, 50>,
     }
 }
+
+zigbee_device! {
+    /// A Type F smart plug
+    #[mapping_tests]
+    pub SmartPlug {
+        "https://www.zigbee2mqtt.io/devices/S26R2ZB.html",
+        exposes "exposes/S26R2ZB.json"
+    }
+}
@@ -2,21 +2,31 @@
 
 mod attribute;
 mod publish;
+mod reported;
 
-use crate::publish::Publish;
+use crate::publish::{Publish, Publisher};
+pub use crate::reported::Reported;
 use bon::bon;
 use control::ReadValue;
+use futures::future::join;
 use control::Sensor;
 use control::ToggleValue;
 use control::WriteValue;
-use control::device_manager::DeviceManager;
-use rumqttc::{AsyncClient, Event, EventLoop, Incoming, MqttOptions, QoS};
+use control::device_manager::{DeviceManager, Supervisor};
+use control::dry_run::DryRun;
+use control::connection::ConnectionStats;
+use control::health::{Health, HealthReporter};
+use rumqttc::{AsyncClient, Event, EventLoop, Incoming, MqttOptions, QoS, SubscribeFilter};
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::broadcast::Sender;
 use tokio::sync::oneshot::Receiver;
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{Mutex as AsyncMutex, broadcast, mpsc, oneshot};
+use tokio::time::sleep;
 use tokio::{select, spawn};
 use tokio_stream::Stream;
 use tokio_stream::StreamExt;
@@ -37,12 +47,23 @@ pub mod devices {
     pub mod sonoff;
 }
 
+/// The topic, under the base topic, on which zigbee2mqtt publishes the list of devices paired with
+/// the bridge
+const BRIDGE_DEVICES_TOPIC: &str = "bridge/devices";
+
 /// sets up the zigbee environment, defining MQTT connection parameters and devices
 pub struct Manager {
     mqtt_options: MqttOptions,
+    base_topic: String,
+    renames: HashMap<String, String>,
+    reconnect_delay: Duration,
+    device_names: Vec<String>,
     subscriptions: Vec<Subscription>,
     publishes: mpsc::Sender<Publish>,
     outgoing: mpsc::Receiver<Publish>,
+    health: Option<HealthReporter>,
+    connection: Arc<ConnectionStats>,
+    dry_run: DryRun,
 }
 
 #[bon]
@@ -52,64 +73,248 @@ impl Manager {
     pub fn new(
         /// The MQTT options used to establish a connection
         mqtt_options: MqttOptions,
+        /// Devices to rename on startup, mapping the friendly name currently known to
+        /// zigbee2mqtt to the name used in the device definition. A device is only renamed if
+        /// it is missing from the bridge and its old name is present
+        #[builder(default)]
+        renames: HashMap<String, String>,
+        /// How long to wait before reconnecting after the connection fails, defaults to 5 seconds
+        #[builder(default = Duration::from_secs(5))]
+        reconnect_delay: Duration,
+        /// The base topic zigbee2mqtt is configured with, defaults to `zigbee2mqtt`
+        #[builder(into, default = "zigbee2mqtt".to_string())]
+        base_topic: String,
     ) -> Self {
         let (publishes, outgoing) = mpsc::channel::<Publish>(100);
         Self {
             mqtt_options,
+            base_topic,
+            renames,
+            reconnect_delay,
+            device_names: vec![],
             subscriptions: vec![],
             publishes,
             outgoing,
+            health: None,
+            connection: Arc::default(),
+            dry_run: DryRun::default(),
         }
     }
 }
 
+#[cfg(feature = "mock")]
+impl testing::TestDeviceManager for Manager {
+    fn connect(mqtt: MqttOptions, base_topic: &str) -> Self {
+        Self::builder().mqtt_options(mqtt).base_topic(base_topic).build()
+    }
+}
+
 impl DeviceManager for Manager {
     fn start(self: Box<Self>, token: CancellationToken) {
-        let mqttoptions = self.mqtt_options;
-        let (client, event_loop) = AsyncClient::new(mqttoptions, 10);
+        self.supervise(&Supervisor::builder().token(token).build());
+    }
 
-        let (ready_send, ready_recv) = oneshot::channel();
-        spawn(Self::subscription_job(
-            event_loop,
-            self.subscriptions.clone(),
+    fn supervise(self: Box<Self>, supervisor: &Supervisor) {
+        let token = supervisor.token();
+        let (bridge_send, bridge_recv) = broadcast::channel::<Publish>(1);
+        let mut subscriptions = self.subscriptions;
+        subscriptions.push(Subscription {
+            topic: format!("{}/{BRIDGE_DEVICES_TOPIC}", self.base_topic),
+            sender: bridge_send,
+        });
+        spawn(Self::check_devices(
+            bridge_recv,
+            self.device_names,
+            self.renames,
+            self.publishes,
             token.clone(),
-            ready_send,
-        ).instrument(info_span!("zigbee::subscription_job")));
-        spawn(Self::publish_job(
-            client,
-            self.outgoing,
-            self.subscriptions,
-            token,
-            ready_recv,
-        ).instrument(info_span!("zigbee::publish_job")));
+        ).instrument(info_span!("zigbee::check_devices")));
+
+        let (host, port) = self.mqtt_options.broker_address();
+        let health = self.health.map(|health| (health, format!("{host}:{port}")));
+        // the receiver is kept across restarts, so publishes queued while restarting are not lost
+        let outgoing = Arc::new(AsyncMutex::new(self.outgoing));
+        let (mqtt_options, connection, reconnect_delay) = (self.mqtt_options, self.connection, self.reconnect_delay);
+        let base_topic = self.base_topic;
+        supervisor.spawn("zigbee", move || Self::connection_job(
+            mqtt_options.clone(),
+            base_topic.clone(),
+            subscriptions.clone(),
+            token.clone(),
+            health.clone(),
+            connection.clone(),
+            reconnect_delay,
+            outgoing.clone(),
+        ));
+    }
+
+    fn attach_health(&mut self, health: &Health) {
+        self.health = Some(health.reporter("zigbee"));
+    }
+
+    fn set_dry_run(&mut self, dry_run: DryRun) {
+        self.dry_run = dry_run;
     }
 }
 
 impl Manager {
+    /// Registers the name of a device so that it can be checked against the devices known to
+    /// the zigbee2mqtt bridge on startup
+    pub(crate) fn register_device(&mut self, name: String) {
+        self.device_names.push(name);
+    }
+
     pub(crate) fn subscribe<T>(&mut self, topic: String) -> Updates<T>
     where
         T: for<'de> Deserialize<'de>,
     {
         let (sender, _) = broadcast::channel::<Publish>(100);
         self.subscriptions.push(Subscription {
-            topic: format!("zigbee2mqtt/{topic}"),
+            topic: format!("{}/{topic}", self.base_topic),
             sender: sender.clone(),
         });
         Updates {
             sender,
+            device: topic,
             _t: PhantomData,
         }
     }
 
-    pub(crate) fn outgoing_publishes(&self) -> mpsc::Sender<Publish> {
-        self.publishes.clone()
+    /// The state of the connection to the MQTT broker, eg: for exporting as metrics or for an
+    /// automation which reacts to the broker becoming unreachable
+    pub fn connection(&self) -> Arc<ConnectionStats> {
+        self.connection.clone()
+    }
+
+    pub(crate) fn outgoing_publishes(&self) -> Publisher {
+        Publisher::new(self.publishes.clone(), self.dry_run)
+    }
+
+    /// Compares the registered devices against the devices known to the bridge, any device
+    /// missing from the bridge will silently never receive updates, so these are reported loudly
+    async fn check_devices(
+        mut bridge: broadcast::Receiver<Publish>,
+        device_names: Vec<String>,
+        renames: HashMap<String, String>,
+        publishes: mpsc::Sender<Publish>,
+        token: CancellationToken,
+    ) {
+        let publish = select! {
+            _ = token.cancelled() => return,
+            result = bridge.recv() => match result {
+                Ok(publish) => publish,
+                Err(error) => {
+                    warn!("Unable to receive device list from zigbee2mqtt bridge: {error}");
+                    return;
+                }
+            }
+        };
+        let bridge_devices: Vec<BridgeDevice> = match publish.payload() {
+            Ok(devices) => devices,
+            Err(error) => {
+                warn!("Failed to parse device list from zigbee2mqtt bridge: {error}");
+                return;
+            }
+        };
+        let known: HashSet<&str> = bridge_devices
+            .iter()
+            .filter(|device| device.device_type != "Coordinator")
+            .map(|device| device.friendly_name.as_str())
+            .collect();
+
+        for name in &device_names {
+            if known.contains(name.as_str()) {
+                continue;
+            }
+            let old_name = renames
+                .iter()
+                .find(|(old, new)| *new == name && known.contains(old.as_str()))
+                .map(|(old, _)| old);
+            let Some(old_name) = old_name else {
+                error!("Device '{name}' is not known to the zigbee2mqtt bridge, it will not receive any updates, has it been renamed?");
+                continue;
+            };
+            warn!("Renaming zigbee device '{old_name}' to '{name}'");
+            let publish = Publish::new(
+                "bridge/request/device/rename".to_string(),
+                json!({"from": old_name, "to": name}),
+            );
+            let result = match publish {
+                Ok(publish) => publishes.send(publish).await.map_err(|error| error.to_string()),
+                Err(error) => Err(error.to_string()),
+            };
+            if let Err(error) = result {
+                error!("Failed to rename device '{old_name}' to '{name}': {error}");
+            }
+        }
+
+        // a bridge is often shared with other applications, so the unused devices are only
+        // summarised
+        let mut unused: Vec<&str> = known
+            .into_iter()
+            .filter(|name| !device_names.iter().any(|device| device == name) && !renames.contains_key(*name))
+            .collect();
+        if !unused.is_empty() {
+            unused.sort_unstable();
+            debug!(
+                "{} zigbee2mqtt devices are not used by any device definition: {}",
+                unused.len(),
+                unused.join(", ")
+            );
+        }
+    }
+
+    /// Connect to the broker and run the subscription and publish jobs until shutdown, a panic in
+    /// either job ends both so the supervisor can start them again on a new connection
+    #[allow(clippy::too_many_arguments, reason = "The job takes ownership of each part of the manager it uses")]
+    async fn connection_job(
+        mqtt_options: MqttOptions,
+        base_topic: String,
+        subscriptions: Vec<Subscription>,
+        token: CancellationToken,
+        health: Option<(HealthReporter, String)>,
+        connection: Arc<ConnectionStats>,
+        reconnect_delay: Duration,
+        outgoing: Arc<AsyncMutex<mpsc::Receiver<Publish>>>,
+    ) -> anyhow::Result<()> {
+        let (client, event_loop) = AsyncClient::new(mqtt_options, 10);
+        let mut publishes = outgoing.lock().await;
+        let (ready_send, ready_recv) = oneshot::channel();
+        join(
+            Self::subscription_job(
+                event_loop,
+                client.clone(),
+                &base_topic,
+                subscriptions.clone(),
+                token.clone(),
+                ready_send,
+                health,
+                connection,
+                reconnect_delay,
+            ).instrument(info_span!("zigbee::subscription_job")),
+            Self::publish_job(
+                client,
+                &base_topic,
+                &mut publishes,
+                subscriptions,
+                token,
+                ready_recv,
+            ).instrument(info_span!("zigbee::publish_job")),
+        ).await;
+        Ok(())
     }
 
+    #[allow(clippy::too_many_arguments, reason = "The job takes ownership of each part of the manager it uses")]
     async fn subscription_job(
         mut event_loop: EventLoop,
+        client: AsyncClient,
+        base_topic: &str,
         subscriptions: Vec<Subscription>,
         token: CancellationToken,
         ready: oneshot::Sender<()>,
+        health: Option<(HealthReporter, String)>,
+        connection: Arc<ConnectionStats>,
+        reconnect_delay: Duration,
     ) {
         if ready.send(()).is_err() {
             error!("Ready channel dropped before ready signal could be send to publish thread")
@@ -122,7 +327,17 @@ impl Manager {
                         Ok(event) => event,
                         Err(err) => {
                             warn!("Error from connection: {err}");
-                            break;
+                            if let Some((health, broker)) = &health {
+                                health.unhealthy(format!("lost connection to {broker}: {err}"));
+                            }
+                            connection.report_error(err.to_string());
+                            select! {
+                                _ = token.cancelled() => break,
+                                _ = sleep(reconnect_delay) => {}
+                            }
+                            // the next poll reconnects
+                            connection.report_reconnect_attempt();
+                            continue;
                         }
                     }
                 }
@@ -130,6 +345,16 @@ impl Manager {
             match event {
                 Event::Outgoing(_) => {}
                 Event::Incoming(message) => {
+                    if let Incoming::ConnAck(_) = &message {
+                        if let Some((health, broker)) = &health {
+                            health.healthy(format!("connected to {broker}"));
+                        }
+                        // the subscriptions of the first connection are made by the publish job
+                        if connection.connects() > 0 {
+                            Self::resubscribe(&client, &subscriptions);
+                        }
+                        connection.report_connected();
+                    }
                     let Incoming::Publish(publish) = message else {
                         continue;
                     };
@@ -137,7 +362,8 @@ impl Manager {
                         error!("failed to decode incoming publish payload");
                         continue;
                     };
-                    debug!("received publish: {publish:?}");
+                    let topic = publish.topic.strip_prefix(base_topic).unwrap_or(&publish.topic);
+                    debug!(device = device_of(topic), "received publish: {publish:?}");
                     for Subscription { sender, .. } in subscriptions
                         .iter()
                         .filter(|s| publish.topic.starts_with(&s.topic))
@@ -151,9 +377,23 @@ impl Manager {
         }
     }
 
+    /// Subscribe to every topic again after reconnecting, the broker may not have kept the
+    /// subscriptions of the previous session
+    fn resubscribe(client: &AsyncClient, subscriptions: &[Subscription]) {
+        let filters = subscriptions
+            .iter()
+            .map(|subscription| SubscribeFilter::new(subscription.topic.clone(), QoS::AtLeastOnce));
+        // this runs on the event loop's task, so waiting for room in the request queue could
+        // block forever
+        if let Err(error) = client.try_subscribe_many(filters) {
+            error!("Failed to resubscribe after reconnecting: {error}");
+        }
+    }
+
     async fn publish_job(
         client: AsyncClient,
-        mut publishes: mpsc::Receiver<Publish>,
+        base_topic: &str,
+        publishes: &mut mpsc::Receiver<Publish>,
         subscriptions: Vec<Subscription>,
         token: CancellationToken,
         ready: Receiver<()>,
@@ -182,23 +422,36 @@ impl Manager {
                 option = publishes.recv() => option
             };
             let Some(publish) = option else { break };
-            debug!("sending publish: {publish:?}");
-            if let Err(error) = client
-                .publish(
-                    format!("zigbee2mqtt/{}", publish.topic),
-                    QoS::AtMostOnce,
-                    false,
-                    publish.raw_payload,
-                )
-                .await
-            {
-                error!("Failed to publish payload: {error}");
+            let span = info_span!("publish", device = device_of(&publish.topic));
+            async {
+                debug!("sending publish: {publish:?}");
+                if let Err(error) = client
+                    .publish(
+                        format!("{base_topic}/{}", publish.topic),
+                        QoS::AtMostOnce,
+                        false,
+                        publish.raw_payload,
+                    )
+                    .await
+                {
+                    error!("Failed to publish payload: {error}");
+                }
             }
+            .instrument(span)
+            .await;
         }
         debug!("finishing subscription loop");
     }
 }
 
+/// A device as listed by the zigbee2mqtt bridge, only the fields needed are included
+#[derive(Debug, Deserialize)]
+struct BridgeDevice {
+    friendly_name: String,
+    #[serde(rename = "type")]
+    device_type: String,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Subscription {
     topic: String,
@@ -208,6 +461,8 @@ pub(crate) struct Subscription {
 #[derive(Debug, Clone)]
 pub(crate) struct Updates<T> {
     sender: Sender<Publish>,
+    /// The name of the device the updates are for, it is included in the logs of the stream
+    device: String,
     _t: PhantomData<T>,
 }
 
@@ -217,23 +472,24 @@ where
 {
     fn subscribe(&self) -> impl Stream<Item = T> {
         BroadcastStream::new(self.sender.subscribe())
-            .ignore_lag()
-            .payload::<T>()
+            .ignore_lag(&self.device)
+            .payload::<T>(&self.device)
 
         // warn!("failed to parse value: '{error}' from payload: {object:?} for topic: '{}'", topic);
     }
 }
 
 trait BroadcastStreamExt<T> {
-    fn ignore_lag(self) -> impl Stream<Item = T>;
+    fn ignore_lag(self, device: &str) -> impl Stream<Item = T>;
 }
 
 impl<T: 'static + Clone + Send> BroadcastStreamExt<T> for BroadcastStream<T> {
-    fn ignore_lag(self) -> impl Stream<Item = T> {
-        self.filter_map(|result| match result {
+    fn ignore_lag(self, device: &str) -> impl Stream<Item = T> {
+        let device = device.to_string();
+        self.filter_map(move |result| match result {
             Ok(publish) => Some(publish),
             Err(BroadcastStreamRecvError::Lagged(n)) => {
-                warn!("dropped {n} messages");
+                warn!(device = %device, "dropped {n} messages");
                 None
             }
         })
@@ -241,15 +497,16 @@ impl<T: 'static + Clone + Send> BroadcastStreamExt<T> for BroadcastStream<T> {
 }
 
 trait StreamCustomExt: Stream {
-    fn payload<P>(self) -> impl Stream<Item = P>
+    fn payload<P>(self, device: &str) -> impl Stream<Item = P>
     where
         P: for<'de> Deserialize<'de>,
         Self: Stream<Item = Publish> + Sized,
     {
-        self.filter_map(|publish| match publish.payload() {
+        let device = device.to_string();
+        self.filter_map(move |publish| match publish.payload() {
             Ok(payload) => Some(payload),
             Err(error) => {
-                warn!("failed to parse payload: '{error}' for publish: {publish:?}");
+                warn!(device = %device, "failed to parse payload: '{error}' for publish: {publish:?}");
                 None
             }
         })
@@ -258,6 +515,13 @@ trait StreamCustomExt: Stream {
 
 impl<S: Stream> StreamCustomExt for S {}
 
+/// The name of the device a topic under the base topic belongs to, eg: `hallway_light` for
+/// `hallway_light/set`
+fn device_of(topic: &str) -> &str {
+    let topic = topic.strip_prefix('/').unwrap_or(topic);
+    topic.split('/').next().unwrap_or(topic)
+}
+
 fn get_request(field: &str) -> Value {
     serde_json::json!({field: ""})
 }
@@ -1,5 +1,9 @@
+use control::dry_run::DryRun;
 use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
 use std::string::FromUtf8Error;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendError;
 
 #[derive(Debug, Clone)]
 pub(crate) struct Publish {
@@ -31,3 +35,26 @@ impl TryFrom<rumqttc::Publish> for Publish {
         })
     }
 }
+
+/// Sends the publishes of the devices to the manager, the devices check it before each write so
+/// that writes are only logged in dry-run mode
+#[derive(Debug, Clone)]
+pub(crate) struct Publisher {
+    sender: mpsc::Sender<Publish>,
+    dry_run: DryRun,
+}
+
+impl Publisher {
+    pub fn new(sender: mpsc::Sender<Publish>, dry_run: DryRun) -> Self {
+        Self { sender, dry_run }
+    }
+
+    /// Returns true if the write should be skipped, see [DryRun::intercept]
+    pub fn intercept(&self, device: &str, attribute: &str, value: impl Debug) -> bool {
+        self.dry_run.intercept(device, attribute, value)
+    }
+
+    pub async fn send(&self, publish: Publish) -> Result<(), SendError<Publish>> {
+        self.sender.send(publish).await
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Deserializer};
+
+/// The state of an attribute in an update from a device, zigbee2mqtt leaves out attributes which
+/// were not reported and sends `null` for attributes the device reported as unknown
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Reported<T> {
+    /// The attribute was not included in the update
+    #[default]
+    Absent,
+    /// The attribute was included as `null`, eg: the brightness of a light which is unknown
+    Null,
+    /// The attribute was included with a value
+    Value(T),
+}
+
+impl<T> Reported<T> {
+    /// Returns the value, if one was reported
+    pub fn value(self) -> Option<T> {
+        match self {
+            Self::Value(value) => Some(value),
+            Self::Absent | Self::Null => None,
+        }
+    }
+
+    /// Returns true if the attribute was included in the update, even as `null`
+    pub fn is_reported(&self) -> bool {
+        !matches!(self, Self::Absent)
+    }
+
+    /// Applies a function to the reported value
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Reported<U> {
+        match self {
+            Self::Absent => Reported::Absent,
+            Self::Null => Reported::Null,
+            Self::Value(value) => Reported::Value(f(value)),
+        }
+    }
+}
+
+impl<T> From<Option<T>> for Reported<T> {
+    /// Converts an attribute which is known to be present, `None` being `null`
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Self::Value)
+    }
+}
+
+// only called for attributes which are present, absent attributes use the default
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Reported<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<T>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used, clippy::unwrap_used)]
+mod tests {
+    use crate::Reported;
+    use crate::devices::philips::HueSmartButtonUpdate;
+    use control::ButtonEvent;
+    use macros::zigbee_device;
+    use serde_json::{Value, json};
+
+    /// Reads a pressure reported in tenths of a hectopascal, for testing attributes deserialized
+    /// with a module
+    mod tenths {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+            u32::deserialize(deserializer).map(|tenths| f64::from(tenths) / 10.0)
+        }
+
+        #[allow(dead_code, reason = "only used by the mock of the device")]
+        pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_f64((value * 10.0).round())
+        }
+    }
+
+    zigbee_device!{
+        /// A device with an attribute deserialized in each of the ways an attribute can be
+        pub TestSensor {
+            "https://www.zigbee2mqtt.io/devices/test.html",
+            /// A plain attribute
+            stream "temperature" => f64,
+            /// An attribute deserialized with a module
+            #[with = tenths]
+            stream "pressure" => f64,
+            /// An enum attribute
+            stream "action" => enum ButtonEvent {
+                "press" => Press,
+                "hold" => Hold,
+                "release" => Release,
+            },
+            /// A bool attribute mapped from strings
+            stream "state" => bool {
+                "ON" => true,
+                "OFF" => false,
+            }
+        }
+    }
+
+    fn update(json: Value) -> TestSensorUpdate {
+        serde_json::from_value(json).expect("failed to deserialize update")
+    }
+
+    #[test]
+    fn absent() {
+        let update = update(json!({}));
+        assert_eq!(update.temperature, Reported::Absent);
+        assert_eq!(update.pressure, Reported::Absent);
+        assert_eq!(update.action, Reported::Absent);
+        assert_eq!(update.state, Reported::Absent);
+    }
+
+    #[test]
+    fn null() {
+        let update = update(json!({"temperature": null, "pressure": null, "action": null, "state": null}));
+        assert_eq!(update.temperature, Reported::Null);
+        assert_eq!(update.pressure, Reported::Null);
+        assert_eq!(update.action, Reported::Null);
+        assert_eq!(update.state, Reported::Null);
+        assert!(update.state.is_reported());
+        assert_eq!(update.state.value(), None);
+    }
+
+    #[test]
+    fn value() {
+        let update = update(json!({"temperature": 21.5, "pressure": 10132, "action": "hold", "state": "ON"}));
+        assert_eq!(update.temperature, Reported::Value(21.5));
+        assert_eq!(update.pressure, Reported::Value(1013.2));
+        assert_eq!(update.action, Reported::Value(ButtonEvent::Hold));
+        assert_eq!(update.state, Reported::Value(true));
+    }
+
+    #[test]
+    fn mixed() {
+        // only the attributes included are reported
+        let update = update(json!({"pressure": 10132, "state": null}));
+        assert_eq!(update.temperature, Reported::Absent);
+        assert_eq!(update.pressure, Reported::Value(1013.2));
+        assert_eq!(update.action, Reported::Absent);
+        assert_eq!(update.state, Reported::Null);
+    }
+
+    #[test]
+    fn invalid_values() {
+        for invalid in [
+            json!({"temperature": "warm"}),
+            json!({"pressure": -1}),
+            json!({"action": "spin"}),
+            json!({"state": "TOGGLE"}),
+            json!({"state": true}),
+        ] {
+            assert!(serde_json::from_value::<TestSensorUpdate>(invalid.clone()).is_err(), "{invalid} was accepted");
+        }
+    }
+
+    #[test]
+    fn shared_attribute() {
+        // both the events and the switch of the button are reported as "action"
+        let update: HueSmartButtonUpdate = serde_json::from_value(json!({"action": "press"})).unwrap();
+        assert_eq!(update.clone().events(), Some(ButtonEvent::Press));
+        assert_eq!(update.switch(), None);
+
+        let update: HueSmartButtonUpdate = serde_json::from_value(json!({"action": "on"})).unwrap();
+        assert_eq!(update.clone().events(), None);
+        assert_eq!(update.switch(), Some(true));
+
+        let update: HueSmartButtonUpdate = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(update.clone().events(), None);
+        assert_eq!(update.switch(), None);
+    }
+}
@@ -0,0 +1,217 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! A small demo application which can run against either real hardware or a mock fleet
+//!
+//! By default the demo connects to a zigbee2mqtt broker at `localhost:1883`, passing `--mock`
+//! starts a local broker along with mock devices from the `testing` crate instead, which then
+//! plays out a few button presses so the automations can be watched in the logs.
+//!
+//! Pressing the button toggles the light, holding it applies a scene and the light is switched off
+//! on a schedule in case it was left on, the demo is also run against the mock fleet by the `demo`
+//! test.
+//!
+//! ```sh
+//! cargo run --example demo --features zigbee -- --mock
+//! ```
+
+use control::{ButtonEvent, Manager, Sensor, StreamCustomExt, ToggleValue, WriteValue};
+use light_ranged_integers::RangedU8;
+use log::{Level, info};
+use macros::{AutomationSet, DeviceSet, automation};
+use rumqttc::MqttOptions;
+use simple_log::LogConfigBuilder;
+use std::sync::Arc;
+use std::time::Duration;
+use testing::{Connection, start_mqtt_broker};
+use tintean::automation::Automation;
+use tintean::zigbee::devices::philips::{HueSmartButton, Light, MockHueSmartButton, MockLight};
+use tokio::spawn;
+use tokio::time::{Instant, interval_at, sleep};
+use tokio_stream::wrappers::IntervalStream;
+use tokio_util::sync::CancellationToken;
+
+/// The backend the demo should run against
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Backend {
+    /// Real devices reachable through a zigbee2mqtt broker
+    Hardware,
+    /// Mock devices from the testing crate, with a locally started broker
+    Mock,
+}
+
+impl Backend {
+    fn from_args() -> Self {
+        let mut backend = Self::Hardware;
+        for arg in std::env::args().skip(1) {
+            match arg.as_str() {
+                "--mock" => backend = Self::Mock,
+                "--hardware" => backend = Self::Hardware,
+                unknown => panic!("unknown argument: {unknown}, expected --mock or --hardware"),
+            }
+        }
+        backend
+    }
+}
+
+/// How often the light is switched off in case it was left on
+const LIGHTS_OUT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(DeviceSet)]
+struct Devices {
+    /// The button used to control the office light
+    test_button: HueSmartButton,
+    /// The light in the office
+    test_light: Light,
+}
+
+#[tokio::main]
+async fn main() {
+    simple_log::new(
+        LogConfigBuilder::builder()
+            .level(Level::Info)
+            .unwrap()
+            .output_console()
+            .build(),
+    )
+    .expect("failed to start logger");
+
+    let backend = Backend::from_args();
+    info!("Starting demo against {backend:?} backend");
+
+    // the broker and mocks must be kept alive for as long as the manager is running
+    let (mqttoptions, _mocks) = if backend == Backend::Mock {
+        let (conn, guard) = start_mqtt_broker();
+        let (button, light) = mock_fleet(&conn).await;
+        spawn(async move {
+            for i in 1.. {
+                sleep(Duration::from_secs(2)).await;
+                // every fifth press is held instead
+                let event = if i % 5 == 0 { ButtonEvent::Hold } else { ButtonEvent::Press };
+                button.publish_events(event).await;
+                button.publish_events(ButtonEvent::Release).await;
+                info!("mock light is now {}", if light.state().unwrap_or_default() { "on" } else { "off" });
+            }
+        });
+        (conn.mqtt_options("tintean-demo"), Some((conn, guard)))
+    } else {
+        let mut mqttoptions = MqttOptions::new("tintean-demo", "localhost", 1883);
+        mqttoptions.set_keep_alive(Duration::from_secs(5));
+        (mqttoptions, None)
+    };
+
+    run(mqttoptions, LIGHTS_OUT, CancellationToken::new()).await;
+}
+
+/// Create the mock devices of the demo on the test broker, the light starts off
+pub(crate) async fn mock_fleet(conn: &Connection) -> (Arc<MockHueSmartButton>, Arc<MockLight>) {
+    let button = MockHueSmartButton::new(conn, "test_button").await;
+    let light = MockLight::new(conn, "test_light").await;
+    light.publish_state(false).await;
+    (button, light)
+}
+
+/// Run the demo's automations against the broker until `shutdown` is cancelled or the process is
+/// interrupted, `lights_out` is how often the light is switched off
+pub(crate) async fn run(mqttoptions: MqttOptions, lights_out: Duration, shutdown: CancellationToken) {
+    let mut manager = Manager::builder()
+        .add_device_manager(zigbee::Manager::builder()
+            .mqtt_options(mqttoptions)
+            .build())
+        .build();
+    let devices: Devices = manager.create().await.expect("failed to create devices");
+
+    let automations = Automations {
+        toggle_light: toggle_light_on_press(devices.test_button.events(), devices.test_light.state()),
+        log_presses: log_double_presses(devices.test_button.events()),
+        scene: scene_on_hold(devices.test_button.events(), &devices.test_light, Scene::evening()),
+        lights_out: lights_out_on_schedule(devices.test_light.state(), lights_out),
+    };
+    let stop = manager.shutdown_token();
+    spawn(async move {
+        shutdown.cancelled().await;
+        stop.cancel();
+    });
+    manager.start(automations).await;
+}
+
+/// The automations of the demo, grouped by field
+#[derive(AutomationSet)]
+struct Automations<'a> {
+    toggle_light: Automation<'a>,
+    log_presses: Automation<'a>,
+    scene: Automation<'a>,
+    lights_out: Automation<'a>,
+}
+
+/// A scene, the state the light is set to in one go
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Scene {
+    name: &'static str,
+    pub(crate) brightness: RangedU8<0, 254>,
+}
+
+impl Scene {
+    /// A dimmed light for the evening
+    pub(crate) fn evening() -> Self {
+        Self {
+            name: "evening",
+            brightness: RangedU8::new(80),
+        }
+    }
+}
+
+fn toggle_light_on_press<'a>(
+    button: &'a impl Sensor<Item = ButtonEvent>,
+    light: &'a (impl ToggleValue + Send + Sync),
+) -> Automation<'a> {
+    automation! {
+        name: "toggle_light",
+        trigger: button.subscribe(),
+        when: |event| *event == ButtonEvent::Press,
+        then: async |_| {
+            light
+                .toggle()
+                .await
+                .map_err(|err| format!("failed to toggle light: {err}"))
+        },
+    }
+}
+
+fn log_double_presses<'a>(button: &'a impl Sensor<Item = ButtonEvent>) -> Automation<'a> {
+    let presses = button.subscribe().count_presses::<3>();
+    Automation::new("log_presses", presses, async |event| {
+        info!("button event: {event:?}");
+        Ok(())
+    })
+}
+
+fn scene_on_hold<'a>(
+    button: &'a impl Sensor<Item = ButtonEvent>,
+    light: &'a Light,
+    scene: Scene,
+) -> Automation<'a> {
+    let holds = button.subscribe().filter_eq(ButtonEvent::Hold);
+    Automation::new("scene", holds, async move |_| {
+        info!("applying the {} scene", scene.name);
+        light
+            .state()
+            .set(true)
+            .await
+            .map_err(|err| format!("failed to turn on light: {err}"))?;
+        light
+            .brightness()
+            .set(scene.brightness)
+            .await
+            .map_err(|err| format!("failed to dim light: {err}"))
+    })
+}
+
+fn lights_out_on_schedule<'a>(light: &'a (impl WriteValue<Item = bool> + Sync), every: Duration) -> Automation<'a> {
+    let schedule = IntervalStream::new(interval_at(Instant::now() + every, every));
+    Automation::new("lights_out", schedule, async |_| {
+        info!("switching the light off on schedule");
+        light
+            .set(false)
+            .await
+            .map_err(|err| format!("failed to switch off light: {err}"))
+    })
+}
@@ -5,7 +5,7 @@
 
 use control::Manager;
 use tintean::arp::{ArpDevice, MacAddr};
-use macros::DeviceSet;
+use macros::{DeviceSet, tagged};
 use rumqttc::MqttOptions;
 use std::net::Ipv4Addr;
 use std::time::Duration;
@@ -42,9 +42,11 @@ enum Room {
 }
 
 #[allow(dead_code)]
+#[tagged]
 #[derive(DeviceSet)]
 struct Devices {
     /// The light in the office
+    #[tag(downstairs, lights)]
     #[device(tags = {
         room = Room::Office
     })]
@@ -90,19 +92,21 @@ struct Devices {
     })]
     bedroom_shades: RollerShadeDriver,
 
+    /// `None` if the bulb could not be reached, the other devices are still created
     #[device(
         ip = Ipv4Addr::new(192,168,1,62),
         tags = {
             room = Room::Bedroom
         }
     )]
-    bedroom_light: wiz::Light,
+    bedroom_light: Option<wiz::Light>,
 
     #[device(tags = {
         room = Room::Hallway
     })]
     downstairs_thermostat: TemperatureAndHumiditySensor,
 
+    #[tag(downstairs, lights)]
     #[device(tags = {
         room = Room::Hallway
     })]
@@ -123,37 +127,23 @@ struct Devices {
     })]
     guest_room_button: HueSmartButton,
 
-    #[device(tags = {
-        room = Room::Kitchen
-    })]
-    leak_sensor: WaterLeakSensor,
-
-    #[device(tags = {
-        room = Room::Kitchen
-    })]
-    kitchen_button: HueSmartButton,
-
-    #[device(tags = {
-        room = Room::Kitchen
-    })]
-    kitchen_light_north: Light,
-
-    #[device(tags = {
-        room = Room::Kitchen
-    })]
-    kitchen_light_south: Light,
+    /// The devices in the kitchen, a nested set is created by calling it's own `DeviceSet::new`
+    #[device(set)]
+    kitchen: KitchenDevices,
 
     #[device(tags = {
         room = Room::Living
     })]
     living_room_button: HueSmartButton,
 
+    #[tag(downstairs, lights)]
     #[device(ip = Ipv4Addr::new(192,168,1,61))]
     #[device(tags = {
         room = Room::Living
     })]
     living_room_light: wiz::Light,
 
+    #[tag(upstairs, lights)]
     #[device(tags = {
         room = Room::Bathroom
     })]
@@ -169,6 +159,7 @@ struct Devices {
     })]
     toilet_button: HueSmartButton,
 
+    #[tag(downstairs, lights)]
     #[device(tags = {
         room = Room::Toilet
     })]
@@ -179,6 +170,7 @@ struct Devices {
     })]
     upstairs_hallway_button: HueSmartButton,
 
+    #[tag(upstairs, lights)]
     #[device(tags = {
         room = Room::Landing
     })]
@@ -189,6 +181,10 @@ struct Devices {
     })]
     upstairs_hallway_sockets: DoubleWallSocketTypeG,
 
+    /// Only created for the `home` profile, there is no shade driver on the test rig
+    #[device(profiles = ["home"])]
+    garage_shades: Option<RollerShadeDriver>,
+
     #[device(tags = {
         room = Room::Utility
     })]
@@ -198,12 +194,38 @@ struct Devices {
         timeout = Duration::from_secs(2),
         confirm_interval = Duration::from_secs(30),
         scan_interval = Duration::from_secs(10),
-        ip_range = Ipv4Addr::new(192,168,1,1)..Ipv4Addr::new(192,168,1,255),
         device = MacAddr(0xe8, 0x78, 0x29, 0xc5, 0xaf, 0x6f),
     )]
     dylan_phone: ArpDevice,
 }
 
+#[allow(dead_code)]
+#[derive(DeviceSet)]
+struct KitchenDevices {
+    #[device(tags = {
+        room = Room::Kitchen
+    })]
+    leak_sensor: WaterLeakSensor,
+
+    #[device(tags = {
+        room = Room::Kitchen
+    })]
+    kitchen_button: HueSmartButton,
+
+    /// The ceiling lights, each named by the `names` param
+    #[device(
+        names = ["kitchen_light_north", "kitchen_light_south"],
+        tags = {
+            room = Room::Kitchen
+        }
+    )]
+    kitchen_lights: [Light; 2],
+
+    /// Not a device, skipped fields are created with `Default::default()`
+    #[device(skip)]
+    brightness_presets: Vec<u8>,
+}
+
 #[tokio::main]
 async fn main() {
     let mut manager = Manager::builder()
@@ -213,6 +235,11 @@ async fn main() {
                 .build(),
         )
         .add_device_manager(arp::ArpManager::new())
+        .add_device_manager(wiz::Manager::builder().build())
+        .profile("home")
         .build();
-    let _devices: Devices = manager.create().await.expect("failed to create devices");
+    let devices: Devices = manager.create().await.expect("failed to create devices");
+    for device in devices.with_tag(DevicesTag::Downstairs) {
+        println!("{} is downstairs", device.name());
+    }
 }
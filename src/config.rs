@@ -0,0 +1,705 @@
+//! Setting up devices and simple automations from a TOML or YAML file rather than code, so
+//! renaming a light or adding a plug does not need a recompile, eg:
+//! ```toml
+//! profile = "home"
+//!
+//! [zigbee]
+//! host = "localhost"
+//!
+//! [[devices]]
+//! id = "front_door"
+//! type = "zigbee::sonoff::ContactSensor"
+//!
+//! [[devices]]
+//! id = "hallway_light"
+//! name = "Hallway Light"
+//! type = "zigbee::philips::Light"
+//! tags = { room = "hallway" }
+//!
+//! [[automations]]
+//! name = "hallway light on door open"
+//! when = { device = "front_door", field = "contact", equals = false }
+//! then = [{ device = "hallway_light", field = "state", set = true }]
+//! ```
+//! which is then loaded with:
+//! ```ignore
+//! let config = Config::load("home.toml")?;
+//! let mut manager = config.manager();
+//! let devices = config.create_devices(&Registry::default(), &mut manager).await?;
+//! let automations = config.automations(&devices)?;
+//! manager.start(automations).await;
+//! ```
+//!
+//! The devices are created dynamically, so automations written in code should keep using a
+//! `DeviceSet`, this trades the compile-time guarantees for not needing a recompile
+//!
+//! The automations can be reloaded from the file while running, see [AutomationReloader]
+
+use control::automation::{Automation, AutomationHandle, ManagerStopped, ReloadRequests, ReloadTrigger, Reloaded, reload_channel};
+use control::{Manager, Service};
+use control::device::{CreateDeviceError, Device, optional_device};
+use control::reflect::value::{Value, ValueReadError};
+use control::reflect::{self, DeviceInfo, DeviceType, Field, Operation};
+use futures::future::LocalBoxFuture;
+use futures::{StreamExt, stream};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+use thiserror::Error;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{debug, error, info};
+
+/// The contents of a config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The profile to create devices for, see [DeviceConfig::profiles]
+    pub profile: Option<String>,
+    /// The connection to zigbee2mqtt, the zigbee manager is only added if this is set
+    #[cfg(feature = "zigbee")]
+    pub zigbee: Option<ZigbeeConfig>,
+    /// Options for the wiz manager, the wiz manager is always added since it only opens a socket
+    /// once a light is created
+    #[cfg(feature = "wiz")]
+    #[serde(default)]
+    pub wiz: WizConfig,
+    /// The devices to create
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+    /// The automations to run
+    #[serde(default)]
+    pub automations: Vec<AutomationConfig>,
+}
+
+/// The connection to the MQTT broker zigbee2mqtt publishes to
+#[cfg(feature = "zigbee")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZigbeeConfig {
+    /// The host of the broker
+    pub host: String,
+    /// The port of the broker
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// The client id to connect with
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    /// Devices to rename on startup, see `zigbee::Manager::builder`
+    #[serde(default)]
+    pub renames: HashMap<String, String>,
+}
+
+#[cfg(feature = "zigbee")]
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+#[cfg(feature = "zigbee")]
+fn default_client_id() -> String {
+    "tintean".to_string()
+}
+
+/// Options for the wiz manager, the defaults of the manager are used for any option not set
+#[cfg(feature = "wiz")]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WizConfig {
+    /// The time to wait for a response from a bulb before resending the request, in milliseconds
+    pub timeout_ms: Option<u64>,
+    /// The number of times a request is resent before giving up
+    pub retries: Option<u32>,
+}
+
+/// A device to create
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// The id of the device, used to refer to it in automations
+    pub id: String,
+    /// The type of the device, as registered in the [Registry], eg: `zigbee::philips::Light`
+    #[serde(rename = "type")]
+    pub device_type: String,
+    /// The name of the device, defaults to the id
+    pub name: Option<String>,
+    /// A description of the device
+    pub description: Option<String>,
+    /// The tags of the device
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// The args to create the device with, eg: the ip of a wiz light, most devices have none
+    #[serde(default)]
+    pub args: serde_json::Value,
+    /// If set, the device is only created when the profile is one of these, every device is
+    /// created if there is no profile
+    pub profiles: Option<Vec<String>>,
+    /// A device which fails to be created is logged and left out instead of failing the config,
+    /// eg: an unreachable bulb
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// An automation which updates devices when a field of another device changes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutomationConfig {
+    /// The name of the automation, used in logs
+    pub name: String,
+    /// The trigger of the automation
+    pub when: TriggerConfig,
+    /// The actions run in order each time the automation triggers
+    pub then: Vec<ActionConfig>,
+}
+
+/// Triggers an automation on each update of a field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerConfig {
+    /// The id of the device
+    pub device: String,
+    /// The field subscribed to
+    pub field: String,
+    /// Only trigger on updates to this value
+    pub equals: Option<Value>,
+}
+
+/// Sets or toggles a field, exactly one of `set` and `toggle` must be given
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActionConfig {
+    /// The id of the device
+    pub device: String,
+    /// The field to update
+    pub field: String,
+    /// The value to set the field to
+    pub set: Option<Value>,
+    /// Toggle the field
+    #[serde(default)]
+    pub toggle: bool,
+}
+
+/// An error loading a config or creating the devices and automations it describes
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The config file could not be read
+    #[error("failed to read config file: {0}")]
+    Io(#[from] io::Error),
+    /// The config file has an extension other than `toml`, `yaml` or `yml`
+    #[error("unsupported config file '{0}', expected a .toml, .yaml or .yml file")]
+    UnknownFormat(String),
+    /// The TOML config is invalid
+    #[error("failed to parse config: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// The YAML config is invalid
+    #[error("failed to parse config: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    /// Two devices have the same id
+    #[error("device '{0}' is defined more than once")]
+    DuplicateDevice(String),
+    /// The type of a device is not registered
+    #[error("unknown type '{device_type}' for device '{id}'")]
+    UnknownDeviceType {
+        /// The id of the device
+        id: String,
+        /// The unknown type
+        device_type: String,
+    },
+    /// The args of a device are invalid for its type
+    #[error("invalid args for device '{id}': {source}")]
+    Args {
+        /// The id of the device
+        id: String,
+        /// The reason the args are invalid
+        source: serde_json::Error,
+    },
+    /// A device could not be created
+    #[error("failed to create device '{id}': {source}")]
+    Create {
+        /// The id of the device
+        id: String,
+        /// The reason the device could not be created
+        source: CreateDeviceError,
+    },
+    /// An automation refers to a device which does not exist
+    #[error("automation '{automation}' refers to unknown device '{device}'")]
+    UnknownDevice {
+        /// The name of the automation
+        automation: String,
+        /// The unknown device
+        device: String,
+    },
+    /// An automation uses a field which does not exist or does not support the operation
+    #[error("invalid automation '{automation}': {source}")]
+    Field {
+        /// The name of the automation
+        automation: String,
+        /// The reason the field cannot be used
+        source: reflect::Error,
+    },
+    /// An automation compares or sets a field with a value of the wrong type
+    #[error("invalid value in automation '{automation}': {source}")]
+    Value {
+        /// The name of the automation
+        automation: String,
+        /// The reason the value is invalid
+        source: ValueReadError,
+    },
+    /// An action either both sets and toggles a field or does neither
+    #[error("an action of automation '{0}' must either set or toggle a field")]
+    InvalidAction(String),
+    /// The automations could not be replaced since the manager has stopped
+    #[error(transparent)]
+    ManagerStopped(#[from] ManagerStopped),
+}
+
+impl Config {
+    /// Load a config file, the format is picked by the extension of the file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("yaml" | "yml") => Self::from_yaml(&contents),
+            _ => Err(ConfigError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    /// Parse a TOML config
+    pub fn from_toml(contents: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Parse a YAML config
+    pub fn from_yaml(contents: &str) -> Result<Self, ConfigError> {
+        Ok(serde_yaml::from_str(contents)?)
+    }
+
+    /// Build a manager with the profile and the device managers of this config, more device
+    /// managers and services can still be added to the manager before starting it
+    pub fn manager<'a>(&self) -> Manager<'a> {
+        #[allow(unused_mut, reason = "Only mutated when a manager feature is enabled")]
+        let mut builder = Manager::builder();
+        #[cfg(feature = "zigbee")]
+        if let Some(zigbee) = &self.zigbee {
+            let mqtt_options = rumqttc::MqttOptions::new(&zigbee.client_id, &zigbee.host, zigbee.port);
+            builder = builder.add_device_manager(
+                zigbee::Manager::builder()
+                    .mqtt_options(mqtt_options)
+                    .renames(zigbee.renames.clone())
+                    .build(),
+            );
+        }
+        #[cfg(feature = "wiz")]
+        {
+            builder = builder.add_device_manager(
+                wiz::Manager::builder()
+                    .maybe_timeout(self.wiz.timeout_ms.map(std::time::Duration::from_millis))
+                    .maybe_retries(self.wiz.retries)
+                    .build(),
+            );
+        }
+        builder.maybe_profile(self.profile.clone()).build()
+    }
+
+    /// Create the devices of this config, the device managers of the devices must have been added
+    /// to the manager, see [Config::manager]
+    pub async fn create_devices(&self, registry: &Registry, manager: &mut Manager<'_>) -> Result<Devices, ConfigError> {
+        let mut devices: Vec<Box<dyn reflect::Device>> = Vec::new();
+        for config in &self.devices {
+            if self.devices.iter().filter(|device| device.id == config.id).count() > 1 {
+                return Err(ConfigError::DuplicateDevice(config.id.clone()));
+            }
+            if let Some(profiles) = &config.profiles
+                && manager.profile().is_some_and(|profile| !profiles.iter().any(|p| p == profile))
+            {
+                debug!("skipping device {} outside of the current profile", config.id);
+                continue;
+            }
+            let entry = registry
+                .types
+                .get(&config.device_type)
+                .ok_or_else(|| ConfigError::UnknownDeviceType {
+                    id: config.id.clone(),
+                    device_type: config.device_type.clone(),
+                })?;
+            let info = DeviceInfo {
+                id: config.id.clone(),
+                name: config.name.clone().unwrap_or_else(|| config.id.clone()),
+                description: config.description.clone(),
+                device_type: entry.device_type,
+                tags: config.tags.clone(),
+            };
+            let result = (entry.create)(manager, info, config.args.clone()).await;
+            let device = if config.optional {
+                optional_device(&config.id, result)
+            } else {
+                Some(result?)
+            };
+            devices.extend(device);
+        }
+        Ok(Devices { devices })
+    }
+
+    /// Create the automations of this config
+    pub fn automations<'a>(&self, devices: &'a Devices) -> Result<Vec<Automation<'a>>, ConfigError> {
+        self.automations
+            .iter()
+            .map(|automation| automation.build(devices))
+            .collect()
+    }
+}
+
+/// Reloads the automations of a config file while the manager keeps running, so the devices stay
+/// connected, a reload happens on `SIGHUP` or when requested through it's [trigger](Self::trigger),
+/// eg: by the REST API. Add it to the manager as a service:
+/// ```ignore
+/// let reloader = AutomationReloader::new("home.toml", &devices, manager.automations());
+/// manager.add_service(rest::Server::builder().port(8080).reload(reloader.trigger()).build());
+/// manager.add_service(reloader);
+/// ```
+/// Only the automations are reloaded, a change to the devices still needs a restart, an invalid
+/// file leaves the current automations running
+pub struct AutomationReloader<'a> {
+    path: PathBuf,
+    devices: &'a Devices,
+    automations: AutomationHandle<'a>,
+    trigger: ReloadTrigger,
+    requests: ReloadRequests,
+}
+
+impl<'a> AutomationReloader<'a> {
+    /// Create a reloader for the config file at `path` whose devices were created as `devices`
+    pub fn new(path: impl Into<PathBuf>, devices: &'a Devices, automations: AutomationHandle<'a>) -> Self {
+        let (trigger, requests) = reload_channel();
+        Self {
+            path: path.into(),
+            devices,
+            automations,
+            trigger,
+            requests,
+        }
+    }
+
+    /// A trigger which reloads the automations when used
+    pub fn trigger(&self) -> ReloadTrigger {
+        self.trigger.clone()
+    }
+
+    /// Load the file again and replace the running automations with it's automations
+    ///
+    /// # Errors
+    /// Returns an error if the file is invalid or the manager has stopped
+    pub fn reload(&self) -> Result<Reloaded, ConfigError> {
+        let config = Config::load(&self.path)?;
+        let automations = config.automations(self.devices)?;
+        let reloaded = automations
+            .iter()
+            .map(|automation| (automation.name().to_string(), automation.stats()))
+            .collect();
+        self.automations.replace(automations)?;
+        Ok(reloaded)
+    }
+
+    /// Reload, logging the outcome
+    fn reload_logged(&self) -> Result<Reloaded, ConfigError> {
+        let result = self.reload();
+        match &result {
+            Ok(reloaded) => info!("reloaded {} automations from {}", reloaded.len(), self.path.display()),
+            Err(error) => error!("failed to reload {}, keeping the current automations: {error}", self.path.display()),
+        }
+        result
+    }
+}
+
+impl<'a> Service<'a> for AutomationReloader<'a> {
+    fn name(&self) -> String {
+        "config-reload".to_string()
+    }
+
+    async fn start(mut self) -> anyhow::Result<()> {
+        let mut hangup = signal(SignalKind::hangup())?;
+        loop {
+            // the reloader holds a trigger itself, so the requests never end
+            let request = tokio::select! {
+                signal = hangup.recv() => match signal {
+                    Some(()) => None,
+                    None => return Ok(()),
+                },
+                Some(request) = self.requests.next() => Some(request),
+            };
+            let result = self.reload_logged();
+            match request {
+                Some(request) => request.respond(result.map_err(|error| error.to_string())),
+                None if matches!(result, Err(ConfigError::ManagerStopped(_))) => return Ok(()),
+                None => {}
+            }
+        }
+    }
+}
+
+impl AutomationConfig {
+    fn build<'a>(&self, devices: &'a Devices) -> Result<Automation<'a>, ConfigError> {
+        let device = self.device(devices, &self.when.device)?;
+        let field = self.field(device, &self.when.field, Operation::Subscribe)?;
+        if let Some(equals) = &self.when.equals {
+            self.validate(&field, equals)?;
+        }
+        let steps = self
+            .then
+            .iter()
+            .map(|action| {
+                let device = self.device(devices, &action.device)?;
+                let value = match (&action.set, action.toggle) {
+                    (Some(value), false) => {
+                        let field = self.field(device, &action.field, Operation::Set)?;
+                        self.validate(&field, value)?;
+                        Some(value.clone())
+                    }
+                    (None, true) => {
+                        self.field(device, &action.field, Operation::Toggle)?;
+                        None
+                    }
+                    _ => return Err(ConfigError::InvalidAction(self.name.clone())),
+                };
+                Ok(Step {
+                    device,
+                    field: action.field.clone(),
+                    value,
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        let steps = Arc::new(steps);
+
+        let subscription = device
+            .subscribe(&self.when.field)
+            .map_err(|source| ConfigError::Field {
+                automation: self.name.clone(),
+                source,
+            })?;
+        let equals = self.when.equals.clone();
+        let trigger = stream::once(subscription)
+            .flatten()
+            .filter(move |value| futures::future::ready(equals.as_ref().is_none_or(|equals| equals == value)));
+        Ok(Automation::new(self.name.clone(), trigger, move |_: Value| {
+            let steps = steps.clone();
+            async move {
+                for step in steps.iter() {
+                    step.run().await?;
+                }
+                Ok(())
+            }
+        }))
+    }
+
+    fn device<'a>(&self, devices: &'a Devices, id: &str) -> Result<&'a dyn reflect::Device, ConfigError> {
+        devices.get(id).ok_or_else(|| ConfigError::UnknownDevice {
+            automation: self.name.clone(),
+            device: id.to_string(),
+        })
+    }
+
+    /// Returns the field of the device if it supports the operation
+    fn field(&self, device: &dyn reflect::Device, name: &str, operation: Operation) -> Result<Field, ConfigError> {
+        let error = |source| ConfigError::Field {
+            automation: self.name.clone(),
+            source,
+        };
+        let field = device
+            .fields()
+            .into_iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| error(reflect::Error::FieldNotFound {
+                device: device.name(),
+                field: name.to_string(),
+            }))?;
+        let supported = match operation {
+            Operation::Subscribe => field.operations.subscribe,
+            Operation::Get => field.operations.get,
+            Operation::Set => field.operations.set,
+            Operation::Toggle => field.operations.toggle,
+        };
+        if !supported {
+            return Err(error(reflect::Error::OperationNotSupported {
+                device: device.name(),
+                field: name.to_string(),
+                operation,
+            }));
+        }
+        Ok(field)
+    }
+
+    fn validate(&self, field: &Field, value: &Value) -> Result<(), ConfigError> {
+        field.value_type.validate(value).map_err(|source| ConfigError::Value {
+            automation: self.name.clone(),
+            source,
+        })
+    }
+}
+
+/// A single action of an automation, a value to set or a toggle if there is no value
+struct Step<'a> {
+    device: &'a dyn reflect::Device,
+    field: String,
+    value: Option<Value>,
+}
+
+impl Step<'_> {
+    async fn run(&self) -> Result<(), String> {
+        let device = self.device.name();
+        let field = &self.field;
+        match &self.value {
+            Some(value) => self
+                .device
+                .set(field, value.clone())
+                .map_err(|err| format!("failed to set {field} of {device}: {err}"))?
+                .await
+                .map_err(|err| format!("failed to set {field} of {device}: {err}")),
+            None => self
+                .device
+                .toggle(field)
+                .map_err(|err| format!("failed to toggle {field} of {device}: {err}"))?
+                .await
+                .map_err(|err| format!("failed to toggle {field} of {device}: {err}")),
+        }
+    }
+}
+
+/// The devices created from a config
+pub struct Devices {
+    devices: Vec<Box<dyn reflect::Device>>,
+}
+
+impl Devices {
+    /// Returns the device with the given id
+    pub fn get(&self, id: &str) -> Option<&dyn reflect::Device> {
+        self.devices
+            .iter()
+            .find(|device| device.info().id == id)
+            .map(|device| device.as_ref())
+    }
+
+    /// Iterate over the devices
+    pub fn iter(&self) -> impl Iterator<Item = &dyn reflect::Device> {
+        self.devices.iter().map(|device| device.as_ref())
+    }
+
+    /// The number of devices
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// Returns true if there are no devices
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+}
+
+impl IntoIterator for Devices {
+    type Item = Box<dyn reflect::Device>;
+    type IntoIter = std::vec::IntoIter<Box<dyn reflect::Device>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.devices.into_iter()
+    }
+}
+
+/// Creates a device of a registered type from its info and args
+type CreateFn = for<'m, 'a> fn(
+    &'m mut Manager<'a>,
+    DeviceInfo,
+    serde_json::Value,
+) -> LocalBoxFuture<'m, Result<Box<dyn reflect::Device>, ConfigError>>;
+
+struct RegisteredType {
+    device_type: DeviceType,
+    create: CreateFn,
+}
+
+/// The device types which can be used in a config, by the name used as the `type` of a device,
+/// the default registry includes the devices of the enabled integrations, eg:
+/// `zigbee::philips::Light` or `wiz::Light`
+pub struct Registry {
+    types: HashMap<String, RegisteredType>,
+}
+
+impl Registry {
+    /// Create a registry without any device types
+    pub fn empty() -> Self {
+        Self {
+            types: HashMap::new(),
+        }
+    }
+
+    /// Register a device type under the given name, eg: a device defined outside of this crate,
+    /// the args of a device in the config are deserialized as the args of the device type
+    pub fn register<D>(mut self, name: impl Into<String>, device_type: DeviceType) -> Self
+    where
+        D: Device + reflect::Device + 'static,
+        D::Args: DeserializeOwned,
+    {
+        self.types.insert(name.into(), RegisteredType {
+            device_type,
+            create: create::<D>,
+        });
+        self
+    }
+
+    /// The names of the registered device types
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.types.keys().map(String::as_str)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        #[allow(unused_mut, reason = "Only mutated when an integration feature is enabled")]
+        let mut registry = Self::empty();
+        #[cfg(feature = "zigbee")]
+        {
+            use zigbee::devices::{aqara, aurora, philips, sonoff};
+            registry = registry
+                .register::<aqara::SmartWallSwitchSingle>("zigbee::aqara::SmartWallSwitchSingle", DeviceType::Switch)
+                .register::<aqara::RollerShadeDriver>("zigbee::aqara::RollerShadeDriver", DeviceType::Other)
+                .register::<aqara::WaterLeakSensor>("zigbee::aqara::WaterLeakSensor", DeviceType::Sensor)
+                .register::<aurora::DoubleWallSocketTypeG>("zigbee::aurora::DoubleWallSocketTypeG", DeviceType::Switch)
+                .register::<philips::HueSmartButton>("zigbee::philips::HueSmartButton", DeviceType::Switch)
+                .register::<philips::Light>("zigbee::philips::Light", DeviceType::Light)
+                .register::<philips::WhiteAmbianceLight>("zigbee::philips::WhiteAmbianceLight", DeviceType::Light)
+                .register::<sonoff::ContactSensor>("zigbee::sonoff::ContactSensor", DeviceType::Sensor)
+                .register::<sonoff::WirelessButton>("zigbee::sonoff::WirelessButton", DeviceType::Switch)
+                .register::<sonoff::TemperatureAndHumiditySensor>("zigbee::sonoff::TemperatureAndHumiditySensor", DeviceType::Sensor)
+                .register::<sonoff::SmartPlug>("zigbee::sonoff::SmartPlug", DeviceType::Switch);
+        }
+        #[cfg(feature = "wiz")]
+        {
+            registry = registry.register::<wiz::Light>("wiz::Light", DeviceType::Light);
+        }
+        registry
+    }
+}
+
+fn create<'m, 'a, D>(
+    manager: &'m mut Manager<'a>,
+    info: DeviceInfo,
+    args: serde_json::Value,
+) -> LocalBoxFuture<'m, Result<Box<dyn reflect::Device>, ConfigError>>
+where
+    D: Device + reflect::Device + 'static,
+    D::Args: DeserializeOwned,
+{
+    Box::pin(async move {
+        let id = info.id.clone();
+        let args = serde_json::from_value(args).map_err(|source| ConfigError::Args {
+            id: id.clone(),
+            source,
+        })?;
+        let create = async {
+            let manager = manager.device_manager::<D::Manager>()?;
+            Ok::<_, CreateDeviceError>(D::new_with_args(manager, info, args).await?)
+        };
+        let device = create.await.map_err(|source| ConfigError::Create { id, source })?;
+        Ok(Box::new(device) as Box<dyn reflect::Device>)
+    })
+}
@@ -2,7 +2,23 @@
 
 pub use control::*;
 pub use light_ranged_integers;
-pub use macros::DeviceSet;
+pub use macros::{AutomationSet, DeviceSet, automation, tagged};
+
+/// The traits and types needed by most automations, glob import this to get started:
+/// ```
+/// use tintean::prelude::*;
+/// ```
+pub mod prelude {
+    pub use control::automation::{Automation, AutomationSet, Cancellable};
+    pub use control::device::{Device, DeviceSet};
+    pub use control::{
+        ColorLight, Manager, ReadValue, Sensor, StreamCustomExt, ToggleValue, ValueExt, WriteValue,
+    };
+    pub use macros::{AutomationSet, DeviceSet, automation, tagged};
+}
+
+#[cfg(feature = "config")]
+pub mod config;
 
 #[cfg(feature = "zigbee")]
 pub use zigbee;
@@ -14,6 +30,34 @@ pub use wiz;
 #[cfg(feature = "arp")]
 pub use arp;
 
+#[cfg(feature = "mdns")]
+#[doc = include_str!("../crates/mdns/README.md")]
+pub use mdns;
+
+#[cfg(feature = "ble")]
+#[doc = include_str!("../crates/ble/README.md")]
+pub use ble;
+
+#[cfg(feature = "history")]
+#[doc = include_str!("../crates/history/README.md")]
+pub use history;
+
+#[cfg(feature = "influxdb")]
+#[doc = include_str!("../crates/influxdb/README.md")]
+pub use influxdb;
+
+#[cfg(feature = "metrics")]
+#[doc = include_str!("../crates/metrics/README.md")]
+pub use metrics;
+
+#[cfg(feature = "rest")]
+#[doc = include_str!("../crates/rest/README.md")]
+pub use rest;
+
+#[cfg(feature = "mqtt")]
+#[doc = include_str!("../crates/mqtt/README.md")]
+pub use mqtt;
+
 #[cfg(feature = "web")]
 #[doc = include_str!("../crates/web/README.md")]
 pub mod web {
@@ -0,0 +1,70 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests ARP presence detection against a mock network, without root privileges or a real
+//! network interface
+
+use control::device::Device;
+use control::reflect::{DeviceInfo, DeviceType};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+use testing::MockNetwork;
+use tintean::arp::{ArpDevice, ArpManager, MacAddr, NetworkScannerConfig, ScanMode};
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+const PHONE: MacAddr = MacAddr(0x02, 0, 0, 0, 0, 0x07);
+/// How long to wait for the scanner to notice a change
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn test_presence() {
+    let network = MockNetwork::new(Ipv4Addr::new(10, 0, 0, 1));
+    network.add_device(PHONE, Ipv4Addr::new(10, 0, 0, 7));
+
+    let mut manager = ArpManager::with_datalink(network.clone());
+    let info = DeviceInfo {
+        id: "phone".to_string(),
+        name: "Phone".to_string(),
+        description: None,
+        device_type: DeviceType::Sensor,
+        tags: Default::default(),
+    };
+    let phone = ArpDevice::new_with_args(&mut manager, info, NetworkScannerConfig {
+        name: "Phone".to_string(),
+        interface_name: None,
+        timeout: Duration::from_millis(50),
+        confirm_interval: Duration::from_millis(50),
+        scan_interval: Duration::from_millis(50),
+        ip_range: Some(Ipv4Addr::new(10, 0, 0, 1)..Ipv4Addr::new(10, 0, 0, 16)),
+        devices: vec![PHONE],
+        mode: ScanMode::Arp,
+        ping_fallback: false,
+        misses_before_offline: 1,
+    })
+    .await
+    .unwrap();
+    let token = CancellationToken::new();
+    let scanning = tokio::spawn(manager.run(token.clone()));
+
+    let mut changes = phone.ip_addr_changes();
+    let mut wait_for = async |expected: Option<IpAddr>| {
+        timeout(TIMEOUT, async {
+            while phone.ip_addr() != expected {
+                changes.next().await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("expected the phone at {expected:?}, found {:?}", phone.ip_addr()));
+    };
+
+    wait_for(Some(Ipv4Addr::new(10, 0, 0, 7).into())).await;
+    network.set_online(PHONE, false);
+    wait_for(None).await;
+    network.move_device(PHONE, Ipv4Addr::new(10, 0, 0, 12));
+    network.set_online(PHONE, true);
+    wait_for(Some(Ipv4Addr::new(10, 0, 0, 12).into())).await;
+    assert!(network.requests().contains(&Ipv4Addr::new(10, 0, 0, 12)));
+
+    token.cancel();
+    scanning.await.unwrap();
+}
@@ -0,0 +1,77 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests counting button presses with a paused clock, so the press interval can be stepped over
+//! deterministically rather than sleeping
+
+use control::{ButtonEvent, ButtonPressEvent, StreamCustomExt};
+use light_ranged_integers::RangedU8;
+use std::time::Duration;
+use testing::{advance, pause_time, settle};
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Start counting presses of a button, returning the sender of button events and the receiver of
+/// counted presses
+fn count_presses() -> (Sender<ButtonEvent>, Receiver<ButtonPressEvent<3>>) {
+    let (events, button) = channel(10);
+    let (send, presses) = channel(10);
+    tokio::spawn(async move {
+        let mut stream = ReceiverStream::new(button).count_presses::<3>();
+        while let Some(press) = stream.next().await {
+            send.send(press).await.expect("failed to send press");
+        }
+    });
+    (events, presses)
+}
+
+async fn press(events: &Sender<ButtonEvent>, release: bool) {
+    events.send(ButtonEvent::Press).await.unwrap();
+    if release {
+        events.send(ButtonEvent::Release).await.unwrap();
+    }
+    settle().await;
+}
+
+#[tokio::test]
+async fn single_press() {
+    pause_time();
+    let (events, mut presses) = count_presses();
+    press(&events, true).await;
+    advance(Duration::from_millis(400)).await;
+    assert!(presses.try_recv().is_err(), "press reported before the interval ended");
+    advance(Duration::from_millis(200)).await;
+    assert_eq!(presses.try_recv().unwrap(), ButtonPressEvent::Press(RangedU8::new(1)));
+}
+
+#[tokio::test]
+async fn double_press() {
+    pause_time();
+    let (events, mut presses) = count_presses();
+    press(&events, true).await;
+    advance(Duration::from_millis(300)).await;
+    press(&events, true).await;
+    advance(Duration::from_millis(300)).await;
+    assert!(presses.try_recv().is_err(), "second press did not restart the interval");
+    advance(Duration::from_millis(300)).await;
+    assert_eq!(presses.try_recv().unwrap(), ButtonPressEvent::Press(RangedU8::new(2)));
+}
+
+#[tokio::test]
+async fn held_press() {
+    pause_time();
+    let (events, mut presses) = count_presses();
+    press(&events, false).await;
+    advance(Duration::from_millis(600)).await;
+    assert_eq!(presses.try_recv().unwrap(), ButtonPressEvent::Hold(RangedU8::new(1)));
+}
+
+#[tokio::test]
+async fn max_presses() {
+    pause_time();
+    let (events, mut presses) = count_presses();
+    for _ in 0..3 {
+        press(&events, true).await;
+    }
+    // the maximum is reported straight away, without waiting for the interval
+    assert_eq!(presses.try_recv().unwrap(), ButtonPressEvent::Press(RangedU8::new(3)));
+}
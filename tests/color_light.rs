@@ -0,0 +1,59 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests setting the colour temperature of zigbee lights through [ColorLight], as the circadian
+//! recipe does
+
+use control::{ColorLight, Percentage};
+use light_ranged_integers::{RangedU8, RangedU16};
+use macros::DeviceSet;
+use std::time::Duration;
+use testing::{Connection, TestHarness, run};
+use tintean::zigbee::devices::philips::{MockWhiteAmbianceLight, WhiteAmbianceLight};
+use tokio::time::sleep;
+
+#[derive(DeviceSet)]
+struct Devices {
+    lamp_on: WhiteAmbianceLight,
+    lamp_off: WhiteAmbianceLight,
+}
+
+#[tokio::test]
+async fn set_white() {
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let on = MockWhiteAmbianceLight::new(conn, "lamp_on").await;
+            on.publish_state(true).await;
+            let off = MockWhiteAmbianceLight::new(conn, "lamp_off").await;
+            off.publish_state(false).await;
+            (on, off)
+        })
+        .start()
+        .await;
+    let (mock_on, mock_off) = &harness.mocks;
+    let devices = &harness.devices;
+    let manager = harness.manager;
+    run(manager, [], async {
+        assert!(devices.lamp_on.is_on().await.unwrap());
+        assert!(!devices.lamp_off.is_on().await.unwrap());
+
+        // 4000K is 250 mireds, half brightness is 127 of 254
+        devices.lamp_on.set_white(4000, Percentage::new(50)).await.unwrap();
+        devices.lamp_off.set_white(4000, Percentage::new(50)).await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(mock_on.color_temp(), Some(RangedU16::new(250)));
+        assert_eq!(mock_on.brightness(), Some(RangedU8::new(127)));
+        assert_eq!(mock_on.state(), Some(true));
+
+        // the brightness of a light which is off is left alone, since setting it turns the
+        // light on
+        assert_eq!(mock_off.color_temp(), Some(RangedU16::new(250)));
+        assert_eq!(mock_off.brightness(), None);
+        assert_eq!(mock_off.state(), Some(false));
+
+        // colour temperatures beyond what the bulb supports are clamped
+        devices.lamp_on.set_white(10_000, Percentage::new(100)).await.unwrap();
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(mock_on.color_temp(), Some(RangedU16::new(153)));
+        assert_eq!(mock_on.brightness(), Some(RangedU8::new(254)));
+    })
+    .await;
+}
@@ -0,0 +1,194 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests creating devices and automations from a config file rather than code
+
+use serde_json::json;
+use std::fs;
+use std::time::Duration;
+use testing::{mock_contact_sensor, run, start_mqtt_broker};
+use tintean::config::{AutomationReloader, Config, ConfigError, Registry};
+use tintean::zigbee::devices::philips::MockLight;
+
+/// How long to wait for the automation to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn automation_from_config() {
+    let (conn, _guard) = start_mqtt_broker();
+    let door = mock_contact_sensor(&conn, "front_door", true).await;
+    let light = MockLight::new(&conn, "hallway_light").await;
+    light.publish_state(false).await;
+
+    let config = Config::from_toml(&format!(
+        r#"
+        [zigbee]
+        host = "localhost"
+        port = {port}
+        client_id = "config-test"
+
+        [[devices]]
+        id = "front_door"
+        type = "zigbee::sonoff::ContactSensor"
+
+        [[devices]]
+        id = "hallway_light"
+        type = "zigbee::philips::Light"
+        tags = {{ room = "hallway" }}
+
+        [[automations]]
+        name = "hallway light on door open"
+        when = {{ device = "front_door", field = "contact", equals = false }}
+        then = [{{ device = "hallway_light", field = "state", set = true }}]
+        "#,
+        port = conn.port(),
+    ))
+    .unwrap();
+    let mut manager = config.manager();
+    let devices = config.create_devices(&Registry::default(), &mut manager).await.unwrap();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices.get("hallway_light").unwrap().info().tags["room"], "hallway");
+    let automations = config.automations(&devices).unwrap();
+
+    run(manager, automations, async {
+        let light_on = conn.expect_publish(&conn.topic("hallway_light"), json!({"state": "ON"}), TIMEOUT);
+        door.update("contact", false).await;
+        light_on.await;
+        assert_eq!(light.state(), Some(true));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn yaml_config() {
+    let config = Config::from_yaml(
+        r#"
+        profile: test_rig
+        devices:
+          - id: kitchen_plug
+            type: zigbee::sonoff::SmartPlug
+            profiles: [home]
+        "#,
+    )
+    .unwrap();
+    let mut manager = config.manager();
+    // the plug is not in the profile so is not created, no broker is needed
+    let devices = config.create_devices(&Registry::default(), &mut manager).await.unwrap();
+    assert!(devices.is_empty());
+}
+
+#[tokio::test]
+async fn unknown_device_type() {
+    let config = Config::from_toml(
+        r#"
+        [[devices]]
+        id = "kettle"
+        type = "zigbee::acme::Kettle"
+        "#,
+    )
+    .unwrap();
+    let mut manager = config.manager();
+    let Err(error) = config.create_devices(&Registry::default(), &mut manager).await else {
+        panic!("expected an unknown device type to fail");
+    };
+    assert!(matches!(error, ConfigError::UnknownDeviceType { ref device_type, .. } if device_type == "zigbee::acme::Kettle"), "{error}");
+}
+
+#[tokio::test]
+async fn automation_with_unknown_device() {
+    let config = Config::from_toml(
+        r#"
+        [[automations]]
+        name = "missing"
+        when = { device = "front_door", field = "contact" }
+        then = [{ device = "hallway_light", field = "state", toggle = true }]
+        "#,
+    )
+    .unwrap();
+    let mut manager = config.manager();
+    let devices = config.create_devices(&Registry::default(), &mut manager).await.unwrap();
+    let Err(error) = config.automations(&devices) else {
+        panic!("expected an automation with an unknown device to fail");
+    };
+    assert!(matches!(error, ConfigError::UnknownDevice { ref device, .. } if device == "front_door"), "{error}");
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    let error = Config::from_toml(
+        r#"
+        [[devices]]
+        id = "kettle"
+        type = "zigbee::sonoff::SmartPlug"
+        nmae = "Kettle"
+        "#,
+    )
+    .unwrap_err();
+    assert!(matches!(error, ConfigError::Toml(_)), "{error}");
+}
+
+#[tokio::test]
+async fn reload_automations() {
+    let (conn, _guard) = start_mqtt_broker();
+    let door = mock_contact_sensor(&conn, "back_door", true).await;
+    let light = MockLight::new(&conn, "landing_light").await;
+    light.publish_state(false).await;
+
+    let path = std::env::temp_dir().join(format!("tintean-reload-{}.toml", conn.port()));
+    fs::write(&path, landing_config(conn.port(), "light on", true)).unwrap();
+    let config = Config::load(&path).unwrap();
+    let mut manager = config.manager();
+    let devices = config.create_devices(&Registry::default(), &mut manager).await.unwrap();
+    let automations = config.automations(&devices).unwrap();
+    let reloader = AutomationReloader::new(&path, &devices, manager.automations());
+    let trigger = reloader.trigger();
+    manager.add_service(reloader);
+
+    run(manager, automations, async {
+        let light_on = conn.expect_publish(&conn.topic("landing_light"), json!({"state": "ON"}), TIMEOUT);
+        door.update("contact", false).await;
+        light_on.await;
+        door.update("contact", true).await;
+
+        // the devices stay connected, only the automation is replaced
+        fs::write(&path, landing_config(conn.port(), "light off", false)).unwrap();
+        let reloaded = trigger.reload().await.unwrap();
+        let names: Vec<_> = reloaded.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["light off"]);
+
+        let light_off = conn.expect_publish(&conn.topic("landing_light"), json!({"state": "OFF"}), TIMEOUT);
+        door.update("contact", false).await;
+        light_off.await;
+        assert_eq!(light.state(), Some(false));
+
+        // an invalid file keeps the current automations
+        fs::write(&path, "[[automations]]").unwrap();
+        assert!(trigger.reload().await.is_err());
+        fs::remove_file(&path).unwrap();
+    })
+    .await;
+}
+
+/// A config with a contact sensor and a light which is set when the door opens, the devices differ
+/// from the other tests as they share the broker
+fn landing_config(port: u16, automation: &str, set: bool) -> String {
+    format!(
+        r#"
+        [zigbee]
+        host = "localhost"
+        port = {port}
+        client_id = "reload-test"
+
+        [[devices]]
+        id = "back_door"
+        type = "zigbee::sonoff::ContactSensor"
+
+        [[devices]]
+        id = "landing_light"
+        type = "zigbee::philips::Light"
+
+        [[automations]]
+        name = "{automation}"
+        when = {{ device = "back_door", field = "contact", equals = false }}
+        then = [{{ device = "landing_light", field = "state", set = {set} }}]
+        "#
+    )
+}
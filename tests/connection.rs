@@ -0,0 +1,100 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests the connection state reported by the zigbee manager, and checking the devices against the
+//! bridge once connected
+
+use control::Sensor;
+use control::connection::ConnectionEvent;
+use control::device_manager::DeviceManager;
+use macros::DeviceSet;
+use rumqttc::MqttOptions;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::time::Duration;
+use testing::{Connection, MockDevice, TestHarness, run, start_mqtt_broker};
+use tintean::zigbee::devices::philips::Light;
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+/// How long to wait for the manager to connect
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn connected() {
+    let (conn, _guard) = start_mqtt_broker();
+    let manager = zigbee::Manager::builder().mqtt_options(conn.mqtt_options("connection-test")).build();
+    let connection = manager.connection();
+    let mut events = connection.subscribe();
+    let token = CancellationToken::new();
+    Box::new(manager).start(token.clone());
+
+    assert_eq!(timeout(TIMEOUT, events.next()).await.unwrap(), Some(ConnectionEvent::Connected));
+    assert!(connection.is_connected());
+    assert_eq!(connection.connects(), 1);
+    assert_eq!(connection.last_error(), None);
+    token.cancel();
+}
+
+#[tokio::test]
+async fn unreachable_broker() {
+    // nothing listens on the port once the listener is dropped
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let manager = zigbee::Manager::builder()
+        .mqtt_options(MqttOptions::new("unreachable-test", "127.0.0.1", port))
+        .reconnect_delay(Duration::from_millis(20))
+        .build();
+    let connection = manager.connection();
+    let mut events = connection.subscribe();
+    let token = CancellationToken::new();
+    Box::new(manager).start(token.clone());
+
+    assert_eq!(timeout(TIMEOUT, events.next()).await.unwrap(), Some(ConnectionEvent::Reconnecting));
+    sleep(Duration::from_millis(100)).await;
+    assert!(!connection.is_connected());
+    assert!(connection.reconnect_attempts() >= 2, "{} attempts", connection.reconnect_attempts());
+    assert!(connection.last_error().is_some());
+    // the connection was never established, so it was never lost
+    assert_eq!(connection.disconnects(), 0);
+    token.cancel();
+}
+
+#[derive(DeviceSet)]
+struct Renamed {
+    renamed_light: Light,
+}
+
+#[tokio::test]
+async fn renamed_on_startup() {
+    let harness: TestHarness<Renamed, _> = TestHarness::builder()
+        .device_manager(|mqtt: MqttOptions, base_topic: &str| {
+            zigbee::Manager::builder()
+                .mqtt_options(mqtt)
+                .base_topic(base_topic)
+                .renames(HashMap::from([("old_light".to_string(), "renamed_light".to_string())]))
+                .build()
+        })
+        .mocks(async |conn: &Connection| MockDevice::connect(conn, "bridge/devices").await.0)
+        .start()
+        .await;
+    let bridge = &harness.mocks;
+    let conn = &harness.connection;
+    let manager = harness.manager;
+    run(manager, [], async {
+        let renamed = conn.expect_publish(
+            &conn.topic("bridge/request/device/rename"),
+            json!({"from": "old_light", "to": "renamed_light"}),
+            TIMEOUT,
+        );
+        // the light is only known to the bridge by it's old name
+        bridge
+            .publish(json!([
+                {"friendly_name": "Coordinator", "type": "Coordinator"},
+                {"friendly_name": "old_light", "type": "Router"},
+                {"friendly_name": "unused_plug", "type": "Router"},
+            ]))
+            .await;
+        renamed.await;
+    })
+    .await;
+}
@@ -0,0 +1,39 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests the dashboard is served alongside the REST API
+
+use control::Service;
+use reqwest::header::CONTENT_TYPE;
+use std::net::TcpListener;
+use std::time::Duration;
+use tintean::rest;
+use tokio::spawn;
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn serves_dashboard() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let server = spawn(
+        rest::Server::builder()
+            .bind_address("127.0.0.1")
+            .port(port)
+            .build()
+            .start(),
+    );
+    sleep(Duration::from_millis(100)).await;
+
+    let index = reqwest::get(format!("http://127.0.0.1:{port}/")).await.unwrap();
+    assert!(index.headers()[CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+    let index = index.text().await.unwrap();
+    assert!(index.contains("/dashboard.js"), "{index}");
+
+    for (asset, content_type) in [("dashboard.js", "text/javascript"), ("dashboard.css", "text/css")] {
+        let response = reqwest::get(format!("http://127.0.0.1:{port}/{asset}")).await.unwrap();
+        assert!(response.status().is_success(), "failed to fetch {asset}");
+        assert!(response.headers()[CONTENT_TYPE].to_str().unwrap().starts_with(content_type));
+    }
+
+    // the API is still served alongside the dashboard
+    let devices = reqwest::get(format!("http://127.0.0.1:{port}/api/devices")).await.unwrap().text().await.unwrap();
+    assert_eq!(devices, "[]");
+    server.abort();
+}
@@ -0,0 +1,53 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Runs the demo example against the mock fleet, the light should follow the button presses, the
+//! scene and the schedule
+
+#[path = "../examples/demo.rs"]
+#[allow(dead_code, reason = "the demo's entry point is not run by the test")]
+mod demo;
+
+use control::ButtonEvent;
+use demo::Scene;
+use std::time::Duration;
+use testing::start_mqtt_broker;
+use tokio::join;
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
+
+/// How long to wait for the demo's automations to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the demo switches the light off, short enough to be seen by the test
+const LIGHTS_OUT: Duration = Duration::from_millis(500);
+
+#[tokio::test]
+async fn demo_against_mocks() {
+    let (conn, _guard) = start_mqtt_broker();
+    let (button, light) = demo::mock_fleet(&conn).await;
+    let shutdown = CancellationToken::new();
+
+    join!(demo::run(conn.mqtt_options("tintean-demo"), LIGHTS_OUT, shutdown.clone()), async {
+        sleep(Duration::from_millis(50)).await;
+        button.publish_events(ButtonEvent::Press).await;
+        button.publish_events(ButtonEvent::Release).await;
+        wait_for(|| light.state() == Some(true)).await;
+
+        button.publish_events(ButtonEvent::Hold).await;
+        button.publish_events(ButtonEvent::Release).await;
+        wait_for(|| light.brightness() == Some(Scene::evening().brightness)).await;
+
+        // the light is left on until the schedule switches it off
+        wait_for(|| light.state() == Some(false)).await;
+        shutdown.cancel();
+    });
+}
+
+/// Wait for the condition to hold, failing the test after [TIMEOUT]
+async fn wait_for(condition: impl Fn() -> bool) {
+    timeout(TIMEOUT, async {
+        while !condition() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the demo did not update the light in time");
+}
@@ -0,0 +1,79 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests that writes are skipped in dry-run mode while sensors keep updating
+
+use control::{Sensor, ToggleValue, WriteValue};
+use macros::DeviceSet;
+use std::time::Duration;
+use testing::{Connection, TestHarness, mock_contact_sensor, run};
+use tintean::automation::Automation;
+use tintean::zigbee::devices::philips::{Light, MockLight};
+use tintean::zigbee::devices::sonoff::ContactSensor;
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
+
+#[derive(DeviceSet)]
+struct Devices {
+    hallway_light: Light,
+    front_door: ContactSensor,
+}
+
+#[derive(DeviceSet)]
+struct Lights {
+    hallway_light: Light,
+}
+
+#[tokio::test]
+async fn writes_skipped() {
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "hallway_light").await;
+            light.publish_state(false).await;
+            let door = mock_contact_sensor(conn, "front_door", true).await;
+            (light, door)
+        })
+        .dry_run(true)
+        .start()
+        .await;
+    let (light, door) = &harness.mocks;
+    let devices = &harness.devices;
+    let manager = harness.manager;
+    assert!(manager.dry_run().is_enabled());
+    let opened = devices.front_door.contact().subscribe().filter(|contact| !*contact);
+    let automation = Automation::new("hallway light", opened, async |_| {
+        devices.hallway_light.state().set(true).await.map_err(|err| err.to_string())
+    });
+    let runs = automation.stats();
+
+    run(manager, [automation], async {
+        door.update("contact", false).await;
+        devices.hallway_light.state().toggle().await.unwrap();
+        sleep(Duration::from_millis(200)).await;
+
+        // the automation still ran and succeeded, but the light never heard about it
+        assert_eq!(runs.succeeded(), 1);
+        assert_eq!(light.state(), Some(false));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn only_dry_run_manager_skips_writes() {
+    let dry: TestHarness<Lights, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| MockLight::new(conn, "hallway_light").await)
+        .dry_run(true)
+        .start()
+        .await;
+    let live: TestHarness<Lights, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| MockLight::new(conn, "hallway_light").await)
+        .start()
+        .await;
+    assert!(!live.manager.dry_run().is_enabled());
+
+    dry.devices.hallway_light.state().set(true).await.unwrap();
+    live.devices.hallway_light.state().set(true).await.unwrap();
+    sleep(Duration::from_millis(200)).await;
+
+    // a manager in dry-run mode does not change the mode of any other manager
+    assert_eq!(dry.mocks.state(), None);
+    assert_eq!(live.mocks.state(), Some(true));
+}
@@ -0,0 +1,103 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests of the energy meter integrating simulated power readings on a paused clock
+
+use control::ReadValue;
+use control::energy::{EnergyMeter, Period};
+use std::time::{Duration, UNIX_EPOCH};
+use testing::{Script, Simulation, advance, pause_time};
+
+/// A minute of simulated time
+const MINUTE: Duration = Duration::from_secs(60);
+/// A day of simulated time
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// 2024-01-31 22:30 UTC, 23:30 in a timezone an hour ahead of UTC
+const BEFORE_MIDNIGHT: Duration = Duration::from_secs(1_706_740_200);
+
+async fn read(meter: &EnergyMeter, period: Period) -> f64 {
+    meter.energy(period).get().await.unwrap()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "expected {expected}kWh, got {actual}kWh");
+}
+
+#[tokio::test]
+async fn integration() {
+    let mut simulation = Simulation::new();
+    let power = simulation.sensor(
+        Script::new()
+            .at(Duration::ZERO, 1000.0)
+            .at(MINUTE * 30, 1000.0)
+            .at(MINUTE * 60, 2000.0),
+    );
+    let meter = EnergyMeter::new(0).with_total(10.0);
+
+    simulation.run([meter.automation("meter", &power)], MINUTE * 90).await;
+
+    // half an hour at 1kW then half an hour ramping from 1kW to 2kW
+    assert_close(read(&meter, Period::Total).await, 10.0 + 0.5 + 0.75);
+}
+
+#[tokio::test]
+async fn summed_sensors() {
+    let mut simulation = Simulation::new();
+    let heater = simulation.sensor(Script::new().at(Duration::ZERO, 2000.0).at(MINUTE * 30, 2000.0));
+    let lamp = simulation.sensor(Script::new().at(Duration::ZERO, 60.0).at(MINUTE * 60, 60.0));
+    let meter = EnergyMeter::new(0);
+
+    simulation
+        .run([meter.automation("heater", &heater), meter.automation("lamp", &lamp)], MINUTE * 90)
+        .await;
+
+    assert_close(read(&meter, Period::Total).await, 1.0 + 0.06);
+}
+
+#[tokio::test]
+async fn gaps_not_integrated() {
+    let mut simulation = Simulation::new();
+    let power = simulation.sensor(
+        Script::new()
+            .at(Duration::ZERO, 1000.0)
+            .at(MINUTE * 15, 1000.0)
+            // the sensor was offline for longer than the meter will integrate over
+            .at(MINUTE * 31, 1000.0)
+            .at(MINUTE * 46, 1000.0),
+    );
+    let meter = EnergyMeter::new(0);
+
+    simulation.run([meter.automation("meter", &power)], MINUTE * 60).await;
+
+    assert_close(read(&meter, Period::Total).await, 0.5);
+}
+
+#[tokio::test]
+async fn rollover() {
+    pause_time();
+    let mut simulation = Simulation::new();
+    let power = simulation.sensor(
+        Script::new()
+            .at(Duration::ZERO, 1000.0)
+            .at(MINUTE * 20, 1000.0)
+            // the energy used is counted in the period of the later reading
+            .at(MINUTE * 40, 1000.0),
+    );
+    let meter = EnergyMeter::new(60).with_start_time(UNIX_EPOCH + BEFORE_MIDNIGHT);
+
+    simulation.run([meter.automation("meter", &power)], MINUTE * 45).await;
+
+    // the meter started on the 31st of January, local time, and the last reading was on the 1st of February
+    let third = 1.0 / 3.0;
+    assert_close(read(&meter, Period::Total).await, 2.0 * third);
+    assert_close(read(&meter, Period::Today).await, third);
+    assert_close(read(&meter, Period::Yesterday).await, third);
+    assert_close(read(&meter, Period::ThisMonth).await, third);
+    assert_close(read(&meter, Period::LastMonth).await, third);
+
+    // a day without readings leaves nothing to report for yesterday
+    advance(DAY * 2).await;
+    assert_close(read(&meter, Period::Today).await, 0.0);
+    assert_close(read(&meter, Period::Yesterday).await, 0.0);
+    assert_close(read(&meter, Period::ThisMonth).await, third);
+    assert_close(read(&meter, Period::LastMonth).await, third);
+}
+
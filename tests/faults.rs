@@ -0,0 +1,39 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests the faults injected into the publishes passing through the test connection
+
+use serde_json::json;
+use std::time::{Duration, Instant};
+use testing::{Direction, Faults, MockDevice, start_mqtt_broker};
+
+#[tokio::test]
+async fn test_faults() {
+    let (conn, _guard) = start_mqtt_broker();
+    let (device, _requests) = MockDevice::connect(&conn, "faulty").await;
+
+    // a delayed publish still arrives, just later
+    conn.set_faults(Faults::builder().direction(Direction::FromDevices).delay(Duration::from_millis(200)).build());
+    let start = Instant::now();
+    let delayed = conn.expect_publish("zigbee2mqtt/faulty", json!({"reading": 1}), Duration::from_secs(1));
+    device.publish(json!({"reading": 1})).await;
+    delayed.await;
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // a held back publish is delivered after the next
+    conn.set_faults(Faults::builder().direction(Direction::FromDevices).reorder(1.0).build());
+    let first = conn.expect_publish("zigbee2mqtt/faulty", json!({"reading": 2}), Duration::from_secs(1));
+    device.publish(json!({"reading": 2})).await;
+    device.publish(json!({"reading": 3})).await;
+    first.await;
+
+    // a dropped publish never arrives
+    conn.set_faults(Faults::builder().direction(Direction::FromDevices).drop(1.0).build());
+    let dropped = tokio::spawn(conn.expect_publish("zigbee2mqtt/faulty", json!({"reading": 4}), Duration::from_millis(300)));
+    device.publish(json!({"reading": 4})).await;
+    assert!(dropped.await.is_err(), "a dropped publish was received");
+
+    // publishes arrive normally once the faults are cleared
+    conn.clear_faults();
+    let received = conn.expect_publish("zigbee2mqtt/faulty", json!({"reading": 5}), Duration::from_secs(1));
+    device.publish(json!({"reading": 5})).await;
+    received.await;
+}
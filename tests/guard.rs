@@ -0,0 +1,76 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests rejecting writes which exceed a rate limit or are blocked by an interlock
+
+use control::{GuardError, Interlock, ToggleValue, ValueExt, WriteValue};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::Duration;
+use testing::{advance, pause_time};
+
+/// A value which records every write made to it
+#[derive(Default)]
+struct Recorder(Mutex<Vec<bool>>);
+
+impl Recorder {
+    fn writes(&self) -> Vec<bool> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl WriteValue for Recorder {
+    type Item = bool;
+
+    fn set(&self, value: Self::Item) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
+        self.0.lock().unwrap().push(value);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl ToggleValue for Recorder {
+    fn toggle(&self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test]
+async fn rate_limit() {
+    pause_time();
+    let heater = Recorder::default();
+    let guarded = (&heater).guarded("towel_heater").with_rate_limit(2, Duration::from_millis(100));
+
+    guarded.set(true).await.unwrap();
+    guarded.set(false).await.unwrap();
+    let error = guarded.set(true).await.unwrap_err();
+    assert!(matches!(error.downcast_ref(), Some(GuardError::RateLimited { max_writes: 2, .. })), "{error}");
+    assert_eq!(heater.writes(), [true, false]);
+
+    // writes are allowed again once the earlier writes fall out of the period
+    advance(Duration::from_millis(99)).await;
+    assert!(guarded.set(true).await.is_err());
+    advance(Duration::from_millis(1)).await;
+    guarded.set(true).await.unwrap();
+    assert_eq!(heater.writes(), [true, false, true]);
+}
+
+#[tokio::test]
+async fn interlock() {
+    let heater = Recorder::default();
+    let leak = Interlock::new("leak");
+    let guarded = (&heater).guarded("towel_heater").with_interlock(&leak, |on| *on);
+
+    // the interlock is engaged until the state of the leak sensor is known
+    assert!(leak.is_engaged());
+    assert!(guarded.set(true).await.is_err());
+    leak.set_engaged(false);
+    guarded.set(true).await.unwrap();
+    leak.set_engaged(true);
+    let error = guarded.set(true).await.unwrap_err();
+    assert!(matches!(error.downcast_ref(), Some(GuardError::Interlocked { interlock, .. }) if interlock == "leak"), "{error}");
+    assert!(guarded.toggle().await.is_err());
+
+    // turning the heater off is still allowed
+    guarded.set(false).await.unwrap();
+    leak.set_engaged(false);
+    guarded.set(true).await.unwrap();
+    assert_eq!(heater.writes(), [true, false, true]);
+}
@@ -0,0 +1,206 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests of batching and downsampling writes to a history backend, against fake backends on a
+//! paused clock
+
+use control::reflect::value::Value;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use testing::{Simulation, advance, pause_time, settle};
+use tintean::history::{Backend, Batching, Downsample, Recorder, Sample, Writer};
+use tokio::join;
+use tokio::time::{Instant, sleep_until};
+use tokio_util::sync::CancellationToken;
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// A backend which records the batches written to it, and fails while told to
+#[derive(Clone, Default)]
+struct Fake {
+    batches: Arc<Mutex<Vec<Vec<u32>>>>,
+    failing: Arc<AtomicBool>,
+}
+
+impl Fake {
+    fn written(&self) -> Vec<u32> {
+        self.batches.lock().unwrap().concat()
+    }
+
+    fn fail(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+}
+
+impl Backend for Fake {
+    type Item = u32;
+
+    fn name(&self) -> String {
+        "fake".to_string()
+    }
+
+    fn write<'a>(&'a self, items: &'a [u32]) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>> {
+        let result = if self.failing.load(Ordering::SeqCst) {
+            Err(anyhow::anyhow!("backend is down"))
+        } else {
+            self.batches.lock().unwrap().push(items.to_vec());
+            Ok(())
+        };
+        Box::pin(async move { result })
+    }
+}
+
+/// Create a writer which has already written it's first item, the first interval ticks as soon as
+/// the writer starts so the batches after this are predictable
+async fn started(backend: &Fake, size: usize, max_buffered: usize) -> Writer<Fake> {
+    pause_time();
+    let writer = Writer::new(
+        backend.clone(),
+        Batching {
+            size,
+            interval: INTERVAL,
+            max_buffered,
+        },
+    );
+    writer.write(0).unwrap();
+    settle().await;
+    advance(INTERVAL).await;
+    assert_eq!(backend.written(), [0]);
+    writer
+}
+
+#[tokio::test]
+async fn batches() {
+    let backend = Fake::default();
+    let writer = started(&backend, 3, 100).await;
+
+    for item in 1..=7 {
+        writer.write(item).unwrap();
+    }
+    settle().await;
+    // full batches are written straight away, the rest waits for the interval
+    assert_eq!(*backend.batches.lock().unwrap(), [vec![0], vec![1, 2, 3], vec![4, 5, 6]]);
+    advance(INTERVAL).await;
+    assert_eq!(backend.written(), [0, 1, 2, 3, 4, 5, 6, 7]);
+}
+
+#[tokio::test]
+async fn retried_on_failure() {
+    let backend = Fake::default();
+    let writer = started(&backend, 3, 100).await;
+
+    backend.fail(true);
+    for item in 1..=4 {
+        writer.write(item).unwrap();
+    }
+    settle().await;
+    advance(INTERVAL).await;
+    assert_eq!(backend.written(), [0], "nothing should be written while the backend is failing");
+
+    // the items are kept until the backend recovers, then written together
+    backend.fail(false);
+    advance(INTERVAL).await;
+    assert_eq!(*backend.batches.lock().unwrap(), [vec![0], vec![1, 2, 3, 4]]);
+}
+
+#[tokio::test]
+async fn drops_oldest_beyond_max_buffered() {
+    let backend = Fake::default();
+    let writer = started(&backend, 100, 5).await;
+
+    backend.fail(true);
+    for item in 1..=8 {
+        writer.write(item).unwrap();
+    }
+    settle().await;
+    advance(INTERVAL).await;
+
+    backend.fail(false);
+    advance(INTERVAL).await;
+    assert_eq!(*backend.batches.lock().unwrap(), [vec![0], vec![4, 5, 6, 7, 8]]);
+}
+
+#[tokio::test]
+async fn flushed_when_dropped() {
+    let backend = Fake::default();
+    let writer = started(&backend, 100, 100).await;
+
+    writer.write(1).unwrap();
+    drop(writer);
+    settle().await;
+    assert_eq!(backend.written(), [0, 1]);
+}
+
+/// A backend which records the value of each sample written to it and when, on tokio's clock
+#[derive(Clone)]
+struct Recorded {
+    start: Instant,
+    samples: Arc<Mutex<Vec<(Duration, Value)>>>,
+}
+
+impl Backend for Recorded {
+    type Item = Sample;
+
+    fn name(&self) -> String {
+        "recorded".to_string()
+    }
+
+    fn write<'a>(&'a self, samples: &'a [Sample]) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>> {
+        let at = self.start.elapsed();
+        self.samples.lock().unwrap().extend(samples.iter().map(|sample| (at, sample.value.clone())));
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn downsampled_on_change() {
+    let start = Instant::now();
+    let backend = Recorded { start, samples: Arc::default() };
+    let recorder = Recorder::builder()
+        .backend(backend.clone())
+        // each sample is written as soon as it's recorded
+        .batching(Batching { size: 1, interval: INTERVAL, max_buffered: 100 })
+        .downsample(
+            "meter",
+            "power",
+            Downsample::OnChange {
+                min_interval: Duration::from_secs(10),
+                threshold: 0.5,
+                max_interval: Some(Duration::from_secs(60)),
+            },
+        )
+        .build();
+    let power = Simulation::new().value("power", 20.0);
+    let token = CancellationToken::new();
+
+    join!(recorder.record_sensor("meter", "power", &power, token.clone()), async {
+        for (at, value) in [
+            // within the threshold of the last value recorded
+            (2, 20.3),
+            // within the minimum interval, so recorded once it passes
+            (4, 21.0),
+            (20, 21.2),
+            (40, 25.0),
+            // changed back within the threshold before the minimum interval passed
+            (45, 26.0),
+            (47, 25.1),
+        ] {
+            sleep_until(start + Duration::from_secs(at)).await;
+            power.change(value);
+        }
+        sleep_until(start + Duration::from_secs(110)).await;
+        token.cancel();
+    });
+
+    let recorded = backend.samples.lock().unwrap().clone();
+    assert_eq!(
+        recorded,
+        [
+            (Duration::ZERO, Value::Float(20.0)),
+            (Duration::from_secs(10), Value::Float(21.0)),
+            (Duration::from_secs(40), Value::Float(25.0)),
+            // unchanged for the maximum interval
+            (Duration::from_secs(100), Value::Float(25.0)),
+        ]
+    );
+}
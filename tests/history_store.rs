@@ -0,0 +1,254 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests of the history backends which can be queried, each test is a function generic over the
+//! backend so every backend is held to the same behaviour
+//!
+//! The Postgres tests need a database to run against, they are skipped unless
+//! `HISTORY_POSTGRES_URL` is set, eg: `HISTORY_POSTGRES_URL="host=localhost user=home dbname=test"`
+//! along with `--features sqlite,postgres`
+
+use control::reflect::value::Value;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tintean::history::{Backend, Query, Sample};
+
+/// 2023-11-14 22:13:20 UTC, an arbitrary start for the recorded samples
+const START: Duration = Duration::from_secs(1_700_000_000);
+
+fn at(seconds: u64) -> SystemTime {
+    UNIX_EPOCH + START + Duration::from_secs(seconds)
+}
+
+fn sample(seconds: u64, device: &str, attribute: &str, value: Value) -> Sample {
+    Sample {
+        timestamp: at(seconds),
+        device: device.to_string(),
+        attribute: attribute.to_string(),
+        tags: Arc::new(vec![("room".to_string(), "kitchen".to_string())]),
+        value,
+    }
+}
+
+/// Returns a device name unique to this run, so tests against a persistent database don't see
+/// the samples of earlier runs
+fn unique(device: &str) -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    format!("{device}-{}-{nanos}", std::process::id())
+}
+
+async fn round_trip<B: Backend<Item = Sample> + Query>(backend: &B) {
+    let device = unique("round_trip");
+    let values = [
+        Value::Bool(true),
+        Value::Bool(false),
+        Value::Int(-3),
+        Value::Float(21.5),
+        Value::String("heat".to_string()),
+    ];
+    let samples: Vec<_> = (0..).zip(&values).map(|(seconds, value)| sample(seconds, &device, "value", value.clone())).collect();
+    backend.write(&samples).await.unwrap();
+
+    let read = backend.range(&device, "value", at(0)..at(60)).await.unwrap();
+    let expected: Vec<_> = (0..).zip(values).map(|(seconds, value)| (at(seconds), value)).collect();
+    assert_eq!(read, expected);
+}
+
+async fn range_bounds<B: Backend<Item = Sample> + Query>(backend: &B) {
+    let device = unique("range_bounds");
+    let other = unique("range_bounds_other");
+    backend
+        .write(&[
+            sample(0, &device, "temperature", Value::Float(20.0)),
+            sample(10, &device, "temperature", Value::Float(21.0)),
+            sample(20, &device, "temperature", Value::Float(22.0)),
+            sample(30, &device, "temperature", Value::Float(23.0)),
+            // neither another attribute nor another device are included
+            sample(10, &device, "humidity", Value::Float(40.0)),
+            sample(10, &other, "temperature", Value::Float(0.0)),
+        ])
+        .await
+        .unwrap();
+
+    // the start of the range is included and the end is not
+    let read = backend.range(&device, "temperature", at(10)..at(30)).await.unwrap();
+    assert_eq!(read, [(at(10), Value::Float(21.0)), (at(20), Value::Float(22.0))]);
+    assert!(backend.range(&device, "temperature", at(31)..at(60)).await.unwrap().is_empty());
+}
+
+async fn latest<B: Backend<Item = Sample> + Query>(backend: &B) {
+    let device = unique("latest");
+    // written out of order, the latest is by timestamp rather than by when it was written
+    backend
+        .write(&[
+            sample(20, &device, "temperature", Value::Float(22.0)),
+            sample(30, &device, "temperature", Value::Float(23.0)),
+            sample(10, &device, "temperature", Value::Float(21.0)),
+            sample(0, &device, "mode", Value::String("heat".to_string())),
+            sample(5, &device, "mode", Value::String("off".to_string())),
+        ])
+        .await
+        .unwrap();
+
+    assert_eq!(backend.latest(&device, "temperature").await.unwrap(), Some((at(30), Value::Float(23.0))));
+    assert_eq!(backend.latest(&device, "mode").await.unwrap(), Some((at(5), Value::String("off".to_string()))));
+    assert_eq!(backend.latest(&device, "humidity").await.unwrap(), None);
+}
+
+mod sqlite {
+    use super::*;
+    use control::ReadValue;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use tintean::history::export::{Format, export};
+    use tintean::history::{Prune, Retention, Sqlite};
+
+    /// Returns a path for a database file which doesn't exist yet
+    fn database(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("tintean-{name}-{}.sqlite", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        super::round_trip(&Sqlite::in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn range_bounds() {
+        super::range_bounds(&Sqlite::in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn latest() {
+        super::latest(&Sqlite::in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn migrated_once() {
+        let path = database("migrated_once");
+        let history = Sqlite::open(&path).unwrap();
+        history.write(&[sample(0, "thermostat", "setpoint", Value::Float(21.0))]).await.unwrap();
+        drop(history);
+        // reopening the database doesn't apply the migrations again, which would fail
+        let history = Sqlite::open(&path).unwrap();
+        let latest = history.latest("thermostat", "setpoint").await.unwrap();
+        drop(history);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(latest, Some((at(0), Value::Float(21.0))));
+    }
+
+    #[tokio::test]
+    async fn last_value_restored() {
+        let path = database("last_value_restored");
+        let history = Sqlite::open(&path).unwrap();
+        history
+            .write(&[
+                sample(0, "thermostat", "setpoint", Value::Float(19.0)),
+                sample(10, "thermostat", "setpoint", Value::Float(21.0)),
+            ])
+            .await
+            .unwrap();
+        drop(history);
+
+        // as if after a restart
+        let history = Sqlite::open(&path).unwrap();
+        let setpoint = history.last_value::<f64>("thermostat", "setpoint").get().await.unwrap();
+        let unrecorded = history.last_value::<f64>("thermostat", "humidity").get().await.unwrap();
+        drop(history);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(setpoint, Some(21.0));
+        assert_eq!(unrecorded, None);
+    }
+
+    #[tokio::test]
+    async fn pruned() {
+        let history = Sqlite::in_memory().unwrap();
+        let now = SystemTime::now();
+        let recorded = |minutes_ago: u64, attribute: &str| Sample {
+            timestamp: now - Duration::from_secs(minutes_ago * 60),
+            device: "thermostat".to_string(),
+            attribute: attribute.to_string(),
+            tags: Arc::default(),
+            value: Value::Int(minutes_ago.try_into().unwrap()),
+        };
+        history
+            .write(&[
+                recorded(120, "temperature"),
+                recorded(90, "temperature"),
+                recorded(30, "temperature"),
+                recorded(20, "temperature"),
+                recorded(10, "temperature"),
+                recorded(120, "humidity"),
+                recorded(5, "humidity"),
+            ])
+            .await
+            .unwrap();
+        let remaining = async |attribute: &str| -> Vec<Value> {
+            let samples = history.range("thermostat", attribute, UNIX_EPOCH..now + Duration::from_secs(1)).await.unwrap();
+            samples.into_iter().map(|(_, value)| value).collect()
+        };
+
+        // samples either side of the cutoff
+        let by_age = Retention { max_age: Some(Duration::from_secs(60 * 60)), max_rows: None };
+        assert_eq!(history.prune(by_age).await.unwrap(), 3);
+        assert_eq!(remaining("temperature").await, [Value::Int(30), Value::Int(20), Value::Int(10)]);
+        assert_eq!(remaining("humidity").await, [Value::Int(5)]);
+
+        // the limit on rows applies to each attribute on it's own
+        let by_rows = Retention { max_age: None, max_rows: Some(2) };
+        assert_eq!(history.prune(by_rows).await.unwrap(), 1);
+        assert_eq!(remaining("temperature").await, [Value::Int(20), Value::Int(10)]);
+        assert_eq!(remaining("humidity").await, [Value::Int(5)]);
+
+        // nothing is left to prune
+        assert_eq!(history.prune(Retention { max_age: by_age.max_age, max_rows: by_rows.max_rows }).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn exported_to_csv() {
+        let history = Sqlite::in_memory().unwrap();
+        history
+            .write(&[
+                sample(0, "thermostat", "setpoint", Value::Float(21.5)),
+                sample(10, "thermostat", "setpoint", Value::Bool(true)),
+                sample(20, "thermostat", "setpoint", Value::String("said \"hi\", twice".to_string())),
+                sample(30, "thermostat", "setpoint", Value::Int(-3)),
+                // after the end of the range
+                sample(40, "thermostat", "setpoint", Value::Float(22.0)),
+            ])
+            .await
+            .unwrap();
+
+        let mut output = Vec::new();
+        let exported = export(&history, "thermostat", "setpoint", at(0)..at(40), Format::Csv, &mut output).await.unwrap();
+        assert_eq!(exported, 4);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "timestamp,value\n\
+            2023-11-14T22:13:20.000Z,21.5\n\
+            2023-11-14T22:13:30.000Z,true\n\
+            2023-11-14T22:13:40.000Z,\"said \"\"hi\"\", twice\"\n\
+            2023-11-14T22:13:50.000Z,-3\n"
+        );
+    }
+}
+
+#[cfg(feature = "postgres")]
+mod postgres {
+    use std::env;
+    use tintean::history::Postgres;
+
+    /// A single test so the migrations aren't raced by several connections to a fresh database
+    #[tokio::test]
+    async fn shared() {
+        let Ok(url) = env::var("HISTORY_POSTGRES_URL") else {
+            eprintln!("HISTORY_POSTGRES_URL is not set, skipping");
+            return;
+        };
+        let backend = Postgres::connect(&url, false).await.unwrap();
+        super::round_trip(&backend).await;
+        super::range_bounds(&backend).await;
+        super::latest(&backend).await;
+    }
+}
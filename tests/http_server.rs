@@ -1,16 +1,10 @@
 #![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
-//! A working test which mocks some simple devices
-//!
-//! This test is designed to ensure that automations are triggered and running properly in the general case
+//! Tests serving a router through the web server service alongside a device manager
 
-use async_scoped::TokioScope;
 use control::Manager;
 use log::Level;
-use rumqttc::MqttOptions;
 use simple_log::LogConfigBuilder;
-use std::time::Duration;
-use testing::{mock_philips_button, mock_philips_light, start_mqtt_broker};
-use tokio::time::sleep;
+use testing::{run, start_mqtt_broker};
 use tintean::web::axum::Router;
 use web::axum::routing::get;
 use web::WebServer;
@@ -27,11 +21,7 @@ async fn http_server() {
     .expect("failed to start logger");
     let (conn, _guard) = start_mqtt_broker();
 
-    let mut mock_button = mock_philips_button(&conn, "test_button").await;
-    let mock_light = mock_philips_light(&conn, "test_light", true, 254).await;
-
-    let mut mqttoptions = MqttOptions::new("rumqtt-sync", "localhost", 1883);
-    mqttoptions.set_keep_alive(Duration::from_secs(5));
+    let mqttoptions = conn.mqtt_options("rumqtt-sync");
 
     let mut manager = Manager::builder()
         .add_device_manager(zigbee::Manager::builder()
@@ -47,20 +37,12 @@ async fn http_server() {
                 .build()
         );
     // let devices: Devices = manager.create().await.unwrap();
-    TokioScope::scope_and_block(|scope| {
-        scope.spawn(async move {
-            sleep(Duration::from_millis(50)).await;
-            assert!(mock_light.state());
-            mock_button.action("off").await;
-            assert!(!mock_light.state());
-            mock_button.action("on").await;
-            assert!(mock_light.state());
-        });
-        scope.spawn(async move {
-            let manager = manager;
-            manager.start([]).await;
-        });
-    });
+
+    run(manager, [], async {
+        let response = reqwest::get("http://127.0.0.1:8088/").await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "Hello world");
+    })
+    .await;
 }
 
 // #[derive(DeviceSet)]
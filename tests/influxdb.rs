@@ -0,0 +1,34 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests encoding points in the InfluxDB line protocol
+
+use tintean::influxdb::Point;
+
+#[test]
+fn escaping() {
+    let point = Point::new("living room,temp")
+        .tag("room name", "a b=c,d")
+        .tag("empty", "")
+        .field("temp value", 21.5)
+        .field("on", true)
+        .field("count", 3)
+        .field("name", r#"say "hi" \"#)
+        .timestamp(1_000);
+    assert_eq!(
+        point.to_string(),
+        r#"living\ room\,temp,room\ name=a\ b\=c\,d temp\ value=21.5,on=true,count=3i,name="say \"hi\" \\" 1000"#
+    );
+}
+
+#[test]
+fn non_finite_fields_skipped() {
+    let point = Point::new("power")
+        .field("nan", f64::NAN)
+        .field("watts", 1.0)
+        .field("infinite", f64::INFINITY)
+        .timestamp(1_000);
+    assert!(point.is_writable());
+    assert_eq!(point.to_string(), "power watts=1 1000");
+
+    let point = Point::new("power").field("watts", f64::NEG_INFINITY);
+    assert!(!point.is_writable());
+}
@@ -0,0 +1,79 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests telling writes made by automations apart from manual changes, and suppressing automations
+//! after a manual change
+
+use control::{ManualOverride, Sensor, WriteValue};
+use std::time::Duration;
+use testing::{Report, Script, Simulation};
+use tintean::automation::Automation;
+
+/// A minute of simulated time
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// The writes made to the named value, along with when they were made
+fn writes<'a>(report: &'a Report, device: &'a str) -> Vec<(Duration, &'a str)> {
+    report.writes_to(device).map(|write| (write.at, write.value.as_str())).collect()
+}
+
+#[tokio::test]
+async fn suppressed_by_manual_change() {
+    let mut simulation = Simulation::new();
+    let motion = simulation.sensor(
+        Script::new()
+            .at(MINUTE, 1)
+            .at(MINUTE * 3, 2)
+            .at(MINUTE * 6, 3)
+            .at(MINUTE * 16, 4),
+    );
+    // a person switches the light off by hand after five minutes
+    let switch = simulation.sensor(Script::new().at(MINUTE * 5, false));
+    let light = ManualOverride::new(simulation.value("light", false), MINUTE * 10);
+
+    let automations = [
+        light.monitor("light override"),
+        Automation::new("motion", light.suppress(motion.subscribe()), async |_| {
+            light.set(true).await.map_err(|err| err.to_string())
+        }),
+        Automation::new("person", switch.subscribe(), async |on| {
+            light.inner().change(on);
+            Ok(())
+        }),
+    ];
+    let report = simulation.run(automations, MINUTE * 20).await;
+
+    // the light turning on after each write is not a manual change, so the second motion still
+    // turns it on, the motion while switched off by hand is suppressed until the period is over
+    assert_eq!(writes(&report, "light"), [(MINUTE, "true"), (MINUTE * 3, "true"), (MINUTE * 16, "true")]);
+    assert!(!light.is_overridden(), "the override should have expired");
+}
+
+#[tokio::test]
+async fn echo_window() {
+    let mut simulation = Simulation::new();
+    let motion = simulation.sensor(Script::new().at(MINUTE, 1).at(MINUTE * 2, 2).at(MINUTE * 3, 3));
+    // changes reported soon after a write, eg: the light settling on a state, are not manual
+    let switch = simulation.sensor(
+        Script::new()
+            .at(MINUTE + Duration::from_secs(4), false)
+            .at(MINUTE * 2 + Duration::from_secs(6), false),
+    );
+    let light = ManualOverride::new(simulation.value("light", false), MINUTE * 10)
+        .with_echo_window(Duration::from_secs(5));
+
+    let automations = [
+        light.monitor("light override"),
+        Automation::new("motion", light.suppress(motion.subscribe()), async |_| {
+            light.set(true).await.map_err(|err| err.to_string())
+        }),
+        Automation::new("person", switch.subscribe(), async |on| {
+            light.inner().change(on);
+            Ok(())
+        }),
+    ];
+    let report = simulation.run(automations, MINUTE * 4).await;
+
+    // the change 4 seconds after the first write is within the echo window, the change 6 seconds
+    // after the second is not, so the third motion is suppressed
+    assert_eq!(writes(&report, "light"), [(MINUTE, "true"), (MINUTE * 2, "true")]);
+    assert!(light.is_overridden());
+}
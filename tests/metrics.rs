@@ -0,0 +1,155 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests the Prometheus exporter against a device which answers some reads and not others, and
+//! against sensors which are not fields of any device
+
+use control::{Sensor, Service};
+use control::reflect::value::{Value, ValueType};
+use control::reflect::{Device, DeviceInfo, DeviceType, Error, Field, Operation, Operations, SetError};
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::time::Duration;
+use tintean::metrics::Exporter;
+use tokio::spawn;
+use tokio::time::{Instant, sleep};
+use tokio_stream::{Stream, StreamExt};
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + 'a>>;
+
+/// A device with a field of each kind the exporter treats differently
+struct Bench;
+
+fn field(name: &str, value_type: ValueType, subscribe: bool, get: bool) -> Field {
+    Field {
+        name: name.to_string(),
+        description: String::new(),
+        operations: Operations {
+            subscribe,
+            get,
+            set: !subscribe && !get,
+            toggle: false,
+        },
+        value_type,
+    }
+}
+
+impl Device for Bench {
+    fn info(&self) -> DeviceInfo {
+        DeviceInfo {
+            id: "bench".to_string(),
+            name: "bench".to_string(),
+            description: None,
+            device_type: DeviceType::Other,
+            tags: Default::default(),
+        }
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            field("temperature", ValueType::Float, false, true),
+            field("on", ValueType::Bool, true, false),
+            field("mode", ValueType::String { values: None }, false, true),
+            // never answers, the scrape should go on without it
+            field("stuck", ValueType::Float, false, true),
+            // can only be set, so there is nothing to export
+            field("target", ValueType::Float, false, false),
+        ]
+    }
+
+    fn subscribe(&self, field: &str) -> Result<BoxFuture<'_, BoxStream<'_, Value>>, Error> {
+        assert_eq!(field, "on", "only subscribable fields should be subscribed to");
+        let stream: BoxStream<'_, Value> = Box::pin(tokio_stream::iter([Value::Bool(true)]).chain(tokio_stream::pending()));
+        Ok(Box::pin(async move { stream }))
+    }
+
+    fn get(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<Value>>, Error> {
+        let value = match field {
+            "temperature" => Value::Float(21.5),
+            "mode" => Value::String("heat".to_string()),
+            "stuck" => return Ok(Box::pin(std::future::pending())),
+            field => panic!("{field} should not be read"),
+        };
+        Ok(Box::pin(async move { Ok(value) }))
+    }
+
+    fn set(&self, field: &str, _: Value) -> Result<BoxFuture<'_, anyhow::Result<()>>, SetError> {
+        panic!("{field} should not be set")
+    }
+
+    fn toggle(&self, field: &str) -> Result<BoxFuture<'_, anyhow::Result<()>>, Error> {
+        Err(Error::OperationNotSupported {
+            device: "bench".to_string(),
+            field: field.to_string(),
+            operation: Operation::Toggle,
+        })
+    }
+}
+
+#[tokio::test]
+async fn rendered() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let exporter = Exporter::builder().bind_address("127.0.0.1").port(port).add_device(Bench).build();
+    spawn(exporter.start());
+    sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    let response = reqwest::get(format!("http://127.0.0.1:{port}/metrics")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "text/plain; version=0.0.4");
+    let body = response.text().await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5), "the stuck field should have timed out");
+    assert_eq!(
+        body,
+        "# HELP tintean_device_value The current value of each numeric or boolean device attribute\n\
+        # TYPE tintean_device_value gauge\n\
+        tintean_device_value{device=\"bench\",attribute=\"temperature\"} 21.5\n\
+        tintean_device_value{device=\"bench\",attribute=\"on\"} 1\n\
+        # HELP tintean_device_state The current state of each textual device attribute, the value is always 1\n\
+        # TYPE tintean_device_state gauge\n\
+        tintean_device_state{device=\"bench\",attribute=\"mode\",state=\"heat\"} 1\n"
+    );
+}
+
+/// A sensor which reports a single value then goes quiet
+struct Once<T>(T);
+
+impl<T: Clone + Send + 'static> Sensor for Once<T> {
+    type Item = T;
+
+    fn subscribe(&self) -> BoxStream<'_, T> {
+        Box::pin(tokio_stream::iter([self.0.clone()]).chain(tokio_stream::pending()))
+    }
+}
+
+#[tokio::test]
+async fn sensors() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let outdoor = Once(12.5);
+    let occupied = Once(true);
+    let season = Once("winter".to_string());
+    let exporter = Exporter::builder()
+        .bind_address("127.0.0.1")
+        .port(port)
+        .add_sensor("outdoor_temperature", &outdoor)
+        .add_sensor("occupied", &occupied)
+        .add_sensor("season", &season)
+        .build();
+    // the sensors are borrowed, so the exporter runs on this task rather than being spawned
+    let scrape = async {
+        sleep(Duration::from_millis(100)).await;
+        reqwest::get(format!("http://127.0.0.1:{port}/metrics")).await.unwrap().text().await.unwrap()
+    };
+    let body = tokio::select! {
+        result = exporter.start() => panic!("the exporter stopped: {result:?}"),
+        body = scrape => body,
+    };
+    assert_eq!(
+        body,
+        "# HELP tintean_sensor_value The current value of each numeric or boolean sensor\n\
+        # TYPE tintean_sensor_value gauge\n\
+        tintean_sensor_value{sensor=\"outdoor_temperature\"} 12.5\n\
+        tintean_sensor_value{sensor=\"occupied\"} 1\n\
+        # HELP tintean_sensor_state The current state of each textual sensor, the value is always 1\n\
+        # TYPE tintean_sensor_state gauge\n\
+        tintean_sensor_state{sensor=\"season\",state=\"winter\"} 1\n"
+    );
+}
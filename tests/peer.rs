@@ -0,0 +1,68 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests sharing state and handing over zones between two controllers
+
+use control::{ReadValue, Sensor, Service};
+use std::pin::Pin;
+use std::time::Duration;
+use testing::start_mqtt_broker;
+use tintean::mqtt::Peer;
+use tokio::spawn;
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout};
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::WatchStream;
+
+/// How long to wait for the other controller to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A flag set by the test, eg: whether anyone is home
+#[derive(Default)]
+struct Flag(watch::Sender<bool>);
+
+impl Sensor for Flag {
+    type Item = bool;
+
+    fn subscribe(&self) -> Pin<Box<dyn Stream<Item = Self::Item> + Send + '_>> {
+        Box::pin(WatchStream::new(self.0.subscribe()))
+    }
+}
+
+#[tokio::test]
+async fn peers() {
+    let (conn, _guard) = start_mqtt_broker();
+    let anyone_home: &'static Flag = Box::leak(Box::default());
+
+    let house = Peer::builder()
+        .mqtt_options(conn.mqtt_options("peer-house"))
+        .controller("house")
+        .add_sensor("anyone_home", anyone_home)
+        .build();
+    let outbuilding = Peer::builder()
+        .mqtt_options(conn.mqtt_options("peer-outbuilding"))
+        .controller("outbuilding")
+        .build();
+    let house_zone = house.zone("outbuilding", ["outbuilding", "house"]);
+    let outbuilding_zone = outbuilding.zone("outbuilding", ["outbuilding", "house"]);
+    let remote = outbuilding.remote::<bool>("house", "anyone_home");
+
+    let house = spawn(house.start());
+    let outbuilding = spawn(outbuilding.start());
+    sleep(Duration::from_millis(200)).await;
+
+    // the outbuilding owns it's zone while it is up
+    assert!(outbuilding_zone.is_active());
+    assert!(!house_zone.is_active());
+    assert_eq!(house_zone.owner().as_deref(), Some("outbuilding"));
+
+    assert!(!remote.get().await.unwrap());
+    let mut updates = remote.subscribe();
+    anyone_home.0.send_replace(true);
+    assert_eq!(timeout(TIMEOUT, updates.next()).await.unwrap(), Some(true));
+
+    // the house takes over once the outbuilding's connection drops
+    let mut ownership = house_zone.subscribe();
+    outbuilding.abort();
+    assert_eq!(timeout(TIMEOUT, ownership.next()).await.unwrap(), Some(true));
+    assert_eq!(house_zone.owner().as_deref(), Some("house"));
+    house.abort();
+}
@@ -0,0 +1,262 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests of the recipes, mostly running them against simulated devices
+
+use control::recipes::{
+    Circadian, LuxGate, PidController, PidGains, PresenceSimulation, ScheduleEntry, motion_light, pid_thermostat,
+};
+use control::{ManualOverride, Percentage, Sensor};
+use std::time::{Duration, UNIX_EPOCH};
+use testing::{Report, Script, Simulation, pause_time};
+use tintean::automation::Automation;
+
+/// A minute of simulated time
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// An hour since local midnight
+const HOUR: Duration = Duration::from_secs(60 * 60);
+/// A second of time
+const SECOND: Duration = Duration::from_secs(1);
+/// 2024-02-01 00:00 UTC
+const MIDNIGHT: Duration = Duration::from_secs(1_706_745_600);
+
+/// The writes made to the named value, along with when they were made
+fn writes<'a>(report: &'a Report, device: &'a str) -> Vec<(Duration, &'a str)> {
+    report.writes_to(device).map(|write| (write.at, write.value.as_str())).collect()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "expected {expected}, got {actual}");
+}
+
+fn gains(kp: f64, ki: f64, kd: f64) -> PidGains {
+    PidGains { kp, ki, kd }
+}
+
+#[tokio::test]
+async fn same_recipe_twice() {
+    let mut simulation = Simulation::new();
+    let hall_motion = simulation.sensor(Script::new().at(MINUTE, true));
+    let landing_motion = simulation.sensor(Script::new().at(MINUTE * 2, true));
+    let hall_light = ManualOverride::new(simulation.value("hall_light", false), MINUTE * 30);
+    let landing_light = ManualOverride::new(simulation.value("landing_light", false), MINUTE * 30);
+
+    // each automation is given it's own name, so neither replaces the other
+    let report = simulation
+        .run(
+            [
+                motion_light("hall_light", &hall_motion, &hall_light, MINUTE * 5, None),
+                motion_light("landing_light", &landing_motion, &landing_light, MINUTE * 5, None),
+            ],
+            MINUTE * 10,
+        )
+        .await;
+
+    assert_eq!(writes(&report, "hall_light"), [(MINUTE, "true"), (MINUTE * 6, "false")]);
+    assert_eq!(writes(&report, "landing_light"), [(MINUTE * 2, "true"), (MINUTE * 7, "false")]);
+}
+
+#[tokio::test]
+async fn motion_light_off_timer() {
+    let mut simulation = Simulation::new();
+    // motion while the light is on restarts the off timer, motion ending does not stop it
+    let motion = simulation.sensor(
+        Script::new()
+            .at(MINUTE, true)
+            .at(MINUTE * 2, false)
+            .at(MINUTE * 4, true)
+            .at(MINUTE * 5, false),
+    );
+    let light = ManualOverride::new(simulation.value("light", false), MINUTE * 30);
+
+    let report = simulation
+        .run([motion_light("motion_light", &motion, &light, MINUTE * 5, None)], MINUTE * 20)
+        .await;
+
+    assert_eq!(writes(&report, "light"), [(MINUTE, "true"), (MINUTE * 9, "false")]);
+}
+
+#[tokio::test]
+async fn motion_light_lux_gate() {
+    let mut simulation = Simulation::new();
+    let motion = simulation.sensor(Script::new().at(MINUTE, true).at(MINUTE * 11, true));
+    let lux = simulation.sensor(Script::new().at(Duration::ZERO, 500_u32).at(MINUTE * 10, 50));
+    let light = ManualOverride::new(simulation.value("light", false), MINUTE * 30);
+    let gate = LuxGate {
+        sensor: &lux,
+        max_lux: 100,
+    };
+
+    let report = simulation
+        .run([motion_light("motion_light", &motion, &light, MINUTE * 5, Some(gate))], MINUTE * 20)
+        .await;
+
+    // the first motion is while the room is bright, so only the second turns the light on
+    assert_eq!(writes(&report, "light"), [(MINUTE * 11, "true"), (MINUTE * 16, "false")]);
+}
+
+#[tokio::test]
+async fn motion_light_manual_override() {
+    let mut simulation = Simulation::new();
+    let motion = simulation.sensor(Script::new().at(MINUTE, true).at(MINUTE * 8, true).at(MINUTE * 19, true));
+    // a person switches the light off by hand while it is on
+    let switch = simulation.sensor(Script::new().at(MINUTE * 3, false));
+    let light = ManualOverride::new(simulation.value("light", false), MINUTE * 15);
+
+    let automations = [
+        motion_light("motion_light", &motion, &light, MINUTE * 5, None),
+        Automation::new("person", switch.subscribe(), async |on| {
+            light.inner().change(on);
+            Ok(())
+        }),
+    ];
+    let report = simulation.run(automations, MINUTE * 25).await;
+
+    // the light turning on after the first write is not a manual change, the switch is, so both
+    // the off timer and the second motion are ignored until the override is over
+    assert_eq!(
+        writes(&report, "light"),
+        [(MINUTE, "true"), (MINUTE * 19, "true"), (MINUTE * 24, "false")]
+    );
+}
+
+#[test]
+fn pid_output_clamped() {
+    let mut pid = PidController::new(gains(1.0, 0.0, 0.0));
+    assert_close(pid.update(0.25, SECOND), 0.25);
+    assert_close(pid.update(2.0, SECOND), 1.0);
+    assert_close(pid.update(-1.0, SECOND), 0.0);
+}
+
+#[test]
+fn pid_integral() {
+    let mut pid = PidController::new(gains(0.0, 0.01, 0.0));
+    assert_close(pid.update(1.0, SECOND * 10), 0.1);
+    assert_close(pid.update(1.0, SECOND * 10), 0.2);
+    assert_close(pid.update(-1.0, SECOND * 10), 0.1);
+}
+
+#[test]
+fn pid_anti_windup() {
+    let mut pid = PidController::new(gains(0.0, 0.1, 0.0));
+    // a large error saturates the output, so the integral is not accumulated
+    assert_close(pid.update(100.0, SECOND * 60), 1.0);
+    assert_close(pid.update(100.0, SECOND * 60), 1.0);
+    // without anti-windup the integral would keep the output saturated long after the error falls
+    assert_close(pid.update(1.0, SECOND), 0.1);
+
+    // the same goes for an output saturated at zero
+    let mut pid = PidController::new(gains(0.0, 0.1, 0.0));
+    assert_close(pid.update(-100.0, SECOND * 60), 0.0);
+    assert_close(pid.update(1.0, SECOND), 0.1);
+}
+
+#[test]
+fn pid_derivative() {
+    let mut pid = PidController::new(gains(0.0, 0.0, 10.0));
+    // there is no rate of change until the second update
+    assert_close(pid.update(0.5, SECOND), 0.0);
+    assert_close(pid.update(0.55, SECOND), 0.5);
+    // nor when no time has passed
+    assert_close(pid.update(0.6, Duration::ZERO), 0.0);
+}
+
+#[tokio::test]
+async fn pid_thermostat_duty_cycle() {
+    let mut simulation = Simulation::new();
+    // half a degree below the setpoint, then above it from the second cycle on
+    let temperature = simulation.sensor(Script::new().at(Duration::ZERO, 19.5).at(MINUTE * 11, 21.0));
+    let heater = simulation.value("heater", false);
+
+    let report = simulation
+        .run(
+            [pid_thermostat("heating", &temperature, &heater, 20.0, gains(0.5, 0.0, 0.0), MINUTE * 10)],
+            MINUTE * 21,
+        )
+        .await;
+
+    // the heater runs for a quarter of each cycle, the output is only recalculated at the start of
+    // a cycle so the rise in temperature during the second cycle only leaves the third off
+    assert_eq!(
+        writes(&report, "heater"),
+        [
+            (Duration::ZERO, "true"),
+            (MINUTE * 5 / 2, "false"),
+            (MINUTE * 10, "true"),
+            (MINUTE * 25 / 2, "false"),
+            (MINUTE * 20, "false"),
+        ]
+    );
+}
+
+#[test]
+fn circadian_curve() {
+    let curve = Circadian::builder().build();
+    let night = (2200, Percentage::new(30));
+    assert_eq!(curve.at(Duration::ZERO), night);
+    assert_eq!(curve.at(HOUR * 3), night);
+    // the curve starts from the night values at sunrise and returns to them at sunset
+    assert_eq!(curve.at(HOUR * 7), night);
+    assert_eq!(curve.at(HOUR * 19), night);
+    assert_eq!(curve.at(HOUR * 23), night);
+    // and peaks at midday, half way between them
+    assert_eq!(curve.at(HOUR * 13), (5500, Percentage::new(100)));
+    // following a sine curve either side, sin(π/4) of the way from the night values
+    assert_eq!(curve.at(HOUR * 10), (4533, Percentage::new(79)));
+    assert_eq!(curve.at(HOUR * 16), (4533, Percentage::new(79)));
+}
+
+#[test]
+fn circadian_custom_curve() {
+    let curve = Circadian::builder()
+        .sunrise(HOUR * 6)
+        .sunset(HOUR * 18)
+        .min_kelvin(2700)
+        .max_kelvin(6500)
+        .min_brightness(10)
+        .max_brightness(90)
+        .build();
+    assert_eq!(curve.at(HOUR * 5), (2700, Percentage::new(10)));
+    assert_eq!(curve.at(HOUR * 12), (6500, Percentage::new(90)));
+    // the curve rises steeply after sunrise, sin(π/12) of the way after an hour
+    assert_eq!(curve.at(HOUR * 7), (3684, Percentage::new(31)));
+}
+
+#[tokio::test]
+async fn presence_across_midnight() {
+    pause_time();
+    let mut simulation = Simulation::new();
+    let light = simulation.value("light", false);
+    let mut presence = PresenceSimulation::new(Duration::ZERO, 0).with_start_time(UNIX_EPOCH + MIDNIGHT - MINUTE * 30);
+    presence.add_light(
+        &light,
+        vec![
+            ScheduleEntry { time_of_day: HOUR * 24 - SECOND * 30, on: true },
+            ScheduleEntry { time_of_day: SECOND * 30, on: false },
+        ],
+    );
+    presence.set_away(true);
+
+    let report = simulation.run([presence.automation("presence")], MINUTE * 60).await;
+
+    // the schedule is checked each minute, the first check after midnight still sees the change
+    // due just before it
+    assert_eq!(writes(&report, "light"), [(MINUTE * 30, "true"), (MINUTE * 31, "false")]);
+}
+
+#[tokio::test]
+async fn presence_jittered_across_midnight() {
+    pause_time();
+    let mut simulation = Simulation::new();
+    let light = simulation.value("light", false);
+    let mut presence = PresenceSimulation::new(HOUR, 0).with_start_time(UNIX_EPOCH + MIDNIGHT - HOUR * 2);
+    presence.add_light(&light, vec![ScheduleEntry { time_of_day: HOUR * 24 - SECOND * 30, on: true }]);
+    presence.set_away(true);
+
+    let report = simulation.run([presence.automation("presence")], HOUR * 4).await;
+
+    // wherever the jitter moves the change to, either side of midnight, it happens exactly once
+    let writes = writes(&report, "light");
+    assert_eq!(writes.len(), 1, "{writes:?}");
+    assert_eq!(writes[0].1, "true");
+    assert!(HOUR < writes[0].0 && writes[0].0 <= HOUR * 3, "{writes:?}");
+}
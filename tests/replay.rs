@@ -0,0 +1,61 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Replays recorded zigbee2mqtt traffic from a real Hue button through the test broker
+
+use control::{ButtonEvent, Manager, Sensor, ToggleValue};
+use tintean::automation::Automation;
+use tintean::zigbee::devices::philips::{HueSmartButton, Light, MockLight};
+use macros::DeviceSet;
+use serde_json::json;
+use std::time::Duration;
+use tokio::time::sleep;
+use testing::{Recording, run, start_mqtt_broker};
+use tokio_stream::StreamExt;
+
+/// How long to wait for the automation to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn replay_button_presses() {
+    let recording = Recording::load(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/recordings/hue_button.jsonl")).unwrap();
+    let (conn, _guard) = start_mqtt_broker();
+    let mock_light = MockLight::new(&conn, "test_light").await;
+    mock_light.publish_state(true).await;
+
+    let mqttoptions = conn.mqtt_options("replay");
+
+    let mut manager = Manager::builder()
+        .add_device_manager(zigbee::Manager::builder()
+            .mqtt_options(mqttoptions)
+            .build())
+        .build();
+    let devices: Devices = manager.create().await.unwrap();
+    let automation = toggle_light_on_press(devices.test_button.events(), devices.test_light.state());
+    run(manager, [automation], async {
+        // the light is toggled by each of the three presses, ending off
+        let light_off = conn.expect_publish("zigbee2mqtt/test_light", json!({"state": "OFF"}), TIMEOUT);
+        recording.replay(&conn, 10.0).await;
+        light_off.await;
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(mock_light.state(), Some(false));
+    })
+    .await;
+}
+
+#[derive(DeviceSet)]
+struct Devices {
+    test_button: HueSmartButton,
+    test_light: Light,
+}
+
+fn toggle_light_on_press<'a>(
+    button: &'a impl Sensor<Item = ButtonEvent>,
+    light: &'a (impl ToggleValue + Send + Sync),
+) -> Automation<'a> {
+    let presses = button.subscribe().filter(|event| *event == ButtonEvent::Press);
+    Automation::new("toggle", presses, async |_| {
+        light
+            .toggle()
+            .await
+            .map_err(|err| format!("failed to toggle light: {err}"))
+    })
+}
@@ -0,0 +1,274 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests controlling devices and inspecting automations through the REST API
+
+use control::{Sensor, Service, ToggleValue, WriteValue};
+use macros::DeviceSet;
+use reqwest::StatusCode;
+use serde_json::{Value, json};
+use std::net::TcpListener;
+use std::time::Duration;
+use testing::{Connection, TestHarness, mock_contact_sensor, run};
+use tintean::automation::Automation;
+use tintean::rest;
+use tintean::zigbee::devices::philips::{Light, MockLight};
+use tintean::zigbee::devices::sonoff::ContactSensor;
+use tokio::spawn;
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/// How long to wait for a device to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(DeviceSet)]
+struct Devices {
+    hallway_light: Light,
+    front_door: ContactSensor,
+}
+
+#[tokio::test]
+async fn rest_api() {
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "hallway_light").await;
+            light.publish_state(true).await;
+            let door = mock_contact_sensor(conn, "front_door", true).await;
+            (light, door)
+        })
+        .start()
+        .await;
+    let (light, door) = &harness.mocks;
+    let conn = &harness.connection;
+    let devices = &harness.devices;
+    let mut manager = harness.manager;
+
+    let automation = Automation::new("never", tokio_stream::empty::<()>(), async |_| {
+        devices.hallway_light.state().toggle().await.map_err(|err| err.to_string())
+    });
+    let port = free_port();
+    manager.add_service(
+        rest::Server::builder()
+            .bind_address("127.0.0.1")
+            .port(port)
+            .add_device(devices.hallway_light.clone())
+            .add_device(devices.front_door.clone())
+            .add_automation(&automation)
+            .build(),
+    );
+    let url = format!("http://127.0.0.1:{port}/api");
+
+    run(manager, [automation], async {
+        sleep(Duration::from_millis(50)).await;
+        let client = reqwest::Client::new();
+
+        let listed: Vec<Value> = client.get(format!("{url}/devices")).send().await.unwrap().json().await.unwrap();
+        let ids: Vec<_> = listed.iter().map(|device| device["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["hallway_light", "front_door"]);
+
+        let state: Value = client.get(format!("{url}/devices/hallway_light/state")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(state, json!(true));
+
+        let light_off = conn.expect_publish(&conn.topic("hallway_light"), json!({"state": "OFF"}), TIMEOUT);
+        let response = client.put(format!("{url}/devices/hallway_light/state")).json(&json!(false)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        light_off.await;
+        assert_eq!(light.state(), Some(false));
+
+        let light_on = conn.expect_publish(&conn.topic("hallway_light"), json!({"state": "ON"}), TIMEOUT);
+        let response = client.post(format!("{url}/devices/hallway_light/state/toggle")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        light_on.await;
+
+        // the contact can only be subscribed to, so the latest reported value is returned
+        door.update("contact", false).await;
+        sleep(Duration::from_millis(50)).await;
+        let contact: Value = client.get(format!("{url}/devices/front_door/contact")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(contact, json!(false));
+
+        let response = client.get(format!("{url}/devices/garage_door")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = client.put(format!("{url}/devices/hallway_light/state")).json(&json!("dim")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let automations: Value = client.get(format!("{url}/automations")).send().await.unwrap().json().await.unwrap();
+        assert_eq!(automations, json!([{"name": "never", "triggered": 0, "succeeded": 0, "failed": 0}]));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn event_stream() {
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "hallway_light").await;
+            light.publish_state(false).await;
+            let door = mock_contact_sensor(conn, "front_door", true).await;
+            (light, door)
+        })
+        .start()
+        .await;
+    let (_light, door) = &harness.mocks;
+    let devices = &harness.devices;
+    let mut manager = harness.manager;
+
+    let opened = devices.front_door.contact().subscribe().filter(|contact| !*contact);
+    let automation = Automation::new("hallway light", opened, async |_| {
+        devices.hallway_light.state().set(true).await.map_err(|err| err.to_string())
+    });
+    let port = free_port();
+    manager.add_service(
+        rest::Server::builder()
+            .bind_address("127.0.0.1")
+            .port(port)
+            .add_device(devices.hallway_light.clone())
+            .add_device(devices.front_door.clone())
+            .add_automation(&automation)
+            .build(),
+    );
+
+    run(manager, [automation], async {
+        sleep(Duration::from_millis(50)).await;
+        let (mut socket, _) = connect_async(format!("ws://127.0.0.1:{port}/api/events")).await.unwrap();
+        door.update("contact", false).await;
+
+        let expected = [
+            json!({"type": "device_update", "device": "front_door", "field": "contact", "value": false}),
+            json!({"type": "automation_run", "automation": "hallway light", "event": "triggered"}),
+            json!({"type": "automation_run", "automation": "hallway light", "event": "succeeded"}),
+        ];
+        let mut received = Vec::new();
+        let result = timeout(TIMEOUT, async {
+            while !expected.iter().all(|event| received.contains(event)) {
+                let Some(message) = socket.next().await else {
+                    panic!("the event stream closed");
+                };
+                let Message::Text(text) = message.unwrap() else {
+                    continue;
+                };
+                received.push(serde_json::from_str::<Value>(&text).unwrap());
+            }
+        })
+        .await;
+        assert!(result.is_ok(), "expected {expected:?}, received {received:?}");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn health_endpoints() {
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "hallway_light").await;
+            let door = mock_contact_sensor(conn, "front_door", true).await;
+            (light, door)
+        })
+        .start()
+        .await;
+    let manager = harness.manager;
+    let port = free_port();
+    // started outside the manager, so it can be queried before the manager is ready
+    let server = spawn(
+        rest::Server::builder()
+            .bind_address("127.0.0.1")
+            .port(port)
+            .health(manager.health())
+            .build()
+            .start(),
+    );
+    sleep(Duration::from_millis(100)).await;
+    let url = format!("http://127.0.0.1:{port}");
+
+    let ready = reqwest::get(format!("{url}/readyz")).await.unwrap();
+    assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let idle = Automation::new("idle", tokio_stream::pending::<()>(), async |_| Ok(()));
+
+    run(manager, [idle], async {
+        sleep(Duration::from_millis(150)).await;
+        let ready = reqwest::get(format!("{url}/readyz")).await.unwrap();
+        assert_eq!(ready.status(), StatusCode::OK);
+
+        let health = reqwest::get(format!("{url}/healthz")).await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+        let health: Value = health.json().await.unwrap();
+        assert_eq!(health["components"]["zigbee"]["healthy"], json!(true), "{health}");
+        assert_eq!(health["components"]["automations"]["healthy"], json!(true), "{health}");
+        server.abort();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn write_routes_require_token() {
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "hallway_light").await;
+            light.publish_state(false).await;
+            let door = mock_contact_sensor(conn, "front_door", true).await;
+            (light, door)
+        })
+        .start()
+        .await;
+    let (light, _door) = &harness.mocks;
+    let devices = &harness.devices;
+    let mut manager = harness.manager;
+    let port = free_port();
+    // listens on localhost by default
+    manager.add_service(
+        rest::Server::builder()
+            .port(port)
+            .token("secret")
+            .add_device(devices.hallway_light.clone())
+            .build(),
+    );
+    let url = format!("http://127.0.0.1:{port}/api");
+    let idle = Automation::new("idle", tokio_stream::pending::<()>(), async |_| Ok(()));
+
+    run(manager, [idle], async {
+        sleep(Duration::from_millis(50)).await;
+        let client = reqwest::Client::new();
+
+        // reading does not need the token
+        let state = client.get(format!("{url}/devices/hallway_light/state")).send().await.unwrap();
+        assert_eq!(state.status(), StatusCode::OK);
+
+        let set = |token: Option<&str>| {
+            let request = client.put(format!("{url}/devices/hallway_light/state")).json(&json!(true));
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+            .send()
+        };
+        assert_eq!(set(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(set(Some("guess")).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let toggle = client.post(format!("{url}/devices/hallway_light/state/toggle")).send().await.unwrap();
+        assert_eq!(toggle.status(), StatusCode::UNAUTHORIZED);
+        let reload = client.post(format!("{url}/reload")).send().await.unwrap();
+        assert_eq!(reload.status(), StatusCode::UNAUTHORIZED);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(light.state(), Some(false));
+
+        assert_eq!(set(Some("secret")).await.unwrap().status(), StatusCode::NO_CONTENT);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(light.state(), Some(true));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn open_write_routes_refused() {
+    // listening on every interface without a token would let anyone on the network change devices
+    let server = rest::Server::builder()
+        .bind_address("0.0.0.0")
+        .port(free_port())
+        .build();
+    let error = server.start().await.unwrap_err();
+    assert!(error.to_string().contains("without a token"), "{error}");
+}
+
+/// Find a port which is free to listen on
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
@@ -0,0 +1,60 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests an automation using the contact sensor and roller shade mocks from the testing crate,
+//! closing the shade when a door is opened
+
+use control::{Manager, Sensor, WriteValue};
+use tintean::automation::Automation;
+use tintean::zigbee::devices::aqara::{RollerShadeDriver, RollerShadeDriverStateCommand};
+use tintean::zigbee::devices::sonoff::ContactSensor;
+use macros::DeviceSet;
+use serde_json::json;
+use std::time::Duration;
+use testing::{mock_contact_sensor, mock_roller_shade, run, start_mqtt_broker};
+use tokio_stream::StreamExt;
+
+/// How long to wait for the automation to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn close_shade_when_door_opens() {
+    let (conn, _guard) = start_mqtt_broker();
+
+    let door = mock_contact_sensor(&conn, "test_door", true).await;
+    let shade = mock_roller_shade(&conn, "test_shade", true).await;
+
+    let mqttoptions = conn.mqtt_options("shade-automation");
+
+    let mut manager = Manager::builder()
+        .add_device_manager(zigbee::Manager::builder()
+            .mqtt_options(mqttoptions)
+            .build())
+        .build();
+    let devices: Devices = manager.create().await.unwrap();
+    let automation = close_shade_on_open(devices.test_door.contact(), devices.test_shade.command());
+    run(manager, [automation], async {
+        let closed = conn.expect_publish(&conn.topic("test_shade"), json!({"state": "CLOSE"}), TIMEOUT);
+        door.update("contact", false).await;
+        closed.await;
+        assert_eq!(shade.value("state"), Some(json!("CLOSE")));
+    })
+    .await;
+}
+
+#[derive(DeviceSet)]
+struct Devices {
+    test_door: ContactSensor,
+    test_shade: RollerShadeDriver,
+}
+
+fn close_shade_on_open<'a>(
+    door: &'a impl Sensor<Item = bool>,
+    shade: &'a (impl WriteValue<Item = RollerShadeDriverStateCommand> + Sync),
+) -> Automation<'a> {
+    let opened = door.subscribe().filter(|contact| !*contact);
+    Automation::new("close shade", opened, async |_| {
+        shade
+            .set(RollerShadeDriverStateCommand::Close)
+            .await
+            .map_err(|err| format!("failed to close shade: {err}"))
+    })
+}
@@ -5,17 +5,18 @@
 
 use control::{ButtonEvent, Manager, Sensor, ToggleValue};
 use tintean::automation::Automation;
-use tintean::zigbee::devices::philips::{HueSmartButton, Light};
+use tintean::zigbee::devices::philips::{HueSmartButton, Light, MockHueSmartButton, MockLight};
 use log::{Level, debug};
 use macros::DeviceSet;
-use rumqttc::MqttOptions;
+use serde_json::json;
 use simple_log::LogConfigBuilder;
 use std::time::Duration;
-use async_scoped::TokioScope;
-use tokio::time::sleep;
-use testing::{mock_philips_button, mock_philips_light, start_mqtt_broker};
+use testing::{Connection, TestHarness, run};
 use tokio_stream::StreamExt;
 
+/// How long to wait for the automation to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
 #[tokio::test]
 async fn test_automation() {
     simple_log::new(
@@ -26,36 +27,74 @@ async fn test_automation() {
             .build(),
     )
     .expect("failed to start logger");
-    let (conn, _guard) = start_mqtt_broker();
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "test_light").await;
+            light.publish_state(true).await;
+            (MockHueSmartButton::new(conn, "test_button").await, light)
+        })
+        .start()
+        .await;
+    let (mock_button, mock_light) = &harness.mocks;
+    let conn = &harness.connection;
+    let automation = toggle_light_on_button(
+        harness.devices.test_button.events(),
+        harness.devices.test_light.state(),
+    );
+    let manager = harness.manager;
+    run(manager, [automation], async {
+        assert_eq!(mock_light.state(), Some(true));
+        let light_off = conn.expect_publish(&conn.topic("test_light"), json!({"state": "OFF"}), TIMEOUT);
+        mock_button.publish_switch(false).await;
+        light_off.await;
+        assert_eq!(mock_light.state(), Some(false));
+        let light_on = conn.expect_publish(&conn.topic("test_light"), json!({"state": "ON"}), TIMEOUT);
+        mock_button.publish_switch(true).await;
+        light_on.await;
+        assert_eq!(mock_light.state(), Some(true));
+    })
+    .await;
+}
 
-    let mut mock_button = mock_philips_button(&conn, "test_button").await;
-    let mock_light = mock_philips_light(&conn, "test_light", true, 254).await;
+#[tokio::test]
+async fn shutdown_token() {
+    let harness: TestHarness<ShutdownDevices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "shutdown_light").await;
+            light.publish_state(false).await;
+            (MockHueSmartButton::new(conn, "shutdown_button").await, light)
+        })
+        .start()
+        .await;
+    let (mock_button, mock_light) = &harness.mocks;
+    let automation = toggle_light_on_button(
+        harness.devices.shutdown_button.events(),
+        harness.devices.shutdown_light.state(),
+    );
+    let manager = harness.manager;
+    // the manager runs alongside the test on the same task, the test fails unless the manager
+    // returns once it's shutdown token is cancelled
+    run(manager, [automation], async {
+        let light_on = harness.connection.expect_publish(&harness.connection.topic("shutdown_light"), json!({"state": "ON"}), TIMEOUT);
+        mock_button.publish_switch(true).await;
+        light_on.await;
+        assert_eq!(mock_light.state(), Some(true));
+    })
+    .await;
+}
 
-    let mut mqttoptions = MqttOptions::new("rumqtt-sync", "localhost", 1883);
-    mqttoptions.set_keep_alive(Duration::from_secs(5));
+#[tokio::test]
+async fn ended_trigger() {
+    let manager = Manager::builder().build();
+    let health = manager.health();
+    let once = Automation::new("once", tokio_stream::iter([()]), async |_| Ok(()));
+    let idle = Automation::new("idle", tokio_stream::pending::<()>(), async |_| Ok(()));
 
-    let mut manager = Manager::builder()
-        .add_device_manager(zigbee::Manager::builder()
-            .mqtt_options(mqttoptions)
-            .build())
-        .build();
-    let devices: Devices = manager.create().await.unwrap();
-    let automation =
-        toggle_light_on_button(devices.test_button.events(), devices.test_light.state());
-    TokioScope::scope_and_block(|scope| {
-        scope.spawn(async move {
-            sleep(Duration::from_millis(50)).await;
-            assert!(mock_light.state());
-            mock_button.action("off").await;
-            assert!(!mock_light.state());
-            mock_button.action("on").await;
-            assert!(mock_light.state());
-        });
-        scope.spawn(async move {
-            let manager = manager;
-            manager.start([automation]).await;
-        });
-    });
+    run(manager, [once, idle], async {
+        // the trigger of the first automation ended as soon as it fired, so only one is running
+        assert_eq!(health.components()["automations"].detail, "1 automations running");
+    })
+    .await;
 }
 
 #[derive(DeviceSet)]
@@ -64,6 +103,13 @@ struct Devices {
     test_light: Light,
 }
 
+/// The tests share the broker, so each uses it's own devices to keep their publishes apart
+#[derive(DeviceSet)]
+struct ShutdownDevices {
+    shutdown_button: HueSmartButton,
+    shutdown_light: Light,
+}
+
 fn toggle_light_on_button<'a>(
     button: &'a impl Sensor<Item = ButtonEvent>,
     light: &'a (impl ToggleValue + Send + Sync),
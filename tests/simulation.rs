@@ -0,0 +1,96 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests running a thermostat automation against a scripted day of temperatures
+
+use control::{Sensor, WriteValue};
+use std::time::{Duration, SystemTime};
+use testing::{Script, Simulation, advance, is_time_paused, pause_time};
+use tintean::automation::Automation;
+use tokio_stream::StreamExt;
+
+/// An hour of simulated time
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+#[tokio::test]
+async fn thermostat() {
+    let mut simulation = Simulation::new();
+    let temperature = simulation.sensor(
+        Script::new()
+            .at(HOUR, 20.0)
+            .at(HOUR * 2, 18.5)
+            .at(HOUR * 6, 20.0)
+            .at(HOUR * 9, 21.5)
+            .at(HOUR * 12, 18.0),
+    );
+    let boiler = simulation.value("boiler", false);
+
+    let report = simulation
+        .run([heat_below(&temperature, &boiler, 19.0, 21.0)], HOUR * 10)
+        .await;
+
+    // the reading after the end of the simulation never arrives
+    let writes: Vec<_> = report.writes_to("boiler").map(|write| (write.at, write.value.as_str())).collect();
+    assert_eq!(writes, [(HOUR * 2, "true"), (HOUR * 9, "false")]);
+    assert_eq!(report.writes[0].automation.as_deref(), Some("thermostat"));
+    assert_eq!(report.writes[0].to_string(), "7200s: boiler = true by thermostat");
+}
+
+#[tokio::test]
+async fn keeps_paused_clock() {
+    let cold_morning = |simulation: &mut Simulation| {
+        let temperature = simulation.sensor(Script::new().at(HOUR, 18.0));
+        let boiler = simulation.value("boiler", false);
+        (temperature, boiler)
+    };
+
+    let mut simulation = Simulation::new();
+    let (temperature, boiler) = cold_morning(&mut simulation);
+    simulation.run([heat_below(&temperature, &boiler, 19.0, 21.0)], HOUR * 2).await;
+    assert!(!is_time_paused(), "the clock was running before the simulation");
+
+    pause_time();
+    let mut simulation = Simulation::new();
+    let (temperature, boiler) = cold_morning(&mut simulation);
+    let report = simulation.run([heat_below(&temperature, &boiler, 19.0, 21.0)], HOUR * 2).await;
+    assert_eq!(report.writes_to("boiler").count(), 1);
+    assert!(is_time_paused(), "the clock was paused before the simulation");
+    // the test can carry on advancing the clock
+    advance(HOUR).await;
+}
+
+#[tokio::test]
+async fn recorded_samples() {
+    let recorded = SystemTime::UNIX_EPOCH + HOUR * 24 * 365;
+    let samples = [(recorded + HOUR, 18.0), (recorded, 22.0)];
+    let mut simulation = Simulation::new();
+    let temperature = simulation.sensor(Script::from_samples(samples));
+    let boiler = simulation.value("boiler", false);
+
+    let report = simulation
+        .run([heat_below(&temperature, &boiler, 19.0, 21.0)], HOUR * 2)
+        .await;
+
+    // the earliest sample is at the start of the simulation
+    let writes: Vec<_> = report.writes_to("boiler").map(|write| (write.at, write.value.as_str())).collect();
+    assert_eq!(writes, [(Duration::ZERO, "false"), (HOUR, "true")]);
+}
+
+/// Turn the boiler on below `low` and off above `high`
+fn heat_below<'a>(
+    temperature: &'a impl Sensor<Item = f64>,
+    boiler: &'a (impl WriteValue<Item = bool> + Sync),
+    low: f64,
+    high: f64,
+) -> Automation<'a> {
+    let changes = temperature.subscribe().filter_map(move |temperature| {
+        if temperature < low {
+            Some(true)
+        } else if temperature > high {
+            Some(false)
+        } else {
+            None
+        }
+    });
+    Automation::new("thermostat", changes, async |on| {
+        boiler.set(on).await.map_err(|err| err.to_string())
+    })
+}
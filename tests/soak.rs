@@ -0,0 +1,157 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! A soak test which runs the full manager against mock devices for a simulated week
+//!
+//! A week's worth of button presses is played out on a paused clock, which is advanced between
+//! presses, while tracking memory growth, lagging state and automation failures. This takes a while
+//! so it is ignored by default, run it with:
+//! ```sh
+//! cargo test --features zigbee --test soak -- --ignored --nocapture
+//! ```
+//! The load can be tuned with the `SOAK_DAYS` and `SOAK_EVENTS_PER_DAY` environment variables
+
+use control::{ButtonEvent, Sensor, StreamCustomExt, ToggleValue};
+use log::{Level, info, warn};
+use macros::DeviceSet;
+use simple_log::LogConfigBuilder;
+use std::time::{Duration, Instant};
+use testing::{Connection, TestHarness, advance, settle};
+use tintean::automation::Automation;
+use tintean::zigbee::devices::philips::{HueSmartButton, Light, MockHueSmartButton, MockLight};
+use tokio::join;
+use tokio::time::timeout;
+
+/// A day of simulated time
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// The real time allowed for a press to propagate through the broker and automation to the light,
+/// the clock is paused so this is measured on the wall clock
+const PROPAGATION_DELAY: Duration = Duration::from_millis(20);
+/// The real time allowed for the manager to subscribe to the devices before the first press
+const STARTUP_DELAY: Duration = Duration::from_millis(50);
+/// The maximum resident memory growth allowed over the whole run
+const MAX_MEMORY_GROWTH_KB: u64 = 16 * 1024;
+
+#[derive(DeviceSet)]
+struct Devices {
+    soak_button: HueSmartButton,
+    soak_light: Light,
+}
+
+#[tokio::test]
+#[ignore = "long running soak test"]
+async fn soak() {
+    simple_log::new(
+        LogConfigBuilder::builder()
+            .level(Level::Warn)
+            .unwrap()
+            .output_console()
+            .build(),
+    )
+    .expect("failed to start logger");
+    let days = env_or("SOAK_DAYS", 7);
+    let events_per_day = env_or("SOAK_EVENTS_PER_DAY", 200);
+    let between_events = DAY / u32::try_from(events_per_day).expect("too many events per day");
+
+    let harness: TestHarness<Devices, _> = TestHarness::with_devices::<zigbee::Manager, _>()
+        .mocks(async |conn: &Connection| {
+            let light = MockLight::new(conn, "soak_light").await;
+            light.publish_state(false).await;
+            (MockHueSmartButton::new(conn, "soak_button").await, light)
+        })
+        .pause_time(true)
+        .start()
+        .await;
+    let (mock_button, mock_light) = &harness.mocks;
+    let automation = toggle_light_on_press(harness.devices.soak_button.events(), harness.devices.soak_light.state());
+    let manager = harness.manager;
+    let shutdown = manager.shutdown_token();
+
+    // the simulated run is bounded by the paused clock, a hang lets the clock skip ahead to here
+    let simulated = DAY * u32::try_from(days + 1).expect("too many days");
+    let run = timeout(simulated, async {
+        join!(manager.start([automation]), async {
+            wait_for(STARTUP_DELAY, || false).await;
+            let start = Instant::now();
+            let initial_memory = resident_memory_kb();
+            let mut expected = mock_light.state().unwrap_or_default();
+            let mut lagged = 0u64;
+            let mut failures = 0u64;
+            for day in 0..days {
+                for _ in 0..events_per_day {
+                    mock_button.publish_events(ButtonEvent::Press).await;
+                    mock_button.publish_events(ButtonEvent::Release).await;
+                    expected = !expected;
+                    let applied = || mock_light.state() == Some(expected);
+                    // allow a second chance before deeming the automation to have failed
+                    if !wait_for(PROPAGATION_DELAY, applied).await {
+                        lagged += 1;
+                        if !wait_for(PROPAGATION_DELAY * 10, applied).await {
+                            failures += 1;
+                            expected = mock_light.state().unwrap_or_default();
+                        }
+                    }
+                    advance(between_events).await;
+                }
+                info!("simulated day {} complete after {:?}", day + 1, start.elapsed());
+            }
+            let memory_growth = resident_memory_kb().saturating_sub(initial_memory);
+            warn!(
+                "soak finished in {:?}: {} events, {lagged} lagged, {failures} failed, memory grew by {memory_growth}kB",
+                start.elapsed(),
+                days * events_per_day,
+            );
+            shutdown.cancel();
+            (failures, memory_growth)
+        })
+    });
+    let ((), (failures, memory_growth)) = run.await.expect("the soak test did not finish");
+    assert_eq!(failures, 0, "automations failed to apply");
+    assert!(
+        memory_growth <= MAX_MEMORY_GROWTH_KB,
+        "memory grew by {memory_growth}kB, expected at most {MAX_MEMORY_GROWTH_KB}kB"
+    );
+}
+
+/// Yield to the runtime until the condition holds, giving up after `limit` of real time, the
+/// runtime is never idle while waiting so the paused clock does not skip ahead
+async fn wait_for(limit: Duration, condition: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while !condition() {
+        if start.elapsed() > limit {
+            return false;
+        }
+        settle().await;
+    }
+    true
+}
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .map(|value| value.parse().expect("environment variable should be an integer"))
+        .unwrap_or(default)
+}
+
+/// Reads the resident memory of this process from procfs, returns 0 where unavailable
+fn resident_memory_kb() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))
+                .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+fn toggle_light_on_press<'a>(
+    button: &'a impl Sensor<Item = ButtonEvent>,
+    light: &'a (impl ToggleValue + Send + Sync),
+) -> Automation<'a> {
+    let presses = button.subscribe().filter_eq(ButtonEvent::Press);
+    Automation::new("soak", presses, async |_| {
+        light
+            .toggle()
+            .await
+            .map_err(|err| format!("failed to toggle light: {err}"))
+    })
+}
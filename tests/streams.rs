@@ -0,0 +1,118 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests of the stream helpers which depend on when values arrive, run on a paused clock so the
+//! time between values is exact
+
+use control::{Aggregation, StreamCustomExt};
+use std::pin::{Pin, pin};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use testing::{advance, pause_time};
+use tokio::sync::mpsc::unbounded_channel;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// A minute of paused time
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Poll the stream once, returning the value it emitted, if any
+fn poll_once<S: Stream>(stream: Pin<&mut S>) -> Option<S::Item> {
+    match stream.poll_next(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(item) => item,
+        Poll::Pending => None,
+    }
+}
+
+fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.expect("no value was emitted");
+    assert!((actual - expected).abs() < 1e-9, "expected {expected}, got {actual}");
+}
+
+#[tokio::test]
+async fn derivative() {
+    pause_time();
+    let (values, receiver) = unbounded_channel();
+    let mut rates = pin!(UnboundedReceiverStream::new(receiver).derivative(MINUTE));
+    // a value is timestamped when the stream reads it, so each is read as soon as it is sent
+    let mut send = |value: f64| {
+        values.send(value).unwrap();
+        poll_once(rates.as_mut())
+    };
+
+    // a rate needs two values
+    assert_eq!(send(20.0), None);
+    advance(MINUTE / 2).await;
+    assert_close(send(21.0), 1.0 / 30.0);
+    advance(MINUTE / 2).await;
+    assert_close(send(22.0), 2.0 / 60.0);
+
+    // the first value falls out of the window, the rate is measured from the second
+    advance(MINUTE / 2).await;
+    assert_close(send(25.0), 4.0 / 60.0);
+
+    // after a gap, the rate spans back to the last value received before the window
+    advance(MINUTE * 10).await;
+    assert_close(send(19.0), -6.0 / 600.0);
+    advance(MINUTE / 2).await;
+    assert_close(send(16.0), -9.0 / 630.0);
+}
+
+/// Send each value at it's offset from the start and return the aggregate emitted for each
+async fn aggregate(aggregation: Aggregation, values: &[(u32, f64)]) -> Vec<f64> {
+    let (sender, receiver) = unbounded_channel();
+    let mut aggregates = pin!(UnboundedReceiverStream::new(receiver).aggregate(MINUTE * 10, aggregation));
+    let mut now = 0;
+    let mut emitted = Vec::new();
+    for &(minute, value) in values {
+        advance(MINUTE * (minute - now)).await;
+        now = minute;
+        sender.send(value).unwrap();
+        emitted.push(poll_once(aggregates.as_mut()).expect("no aggregate was emitted"));
+    }
+    emitted
+}
+
+#[tokio::test]
+async fn aggregate_min() {
+    pause_time();
+    let values = [(0, 5.0), (1, 3.0), (2, 4.0), (3, 6.0), (12, 7.0), (13, 8.0), (30, 9.0)];
+    // the 3 expires at 11 minutes, but the 4 is still exactly on the edge of the window at 12
+    assert_eq!(aggregate(Aggregation::Min, &values).await, [5.0, 3.0, 3.0, 3.0, 4.0, 6.0, 9.0]);
+}
+
+#[tokio::test]
+async fn aggregate_max() {
+    pause_time();
+    let values = [(0, 1.0), (1, 5.0), (2, 3.0), (3, 4.0), (12, 2.0), (14, 1.0), (30, 0.0)];
+    // the 3 was dropped from the queue once the larger 4 arrived, so the maximum falls straight
+    // from 5 to 4 once the 5 expires
+    assert_eq!(aggregate(Aggregation::Max, &values).await, [1.0, 5.0, 5.0, 5.0, 4.0, 2.0, 0.0]);
+}
+
+#[tokio::test]
+async fn aggregate_mean() {
+    pause_time();
+    let values = [(0, 2.0), (5, 4.0), (10, 6.0), (11, 8.0), (30, 10.0)];
+    assert_eq!(aggregate(Aggregation::Mean, &values).await, [2.0, 3.0, 4.0, 6.0, 10.0]);
+}
+
+#[tokio::test]
+async fn aggregate_expires_while_quiet() {
+    pause_time();
+    let (sender, receiver) = unbounded_channel();
+    let mut aggregates = pin!(UnboundedReceiverStream::new(receiver).aggregate(MINUTE * 10, Aggregation::Max));
+    sender.send(5.0).unwrap();
+    assert_eq!(poll_once(aggregates.as_mut()), Some(5.0));
+    advance(MINUTE * 5).await;
+    sender.send(3.0).unwrap();
+    assert_eq!(poll_once(aggregates.as_mut()), Some(5.0));
+    // no new value arrives, the 5 still leaves the window just after 10 minutes
+    advance(MINUTE * 5).await;
+    assert_eq!(poll_once(aggregates.as_mut()), None);
+    advance(Duration::from_millis(1)).await;
+    assert_eq!(poll_once(aggregates.as_mut()), Some(3.0));
+    // once the 3 has also expired there is nothing left to report
+    advance(MINUTE * 5).await;
+    assert_eq!(poll_once(aggregates.as_mut()), None);
+    sender.send(1.0).unwrap();
+    assert_eq!(poll_once(aggregates.as_mut()), Some(1.0));
+}
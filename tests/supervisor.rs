@@ -0,0 +1,75 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests restarting the tasks of device managers which fail or panic
+
+use control::Sensor;
+use control::device_manager::{Supervisor, SupervisorEvent};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+/// How long to wait for the supervisor to react
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+#[tokio::test]
+async fn restarts() {
+    let token = CancellationToken::new();
+    let supervisor = Supervisor::builder()
+        .token(token.clone())
+        .restart_delay(Duration::from_millis(10))
+        .build();
+    let mut events = supervisor.subscribe();
+
+    // the task fails, then panics and then finishes
+    let starts = Arc::new(AtomicU32::new(0));
+    let task_starts = starts.clone();
+    supervisor.spawn("flaky", move || {
+        let start = task_starts.fetch_add(1, Ordering::Relaxed);
+        async move {
+            match start {
+                0 => anyhow::bail!("connection refused"),
+                1 => panic!("unexpected message"),
+                _ => Ok(()),
+            }
+        }
+    });
+
+    let mut next = async || timeout(TIMEOUT, events.next()).await.unwrap().unwrap();
+    assert_eq!(next().await, SupervisorEvent::Stopped {
+        task: "flaky".to_string(),
+        reason: "failed: connection refused".to_string(),
+    });
+    assert_eq!(next().await, SupervisorEvent::Restarted { task: "flaky".to_string(), attempt: 1 });
+    assert!(matches!(next().await, SupervisorEvent::Stopped { reason, .. } if reason.starts_with("panicked")));
+    assert_eq!(next().await, SupervisorEvent::Restarted { task: "flaky".to_string(), attempt: 2 });
+
+    // a task which finished is not restarted
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(starts.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test]
+async fn shutdown() {
+    let token = CancellationToken::new();
+    let supervisor = Supervisor::builder()
+        .token(token.clone())
+        .restart_delay(Duration::from_millis(10))
+        .build();
+
+    let starts = Arc::new(AtomicU32::new(0));
+    let task_starts = starts.clone();
+    supervisor.spawn("failing", move || {
+        task_starts.fetch_add(1, Ordering::Relaxed);
+        async { anyhow::bail!("connection refused") }
+    });
+    sleep(Duration::from_millis(50)).await;
+    token.cancel();
+    let stopped_at = starts.load(Ordering::Relaxed);
+    assert!(stopped_at > 1, "the task was not restarted");
+
+    // no task is restarted after shutdown
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(starts.load(Ordering::Relaxed), stopped_at);
+}
@@ -0,0 +1,75 @@
+#![allow(clippy::expect_used, clippy::unwrap_used, clippy::panic, reason = "Panics are forgivable while testing")]
+//! Tests the wiz crate against a mock bulb on a loopback address
+
+use control::device_manager::DeviceManager;
+use control::reflect::value::Value;
+use control::reflect::{Device, DeviceInfo, DeviceType};
+use serde_json::json;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use testing::MockWizBulb;
+use tintean::wiz::{Error, Kind, Light, Manager};
+use tokio::join;
+use tokio::time::{sleep, timeout};
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+
+/// How long to wait for the bulb to receive a request
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn info() -> DeviceInfo {
+    DeviceInfo {
+        id: "test_bulb".to_string(),
+        name: "Test Bulb".to_string(),
+        description: None,
+        device_type: DeviceType::Light,
+        tags: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn test_wiz_light() {
+    let bulb = MockWizBulb::builder()
+        .module("ESP03_SHTW1C_01")
+        .start(Ipv4Addr::new(127, 0, 0, 2))
+        .await;
+    let manager = Manager::builder().timeout(Duration::from_millis(100)).retries(1).build();
+    let light = Light::verify_new(&manager, info(), bulb.addr()).await.unwrap();
+    assert_eq!(light.kind(), Kind::TunableWhite);
+    assert!(!light.last_state().await.state);
+
+    // commands are sent to the bulb
+    let turned_on = bulb.expect_request("setPilot", json!({"state": true, "dimming": 100}), TIMEOUT);
+    light.turn_on().await.unwrap();
+    turned_on.await;
+    assert_eq!(bulb.pilot()["state"], json!(true));
+
+    // changes from another source are pushed to the light, and on to subscribers of the field
+    let mut states = Device::subscribe(&light, "state").unwrap().await;
+    assert_eq!(states.next().await, Some(Value::from(true)));
+    bulb.change(json!({"state": false})).await;
+    timeout(TIMEOUT, async {
+        while light.last_state().await.state {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("pushed state was not received");
+    assert_eq!(timeout(TIMEOUT, states.next()).await.unwrap(), Some(Value::from(false)));
+
+    // a bulb which stops answering is reported offline
+    bulb.set_responding(false);
+    light.get_state().await.expect_err("an unresponsive bulb should time out");
+    assert!(!light.online());
+    assert_eq!(bulb.received("getPilot").len(), 3);
+
+    // requests fail once the manager has stopped, rather than timing out
+    let token = CancellationToken::new();
+    Box::new(manager).start(token.clone());
+    let (error, ()) = join!(light.get_state(), async {
+        sleep(Duration::from_millis(50)).await;
+        token.cancel();
+    });
+    assert!(matches!(error, Err(Error::Stopped)));
+    assert!(matches!(light.get_state().await, Err(Error::Stopped)));
+}